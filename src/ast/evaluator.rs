@@ -1,16 +1,26 @@
 use std::collections::HashMap;
+use std::fmt::{ Display, Formatter };
+use std::io::Write;
 use crate::ast::{
     ASTAssignmentExpression,
     ASTBinaryExpression,
     ASTBinaryOperatorKind,
     ASTBlockStatement,
     ASTBooleanExpression,
+    ASTBreakStatement,
     ASTCallExpression,
+    ASTContinueStatement,
+    ASTExpression,
+    ASTFloatExpression,
+    ASTForStatement,
     ASTFuncDeclStatement,
     ASTIfStatement,
     ASTLetStatement,
     ASTNumberExpression,
     ASTParenthesizedExpression,
+    ASTReturnStatement,
+    ASTStatement,
+    ASTStringExpression,
     ASTUnaryExpression,
     ASTUnaryOperatorKind,
     ASTVariableExpression,
@@ -18,10 +28,73 @@ use crate::ast::{
 };
 use crate::ast::lexer::TextSpan;
 use crate::ast::visitor::ASTVisitor;
-use crate::compilation_unit::GlobalScope;
+use crate::compilation_unit::{ FunctionBody, GlobalScope };
+use crate::diagnostics::{ codes, Diagnostic, DiagnosticKind };
+use crate::intrinsics::{ Intrinsic, IntrinsicOutcome };
+use crate::types::Type;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    String(String),
+    Unit,
+}
+
+// Unannotated parameters are only typed at runtime, so every use of a value checks its type
+impl Value {
+    pub fn ty(&self) -> Type {
+        match self {
+            Value::Int(_) => Type::Int,
+            Value::Float(_) => Type::Float,
+            Value::Bool(_) => Type::Bool,
+            Value::String(_) => Type::String,
+            Value::Unit => Type::Unit,
+        }
+    }
+
+    pub fn as_int(&self) -> Result<i64, String> {
+        match self {
+            Value::Int(value) => Ok(*value),
+            _ => Err(self.type_mismatch(Type::Int)),
+        }
+    }
+
+    pub fn as_float(&self) -> Result<f64, String> {
+        match self {
+            Value::Float(value) => Ok(*value),
+            _ => Err(self.type_mismatch(Type::Float)),
+        }
+    }
+
+    pub fn as_bool(&self) -> Result<bool, String> {
+        match self {
+            Value::Bool(value) => Ok(*value),
+            _ => Err(self.type_mismatch(Type::Bool)),
+        }
+    }
+
+    fn type_mismatch(&self, expected: Type) -> String {
+        format!("Expected type '{}', found '{}'", expected, self.ty())
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Int(value) => write!(f, "{}", value),
+            // Debug formatting keeps the fraction, e.g. 3.0 rather than 3
+            Value::Float(value) => write!(f, "{:?}", value),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::String(value) => write!(f, "{}", value),
+            Value::Unit => write!(f, "()"),
+        }
+    }
+}
 
 pub struct Frame {
-    variables: HashMap<String, i64>,
+    variables: HashMap<String, Value>,
 }
 
 impl Frame {
@@ -31,11 +104,11 @@ impl Frame {
         }
     }
 
-    fn insert(&mut self, identifier: String, value: i64) {
+    fn insert(&mut self, identifier: String, value: Value) {
         self.variables.insert(identifier, value);
     }
 
-    fn get(&self, identifier: &String) -> Option<&i64> {
+    fn get(&self, identifier: &String) -> Option<&Value> {
         self.variables.get(identifier)
     }
 }
@@ -44,8 +117,14 @@ pub struct Frames {
     frames: Vec<Frame>,
 }
 
+impl Default for Frames {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Frames {
-    fn new() -> Self {
+    pub fn new() -> Self {
         Self {
             frames: vec![Frame::new()],
         }
@@ -59,21 +138,33 @@ impl Frames {
         self.frames.pop();
     }
 
-    fn update(&mut self, identifier: String, value: i64) {
+    fn update(&mut self, identifier: String, value: Value) -> Result<(), ()> {
         for frame in self.frames.iter_mut().rev() {
             if frame.variables.contains_key(&identifier) {
                 frame.insert(identifier, value);
-                return;
+                return Ok(());
             }
         }
-        panic!("Variable {} not found", identifier)
+        Err(())
     }
 
-    fn insert(&mut self, identifier: String, value: i64) {
+    // A call only sees the globals and its own locals, so the caller's frames are set aside
+    fn enter_call(&mut self) -> Vec<Frame> {
+        let caller_frames = self.frames.split_off(1);
+        self.push();
+        caller_frames
+    }
+
+    fn exit_call(&mut self, caller_frames: Vec<Frame>) {
+        self.frames.truncate(1);
+        self.frames.extend(caller_frames);
+    }
+
+    fn insert(&mut self, identifier: String, value: Value) {
         self.frames.last_mut().unwrap().insert(identifier, value);
     }
 
-    fn get(&self, identifier: &String) -> Option<&i64> {
+    fn get(&self, identifier: &String) -> Option<&Value> {
         for frame in self.frames.iter().rev() {
             if let Some(value) = frame.get(identifier) {
                 return Some(value);
@@ -83,23 +174,284 @@ impl Frames {
     }
 }
 
+// Operator semantics shared by every backend, so they agree on results and error messages
+pub fn unary_operation(operator: ASTUnaryOperatorKind, operand: Value) -> Result<Value, String> {
+    Ok(match (operator, operand) {
+        (ASTUnaryOperatorKind::Minus, Value::Float(operand)) => Value::Float(-operand),
+        (ASTUnaryOperatorKind::Minus, Value::Int(operand)) => {
+            let negated = operand.checked_neg();
+            Value::Int(negated.ok_or_else(|| "Integer overflow in '-'".to_string())?)
+        }
+        (ASTUnaryOperatorKind::BitwiseNot, Value::Int(operand)) => Value::Int(!operand),
+        (ASTUnaryOperatorKind::LogicalNot, Value::Bool(operand)) => Value::Bool(!operand),
+        (operator, operand) => {
+            return Err(
+                format!(
+                    "Operator '{}' cannot be applied to type '{}'",
+                    operator.symbol(),
+                    operand.ty()
+                )
+            );
+        }
+    })
+}
+
+// `&&` and `||` short-circuit, so backends handle them before evaluating the right operand
+pub fn binary_operation(
+    operator: ASTBinaryOperatorKind,
+    left: Value,
+    right: Value
+) -> Result<Value, String> {
+    match (operator, left, right) {
+        // Values of different types are never equal, and unit only equals itself
+        (ASTBinaryOperatorKind::Equals, left, right) => Ok(Value::Bool(left == right)),
+        (ASTBinaryOperatorKind::NotEquals, left, right) => Ok(Value::Bool(left != right)),
+        (ASTBinaryOperatorKind::Plus, Value::String(left), Value::String(right)) => {
+            Ok(Value::String(left + &right))
+        }
+        (
+            operator @ (
+                ASTBinaryOperatorKind::BitwiseAnd
+                | ASTBinaryOperatorKind::BitwiseOr
+                | ASTBinaryOperatorKind::BitwiseXor
+            ),
+            left @ Value::Float(_),
+            right @ Value::Float(_),
+        ) => Err(binary_type_mismatch(operator, &left, &right)),
+        (operator, Value::Float(left), Value::Float(right)) => {
+            Ok(float_binary_operation(operator, left, right))
+        }
+        (operator, Value::Int(left), Value::Int(right)) => {
+            int_binary_operation(operator, left, right)
+        }
+        (operator, left, right) => Err(binary_type_mismatch(operator, &left, &right)),
+    }
+}
+
+fn binary_type_mismatch(operator: ASTBinaryOperatorKind, left: &Value, right: &Value) -> String {
+    format!(
+        "Operator '{}' cannot be applied to types '{}' and '{}'",
+        operator.symbol(),
+        left.ty(),
+        right.ty()
+    )
+}
+
+fn int_binary_operation(
+    operator: ASTBinaryOperatorKind,
+    left: i64,
+    right: i64
+) -> Result<Value, String> {
+    let overflow = || format!("Integer overflow in '{}'", operator.symbol());
+    Ok(match operator {
+        ASTBinaryOperatorKind::Plus => Value::Int(left.checked_add(right).ok_or_else(overflow)?),
+        ASTBinaryOperatorKind::Minus => Value::Int(left.checked_sub(right).ok_or_else(overflow)?),
+        ASTBinaryOperatorKind::Multiply => Value::Int(left.checked_mul(right).ok_or_else(overflow)?),
+        ASTBinaryOperatorKind::Divide => {
+            if right == 0 {
+                return Err("Division by zero".to_string());
+            }
+            Value::Int(left.checked_div(right).ok_or_else(overflow)?)
+        }
+        ASTBinaryOperatorKind::Power => {
+            if right < 0 {
+                return Err(format!("Negative exponent {} in integer power", right));
+            }
+            let exponent = u32::try_from(right).map_err(|_| overflow())?;
+            Value::Int(left.checked_pow(exponent).ok_or_else(overflow)?)
+        }
+        ASTBinaryOperatorKind::BitwiseAnd => Value::Int(left & right),
+        ASTBinaryOperatorKind::BitwiseOr => Value::Int(left | right),
+        ASTBinaryOperatorKind::BitwiseXor => Value::Int(left ^ right),
+        ASTBinaryOperatorKind::LessThan => Value::Bool(left < right),
+        ASTBinaryOperatorKind::LessThanOrEqual => Value::Bool(left <= right),
+        ASTBinaryOperatorKind::GreaterThan => Value::Bool(left > right),
+        ASTBinaryOperatorKind::GreaterThanOrEqual => Value::Bool(left >= right),
+        ASTBinaryOperatorKind::Equals => Value::Bool(left == right),
+        ASTBinaryOperatorKind::NotEquals => Value::Bool(left != right),
+        ASTBinaryOperatorKind::LogicalAnd | ASTBinaryOperatorKind::LogicalOr => {
+            unreachable!("Logical operators are evaluated before their operands")
+        }
+    })
+}
+
+fn float_binary_operation(operator: ASTBinaryOperatorKind, left: f64, right: f64) -> Value {
+    match operator {
+        ASTBinaryOperatorKind::Plus => Value::Float(left + right),
+        ASTBinaryOperatorKind::Minus => Value::Float(left - right),
+        ASTBinaryOperatorKind::Multiply => Value::Float(left * right),
+        ASTBinaryOperatorKind::Divide => Value::Float(left / right),
+        ASTBinaryOperatorKind::Power => Value::Float(left.powf(right)),
+        ASTBinaryOperatorKind::LessThan => Value::Bool(left < right),
+        ASTBinaryOperatorKind::LessThanOrEqual => Value::Bool(left <= right),
+        ASTBinaryOperatorKind::GreaterThan => Value::Bool(left > right),
+        ASTBinaryOperatorKind::GreaterThanOrEqual => Value::Bool(left >= right),
+        ASTBinaryOperatorKind::Equals => Value::Bool(left == right),
+        ASTBinaryOperatorKind::NotEquals => Value::Bool(left != right),
+        ASTBinaryOperatorKind::BitwiseAnd |
+        ASTBinaryOperatorKind::BitwiseOr |
+        ASTBinaryOperatorKind::BitwiseXor => {
+            unreachable!("Bitwise operators are not defined for floats")
+        }
+        ASTBinaryOperatorKind::LogicalAnd | ASTBinaryOperatorKind::LogicalOr => {
+            unreachable!("Logical operators are evaluated before their operands")
+        }
+    }
+}
+
+// Deeper recursion is reported as a runtime error rather than overflowing the native stack.
+// Running `main` is not a call, so its body starts at depth zero.
+pub const MAX_CALL_DEPTH: usize = 1000;
+
+// A call that was in progress when a runtime error occurred
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceFrame {
+    pub function: String,
+    pub call_site: TextSpan,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub message: String,
+    pub span: TextSpan,
+    // Innermost call first
+    pub trace: Vec<TraceFrame>,
+}
+
+impl RuntimeError {
+    // The calls that led to the error become related spans
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let diagnostic = Diagnostic::new(
+            &codes::RUNTIME_ERROR,
+            self.message.clone(),
+            self.span.clone(),
+            DiagnosticKind::Error
+        );
+        self.trace.iter().fold(diagnostic, |diagnostic, frame| {
+            let message = format!("'{}' was called here", frame.function);
+            diagnostic.with_related(message, frame.call_site.clone())
+        })
+    }
+}
+
+// Non-local exit that is unwinding through the statements currently being evaluated
+#[derive(Debug, Clone, PartialEq)]
+pub enum ControlFlow {
+    Return,
+    Break,
+    Continue,
+    Exit(i64),
+    Error(RuntimeError),
+}
+
 pub struct ASTEvaluator<'a> {
-    pub last_value: Option<i64>,
+    pub last_value: Option<Value>,
     pub frames: Frames,
     pub global_scope: &'a GlobalScope,
+    pub control_flow: Option<ControlFlow>,
+    call_stack: Vec<TraceFrame>,
+    output: &'a mut dyn Write,
 }
 
 impl<'a> ASTEvaluator<'a> {
-    pub fn new(global_scope: &'a GlobalScope) -> Self {
-        Self { last_value: None, frames: Frames::new(), global_scope }
+    pub fn new(global_scope: &'a GlobalScope, output: &'a mut dyn Write) -> Self {
+        Self {
+            last_value: None,
+            frames: Frames::new(),
+            global_scope,
+            control_flow: None,
+            call_stack: Vec::new(),
+            output,
+        }
     }
 
-    fn eval_boolean_instruction<F>(&self, instruction: F) -> i64 where F: FnOnce() -> bool {
-        let result = instruction();
-        if result {
-            1
-        } else {
+    pub fn is_unwinding(&self) -> bool {
+        self.control_flow.is_some()
+    }
+
+    pub fn exit_code(&self) -> i64 {
+        match self.control_flow {
+            Some(ControlFlow::Exit(code)) => code,
+            Some(ControlFlow::Error(_)) => 1,
+            _ => 0,
+        }
+    }
+
+    pub fn runtime_error(&self) -> Option<&RuntimeError> {
+        match &self.control_flow {
+            Some(ControlFlow::Error(error)) => Some(error),
+            _ => None,
+        }
+    }
+
+    fn report_runtime_error(&mut self, message: String, span: TextSpan) {
+        let trace = self.call_stack.iter().rev().cloned().collect();
+        self.control_flow = Some(ControlFlow::Error(RuntimeError { message, span, trace }));
+        self.last_value = Some(Value::Unit);
+    }
+
+    // Output that can no longer be written, e.g. to a closed pipe, is dropped
+    pub fn flush(&mut self) {
+        self.output.flush().ok();
+    }
+
+    fn call_intrinsic(&mut self, intrinsic: Intrinsic, arguments: Vec<Value>, span: TextSpan) {
+        match intrinsic.call(&arguments, self.output) {
+            IntrinsicOutcome::Value(value) => {
+                self.last_value = Some(value);
+            }
+            IntrinsicOutcome::Exit(code) => {
+                self.control_flow = Some(ControlFlow::Exit(code));
+                self.last_value = Some(Value::Unit);
+            }
+            IntrinsicOutcome::Failure(message) => {
+                self.report_runtime_error(message, span);
+            }
+        }
+    }
+
+    fn evaluate(&mut self, expression: &ASTExpression) -> Value {
+        self.visit_expression(expression);
+        self.last_value.clone().unwrap()
+    }
+
+    // A value that is not a bool is reported at the expression; the result is then unused
+    fn evaluate_condition(&mut self, expression: &ASTExpression) -> bool {
+        let value = self.evaluate(expression);
+        if self.is_unwinding() {
+            return false;
+        }
+        value.as_bool().unwrap_or_else(|message| {
+            self.report_runtime_error(message, expression.span());
+            false
+        })
+    }
+
+    fn evaluate_range_bound(&mut self, expression: &ASTExpression) -> i64 {
+        let value = self.evaluate(expression);
+        if self.is_unwinding() {
+            return 0;
+        }
+        value.as_int().unwrap_or_else(|message| {
+            self.report_runtime_error(message, expression.span());
             0
+        })
+    }
+
+    // Runs one pass of a loop body and tells the loop whether to keep going
+    fn run_loop_body(&mut self, body: &ASTStatement) -> bool {
+        self.visit_statement(body);
+        match self.control_flow {
+            Some(ControlFlow::Break) => {
+                self.control_flow = None;
+                false
+            }
+            Some(ControlFlow::Continue) => {
+                self.control_flow = None;
+                true
+            }
+            Some(_) => false,
+            None => true,
         }
     }
 
@@ -117,42 +469,104 @@ impl<'a> ASTVisitor<'_> for ASTEvaluator<'a> {
 
     fn visit_while_statement(&mut self, while_statement: &ASTWhileStatement) {
         self.push_frame();
-        self.visit_expression(&while_statement.condition);
-        while self.last_value.unwrap() != 0 {
-            self.visit_statement(&while_statement.body);
-            self.visit_expression(&while_statement.condition);
+        loop {
+            let condition = self.evaluate_condition(&while_statement.condition);
+            if self.is_unwinding() || !condition {
+                break;
+            }
+            if !self.run_loop_body(&while_statement.body) {
+                break;
+            }
+        }
+        self.pop_frame();
+        if !self.is_unwinding() {
+            self.last_value = Some(Value::Unit);
+        }
+    }
+
+    fn visit_for_statement(&mut self, for_statement: &ASTForStatement) {
+        let start = self.evaluate_range_bound(&for_statement.start);
+        if self.is_unwinding() {
+            return;
+        }
+        let end = self.evaluate_range_bound(&for_statement.end);
+        if self.is_unwinding() {
+            return;
+        }
+        let identifier = &for_statement.identifier.span.literal;
+        let values: Box<dyn Iterator<Item = i64>> = if for_statement.is_inclusive() {
+            Box::new(start..=end)
+        } else {
+            Box::new(start..end)
+        };
+        self.push_frame();
+        for value in values {
+            self.frames.insert(identifier.clone(), Value::Int(value));
+            if !self.run_loop_body(&for_statement.body) {
+                break;
+            }
         }
         self.pop_frame();
+        if !self.is_unwinding() {
+            self.last_value = Some(Value::Unit);
+        }
     }
 
     fn visit_block_statement(&mut self, block_statement: &ASTBlockStatement) {
         self.push_frame();
         for statement in &block_statement.statements {
             self.visit_statement(statement);
+            if self.is_unwinding() {
+                break;
+            }
         }
         self.pop_frame();
     }
 
+    fn visit_break_statement(&mut self, _break_statement: &ASTBreakStatement) {
+        self.control_flow = Some(ControlFlow::Break);
+    }
+
+    fn visit_continue_statement(&mut self, _continue_statement: &ASTContinueStatement) {
+        self.control_flow = Some(ControlFlow::Continue);
+    }
+
+    fn visit_return_statement(&mut self, return_statement: &ASTReturnStatement) {
+        self.last_value = Some(Value::Unit);
+        if let Some(expression) = &return_statement.return_value {
+            self.visit_expression(expression);
+            if self.is_unwinding() {
+                return;
+            }
+        }
+        self.control_flow = Some(ControlFlow::Return);
+    }
+
     fn visit_if_statement(&mut self, if_statement: &ASTIfStatement) {
         self.push_frame();
-        self.visit_expression(&if_statement.condition);
-        if self.last_value.unwrap() != 0 {
+        let condition = self.evaluate_condition(&if_statement.condition);
+        if self.is_unwinding() {
+            self.pop_frame();
+            return;
+        }
+        if condition {
             self.push_frame();
             self.visit_statement(&if_statement.then_branch);
             self.pop_frame();
-        } else {
-            if let Some(else_branch) = &if_statement.else_branch {
-                self.push_frame();
-                self.visit_statement(&else_branch.else_statement);
-                self.pop_frame();
-            }
+        } else if let Some(else_branch) = &if_statement.else_branch {
+            self.push_frame();
+            self.visit_statement(&else_branch.else_statement);
+            self.pop_frame();
         }
         self.pop_frame();
     }
 
     fn visit_let_statement(&mut self, let_statement: &ASTLetStatement) {
-        self.visit_expression(&let_statement.initializer);
-        self.frames.insert(let_statement.identifier.span.literal.clone(), self.last_value.unwrap());
+        let value = self.evaluate(&let_statement.initializer);
+        if self.is_unwinding() {
+            return;
+        }
+        self.frames.insert(let_statement.identifier.span.literal.clone(), value);
     }
 
     fn visit_call_expression(&mut self, call_expression: &ASTCallExpression) {
@@ -161,40 +575,87 @@ impl<'a> ASTVisitor<'_> for ASTEvaluator<'a> {
             .unwrap();
         let mut arguments = Vec::new();
         for argument in &call_expression.arguments {
-            self.visit_expression(argument);
-            arguments.push(self.last_value.unwrap());
+            arguments.push(self.evaluate(argument));
+            if self.is_unwinding() {
+                return;
+            }
         }
-        self.push_frame();
-        for (i, argument) in arguments.iter().enumerate() {
-            let parameter_name = function.parameters[i].clone();
-            self.frames.insert(parameter_name, *argument);
+        let call_site = call_expression.span();
+        let body = match &function.body {
+            FunctionBody::Declared(body) => body,
+            FunctionBody::Intrinsic(intrinsic) => {
+                self.call_intrinsic(*intrinsic, arguments, call_site);
+                return;
+            }
+        };
+        if self.call_stack.len() == MAX_CALL_DEPTH {
+            self.report_runtime_error("Stack overflow".to_string(), call_site);
+            return;
         }
+        let caller_frames = self.frames.enter_call();
+        for (i, argument) in arguments.into_iter().enumerate() {
+            let parameter_name = function.parameters[i].identifier.clone();
+            self.frames.insert(parameter_name, argument);
+        }
+        self.call_stack.push(TraceFrame {
+            function: call_expression.identifier.span.literal.clone(),
+            call_site,
+        });
 
-        self.visit_statement(&function.body);
-        self.pop_frame();
+        self.visit_statement(body);
+        if self.control_flow == Some(ControlFlow::Return) {
+            self.control_flow = None;
+        } else if !self.is_unwinding() {
+            self.last_value = Some(Value::Unit);
+        }
+        self.call_stack.pop();
+        self.frames.exit_call(caller_frames);
     }
 
     fn visit_assignment_expression(&mut self, assignment_expression: &ASTAssignmentExpression) {
-        let identifier = &assignment_expression.identifier.span.literal;
-        self.visit_expression(&assignment_expression.expression);
-        self.frames.update(identifier.clone(), self.last_value.unwrap());
+        let identifier = &assignment_expression.identifier.span;
+        let value = self.evaluate(&assignment_expression.expression);
+        if self.is_unwinding() {
+            return;
+        }
+        if self.frames.update(identifier.literal.clone(), value).is_err() {
+            // A function can assign a global that the program has not reached yet
+            self.report_runtime_error(
+                format!("Variable '{}' is not defined yet", identifier.literal),
+                identifier.clone()
+            );
+        }
     }
 
     fn visit_variable_expression(&mut self, variable_expression: &ASTVariableExpression) {
-        let identifier = &variable_expression.identifier.span.literal;
-        self.last_value = Some(
-            *self.frames
-                .get(identifier)
-                .expect(format!("Variable {} not found", identifier).as_str())
-        );
+        let identifier = &variable_expression.identifier.span;
+        match self.frames.get(&identifier.literal) {
+            Some(value) => {
+                self.last_value = Some(value.clone());
+            }
+            None => {
+                self.report_runtime_error(
+                    format!("Variable '{}' is not defined yet", identifier.literal),
+                    identifier.clone()
+                );
+            }
+        }
     }
 
     fn visit_number_expression(&mut self, number: &ASTNumberExpression) {
-        self.last_value = Some(number.number);
+        self.last_value = Some(Value::Int(number.number));
+    }
+
+    fn visit_float_expression(&mut self, float: &ASTFloatExpression) {
+        self.last_value = Some(Value::Float(float.number));
+    }
+
+    fn visit_string_expression(&mut self, string: &ASTStringExpression) {
+        self.last_value = Some(Value::String(string.value.clone()));
     }
 
     fn visit_boolean_expression(&mut self, boolean: &ASTBooleanExpression) {
-        self.last_value = Some(boolean.value as i64);
+        self.last_value = Some(Value::Bool(boolean.value));
     }
 
     fn visit_error(&mut self, _span: &TextSpan) {
@@ -202,37 +663,59 @@ impl<'a> ASTVisitor<'_> for ASTEvaluator<'a> {
     }
 
     fn visit_unary_expression(&mut self, unary_expression: &ASTUnaryExpression) {
-        self.visit_expression(&unary_expression.operand);
-        let operand = self.last_value.unwrap();
-        self.last_value = Some(match unary_expression.operator.kind {
-            ASTUnaryOperatorKind::Minus => -operand,
-            ASTUnaryOperatorKind::BitwiseNot => !operand,
-        });
+        let operand = self.evaluate(&unary_expression.operand);
+        if self.is_unwinding() {
+            return;
+        }
+        match unary_operation(unary_expression.operator.kind, operand) {
+            Ok(value) => {
+                self.last_value = Some(value);
+            }
+            Err(message) => {
+                self.report_runtime_error(message, unary_expression.span());
+            }
+        }
     }
 
     fn visit_binary_expression(&mut self, expr: &ASTBinaryExpression) {
-        self.visit_expression(&expr.left);
-        let left = self.last_value.unwrap();
-        self.visit_expression(&expr.right);
-        let right = self.last_value.unwrap();
-        self.last_value = Some(match expr.operator.kind {
-            ASTBinaryOperatorKind::Plus => left + right,
-            ASTBinaryOperatorKind::Minus => left - right,
-            ASTBinaryOperatorKind::Multiply => left * right,
-            ASTBinaryOperatorKind::Divide => left / right,
-            ASTBinaryOperatorKind::Power => left.pow(right as u32),
-            ASTBinaryOperatorKind::BitwiseAnd => left & right,
-            ASTBinaryOperatorKind::BitwiseOr => left | right,
-            ASTBinaryOperatorKind::BitwiseXor => left ^ right,
-            ASTBinaryOperatorKind::Equals => if left == right { 1 } else { 0 }
-            ASTBinaryOperatorKind::NotEquals => self.eval_boolean_instruction(|| left != right),
-            ASTBinaryOperatorKind::LessThan => self.eval_boolean_instruction(|| left < right),
-            ASTBinaryOperatorKind::LessThanOrEqual =>
-                self.eval_boolean_instruction(|| left <= right),
-            ASTBinaryOperatorKind::GreaterThan => self.eval_boolean_instruction(|| left > right),
-            ASTBinaryOperatorKind::GreaterThanOrEqual =>
-                self.eval_boolean_instruction(|| left >= right),
-        });
+        // The right operand is only evaluated when it can change the result
+        let short_circuit_value = match expr.operator.kind {
+            ASTBinaryOperatorKind::LogicalAnd => Some(false),
+            ASTBinaryOperatorKind::LogicalOr => Some(true),
+            _ => None,
+        };
+        if let Some(short_circuit_value) = short_circuit_value {
+            let left = self.evaluate_condition(&expr.left);
+            if self.is_unwinding() {
+                return;
+            }
+            if left == short_circuit_value {
+                self.last_value = Some(Value::Bool(left));
+                return;
+            }
+            let right = self.evaluate_condition(&expr.right);
+            if !self.is_unwinding() {
+                self.last_value = Some(Value::Bool(right));
+            }
+            return;
+        }
+
+        let left = self.evaluate(&expr.left);
+        if self.is_unwinding() {
+            return;
+        }
+        let right = self.evaluate(&expr.right);
+        if self.is_unwinding() {
+            return;
+        }
+        match binary_operation(expr.operator.kind, left, right) {
+            Ok(value) => {
+                self.last_value = Some(value);
+            }
+            Err(message) => {
+                self.report_runtime_error(message, expr.span());
+            }
+        }
     }
 
     fn visit_parenthesized_expression(
@@ -242,3 +725,602 @@ impl<'a> ASTVisitor<'_> for ASTEvaluator<'a> {
         self.visit_expression(&parenthesized_expression.expression);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::io::{ self, ErrorKind, Write };
+    use std::thread;
+
+    use crate::ast::evaluator::{ ASTEvaluator, RuntimeError, Value, MAX_CALL_DEPTH };
+    use crate::bytecode::vm::VirtualMachine;
+    use crate::compilation_unit::CompilationUnit;
+
+    struct Execution {
+        value: Option<Value>,
+        output: String,
+        exit_code: i64,
+        error: Option<RuntimeError>,
+    }
+
+    fn execute(input: &str) -> Execution {
+        let compilation_unit = CompilationUnit::compile(input).expect("Failed to compile");
+        let mut output = Vec::new();
+        let mut eval = ASTEvaluator::new(&compilation_unit.global_scope, &mut output);
+        compilation_unit.execute(&mut eval);
+        let value = eval.last_value.clone();
+        let exit_code = eval.exit_code();
+        let error = eval.runtime_error().cloned();
+        let output = String::from_utf8(output).unwrap();
+
+        // Every program must behave the same on the bytecode VM
+        let program = compilation_unit.compile_bytecode();
+        let mut vm_output = Vec::new();
+        let vm_result = VirtualMachine::new(&program, &mut vm_output).run();
+        assert_eq!(String::from_utf8(vm_output).unwrap(), output, "VM output differs");
+        match (&vm_result, &error) {
+            (Ok(vm_exit_code), None) => assert_eq!(*vm_exit_code, exit_code, "VM exit code differs"),
+            (Err(vm_error), Some(error)) => assert_eq!(vm_error, error, "VM runtime error differs"),
+            _ => panic!("VM result {:?} differs from runtime error {:?}", vm_result, error),
+        }
+
+        Execution { value, output, exit_code, error }
+    }
+
+    fn expect_runtime_error(input: &str) -> RuntimeError {
+        execute(input).error.expect("Expected a runtime error")
+    }
+
+    fn evaluate(input: &str) -> Option<Value> {
+        execute(input).value
+    }
+
+    #[test]
+    fn should_return_value_of_call_expression() {
+        let input = "\
+        func add(a, b) {
+            return a + b
+        }
+        add(2, 3)
+        ";
+
+        assert_eq!(evaluate(input), Some(Value::Int(5)));
+    }
+
+    #[test]
+    fn should_not_execute_statements_after_return() {
+        let input =
+            "\
+        let a = 1
+        func f {
+            a = 2
+            return 3
+            a = 4
+        }
+        let b = f()
+        a
+        ";
+
+        assert_eq!(evaluate(input), Some(Value::Int(2)));
+    }
+
+    #[test]
+    fn should_return_from_nested_if_and_while() {
+        let input =
+            "\
+        func find {
+            let i = 0
+            while i < 10 {
+                if i == 3 {
+                    return i
+                }
+                i = i + 1
+            }
+            return 100
+        }
+        find()
+        ";
+
+        assert_eq!(evaluate(input), Some(Value::Int(3)));
+    }
+
+    #[test]
+    fn should_evaluate_recursive_function() {
+        let input =
+            "\
+        func fact(n) {
+            if n <= 1 {
+                return 1
+            }
+            return n * fact(n - 1)
+        }
+        fact(5)
+        ";
+
+        assert_eq!(evaluate(input), Some(Value::Int(120)));
+    }
+
+    #[test]
+    fn should_produce_unit_for_bare_return() {
+        let input = "\
+        func f {
+            return
+        }
+        f()
+        ";
+
+        assert_eq!(evaluate(input), Some(Value::Unit));
+    }
+
+    #[test]
+    fn should_not_take_next_line_as_value_of_bare_return() {
+        let input = "\
+        func f {
+            return
+            println(\"dead\")
+        }
+        f()
+        ";
+
+        let execution = execute(input);
+        assert_eq!(execution.output, "");
+        assert_eq!(execution.value, Some(Value::Unit));
+    }
+
+    #[test]
+    fn should_evaluate_comparison_to_boolean_value() {
+        let input = "\
+        let a = 1
+        let b = a < 2
+        b
+        ";
+
+        assert_eq!(evaluate(input), Some(Value::Bool(true)));
+    }
+
+    #[test]
+    fn should_concatenate_strings() {
+        let input = "\
+        let a = \"foo\"
+        let b = a + \"\\t\" + \"bar\"
+        b
+        ";
+
+        assert_eq!(evaluate(input), Some(Value::String("foo\tbar".to_string())));
+    }
+
+    #[test]
+    fn should_compare_strings() {
+        let input = "\
+        let a = \"a\\\"b\"
+        a == \"a\\\"b\"
+        ";
+
+        assert_eq!(evaluate(input), Some(Value::Bool(true)));
+    }
+
+    #[test]
+    fn should_write_output_of_print_and_println() {
+        let input = "\
+        let a = 5
+        print(\"a = \")
+        println(a)
+        println(a > 3)
+        ";
+
+        let execution = execute(input);
+        assert_eq!(execution.output, "a = 5\ntrue\n");
+        assert_eq!(execution.exit_code, 0);
+    }
+
+    #[test]
+    fn should_stop_execution_on_exit() {
+        let input =
+            "\
+        func check(a) {
+            if a > 2 {
+                exit(3)
+            }
+            println(a)
+        }
+        check(1)
+        check(5)
+        check(2)
+        ";
+
+        let execution = execute(input);
+        assert_eq!(execution.output, "1\n");
+        assert_eq!(execution.exit_code, 3);
+    }
+
+    #[test]
+    fn should_exit_with_failure_when_assertion_fails() {
+        let input = "\
+        assert(1 < 2)
+        println(\"reached\")
+        assert(2 < 1)
+        println(\"unreachable\")
+        ";
+
+        let execution = execute(input);
+        assert_eq!(execution.output, "reached\n");
+        assert_eq!(execution.exit_code, 1);
+        let error = execution.error.expect("Expected a runtime error");
+        assert_eq!(error.message, "Assertion failed");
+        assert_eq!(error.span.literal, "assert(2 < 1)");
+    }
+
+    #[test]
+    fn should_evaluate_float_arithmetic() {
+        let input = "\
+        let a = 1.5
+        let b = -a * 2.0
+        b
+        ";
+
+        assert_eq!(evaluate(input), Some(Value::Float(-3.0)));
+    }
+
+    #[test]
+    fn should_convert_between_int_and_float() {
+        let input = "\
+        let a: float = to_float(3) / 2.0
+        println(a)
+        println(to_int(a))
+        println(to_int(-2.9))
+        println(to_float(4))
+        ";
+
+        let execution = execute(input);
+        assert_eq!(execution.output, "1.5\n1\n-2\n4.0\n");
+    }
+
+    #[test]
+    fn should_respect_operator_precedence_and_associativity() {
+        let input = "\
+        println(1 + 2 * 3)
+        println(10 - 4 - 3)
+        println(2 ** 3 ** 2)
+        println(1 + 1 < 3)
+        ";
+
+        let execution = execute(input);
+        assert_eq!(execution.output, "7\n3\n512\ntrue\n");
+    }
+
+    #[test]
+    fn should_evaluate_logical_operators() {
+        let input = "\
+        let a = true
+        println(!a)
+        println(a && !a)
+        println(!a || a)
+        ";
+
+        let execution = execute(input);
+        assert_eq!(execution.output, "false\nfalse\ntrue\n");
+    }
+
+    #[test]
+    fn should_short_circuit_logical_operators() {
+        let input =
+            "\
+        func noisy(value: bool) -> bool {
+            println(value)
+            return value
+        }
+        let x = 0
+        println(x != 0 && 10 / x > 1)
+        println(noisy(true) || noisy(false))
+        println(noisy(false) && noisy(true))
+        ";
+
+        let execution = execute(input);
+        assert_eq!(execution.output, "false\ntrue\ntrue\nfalse\nfalse\n");
+    }
+
+    #[test]
+    fn should_report_division_by_zero_with_call_stack() {
+        let input =
+            "\
+        func divide(a, b) {
+            return a / b
+        }
+        func outer(x) {
+            println(divide(10, x))
+        }
+        outer(2)
+        outer(0)
+        println(\"unreachable\")
+        ";
+
+        let execution = execute(input);
+        assert_eq!(execution.output, "5\n");
+        let error = execution.error.expect("Expected a runtime error");
+        assert_eq!(error.message, "Division by zero");
+        assert_eq!(error.span.literal, "a / b");
+        let functions: Vec<&str> = error.trace
+            .iter()
+            .map(|frame| frame.function.as_str())
+            .collect();
+        assert_eq!(functions, vec!["divide", "outer"]);
+        assert_eq!(error.trace[1].call_site.literal, "outer(0)");
+    }
+
+    #[test]
+    fn should_report_stack_overflow_with_call_stack() {
+        let input = "\
+        func f(n) {
+            return f(n + 1)
+        }
+        func start() {
+            f(0)
+        }
+        start()
+        ";
+
+        // The evaluator recurses natively, and test threads have small stacks
+        let error = thread::Builder::new()
+            .stack_size(64 * 1024 * 1024)
+            .spawn(move || expect_runtime_error(input))
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(error.message, "Stack overflow");
+        assert_eq!(error.span.literal, "f(n + 1)");
+        assert_eq!(error.trace.len(), MAX_CALL_DEPTH);
+        assert_eq!(error.trace[MAX_CALL_DEPTH - 2].call_site.literal, "f(0)");
+        assert_eq!(error.trace[MAX_CALL_DEPTH - 1].call_site.literal, "start()");
+    }
+
+    #[test]
+    fn should_report_negative_exponent() {
+        let error = expect_runtime_error("let a = 2 ** -1");
+
+        assert_eq!(error.message, "Negative exponent -1 in integer power");
+        assert_eq!(error.span.literal, "2 ** -1");
+    }
+
+    #[test]
+    fn should_report_integer_overflow() {
+        let error = expect_runtime_error("let a = 9223372036854775807 + 1");
+        assert_eq!(error.message, "Integer overflow in '+'");
+
+        let error = expect_runtime_error("let a = 4611686018427387904 * 2");
+        assert_eq!(error.message, "Integer overflow in '*'");
+    }
+
+    #[test]
+    fn should_report_operands_of_the_wrong_type() {
+        let error = expect_runtime_error("func f(a) { return a + 1 }\nprintln(f(1.5))");
+        assert_eq!(error.message, "Operator '+' cannot be applied to types 'float' and 'int'");
+        assert_eq!(error.span.literal, "a + 1");
+        assert_eq!(error.trace.len(), 1);
+
+        let error = expect_runtime_error("func f(a, b) { return a + b }\nf(1, \"x\")");
+        assert_eq!(error.message, "Operator '+' cannot be applied to types 'int' and 'string'");
+
+        let error = expect_runtime_error("func f(a, b) { return a & b }\nf(1.5, 2.5)");
+        assert_eq!(error.message, "Operator '&' cannot be applied to types 'float' and 'float'");
+
+        let error = expect_runtime_error("func f(a) { return -a }\nf(true)");
+        assert_eq!(error.message, "Operator '-' cannot be applied to type 'bool'");
+        assert_eq!(error.span.literal, "-a");
+    }
+
+    #[test]
+    fn should_only_concatenate_strings_with_strings() {
+        let error = expect_runtime_error("func f(x) { return \"a\" + x }\nf(1)");
+        assert_eq!(error.message, "Operator '+' cannot be applied to types 'string' and 'int'");
+        assert_eq!(error.span.literal, "\"a\" + x");
+
+        let error = expect_runtime_error("func f(x) { return x + \"a\" }\nf(true)");
+        assert_eq!(error.message, "Operator '+' cannot be applied to types 'bool' and 'string'");
+    }
+
+    #[test]
+    fn should_report_conditions_that_are_not_bools() {
+        let error = expect_runtime_error("func f(a) { if a { println(1) } }\nf(3)");
+        assert_eq!(error.message, "Expected type 'bool', found 'int'");
+        assert_eq!(error.span.literal, "a");
+
+        let error = expect_runtime_error("func f(a) { while a { } }\nf(\"x\")");
+        assert_eq!(error.message, "Expected type 'bool', found 'string'");
+
+        let error = expect_runtime_error("func f(a) { return a && true }\nf(1)");
+        assert_eq!(error.message, "Expected type 'bool', found 'int'");
+        assert_eq!(error.span.literal, "a");
+
+        let error = expect_runtime_error("func f(a) { return false || a }\nf(1)");
+        assert_eq!(error.message, "Expected type 'bool', found 'int'");
+        assert_eq!(error.span.literal, "a");
+    }
+
+    #[test]
+    fn should_report_range_bounds_that_are_not_ints() {
+        let error = expect_runtime_error("func f(a) { for i in 0..a { } }\nf(2.5)");
+        assert_eq!(error.message, "Expected type 'int', found 'float'");
+        assert_eq!(error.span.literal, "a");
+    }
+
+    #[test]
+    fn should_report_intrinsic_arguments_of_the_wrong_type() {
+        let error = expect_runtime_error("func f(a) { assert(a) }\nf(1)");
+        assert_eq!(error.message, "Expected type 'bool', found 'int'");
+        assert_eq!(error.span.literal, "assert(a)");
+
+        let error = expect_runtime_error("func f(a) { exit(a) }\nf(true)");
+        assert_eq!(error.message, "Expected type 'int', found 'bool'");
+
+        let error = expect_runtime_error("func f(a) { return to_int(a) }\nf(1)");
+        assert_eq!(error.message, "Expected type 'float', found 'int'");
+    }
+
+    // Output that fails every write the same way
+    struct FailingOutput(ErrorKind);
+
+    impl Write for FailingOutput {
+        fn write(&mut self, _buffer: &[u8]) -> io::Result<usize> {
+            Err(io::Error::from(self.0))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Err(io::Error::from(self.0))
+        }
+    }
+
+    // Runs the program on both backends, writing to outputs that fail with the error
+    fn execute_with_failing_output(input: &str, error: ErrorKind) -> Result<i64, RuntimeError> {
+        let compilation_unit = CompilationUnit::compile(input).expect("Failed to compile");
+        let mut output = FailingOutput(error);
+        let mut eval = ASTEvaluator::new(&compilation_unit.global_scope, &mut output);
+        compilation_unit.execute(&mut eval);
+        let result = match eval.runtime_error() {
+            Some(error) => Err(error.clone()),
+            None => Ok(eval.exit_code()),
+        };
+
+        let program = compilation_unit.compile_bytecode();
+        let mut vm_output = FailingOutput(error);
+        let vm_result = VirtualMachine::new(&program, &mut vm_output).run();
+        assert_eq!(vm_result, result, "VM result differs");
+        result
+    }
+
+    #[test]
+    fn should_end_quietly_when_the_output_is_closed() {
+        let input = "println(1)\nexit(3)";
+
+        assert_eq!(execute_with_failing_output(input, ErrorKind::BrokenPipe), Ok(0));
+    }
+
+    #[test]
+    fn should_report_output_that_cannot_be_written() {
+        let input = "print(1)\nexit(3)";
+
+        let error = execute_with_failing_output(input, ErrorKind::StorageFull).unwrap_err();
+        assert_eq!(error.message, "Failed to write output: no storage space");
+        assert_eq!(error.span.literal, "print(1)");
+    }
+
+    #[test]
+    fn should_report_assignment_to_global_before_its_declaration() {
+        let input = "\
+        func early() {
+            late()
+        }
+        early()
+        let a = 1
+        func late() {
+            a = 2
+        }
+        ";
+
+        let error = expect_runtime_error(input);
+        assert_eq!(error.message, "Variable 'a' is not defined yet");
+        assert_eq!(error.trace.len(), 2);
+    }
+
+    #[test]
+    fn should_not_expose_caller_locals_to_callee() {
+        let input =
+            "\
+        let x = 1
+        func set() {
+            x = 5
+        }
+        func shadow() {
+            let x = 2
+            set()
+            println(x)
+        }
+        shadow()
+        println(x)
+        ";
+
+        let execution = execute(input);
+        assert_eq!(execution.output, "2\n5\n");
+    }
+
+    #[test]
+    fn should_iterate_over_exclusive_and_inclusive_ranges() {
+        let input =
+            "\
+        let sum = 0
+        for i in 0..5 {
+            sum = sum + i
+        }
+        println(sum)
+        for i in 1..=3 {
+            print(i)
+        }
+        for i in 3..3 {
+            println(\"unreachable\")
+        }
+        ";
+
+        let execution = execute(input);
+        assert_eq!(execution.output, "10\n123");
+    }
+
+    #[test]
+    fn should_return_from_inside_for_loop() {
+        let input =
+            "\
+        func first_square_above(limit: int) -> int {
+            for i in 0..limit {
+                if i * i > limit {
+                    return i
+                }
+            }
+            return -1
+        }
+        first_square_above(20)
+        ";
+
+        assert_eq!(evaluate(input), Some(Value::Int(5)));
+    }
+
+    #[test]
+    fn should_break_and_continue_in_while_loop() {
+        let input =
+            "\
+        let i = 0
+        while true {
+            i = i + 1
+            if i == 2 {
+                continue
+            }
+            if i > 4 {
+                break
+            }
+            print(i)
+        }
+        println(i)
+        ";
+
+        let execution = execute(input);
+        assert_eq!(execution.output, "1345\n");
+    }
+
+    #[test]
+    fn should_only_leave_innermost_loop_on_break() {
+        let input =
+            "\
+        for i in 0..3 {
+            for j in 0..3 {
+                if j > i {
+                    break
+                }
+                if j == 1 {
+                    continue
+                }
+                print(j)
+            }
+            println(i)
+        }
+        ";
+
+        let execution = execute(input);
+        assert_eq!(execution.output, "00\n01\n022\n");
+    }
+}
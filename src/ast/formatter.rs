@@ -0,0 +1,400 @@
+use std::collections::VecDeque;
+
+use crate::ast::{
+    Ast,
+    ASTAssignmentExpression,
+    ASTBinaryExpression,
+    ASTBlockStatement,
+    ASTBooleanExpression,
+    ASTBreakStatement,
+    ASTCallExpression,
+    ASTContinueStatement,
+    ASTFloatExpression,
+    ASTForStatement,
+    ASTFuncDeclStatement,
+    ASTIfStatement,
+    ASTLetStatement,
+    ASTNumberExpression,
+    ASTParenthesizedExpression,
+    ASTReturnStatement,
+    ASTStatement,
+    ASTStringExpression,
+    ASTUnaryExpression,
+    ASTVariableExpression,
+    ASTWhileStatement,
+    TypeAnnotation,
+};
+use crate::ast::lexer::TextSpan;
+use crate::ast::visitor::ASTVisitor;
+
+// Prints source in the canonical style: one statement per line, four spaces per level,
+// `} else {`, and at most one blank line wherever the original had any
+pub struct ASTFormatter<'a> {
+    source: &'a str,
+    // Comments not written yet, in source order
+    comments: VecDeque<TextSpan>,
+    // End of the last thing written, in the original source
+    cursor: usize,
+    indent: usize,
+    result: String,
+}
+
+impl<'a> ASTFormatter<'a> {
+    pub fn format(ast: &Ast, comments: Vec<TextSpan>, source: &'a str) -> String {
+        let mut formatter = ASTFormatter {
+            source,
+            comments: comments.into(),
+            cursor: 0,
+            indent: 0,
+            result: String::new(),
+        };
+        formatter.format_statements(&ast.statements, source.len());
+        formatter.result
+    }
+
+    // Each statement goes on its own line, after the comments in front of it
+    fn format_statements(&mut self, statements: &[ASTStatement], end: usize) {
+        let mut is_first = true;
+        for statement in statements {
+            let span = statement.span();
+            self.write_comments_before(span.start, &mut is_first);
+            self.start_line(span.start, &mut is_first);
+            self.visit_statement(statement);
+            self.cursor = self.cursor.max(span.end);
+            self.write_trailing_comments();
+            self.result.push('\n');
+        }
+        self.write_comments_before(end, &mut is_first);
+    }
+
+    fn start_line(&mut self, position: usize, is_first: &mut bool) {
+        let has_blank_line = self.gap_to(position).matches('\n').count() > 1;
+        if has_blank_line && !*is_first {
+            self.result.push('\n');
+        }
+        *is_first = false;
+        self.result.push_str(&"    ".repeat(self.indent));
+    }
+
+    // The original text between the last thing written and `position`
+    fn gap_to(&self, position: usize) -> &str {
+        if position > self.cursor { &self.source[self.cursor..position] } else { "" }
+    }
+
+    fn write_comments_before(&mut self, position: usize, is_first: &mut bool) {
+        while let Some(comment) = self.comments.front() {
+            if comment.start >= position {
+                break;
+            }
+            let comment = self.comments.pop_front().unwrap();
+            self.start_line(comment.start, is_first);
+            self.result.push_str(&comment.literal);
+            self.result.push('\n');
+            self.cursor = self.cursor.max(comment.end);
+        }
+    }
+
+    // Comments on the statement's last line stay there, as do comments from inside the statement,
+    // up to the first line comment since nothing can follow it on the same line
+    fn write_trailing_comments(&mut self) {
+        while let Some(comment) = self.comments.front() {
+            let is_inside = comment.start < self.cursor;
+            if !is_inside && self.gap_to(comment.start).contains('\n') {
+                break;
+            }
+            let comment = self.comments.pop_front().unwrap();
+            self.result.push(' ');
+            self.result.push_str(&comment.literal);
+            self.cursor = self.cursor.max(comment.end);
+            if comment.literal.starts_with("//") {
+                break;
+            }
+        }
+    }
+
+    fn add_text(&mut self, text: &str) {
+        self.result.push_str(text);
+    }
+
+    fn add_type_annotation(&mut self, type_annotation: &Option<TypeAnnotation>) {
+        if let Some(type_annotation) = type_annotation {
+            self.add_text(": ");
+            self.add_text(&type_annotation.type_name.span.literal);
+        }
+    }
+
+    // Bodies follow their header on the same line, braced or not
+    fn add_body(&mut self, body: &ASTStatement) {
+        self.add_text(" ");
+        self.visit_statement(body);
+    }
+}
+
+impl ASTVisitor<'_> for ASTFormatter<'_> {
+    fn visit_func_decl_statement(&mut self, func_decl_statement: &ASTFuncDeclStatement) {
+        self.add_text("func ");
+        self.add_text(&func_decl_statement.identifier.span.literal);
+        self.add_text("(");
+        for (i, parameter) in func_decl_statement.parameters.iter().enumerate() {
+            if i != 0 {
+                self.add_text(", ");
+            }
+            self.add_text(&parameter.identifier.span.literal);
+            self.add_type_annotation(&parameter.type_annotation);
+        }
+        self.add_text(")");
+        if let Some(return_type) = &func_decl_statement.return_type {
+            self.add_text(" -> ");
+            self.add_text(&return_type.type_name.span.literal);
+        }
+        self.add_body(&func_decl_statement.body);
+    }
+
+    fn visit_return_statement(&mut self, return_statement: &ASTReturnStatement) {
+        self.add_text("return");
+        if let Some(expression) = &return_statement.return_value {
+            self.add_text(" ");
+            self.visit_expression(expression);
+        }
+    }
+
+    fn visit_break_statement(&mut self, _break_statement: &ASTBreakStatement) {
+        self.add_text("break");
+    }
+
+    fn visit_continue_statement(&mut self, _continue_statement: &ASTContinueStatement) {
+        self.add_text("continue");
+    }
+
+    fn visit_while_statement(&mut self, while_statement: &ASTWhileStatement) {
+        self.add_text("while ");
+        self.visit_expression(&while_statement.condition);
+        self.add_body(&while_statement.body);
+    }
+
+    fn visit_for_statement(&mut self, for_statement: &ASTForStatement) {
+        self.add_text("for ");
+        self.add_text(&for_statement.identifier.span.literal);
+        self.add_text(" in ");
+        self.visit_expression(&for_statement.start);
+        self.add_text(&for_statement.range_operator.span.literal);
+        self.visit_expression(&for_statement.end);
+        self.add_body(&for_statement.body);
+    }
+
+    fn visit_block_statement(&mut self, block_statement: &ASTBlockStatement) {
+        self.add_text("{");
+        self.cursor = block_statement.open_brace.span.end;
+        let close_brace = block_statement.close_brace.span.start;
+        let has_comments = self.comments.front().is_some_and(|comment| comment.start < close_brace);
+        if block_statement.statements.is_empty() && !has_comments {
+            self.add_text("}");
+            return;
+        }
+        self.result.push('\n');
+        self.indent += 1;
+        self.format_statements(&block_statement.statements, close_brace);
+        self.indent -= 1;
+        self.result.push_str(&"    ".repeat(self.indent));
+        self.add_text("}");
+    }
+
+    fn visit_if_statement(&mut self, if_statement: &ASTIfStatement) {
+        self.add_text("if ");
+        self.visit_expression(&if_statement.condition);
+        self.add_body(&if_statement.then_branch);
+        if let Some(else_branch) = &if_statement.else_branch {
+            self.add_text(" else");
+            self.add_body(&else_branch.else_statement);
+        }
+    }
+
+    fn visit_let_statement(&mut self, let_statement: &ASTLetStatement) {
+        self.add_text("let ");
+        self.add_text(&let_statement.identifier.span.literal);
+        self.add_type_annotation(&let_statement.type_annotation);
+        self.add_text(" = ");
+        self.visit_expression(&let_statement.initializer);
+    }
+
+    fn visit_call_expression(&mut self, call_expression: &ASTCallExpression) {
+        self.add_text(&call_expression.identifier.span.literal);
+        self.add_text("(");
+        for (i, argument) in call_expression.arguments.iter().enumerate() {
+            if i != 0 {
+                self.add_text(", ");
+            }
+            self.visit_expression(argument);
+        }
+        self.add_text(")");
+    }
+
+    fn visit_assignment_expression(&mut self, assignment_expression: &ASTAssignmentExpression) {
+        self.add_text(&assignment_expression.identifier.span.literal);
+        self.add_text(" = ");
+        self.visit_expression(&assignment_expression.expression);
+    }
+
+    fn visit_variable_expression(&mut self, variable_expression: &ASTVariableExpression) {
+        self.add_text(variable_expression.identifier());
+    }
+
+    // Literals keep their spelling, so escapes and float notation survive
+    fn visit_number_expression(&mut self, number: &ASTNumberExpression) {
+        self.add_text(&number.token.span.literal);
+    }
+
+    fn visit_float_expression(&mut self, float: &ASTFloatExpression) {
+        self.add_text(&float.token.span.literal);
+    }
+
+    fn visit_string_expression(&mut self, string: &ASTStringExpression) {
+        self.add_text(&string.token.span.literal);
+    }
+
+    fn visit_boolean_expression(&mut self, boolean: &ASTBooleanExpression) {
+        self.add_text(&boolean.token.span.literal);
+    }
+
+    fn visit_error(&mut self, span: &TextSpan) {
+        self.add_text(&span.literal);
+    }
+
+    fn visit_unary_expression(&mut self, unary_expression: &ASTUnaryExpression) {
+        self.add_text(&unary_expression.operator.token.span.literal);
+        self.visit_expression(&unary_expression.operand);
+    }
+
+    fn visit_binary_expression(&mut self, binary_expression: &ASTBinaryExpression) {
+        self.visit_expression(&binary_expression.left);
+        self.add_text(" ");
+        self.add_text(&binary_expression.operator.token.span.literal);
+        self.add_text(" ");
+        self.visit_expression(&binary_expression.right);
+    }
+
+    fn visit_parenthesized_expression(
+        &mut self,
+        parenthesized_expression: &ASTParenthesizedExpression
+    ) {
+        self.add_text("(");
+        self.visit_expression(&parenthesized_expression.expression);
+        self.add_text(")");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::compilation_unit::CompilationUnit;
+
+    fn format(input: &str) -> String {
+        CompilationUnit::format(input).expect("Failed to format")
+    }
+
+    fn assert_formats_to(input: &str, expected: &str) {
+        let formatted = format(input);
+        assert_eq!(formatted, expected);
+        assert_eq!(format(&formatted), formatted, "Formatting is not idempotent");
+    }
+
+    #[test]
+    pub fn should_print_the_canonical_style() {
+        let input =
+            "\
+let   a:int=1+2*  ( 3-4 )
+func add(x:int,y)->int{return x+y}
+func main   {
+  while a<10 a=a+1
+  for i in 0..=3 { println(  -i ) }
+}
+if a>=10
+{
+    a = add(a,  -1)
+}
+else if !true { a = 0 } else {}
+";
+        let expected =
+            "\
+let a: int = 1 + 2 * (3 - 4)
+func add(x: int, y) -> int {
+    return x + y
+}
+func main() {
+    while a < 10 a = a + 1
+    for i in 0..=3 {
+        println(-i)
+    }
+}
+if a >= 10 {
+    a = add(a, -1)
+} else if !true {
+    a = 0
+} else {}
+";
+        assert_formats_to(input, expected);
+    }
+
+    #[test]
+    pub fn should_keep_comments_and_single_blank_lines() {
+        let input =
+            "\
+// Header
+
+
+let a = 1   // one
+let b = /* inline */ 2
+func f() {  // opens
+
+    /* nested /* block */ comment */
+    return a
+
+
+
+    // before the brace
+
+}
+println(f())
+// Footer
+";
+        let expected =
+            "\
+// Header
+
+let a = 1 // one
+let b = 2 /* inline */
+func f() {
+    // opens
+
+    /* nested /* block */ comment */
+    return a
+
+    // before the brace
+}
+println(f())
+// Footer
+";
+        assert_formats_to(input, expected);
+    }
+
+    #[test]
+    pub fn should_leave_well_formatted_programs_alone() {
+        for source in [
+            include_str!("../codegen/golden/functions.tn"),
+            include_str!("../codegen/golden/globals.tn"),
+        ] {
+            assert_eq!(format(source), source);
+        }
+    }
+
+    #[test]
+    pub fn should_be_idempotent_on_the_showcase() {
+        let formatted = format(include_str!("../../examples/showcase.tn"));
+        assert!(formatted.contains("} else {\n"));
+        assert_eq!(format(&formatted), formatted);
+    }
+
+    #[test]
+    pub fn should_refuse_programs_with_syntax_errors() {
+        assert!(CompilationUnit::format("let = 1").is_err());
+    }
+}
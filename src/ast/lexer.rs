@@ -1,9 +1,13 @@
 use std::fmt::{ Display, Formatter };
 
+use crate::diagnostics::DiagnosticsBagCell;
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum TokenKind {
     // Literals
     Number(i64),
+    Float(f64),
+    String(String),
     // Operators
     Plus,
     Minus,
@@ -13,6 +17,9 @@ pub enum TokenKind {
     Ampersand,
     Pipe,
     Caret,
+    AmpersandAmpersand,
+    PipePipe,
+    Bang,
     DoubleAsterisk,
     Tilde,
     GreaterThan,
@@ -21,6 +28,8 @@ pub enum TokenKind {
     LessThanEquals,
     EqualsEquals,
     BangEquals,
+    DotDot,
+    DotDotEquals,
     // Keywords
     Let,
     If,
@@ -30,15 +39,23 @@ pub enum TokenKind {
     While,
     Func,
     Return,
+    For,
+    In,
+    Break,
+    Continue,
     // Separators
     LeftParen,
     RightParen,
     OpenBrace,
     CloseBrace,
     Comma,
+    Colon,
+    Arrow,
     // Other
     Bad,
     Whitespace,
+    LineComment,
+    BlockComment,
     Identifier,
     Eof,
 }
@@ -47,6 +64,8 @@ impl Display for TokenKind {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             TokenKind::Number(_) => write!(f, "Number"),
+            TokenKind::Float(_) => write!(f, "Float"),
+            TokenKind::String(_) => write!(f, "String"),
             TokenKind::Plus => write!(f, "+"),
             TokenKind::Minus => write!(f, "-"),
             TokenKind::Asterisk => write!(f, "*"),
@@ -55,6 +74,8 @@ impl Display for TokenKind {
             TokenKind::RightParen => write!(f, ")"),
             TokenKind::Bad => write!(f, "Bad"),
             TokenKind::Whitespace => write!(f, "Whitespace"),
+            TokenKind::LineComment => write!(f, "LineComment"),
+            TokenKind::BlockComment => write!(f, "BlockComment"),
             TokenKind::Eof => write!(f, "Eof"),
             TokenKind::Let => write!(f, "Let"),
             TokenKind::Identifier => write!(f, "Identifier"),
@@ -62,6 +83,9 @@ impl Display for TokenKind {
             TokenKind::Ampersand => write!(f, "&"),
             TokenKind::Pipe => write!(f, "|"),
             TokenKind::Caret => write!(f, "^"),
+            TokenKind::AmpersandAmpersand => write!(f, "&&"),
+            TokenKind::PipePipe => write!(f, "||"),
+            TokenKind::Bang => write!(f, "!"),
             TokenKind::DoubleAsterisk => write!(f, "**"),
             TokenKind::Tilde => write!(f, "~"),
             TokenKind::If => write!(f, "If"),
@@ -72,6 +96,8 @@ impl Display for TokenKind {
             TokenKind::LessThanEquals => write!(f, "<="),
             TokenKind::EqualsEquals => write!(f, "=="),
             TokenKind::BangEquals => write!(f, "!="),
+            TokenKind::DotDot => write!(f, ".."),
+            TokenKind::DotDotEquals => write!(f, "..="),
             TokenKind::OpenBrace => write!(f, "{{"),
             TokenKind::CloseBrace => write!(f, "}}"),
             TokenKind::True => write!(f, "True"),
@@ -79,16 +105,29 @@ impl Display for TokenKind {
             TokenKind::While => write!(f, "While"),
             TokenKind::Func => write!(f, "Func"),
             TokenKind::Return => write!(f, "Return"),
+            TokenKind::For => write!(f, "For"),
+            TokenKind::In => write!(f, "In"),
+            TokenKind::Break => write!(f, "Break"),
+            TokenKind::Continue => write!(f, "Continue"),
             TokenKind::Comma => write!(f, "Comma"),
+            TokenKind::Colon => write!(f, ":"),
+            TokenKind::Arrow => write!(f, "->"),
         }
     }
 }
 
+impl TokenKind {
+    // Trivia is kept in the token stream but has no meaning to the parser
+    pub fn is_trivia(&self) -> bool {
+        matches!(self, TokenKind::Whitespace | TokenKind::LineComment | TokenKind::BlockComment)
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct TextSpan {
-    pub(crate) start: usize,
-    pub(crate) end: usize,
-    pub(crate) literal: String,
+    pub start: usize,
+    pub end: usize,
+    pub literal: String,
 }
 
 impl TextSpan {
@@ -96,6 +135,27 @@ impl TextSpan {
         Self { start, end, literal }
     }
 
+    // Spans are joined in source order; gaps between them are filled with spaces
+    pub fn combine(mut spans: Vec<TextSpan>) -> TextSpan {
+        spans.sort_by_key(|span| span.start);
+        let start = spans.first().unwrap().start;
+        let end = spans.last().unwrap().end;
+
+        let mut literal = String::new();
+        let mut position = start;
+        for span in &spans {
+            if span.start > position {
+                literal.push_str(&" ".repeat(span.start - position));
+            }
+            if span.end > position {
+                let skip = position.saturating_sub(span.start);
+                literal.push_str(&span.literal[skip..]);
+                position = span.end;
+            }
+        }
+        TextSpan::new(start, end, literal)
+    }
+
     pub fn length(&self) -> usize {
         self.end - self.start
     }
@@ -103,8 +163,8 @@ impl TextSpan {
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Token {
-    pub(crate) kind: TokenKind,
-    pub(crate) span: TextSpan,
+    pub kind: TokenKind,
+    pub span: TextSpan,
 }
 
 impl Token {
@@ -116,22 +176,32 @@ impl Token {
 // Take expression in as input, transform into tokens as output
 pub struct Lexer<'a> {
     input: &'a str,
-    current_pos: usize,
+    current_pos: usize, // Byte offset into input
+    diagnostics_bag: DiagnosticsBagCell,
 }
 
 impl<'a> Lexer<'a> {
-    pub fn new(input: &'a str) -> Self {
-        Self { input, current_pos: 0 }
+    pub fn new(input: &'a str, diagnostics_bag: DiagnosticsBagCell) -> Self {
+        Self { input, current_pos: 0, diagnostics_bag }
+    }
+
+    pub fn tokenize(&mut self) -> Vec<Token> {
+        let mut tokens = Vec::new();
+        while let Some(token) = self.next_token() {
+            tokens.push(token);
+        }
+        tokens
     }
 
+    #[allow(clippy::needless_return)]
     pub fn next_token(&mut self) -> Option<Token> {
         // Make sure we don't go over
         if self.current_pos == self.input.len() {
-            let eof_char: char = '\0';
+            let end = self.input.len();
             self.current_pos += 1;
 
-            // End of token stream
-            return Some(Token::new(TokenKind::Eof, TextSpan::new(0, 0, eof_char.to_string())));
+            // End of token stream, sitting right after the last character for diagnostics
+            return Some(Token::new(TokenKind::Eof, TextSpan::new(end, end, String::new())));
         }
 
         let c = self.current_char();
@@ -141,9 +211,14 @@ impl<'a> Lexer<'a> {
             let start = self.current_pos;
             let kind;
 
-            if Self::is_number_start(&c) {
-                let number: i64 = self.consume_number();
-                kind = TokenKind::Number(number);
+            if Self::is_number_start(&c) || (c == '.' && self.is_digit_at(1)) {
+                kind = self.consume_number();
+            } else if c == '"' {
+                kind = self.consume_string();
+            } else if c == '/' && self.peek_char(1) == Some('/') {
+                kind = self.consume_line_comment();
+            } else if c == '/' && self.peek_char(1) == Some('*') {
+                kind = self.consume_block_comment();
             } else if Self::is_whitespace(&c) {
                 // Edge case: Whitespace as token
                 self.consume();
@@ -160,6 +235,10 @@ impl<'a> Lexer<'a> {
                     "while" => TokenKind::While,
                     "func" => TokenKind::Func,
                     "return" => TokenKind::Return,
+                    "for" => TokenKind::For,
+                    "in" => TokenKind::In,
+                    "break" => TokenKind::Break,
+                    "continue" => TokenKind::Continue,
                     _ => TokenKind::Identifier,
                 };
             } else {
@@ -179,7 +258,9 @@ impl<'a> Lexer<'a> {
         let c = self.consume().unwrap();
         match c {
             '+' => TokenKind::Plus,
-            '-' => TokenKind::Minus,
+            '-' => {
+                self.lex_potential_double_char_operator('>', TokenKind::Minus, TokenKind::Arrow)
+            }
             '*' => {
                 self.lex_potential_double_char_operator(
                     '*',
@@ -197,8 +278,16 @@ impl<'a> Lexer<'a> {
                     TokenKind::EqualsEquals
                 )
             }
-            '&' => TokenKind::Ampersand,
-            '|' => TokenKind::Pipe,
+            '&' => {
+                self.lex_potential_double_char_operator(
+                    '&',
+                    TokenKind::Ampersand,
+                    TokenKind::AmpersandAmpersand
+                )
+            }
+            '|' => {
+                self.lex_potential_double_char_operator('|', TokenKind::Pipe, TokenKind::PipePipe)
+            }
             '^' => TokenKind::Caret,
             '~' => TokenKind::Tilde,
             '>' => {
@@ -216,11 +305,21 @@ impl<'a> Lexer<'a> {
                 )
             }
             '!' => {
-                self.lex_potential_double_char_operator('=', TokenKind::Bad, TokenKind::BangEquals)
+                self.lex_potential_double_char_operator('=', TokenKind::Bang, TokenKind::BangEquals)
             }
             '{' => { TokenKind::OpenBrace }
             '}' => { TokenKind::CloseBrace }
             ',' => { TokenKind::Comma }
+            ':' => { TokenKind::Colon }
+            // Only ranges use dots; `.5` never gets here because it lexes as a float
+            '.' if self.current_char() == Some('.') => {
+                self.consume();
+                self.lex_potential_double_char_operator(
+                    '=',
+                    TokenKind::DotDot,
+                    TokenKind::DotDotEquals
+                )
+            }
             _ => TokenKind::Bad,
         }
     }
@@ -244,12 +343,17 @@ impl<'a> Lexer<'a> {
     }
 
     // Helper method to see if char is a number
+    #[allow(clippy::is_digit_ascii_radix)]
     fn is_number_start(c: &char) -> bool {
         c.is_digit(10)
     }
 
     fn is_identifier_start(c: &char) -> bool {
-        c.is_alphabetic()
+        c.is_alphabetic() || *c == '_'
+    }
+
+    fn is_identifier_continue(c: &char) -> bool {
+        Self::is_identifier_start(c) || c.is_ascii_digit()
     }
 
     fn is_whitespace(c: &char) -> bool {
@@ -257,25 +361,126 @@ impl<'a> Lexer<'a> {
     }
 
     fn current_char(&self) -> Option<char> {
-        self.input.chars().nth(self.current_pos)
+        self.peek_char(0)
+    }
+
+    fn is_digit_at(&self, offset: usize) -> bool {
+        self.peek_char(offset).is_some_and(|c| c.is_ascii_digit())
+    }
+
+    fn peek_char(&self, offset: usize) -> Option<char> {
+        self.input
+            .get(self.current_pos..)
+            .and_then(|rest| rest.chars().nth(offset))
     }
 
     // Helper method to consume char for consume_number
     fn consume(&mut self) -> Option<char> {
-        if self.current_pos >= self.input.len() {
-            return None;
+        let c = self.current_char()?;
+        self.current_pos += c.len_utf8();
+
+        Some(c)
+    }
+
+    fn span_from(&self, start: usize) -> TextSpan {
+        TextSpan::new(start, self.current_pos, self.input[start..self.current_pos].to_string())
+    }
+
+    // Runs up to, but not including, the end of the line
+    fn consume_line_comment(&mut self) -> TokenKind {
+        while self.current_char().is_some_and(|c| c != '\n') {
+            self.consume();
         }
-        let c = self.current_char();
-        self.current_pos += 1;
+        TokenKind::LineComment
+    }
+
+    // Block comments nest, so `/* a /* b */ c */` is a single comment
+    fn consume_block_comment(&mut self) -> TokenKind {
+        let start = self.current_pos;
+        let mut depth = 0;
+        loop {
+            match (self.current_char(), self.peek_char(1)) {
+                (Some('/'), Some('*')) => {
+                    self.consume();
+                    self.consume();
+                    depth += 1;
+                }
+                (Some('*'), Some('/')) => {
+                    self.consume();
+                    self.consume();
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                (Some(_), _) => {
+                    self.consume();
+                }
+                (None, _) => {
+                    // Point at the opening `/*` rather than the rest of the file
+                    let span = TextSpan::new(start, start + 2, "/*".to_string());
+                    self.diagnostics_bag.borrow_mut().report_unterminated_block_comment(span);
+                    break;
+                }
+            }
+        }
+        TokenKind::BlockComment
+    }
 
-        c
+    // Strings may not span lines; the returned value has its escapes decoded
+    fn consume_string(&mut self) -> TokenKind {
+        let start = self.current_pos;
+        self.consume(); // "
+        let mut value = String::new();
+        loop {
+            match self.current_char() {
+                None | Some('\n') => {
+                    let span = self.span_from(start);
+                    self.diagnostics_bag.borrow_mut().report_unterminated_string(span);
+                    break;
+                }
+                Some('"') => {
+                    self.consume();
+                    break;
+                }
+                Some('\\') => {
+                    let escape_start = self.current_pos;
+                    self.consume();
+                    let escaped = match self.current_char() {
+                        Some('n') => Some('\n'),
+                        Some('t') => Some('\t'),
+                        Some('"') => Some('"'),
+                        Some('\\') => Some('\\'),
+                        _ => None,
+                    };
+                    match escaped {
+                        Some(escaped) => {
+                            self.consume();
+                            value.push(escaped);
+                        }
+                        None => {
+                            if !matches!(self.current_char(), None | Some('\n')) {
+                                self.consume();
+                            }
+                            let span = self.span_from(escape_start);
+                            self.diagnostics_bag.borrow_mut().report_invalid_escape_sequence(span);
+                        }
+                    }
+                }
+                Some(c) => {
+                    self.consume();
+                    value.push(c);
+                }
+            }
+        }
+        TokenKind::String(value)
     }
 
-    // To consume non-numeric, alphabetical identifiers
+    // Identifiers start with a letter or underscore and may contain digits after that
     fn consume_identifier(&mut self) -> String {
         let mut identifier = String::new();
         while let Some(c) = self.current_char() {
-            if Self::is_identifier_start(&c) {
+            if Self::is_identifier_continue(&c) {
                 self.consume().unwrap();
                 identifier.push(c);
             } else {
@@ -285,16 +490,88 @@ impl<'a> Lexer<'a> {
         identifier
     }
 
-    fn consume_number(&mut self) -> i64 {
-        let mut number: i64 = 0;
-        while let Some(c) = self.current_char() {
-            if c.is_digit(10) {
-                self.consume().unwrap();
-                number = number * 10 + (c.to_digit(10).unwrap() as i64);
-            } else {
-                break;
+    fn consume_digits(&mut self) {
+        while self.is_digit_at(0) {
+            self.consume();
+        }
+    }
+
+    // Accepts 42, 3.14, .5 and 1e-9; anything with a fraction or exponent is a float
+    fn consume_number(&mut self) -> TokenKind {
+        let start = self.current_pos;
+        let mut is_float = false;
+        self.consume_digits();
+        if self.current_char() == Some('.') && self.is_digit_at(1) {
+            is_float = true;
+            self.consume();
+            self.consume_digits();
+        }
+        if matches!(self.current_char(), Some('e' | 'E')) {
+            let sign_length = if matches!(self.peek_char(1), Some('+' | '-')) { 1 } else { 0 };
+            if self.is_digit_at(1 + sign_length) {
+                is_float = true;
+                for _ in 0..=sign_length {
+                    self.consume();
+                }
+                self.consume_digits();
+            }
+        }
+
+        let span = self.span_from(start);
+        if is_float {
+            return TokenKind::Float(span.literal.parse().unwrap());
+        }
+        match span.literal.parse() {
+            Ok(number) => TokenKind::Number(number),
+            Err(_) => {
+                self.diagnostics_bag.borrow_mut().report_integer_literal_out_of_range(span);
+                TokenKind::Number(0)
             }
         }
-        number
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::ast::lexer::{ Lexer, TokenKind };
+    use crate::diagnostics::DiagnosticsBag;
+
+    fn tokenize(input: &str) -> Vec<(TokenKind, String)> {
+        let diagnostics_bag = Rc::new(RefCell::new(DiagnosticsBag::new()));
+        Lexer::new(input, diagnostics_bag)
+            .tokenize()
+            .into_iter()
+            .filter(|token| !matches!(token.kind, TokenKind::Whitespace | TokenKind::Eof))
+            .map(|token| (token.kind, token.span.literal))
+            .collect()
+    }
+
+    #[test]
+    fn should_keep_comments_as_trivia_tokens() {
+        let tokens = tokenize("a // b\n/* c /* d */ e */ f");
+
+        assert_eq!(tokens, vec![
+            (TokenKind::Identifier, "a".to_string()),
+            (TokenKind::LineComment, "// b".to_string()),
+            (TokenKind::BlockComment, "/* c /* d */ e */".to_string()),
+            (TokenKind::Identifier, "f".to_string())
+        ]);
+    }
+
+    #[test]
+    fn should_lex_ranges_next_to_numbers() {
+        let tokens = tokenize("0..5 1..=.5");
+
+        assert_eq!(tokens, vec![
+            (TokenKind::Number(0), "0".to_string()),
+            (TokenKind::DotDot, "..".to_string()),
+            (TokenKind::Number(5), "5".to_string()),
+            (TokenKind::Number(1), "1".to_string()),
+            (TokenKind::DotDotEquals, "..=".to_string()),
+            (TokenKind::Float(0.5), ".5".to_string())
+        ]);
     }
 }
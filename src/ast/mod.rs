@@ -1,18 +1,26 @@
 use printer::ASTPrinter;
 use visitor::ASTVisitor;
 
-use crate::ast::lexer::{ TextSpan, Token };
+use crate::ast::lexer::{ TextSpan, Token, TokenKind };
 
 pub mod lexer;
 pub mod parser;
 pub mod evaluator;
 pub mod visitor;
 pub mod printer;
+pub mod optimizer;
+pub mod formatter;
 
 pub struct Ast {
     pub statements: Vec<ASTStatement>,
 }
 
+impl Default for Ast {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Ast {
     pub fn new() -> Self {
         Self { statements: Vec::new() }
@@ -28,6 +36,17 @@ impl Ast {
         }
     }
 
+    // Whether both trees hold the same program, whatever their layout
+    pub fn is_equivalent_to(&self, other: &Ast) -> bool {
+        let render = |ast: &Ast| {
+            let mut printer = ASTPrinter::new();
+            ast.visit(&mut printer);
+            printer.result
+        };
+        render(self) == render(other)
+    }
+
+    #[allow(clippy::unused_unit)]
     pub fn visualize(&self) -> () {
         let mut printer = ASTPrinter::new();
         self.visit(&mut printer);
@@ -42,22 +61,50 @@ pub enum ASTStatementKind {
     If(ASTIfStatement),
     Block(ASTBlockStatement),
     While(ASTWhileStatement),
+    For(ASTForStatement),
     FuncDecl(ASTFuncDeclStatement),
     Return(ASTReturnStatement),
+    Break(ASTBreakStatement),
+    Continue(ASTContinueStatement),
+    // Tokens skipped while recovering from a syntax error
+    Error(TextSpan),
 }
+// Outside of a function, returning ends the program
 #[derive(Debug, Clone)]
 pub struct ASTReturnStatement {
     pub return_keyword: Token,
     pub return_value: Option<ASTExpression>,
 }
 #[derive(Debug, Clone)]
+pub struct ASTBreakStatement {
+    pub break_keyword: Token,
+}
+#[derive(Debug, Clone)]
+pub struct ASTContinueStatement {
+    pub continue_keyword: Token,
+}
+#[derive(Debug, Clone)]
+pub struct TypeAnnotation {
+    pub colon: Token,
+    pub type_name: Token,
+}
+#[derive(Debug, Clone)]
+pub struct FuncReturnType {
+    pub arrow: Token,
+    pub type_name: Token,
+}
+#[derive(Debug, Clone)]
 pub struct FuncDeclParameter {
     pub identifier: Token,
+    pub type_annotation: Option<TypeAnnotation>,
 }
+// Falling off the end of the body returns unit
 #[derive(Debug, Clone)]
 pub struct ASTFuncDeclStatement {
+    pub func_keyword: Token,
     pub identifier: Token,
     pub parameters: Vec<FuncDeclParameter>,
+    pub return_type: Option<FuncReturnType>,
     pub body: Box<ASTStatement>,
 }
 #[derive(Debug, Clone)]
@@ -66,9 +113,31 @@ pub struct ASTWhileStatement {
     pub condition: ASTExpression,
     pub body: Box<ASTStatement>,
 }
+// Runs the body for each int from `start` up to `end`, with both bounds evaluated once up front.
+// Assigning to the loop variable does not change the iteration, and an inclusive range may end
+// at the largest int without overflowing
+#[derive(Debug, Clone)]
+pub struct ASTForStatement {
+    pub for_keyword: Token,
+    pub identifier: Token,
+    pub in_keyword: Token,
+    pub start: Box<ASTExpression>,
+    // Either `..` or `..=`
+    pub range_operator: Token,
+    pub end: Box<ASTExpression>,
+    pub body: Box<ASTStatement>,
+}
+
+impl ASTForStatement {
+    pub fn is_inclusive(&self) -> bool {
+        self.range_operator.kind == TokenKind::DotDotEquals
+    }
+}
 #[derive(Debug, Clone)]
 pub struct ASTBlockStatement {
+    pub open_brace: Token,
     pub statements: Vec<ASTStatement>,
+    pub close_brace: Token,
 }
 #[derive(Debug, Clone)]
 pub struct ASTElseStatement {
@@ -90,7 +159,9 @@ pub struct ASTIfStatement {
 }
 #[derive(Debug, Clone)]
 pub struct ASTLetStatement {
+    pub let_keyword: Token,
     pub identifier: Token,
+    pub type_annotation: Option<TypeAnnotation>,
     pub initializer: ASTExpression,
 }
 
@@ -104,12 +175,63 @@ impl ASTStatement {
         ASTStatement { kind }
     }
 
+    // Whether every path through the statement ends in a `return`; a `while true` loop that
+    // never breaks does not end at all, so it counts too
+    pub fn always_returns(&self) -> bool {
+        match &self.kind {
+            ASTStatementKind::Return(_) => true,
+            ASTStatementKind::Block(block_statement) => {
+                block_statement.statements.iter().any(ASTStatement::always_returns)
+            }
+            ASTStatementKind::If(if_statement) => {
+                if_statement.else_branch.as_ref().is_some_and(|else_branch| {
+                    if_statement.then_branch.always_returns() &&
+                        else_branch.else_statement.always_returns()
+                })
+            }
+            ASTStatementKind::While(while_statement) => {
+                while_statement.condition.as_boolean() == Some(true) &&
+                    !while_statement.body.breaks_out()
+            }
+            _ => false,
+        }
+    }
+
+    // Whether a `break` leaves the loop with this body; nested loops take their own breaks
+    fn breaks_out(&self) -> bool {
+        match &self.kind {
+            ASTStatementKind::Break(_) => true,
+            ASTStatementKind::Block(block_statement) => {
+                block_statement.statements.iter().any(ASTStatement::breaks_out)
+            }
+            ASTStatementKind::If(if_statement) => {
+                if_statement.then_branch.breaks_out() ||
+                    if_statement.else_branch
+                        .as_ref()
+                        .is_some_and(|else_branch| else_branch.else_statement.breaks_out())
+            }
+            _ => false,
+        }
+    }
+
     pub fn expression(expr: ASTExpression) -> Self {
         ASTStatement::new(ASTStatementKind::Expression(expr))
     }
 
-    pub fn let_statement(identifier: Token, initializer: ASTExpression) -> Self {
-        ASTStatement::new(ASTStatementKind::Let(ASTLetStatement { identifier, initializer }))
+    pub fn let_statement(
+        let_keyword: Token,
+        identifier: Token,
+        type_annotation: Option<TypeAnnotation>,
+        initializer: ASTExpression
+    ) -> Self {
+        ASTStatement::new(
+            ASTStatementKind::Let(ASTLetStatement {
+                let_keyword,
+                identifier,
+                type_annotation,
+                initializer,
+            })
+        )
     }
 
     pub fn if_statement(
@@ -128,8 +250,14 @@ impl ASTStatement {
         )
     }
 
-    pub fn block_statement(statements: Vec<ASTStatement>) -> Self {
-        ASTStatement::new(ASTStatementKind::Block(ASTBlockStatement { statements }))
+    pub fn block_statement(
+        open_brace: Token,
+        statements: Vec<ASTStatement>,
+        close_brace: Token
+    ) -> Self {
+        ASTStatement::new(
+            ASTStatementKind::Block(ASTBlockStatement { open_brace, statements, close_brace })
+        )
     }
 
     pub fn while_statement(
@@ -146,30 +274,122 @@ impl ASTStatement {
         )
     }
 
+    pub fn for_statement(
+        for_keyword: Token,
+        identifier: Token,
+        in_keyword: Token,
+        start: ASTExpression,
+        range_operator: Token,
+        end: ASTExpression,
+        body: ASTStatement
+    ) -> Self {
+        ASTStatement::new(
+            ASTStatementKind::For(ASTForStatement {
+                for_keyword,
+                identifier,
+                in_keyword,
+                start: Box::new(start),
+                range_operator,
+                end: Box::new(end),
+                body: Box::new(body),
+            })
+        )
+    }
+
     pub fn return_statement(return_keyword: Token, return_value: Option<ASTExpression>) -> Self {
         ASTStatement::new(
             ASTStatementKind::Return(ASTReturnStatement { return_keyword, return_value })
         )
     }
 
+    pub fn break_statement(break_keyword: Token) -> Self {
+        ASTStatement::new(ASTStatementKind::Break(ASTBreakStatement { break_keyword }))
+    }
+
+    pub fn continue_statement(continue_keyword: Token) -> Self {
+        ASTStatement::new(ASTStatementKind::Continue(ASTContinueStatement { continue_keyword }))
+    }
+
+    pub fn error(span: TextSpan) -> Self {
+        ASTStatement::new(ASTStatementKind::Error(span))
+    }
+
     pub fn func_decl_statement(
+        func_keyword: Token,
         identifier: Token,
         parameters: Vec<FuncDeclParameter>,
+        return_type: Option<FuncReturnType>,
         body: ASTStatement
     ) -> Self {
         ASTStatement::new(
             ASTStatementKind::FuncDecl(ASTFuncDeclStatement {
+                func_keyword,
                 identifier,
                 parameters,
+                return_type,
                 body: Box::new(body),
             })
         )
     }
+
+    pub fn is_expression(&self) -> bool {
+        matches!(self.kind, ASTStatementKind::Expression(_))
+    }
+
+    // Like any combined span, gaps between the first and last token become spaces in the literal
+    pub fn span(&self) -> TextSpan {
+        let (first, last) = match &self.kind {
+            ASTStatementKind::Expression(expression) => {
+                return expression.span();
+            }
+            ASTStatementKind::Let(statement) => {
+                (statement.let_keyword.span.clone(), statement.initializer.span())
+            }
+            ASTStatementKind::If(statement) => {
+                let last = match &statement.else_branch {
+                    Some(else_branch) => else_branch.else_statement.span(),
+                    None => statement.then_branch.span(),
+                };
+                (statement.if_keyword.span.clone(), last)
+            }
+            ASTStatementKind::Block(statement) => {
+                (statement.open_brace.span.clone(), statement.close_brace.span.clone())
+            }
+            ASTStatementKind::While(statement) => {
+                (statement.while_keyword.span.clone(), statement.body.span())
+            }
+            ASTStatementKind::For(statement) => {
+                (statement.for_keyword.span.clone(), statement.body.span())
+            }
+            ASTStatementKind::FuncDecl(statement) => {
+                (statement.func_keyword.span.clone(), statement.body.span())
+            }
+            ASTStatementKind::Return(statement) => {
+                let keyword = statement.return_keyword.span.clone();
+                let last = statement.return_value.as_ref().map_or(keyword.clone(), |value| {
+                    value.span()
+                });
+                (keyword, last)
+            }
+            ASTStatementKind::Break(statement) => {
+                return statement.break_keyword.span.clone();
+            }
+            ASTStatementKind::Continue(statement) => {
+                return statement.continue_keyword.span.clone();
+            }
+            ASTStatementKind::Error(span) => {
+                return span.clone();
+            }
+        };
+        TextSpan::combine(vec![first, last])
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum ASTExpressionKind {
     Number(ASTNumberExpression),
+    Float(ASTFloatExpression),
+    String(ASTStringExpression),
     Binary(ASTBinaryExpression),
     Unary(ASTUnaryExpression),
     Parenthesized(ASTParenthesizedExpression),
@@ -183,7 +403,24 @@ pub enum ASTExpressionKind {
 #[derive(Debug, Clone)]
 pub struct ASTCallExpression {
     pub identifier: Token,
+    pub left_paren: Token,
     pub arguments: Vec<ASTExpression>,
+    pub right_paren: Token,
+}
+
+impl ASTCallExpression {
+    pub fn span(&self) -> TextSpan {
+        let identifier = self.identifier.span.clone();
+        let right_paren = self.right_paren.span.clone();
+        let mut spans = vec![identifier, self.left_paren.span.clone(), right_paren];
+        spans.extend(self.arguments.iter().map(|argument| argument.span()));
+        TextSpan::combine(spans)
+    }
+}
+#[derive(Debug, Clone)]
+pub struct ASTStringExpression {
+    pub value: String,
+    pub token: Token,
 }
 #[derive(Debug, Clone)]
 pub struct ASTBooleanExpression {
@@ -195,15 +432,26 @@ pub struct ASTAssignmentExpression {
     pub identifier: Token,
     pub expression: Box<ASTExpression>,
 }
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ASTUnaryOperatorKind {
     Minus,
     BitwiseNot,
+    LogicalNot,
 }
 #[derive(Debug, Clone)]
 pub struct ASTUnaryOperator {
-    kind: ASTUnaryOperatorKind,
-    token: Token,
+    pub kind: ASTUnaryOperatorKind,
+    pub token: Token,
+}
+
+impl ASTUnaryOperatorKind {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            ASTUnaryOperatorKind::Minus => "-",
+            ASTUnaryOperatorKind::BitwiseNot => "~",
+            ASTUnaryOperatorKind::LogicalNot => "!",
+        }
+    }
 }
 
 impl ASTUnaryOperator {
@@ -216,6 +464,14 @@ pub struct ASTUnaryExpression {
     pub operator: ASTUnaryOperator,
     pub operand: Box<ASTExpression>,
 }
+
+impl ASTUnaryExpression {
+    pub fn span(&self) -> TextSpan {
+        let operator = self.operator.token.span.clone();
+        let operand = self.operand.span();
+        TextSpan::combine(vec![operator, operand])
+    }
+}
 #[derive(Debug, Clone)]
 pub struct ASTVariableExpression {
     pub identifier: Token,
@@ -227,7 +483,7 @@ impl ASTVariableExpression {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ASTBinaryOperatorKind {
     // Arithmetic
     Plus,
@@ -246,11 +502,37 @@ pub enum ASTBinaryOperatorKind {
     LessThanOrEqual,
     GreaterThan,
     GreaterThanOrEqual,
+    // Logical
+    LogicalAnd,
+    LogicalOr,
+}
+
+impl ASTBinaryOperatorKind {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            ASTBinaryOperatorKind::Plus => "+",
+            ASTBinaryOperatorKind::Minus => "-",
+            ASTBinaryOperatorKind::Multiply => "*",
+            ASTBinaryOperatorKind::Divide => "/",
+            ASTBinaryOperatorKind::Power => "**",
+            ASTBinaryOperatorKind::BitwiseAnd => "&",
+            ASTBinaryOperatorKind::BitwiseOr => "|",
+            ASTBinaryOperatorKind::BitwiseXor => "^",
+            ASTBinaryOperatorKind::Equals => "==",
+            ASTBinaryOperatorKind::NotEquals => "!=",
+            ASTBinaryOperatorKind::LessThan => "<",
+            ASTBinaryOperatorKind::LessThanOrEqual => "<=",
+            ASTBinaryOperatorKind::GreaterThan => ">",
+            ASTBinaryOperatorKind::GreaterThanOrEqual => ">=",
+            ASTBinaryOperatorKind::LogicalAnd => "&&",
+            ASTBinaryOperatorKind::LogicalOr => "||",
+        }
+    }
 }
 #[derive(Debug, Clone)]
 pub struct ASTBinaryOperator {
-    kind: ASTBinaryOperatorKind,
-    token: Token,
+    pub kind: ASTBinaryOperatorKind,
+    pub token: Token,
 }
 
 impl ASTBinaryOperator {
@@ -268,28 +550,52 @@ impl ASTBinaryOperator {
             ASTBinaryOperatorKind::BitwiseAnd => 17,
             ASTBinaryOperatorKind::BitwiseXor => 16,
             ASTBinaryOperatorKind::BitwiseOr => 15,
-            ASTBinaryOperatorKind::Equals => 30,
-            ASTBinaryOperatorKind::NotEquals => 30,
-            ASTBinaryOperatorKind::LessThan => 29,
-            ASTBinaryOperatorKind::LessThanOrEqual => 29,
-            ASTBinaryOperatorKind::GreaterThan => 29,
-            ASTBinaryOperatorKind::GreaterThanOrEqual => 29,
+            ASTBinaryOperatorKind::LessThan => 14,
+            ASTBinaryOperatorKind::LessThanOrEqual => 14,
+            ASTBinaryOperatorKind::GreaterThan => 14,
+            ASTBinaryOperatorKind::GreaterThanOrEqual => 14,
+            ASTBinaryOperatorKind::Equals => 13,
+            ASTBinaryOperatorKind::NotEquals => 13,
+            ASTBinaryOperatorKind::LogicalAnd => 12,
+            ASTBinaryOperatorKind::LogicalOr => 11,
         }
     }
+
+    // Everything but `**` groups from the left, so `2 ** 3 ** 2` is `2 ** (3 ** 2)`
+    pub fn is_right_associative(&self) -> bool {
+        matches!(self.kind, ASTBinaryOperatorKind::Power)
+    }
 }
 #[derive(Debug, Clone)]
 pub struct ASTBinaryExpression {
-    left: Box<ASTExpression>,
-    operator: ASTBinaryOperator,
-    right: Box<ASTExpression>,
+    pub left: Box<ASTExpression>,
+    pub operator: ASTBinaryOperator,
+    pub right: Box<ASTExpression>,
+}
+
+impl ASTBinaryExpression {
+    pub fn span(&self) -> TextSpan {
+        let left = self.left.span();
+        let operator = self.operator.token.span.clone();
+        let right = self.right.span();
+        TextSpan::combine(vec![left, operator, right])
+    }
 }
 #[derive(Debug, Clone)]
 pub struct ASTNumberExpression {
-    number: i64,
+    pub number: i64,
+    pub token: Token,
+}
+#[derive(Debug, Clone)]
+pub struct ASTFloatExpression {
+    pub number: f64,
+    pub token: Token,
 }
 #[derive(Debug, Clone)]
 pub struct ASTParenthesizedExpression {
-    expression: Box<ASTExpression>,
+    pub left_paren: Token,
+    pub expression: Box<ASTExpression>,
+    pub right_paren: Token,
 }
 #[derive(Debug, Clone)]
 pub struct ASTExpression {
@@ -301,8 +607,16 @@ impl ASTExpression {
         ASTExpression { kind }
     }
 
-    pub fn number(number: i64) -> Self {
-        ASTExpression::new(ASTExpressionKind::Number(ASTNumberExpression { number }))
+    pub fn number(token: Token, number: i64) -> Self {
+        ASTExpression::new(ASTExpressionKind::Number(ASTNumberExpression { number, token }))
+    }
+
+    pub fn float(token: Token, number: f64) -> Self {
+        ASTExpression::new(ASTExpressionKind::Float(ASTFloatExpression { number, token }))
+    }
+
+    pub fn string(token: Token, value: String) -> Self {
+        ASTExpression::new(ASTExpressionKind::String(ASTStringExpression { value, token }))
     }
 
     pub fn binary(operator: ASTBinaryOperator, left: ASTExpression, right: ASTExpression) -> Self {
@@ -315,10 +629,12 @@ impl ASTExpression {
         )
     }
 
-    pub fn parenthesized(expression: ASTExpression) -> Self {
+    pub fn parenthesized(left_paren: Token, expression: ASTExpression, right_paren: Token) -> Self {
         ASTExpression::new(
             ASTExpressionKind::Parenthesized(ASTParenthesizedExpression {
+                left_paren,
                 expression: Box::new(expression),
+                right_paren,
             })
         )
     }
@@ -346,13 +662,61 @@ impl ASTExpression {
         ASTExpression::new(ASTExpressionKind::Boolean(ASTBooleanExpression { token, value }))
     }
 
-    pub fn call(identifier: Token, arguments: Vec<ASTExpression>) -> Self {
-        ASTExpression::new(ASTExpressionKind::Call(ASTCallExpression { identifier, arguments }))
+    pub fn call(
+        identifier: Token,
+        left_paren: Token,
+        arguments: Vec<ASTExpression>,
+        right_paren: Token
+    ) -> Self {
+        ASTExpression::new(
+            ASTExpressionKind::Call(ASTCallExpression {
+                identifier,
+                left_paren,
+                arguments,
+                right_paren,
+            })
+        )
     }
 
     pub fn error(span: TextSpan) -> Self {
         ASTExpression::new(ASTExpressionKind::Error(span))
     }
+
+    // The value of a `true` or `false` literal, looking through parentheses
+    pub fn as_boolean(&self) -> Option<bool> {
+        match &self.kind {
+            ASTExpressionKind::Boolean(boolean) => Some(boolean.value),
+            ASTExpressionKind::Parenthesized(parenthesized) => {
+                parenthesized.expression.as_boolean()
+            }
+            _ => None,
+        }
+    }
+
+    pub fn span(&self) -> TextSpan {
+        match &self.kind {
+            ASTExpressionKind::Number(expr) => expr.token.span.clone(),
+            ASTExpressionKind::Float(expr) => expr.token.span.clone(),
+            ASTExpressionKind::String(expr) => expr.token.span.clone(),
+            ASTExpressionKind::Binary(expr) => expr.span(),
+            ASTExpressionKind::Unary(expr) => expr.span(),
+            ASTExpressionKind::Parenthesized(expr) => {
+                let left_paren = expr.left_paren.span.clone();
+                let expression = expr.expression.span();
+                let right_paren = expr.right_paren.span.clone();
+                TextSpan::combine(vec![left_paren, expression, right_paren])
+            }
+            ASTExpressionKind::Variable(expr) => expr.identifier.span.clone(),
+            ASTExpressionKind::Assignment(expr) => {
+                let identifier = expr.identifier.span.clone();
+                let expression = expr.expression.span();
+                TextSpan::combine(vec![identifier, expression])
+            }
+            ASTExpressionKind::Boolean(expr) => expr.token.span.clone(),
+            ASTExpressionKind::Call(expr) => expr.span(),
+            ASTExpressionKind::Error(span) => span.clone(),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -364,13 +728,18 @@ mod test {
         ASTBinaryExpression,
         ASTBlockStatement,
         ASTBooleanExpression,
+        ASTBreakStatement,
         ASTCallExpression,
+        ASTContinueStatement,
+        ASTFloatExpression,
+        ASTForStatement,
         ASTFuncDeclStatement,
         ASTIfStatement,
         ASTLetStatement,
         ASTNumberExpression,
         ASTParenthesizedExpression,
         ASTReturnStatement,
+        ASTStringExpression,
         ASTUnaryExpression,
         ASTVariableExpression,
         ASTWhileStatement,
@@ -381,6 +750,8 @@ mod test {
     #[derive(Debug, PartialEq, Eq)]
     enum TestASTNode {
         Number(i64),
+        Float(String),
+        String(String),
         Boolean(bool),
         Binary,
         Unary,
@@ -393,7 +764,10 @@ mod test {
         Else,
         Func,
         While,
+        For,
         Return,
+        Break,
+        Continue,
         Call,
     }
 
@@ -404,7 +778,7 @@ mod test {
 
     impl ASTVerifier {
         pub fn new(input: &str, expected: Vec<TestASTNode>) -> Self {
-            let compilation_unit = CompilationUnit::compile(input).expect("Failed to compile");
+            let compilation_unit = CompilationUnit::analyze(input).expect("Failed to compile");
             let mut verifier = ASTVerifier { expected, actual: Vec::new() };
             verifier.flatten_ast(&compilation_unit.ast);
             verifier
@@ -460,6 +834,21 @@ mod test {
             self.visit_statement(&while_statement.body);
         }
 
+        fn visit_break_statement(&mut self, _break_statement: &ASTBreakStatement) {
+            self.actual.push(TestASTNode::Break);
+        }
+
+        fn visit_continue_statement(&mut self, _continue_statement: &ASTContinueStatement) {
+            self.actual.push(TestASTNode::Continue);
+        }
+
+        fn visit_for_statement(&mut self, for_statement: &ASTForStatement) {
+            self.actual.push(TestASTNode::For);
+            self.visit_expression(&for_statement.start);
+            self.visit_expression(&for_statement.end);
+            self.visit_statement(&for_statement.body);
+        }
+
         fn visit_block_statement(&mut self, block_statement: &ASTBlockStatement) {
             self.actual.push(TestASTNode::Block);
             for statement in &block_statement.statements {
@@ -496,6 +885,14 @@ mod test {
             self.actual.push(TestASTNode::Number(number.number));
         }
 
+        fn visit_float_expression(&mut self, float: &ASTFloatExpression) {
+            self.actual.push(TestASTNode::Float(float.number.to_string()));
+        }
+
+        fn visit_string_expression(&mut self, string: &ASTStringExpression) {
+            self.actual.push(TestASTNode::String(string.value.clone()));
+        }
+
         fn visit_boolean_expression(&mut self, boolean: &ASTBooleanExpression) {
             self.actual.push(TestASTNode::Boolean(boolean.value));
         }
@@ -595,6 +992,7 @@ mod test {
             TestASTNode::Number(1),
             TestASTNode::Let,
             TestASTNode::Binary,
+            TestASTNode::Binary,
             TestASTNode::Parenthesized,
             TestASTNode::Binary,
             TestASTNode::Number(1),
@@ -798,6 +1196,184 @@ mod test {
         assert_tree(input, expected);
     }
 
+    #[test]
+    pub fn should_parse_type_annotations() {
+        let input =
+            "\
+        func add(a: int, b: int) -> int {
+            return a + b
+        }
+        let c: int = add(1, 2)
+        ";
+        let expected = vec![
+            TestASTNode::Func,
+            TestASTNode::Block,
+            TestASTNode::Return,
+            TestASTNode::Binary,
+            TestASTNode::Variable("a".to_string()),
+            TestASTNode::Variable("b".to_string()),
+            TestASTNode::Let,
+            TestASTNode::Call,
+            TestASTNode::Number(1),
+            TestASTNode::Number(2)
+        ];
+
+        assert_tree(input, expected);
+    }
+
+    #[test]
+    pub fn should_parse_string_literal() {
+        let input = "let a = \"hello\\n\" == \"world\"";
+        let expected = vec![
+            TestASTNode::Let,
+            TestASTNode::Binary,
+            TestASTNode::String("hello\n".to_string()),
+            TestASTNode::String("world".to_string())
+        ];
+
+        assert_tree(input, expected);
+    }
+
+    #[test]
+    pub fn should_parse_float_literals() {
+        let input = "\
+        let a = 3.14
+        let b = .5
+        let c = 1e-9
+        ";
+        let expected = vec![
+            TestASTNode::Let,
+            TestASTNode::Float("3.14".to_string()),
+            TestASTNode::Let,
+            TestASTNode::Float("0.5".to_string()),
+            TestASTNode::Let,
+            TestASTNode::Float("0.000000001".to_string())
+        ];
+
+        assert_tree(input, expected);
+    }
+
+    #[test]
+    pub fn should_parse_arithmetic_tighter_than_comparison() {
+        let input = "let a = 1 * 2 + 3 < 10";
+        let expected = vec![
+            TestASTNode::Let,
+            TestASTNode::Binary,
+            TestASTNode::Binary,
+            TestASTNode::Binary,
+            TestASTNode::Number(1),
+            TestASTNode::Number(2),
+            TestASTNode::Number(3),
+            TestASTNode::Number(10)
+        ];
+
+        assert_tree(input, expected);
+    }
+
+    #[test]
+    pub fn should_parse_logical_operators() {
+        let input = "\
+        let b = true
+        let c = false
+        let d = 1
+        let a = !b || c && d == 1";
+        let expected = vec![
+            TestASTNode::Let,
+            TestASTNode::Boolean(true),
+            TestASTNode::Let,
+            TestASTNode::Boolean(false),
+            TestASTNode::Let,
+            TestASTNode::Number(1),
+            TestASTNode::Let,
+            TestASTNode::Binary,
+            TestASTNode::Unary,
+            TestASTNode::Variable("b".to_string()),
+            TestASTNode::Binary,
+            TestASTNode::Variable("c".to_string()),
+            TestASTNode::Binary,
+            TestASTNode::Variable("d".to_string()),
+            TestASTNode::Number(1)
+        ];
+
+        assert_tree(input, expected);
+    }
+
+    #[test]
+    pub fn should_parse_for_statement() {
+        let input = "\
+        let n = 3
+        for i in 0..n + 1 {
+            let a = i
+        }
+        ";
+        let expected = vec![
+            TestASTNode::Let,
+            TestASTNode::Number(3),
+            TestASTNode::For,
+            TestASTNode::Number(0),
+            TestASTNode::Binary,
+            TestASTNode::Variable("n".to_string()),
+            TestASTNode::Number(1),
+            TestASTNode::Block,
+            TestASTNode::Let,
+            TestASTNode::Variable("i".to_string())
+        ];
+
+        assert_tree(input, expected);
+    }
+
+    #[test]
+    pub fn should_parse_break_and_continue_statements() {
+        let input = "\
+        while true {
+            continue
+            break
+        }
+        ";
+        let expected = vec![
+            TestASTNode::While,
+            TestASTNode::Boolean(true),
+            TestASTNode::Block,
+            TestASTNode::Continue,
+            TestASTNode::Break
+        ];
+
+        assert_tree(input, expected);
+    }
+
+    #[test]
+    pub fn should_ignore_comments() {
+        let input =
+            "\
+        // leading comment
+        let a = 1 /* inline /* nested */ still a comment */ + 2
+        /* let b = 3 */
+        let c = a // trailing comment
+        ";
+        let expected = vec![
+            TestASTNode::Let,
+            TestASTNode::Binary,
+            TestASTNode::Number(1),
+            TestASTNode::Number(2),
+            TestASTNode::Let,
+            TestASTNode::Variable("a".to_string())
+        ];
+
+        assert_tree(input, expected);
+    }
+
+    #[test]
+    pub fn should_parse_bare_return_statement() {
+        let input = "\
+        func f {
+            return
+        }
+        ";
+        let expected = vec![TestASTNode::Func, TestASTNode::Block, TestASTNode::Return];
+
+        assert_tree(input, expected);
+    }
+
     #[test]
     pub fn should_parse_call_expression() {
         let input =
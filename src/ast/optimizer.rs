@@ -0,0 +1,690 @@
+use std::collections::HashMap;
+use std::mem;
+
+use crate::ast::{
+    Ast,
+    ASTBinaryOperator,
+    ASTBinaryOperatorKind,
+    ASTExpression,
+    ASTExpressionKind,
+    ASTStatement,
+    ASTStatementKind,
+};
+use crate::ast::evaluator::{ binary_operation, unary_operation, Value };
+use crate::ast::lexer::{ TextSpan, Token, TokenKind };
+use crate::compilation_unit::{ FunctionBody, GlobalScope };
+use crate::diagnostics::DiagnosticsBagCell;
+use crate::types::Type;
+
+// Static type of each checked expression, keyed by the start and end of its span
+pub type ExpressionTypes = HashMap<(usize, usize), Type>;
+
+// Where an expression sits, which decides whether parentheses around it still group anything
+#[derive(Clone, Copy)]
+enum Position {
+    // Statements, initializers, arguments and conditions
+    Standalone,
+    UnaryOperand,
+    // `associates` is true when an operator of the same precedence needs no parentheses here
+    BinaryOperand {
+        precedence: u8,
+        associates: bool,
+    },
+}
+
+impl Position {
+    fn left_of(operator: &ASTBinaryOperator) -> Self {
+        Position::BinaryOperand {
+            precedence: operator.precedence(),
+            associates: !operator.is_right_associative(),
+        }
+    }
+
+    fn right_of(operator: &ASTBinaryOperator) -> Self {
+        Position::BinaryOperand {
+            precedence: operator.precedence(),
+            associates: operator.is_right_associative(),
+        }
+    }
+}
+
+// Rewrites a checked Ast into an equivalent one with constant subtrees folded,
+// identity operations on ints removed and only the parentheses that still group
+pub struct ASTOptimizer<'a> {
+    global_scope: &'a mut GlobalScope,
+    expression_types: &'a ExpressionTypes,
+    diagnostics: DiagnosticsBagCell,
+}
+
+impl<'a> ASTOptimizer<'a> {
+    pub fn optimize(
+        ast: &mut Ast,
+        global_scope: &'a mut GlobalScope,
+        expression_types: &'a ExpressionTypes,
+        diagnostics: DiagnosticsBagCell
+    ) {
+        let mut optimizer = ASTOptimizer { global_scope, expression_types, diagnostics };
+        ast.statements = mem::take(&mut ast.statements)
+            .into_iter()
+            .map(|statement| optimizer.optimize_statement(statement))
+            .collect();
+    }
+
+    fn optimize_statement(&mut self, statement: ASTStatement) -> ASTStatement {
+        let kind = match statement.kind {
+            ASTStatementKind::Expression(expression) => {
+                ASTStatementKind::Expression(self.optimize_expression(expression))
+            }
+            ASTStatementKind::Let(mut let_statement) => {
+                let_statement.initializer = self.optimize_expression(let_statement.initializer);
+                ASTStatementKind::Let(let_statement)
+            }
+            ASTStatementKind::If(mut if_statement) => {
+                if_statement.condition = self.optimize_expression(if_statement.condition);
+                if_statement.then_branch = self.optimize_boxed(if_statement.then_branch);
+                if_statement.else_branch = if_statement.else_branch.map(|mut else_branch| {
+                    else_branch.else_statement = self.optimize_boxed(else_branch.else_statement);
+                    else_branch
+                });
+                ASTStatementKind::If(if_statement)
+            }
+            ASTStatementKind::Block(mut block_statement) => {
+                block_statement.statements = block_statement.statements
+                    .into_iter()
+                    .map(|statement| self.optimize_statement(statement))
+                    .collect();
+                ASTStatementKind::Block(block_statement)
+            }
+            ASTStatementKind::While(mut while_statement) => {
+                while_statement.condition = self.optimize_expression(while_statement.condition);
+                while_statement.body = self.optimize_boxed(while_statement.body);
+                ASTStatementKind::While(while_statement)
+            }
+            ASTStatementKind::For(mut for_statement) => {
+                for_statement.start = Box::new(self.optimize_expression(*for_statement.start));
+                for_statement.end = Box::new(self.optimize_expression(*for_statement.end));
+                for_statement.body = self.optimize_boxed(for_statement.body);
+                ASTStatementKind::For(for_statement)
+            }
+            ASTStatementKind::FuncDecl(mut func_decl_statement) => {
+                func_decl_statement.body = self.optimize_boxed(func_decl_statement.body);
+                // Calls run the body stored in the symbol, so it has to match the tree
+                let name = &func_decl_statement.identifier.span.literal;
+                if let Some(function) = self.global_scope.functions.get_mut(name) {
+                    function.body = FunctionBody::Declared(func_decl_statement.body.clone());
+                }
+                ASTStatementKind::FuncDecl(func_decl_statement)
+            }
+            ASTStatementKind::Return(mut return_statement) => {
+                return_statement.return_value = return_statement.return_value.map(|value| {
+                    self.optimize_expression(value)
+                });
+                ASTStatementKind::Return(return_statement)
+            }
+            kind @ (
+                ASTStatementKind::Break(_) |
+                ASTStatementKind::Continue(_) |
+                ASTStatementKind::Error(_)
+            ) => kind,
+        };
+        ASTStatement::new(kind)
+    }
+
+    fn optimize_boxed(&mut self, statement: Box<ASTStatement>) -> Box<ASTStatement> {
+        Box::new(self.optimize_statement(*statement))
+    }
+
+    fn optimize_expression(&mut self, expression: ASTExpression) -> ASTExpression {
+        self.optimize_at(expression, Position::Standalone)
+    }
+
+    fn optimize_at(&mut self, expression: ASTExpression, position: Position) -> ASTExpression {
+        match expression.kind {
+            ASTExpressionKind::Parenthesized(parenthesized) => {
+                let span = TextSpan::combine(
+                    vec![
+                        parenthesized.left_paren.span.clone(),
+                        parenthesized.expression.span(),
+                        parenthesized.right_paren.span.clone()
+                    ]
+                );
+                // Optimize as if the parentheses were gone, then put them back if still needed
+                let inner = self.optimize_at(*parenthesized.expression, position);
+                if Self::needs_parentheses(&inner, position) {
+                    ASTExpression::parenthesized(
+                        parenthesized.left_paren,
+                        inner,
+                        parenthesized.right_paren
+                    )
+                } else if let Some(value) = Self::constant(&inner) {
+                    // A folded literal takes in the parentheses, so the spans of the expressions
+                    // around it still end at the closing one
+                    Self::literal(value, span)
+                } else {
+                    inner
+                }
+            }
+            ASTExpressionKind::Unary(unary) => {
+                let span = unary.span();
+                let operand = self.optimize_at(*unary.operand, Position::UnaryOperand);
+                if let Some(value) = Self::constant(&operand) {
+                    match unary_operation(unary.operator.kind, value) {
+                        Ok(value) => {
+                            return Self::literal(value, span);
+                        }
+                        Err(message) => self.report_failure(&message, span),
+                    }
+                }
+                ASTExpression::unary(unary.operator, operand)
+            }
+            ASTExpressionKind::Binary(binary) => {
+                let span = binary.span();
+                let left_is_int = self.is_int(&binary.left);
+                let right_is_int = self.is_int(&binary.right);
+                let left = self.optimize_at(*binary.left, Position::left_of(&binary.operator));
+                let right = self.optimize_at(*binary.right, Position::right_of(&binary.operator));
+                if let Some(folded) = self.fold_binary(&binary.operator, &left, &right, &span) {
+                    return folded;
+                }
+                // The remaining operand may have been grouped for the operator that just vanished
+                match Self::identity_operand(&binary.operator, &left, &right) {
+                    Some(true) if left_is_int => Self::regroup(left, position),
+                    Some(false) if right_is_int => Self::regroup(right, position),
+                    _ => ASTExpression::binary(binary.operator, left, right),
+                }
+            }
+            ASTExpressionKind::Assignment(assignment) => {
+                let expression = self.optimize_expression(*assignment.expression);
+                ASTExpression::assignment(assignment.identifier, expression)
+            }
+            ASTExpressionKind::Call(call) => {
+                let arguments = call.arguments
+                    .into_iter()
+                    .map(|argument| self.optimize_expression(argument))
+                    .collect();
+                ASTExpression::call(call.identifier, call.left_paren, arguments, call.right_paren)
+            }
+            kind => ASTExpression::new(kind),
+        }
+    }
+
+    fn fold_binary(
+        &mut self,
+        operator: &ASTBinaryOperator,
+        left: &ASTExpression,
+        right: &ASTExpression,
+        span: &TextSpan
+    ) -> Option<ASTExpression> {
+        let left = Self::constant(left);
+        let right = Self::constant(right);
+        match operator.kind {
+            ASTBinaryOperatorKind::LogicalAnd | ASTBinaryOperatorKind::LogicalOr => {
+                let short_circuits_on = operator.kind == ASTBinaryOperatorKind::LogicalOr;
+                match (left, right) {
+                    // The right operand would never be evaluated
+                    (Some(Value::Bool(left)), _) if left == short_circuits_on => {
+                        Some(Self::literal(Value::Bool(left), span.clone()))
+                    }
+                    (Some(_), Some(right)) => Some(Self::literal(right, span.clone())),
+                    _ => None,
+                }
+            }
+            // Integer division by a constant zero fails whatever the left operand turns out to be
+            ASTBinaryOperatorKind::Divide if left.is_none() && right == Some(Value::Int(0)) => {
+                self.report_failure("Division by zero", span.clone());
+                None
+            }
+            _ => {
+                let (left, right) = (left?, right?);
+                match binary_operation(operator.kind, left, right) {
+                    Ok(value) => Some(Self::literal(value, span.clone())),
+                    Err(message) => {
+                        self.report_failure(&message, span.clone());
+                        None
+                    }
+                }
+            }
+        }
+    }
+
+    // Which operand `x + 0`, `0 + x`, `x - 0`, `x * 1`, `1 * x` or `x / 1` reduces to,
+    // true meaning the left one
+    fn identity_operand(
+        operator: &ASTBinaryOperator,
+        left: &ASTExpression,
+        right: &ASTExpression
+    ) -> Option<bool> {
+        let (identity, commutes) = match operator.kind {
+            ASTBinaryOperatorKind::Plus => (0, true),
+            ASTBinaryOperatorKind::Minus => (0, false),
+            ASTBinaryOperatorKind::Multiply => (1, true),
+            ASTBinaryOperatorKind::Divide => (1, false),
+            _ => {
+                return None;
+            }
+        };
+        let is_identity = |expression: &ASTExpression| {
+            matches!(
+                &expression.kind,
+                ASTExpressionKind::Number(number) if number.number == identity
+            )
+        };
+        if is_identity(right) {
+            Some(true)
+        } else if commutes && is_identity(left) {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    // Only ints are safe to simplify: an unresolved operand, or arithmetic on one, could be a
+    // string at runtime
+    fn is_int(&self, expression: &ASTExpression) -> bool {
+        let span = expression.span();
+        self.expression_types.get(&(span.start, span.end)) == Some(&Type::Int)
+    }
+
+    fn needs_parentheses(expression: &ASTExpression, position: Position) -> bool {
+        match (&expression.kind, position) {
+            (_, Position::Standalone) => false,
+            (ASTExpressionKind::Assignment(_), _) => true,
+            (ASTExpressionKind::Binary(_), Position::UnaryOperand) => true,
+            (
+                ASTExpressionKind::Binary(binary),
+                Position::BinaryOperand { precedence, associates },
+            ) => {
+                let inner = binary.operator.precedence();
+                inner < precedence || (inner == precedence && !associates)
+            }
+            _ => false,
+        }
+    }
+
+    fn regroup(expression: ASTExpression, position: Position) -> ASTExpression {
+        match expression.kind {
+            ASTExpressionKind::Parenthesized(parenthesized) if
+                !Self::needs_parentheses(&parenthesized.expression, position)
+            => {
+                Self::regroup(*parenthesized.expression, position)
+            }
+            kind => ASTExpression::new(kind),
+        }
+    }
+
+    fn constant(expression: &ASTExpression) -> Option<Value> {
+        match &expression.kind {
+            ASTExpressionKind::Number(number) => Some(Value::Int(number.number)),
+            ASTExpressionKind::Float(float) => Some(Value::Float(float.number)),
+            ASTExpressionKind::String(string) => Some(Value::String(string.value.clone())),
+            ASTExpressionKind::Boolean(boolean) => Some(Value::Bool(boolean.value)),
+            _ => None,
+        }
+    }
+
+    // The literal keeps the folded expression's span, so diagnostics still quote the source
+    fn literal(value: Value, span: TextSpan) -> ASTExpression {
+        match value {
+            Value::Int(number) => {
+                ASTExpression::number(Token::new(TokenKind::Number(number), span), number)
+            }
+            Value::Float(number) => {
+                ASTExpression::float(Token::new(TokenKind::Float(number), span), number)
+            }
+            Value::String(string) => {
+                ASTExpression::string(Token::new(TokenKind::String(string.clone()), span), string)
+            }
+            Value::Bool(true) => ASTExpression::boolean(Token::new(TokenKind::True, span), true),
+            Value::Bool(false) => ASTExpression::boolean(Token::new(TokenKind::False, span), false),
+            Value::Unit => unreachable!("Operators never produce unit"),
+        }
+    }
+
+    // The expression is left for the runtime, which reports the same failure if it gets there
+    fn report_failure(&mut self, message: &str, span: TextSpan) {
+        self.diagnostics.borrow_mut().report_constant_failure(message, span);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ast::{ ASTExpression, ASTExpressionKind, ASTStatement, ASTStatementKind };
+    use crate::ast::evaluator::ASTEvaluator;
+    use crate::compilation_unit::{ CompilationUnit, FunctionBody };
+    use crate::diagnostics::DiagnosticKind;
+
+    fn render_expression(expression: &ASTExpression) -> String {
+        match &expression.kind {
+            ASTExpressionKind::Number(number) => number.number.to_string(),
+            ASTExpressionKind::Float(float) => format!("{:?}", float.number),
+            ASTExpressionKind::String(string) => format!("{:?}", string.value),
+            ASTExpressionKind::Boolean(boolean) => boolean.value.to_string(),
+            ASTExpressionKind::Variable(variable) => variable.identifier().to_string(),
+            ASTExpressionKind::Binary(binary) => {
+                format!(
+                    "{} {} {}",
+                    render_expression(&binary.left),
+                    binary.operator.kind.symbol(),
+                    render_expression(&binary.right)
+                )
+            }
+            ASTExpressionKind::Unary(unary) => {
+                let operand = render_expression(&unary.operand);
+                format!("{}{}", unary.operator.token.span.literal, operand)
+            }
+            ASTExpressionKind::Parenthesized(parenthesized) => {
+                format!("({})", render_expression(&parenthesized.expression))
+            }
+            ASTExpressionKind::Assignment(assignment) => {
+                let expression = render_expression(&assignment.expression);
+                format!("{} = {}", assignment.identifier.span.literal, expression)
+            }
+            ASTExpressionKind::Call(call) => {
+                let arguments: Vec<String> = call.arguments.iter().map(render_expression).collect();
+                format!("{}({})", call.identifier.span.literal, arguments.join(", "))
+            }
+            ASTExpressionKind::Error(_) => "<error>".to_string(),
+        }
+    }
+
+    // One line per let, expression and return, looking into blocks and function bodies
+    fn render_statement(statement: &ASTStatement, lines: &mut Vec<String>) {
+        match &statement.kind {
+            ASTStatementKind::Expression(expression) => lines.push(render_expression(expression)),
+            ASTStatementKind::Let(let_statement) => {
+                let initializer = render_expression(&let_statement.initializer);
+                let identifier = &let_statement.identifier.span.literal;
+                lines.push(format!("let {} = {}", identifier, initializer));
+            }
+            ASTStatementKind::Return(return_statement) => {
+                let value = return_statement.return_value.as_ref().map_or(String::new(), |value| {
+                    format!(" {}", render_expression(value))
+                });
+                lines.push(format!("return{}", value));
+            }
+            ASTStatementKind::Block(block_statement) => {
+                for statement in &block_statement.statements {
+                    render_statement(statement, lines);
+                }
+            }
+            ASTStatementKind::FuncDecl(func_decl_statement) => {
+                render_statement(&func_decl_statement.body, lines);
+            }
+            _ => {}
+        }
+    }
+
+    fn optimize(input: &str) -> Vec<String> {
+        let compilation_unit = CompilationUnit::compile(input).expect("Failed to compile");
+        let mut lines = Vec::new();
+        for statement in &compilation_unit.ast.statements {
+            render_statement(statement, &mut lines);
+        }
+        lines
+    }
+
+    const FAILURE_PREFIX: &str = "Evaluating this expression always fails: ";
+
+    fn failure_warnings(input: &str) -> Vec<(String, String)> {
+        let compilation_unit = CompilationUnit::compile(input).expect("Failed to compile");
+        let diagnostics = &compilation_unit.diagnostics_bag.borrow().diagnostics;
+        diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.message.starts_with(FAILURE_PREFIX))
+            .map(|diagnostic| {
+                assert_eq!(diagnostic.kind, DiagnosticKind::Warning);
+                (diagnostic.message.clone(), diagnostic.span.literal.clone())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn should_fold_constant_subtrees() {
+        let input = "\
+        let a = 1 + 2 * 3
+        let b = (2 ** 10 - 24) / 10
+        let c = -(4 - 6) & ~0
+        let d = \"tung\" + \"sten\"
+        let e = !(1 < 2) || 3 == 3
+        let f = 1.5 * 2.0 - 0.5
+        ";
+
+        assert_eq!(optimize(input), vec![
+            "let a = 7",
+            "let b = 100",
+            "let c = 2",
+            "let d = \"tungsten\"",
+            "let e = true",
+            "let f = 2.5"
+        ]);
+    }
+
+    #[test]
+    fn should_fold_constants_inside_larger_expressions() {
+        let input = "\
+        func f(x: int) -> int {
+            return x * (2 + 3) - 4 * 5
+        }
+        println(f(2 * 2))
+        ";
+
+        assert_eq!(optimize(input), vec!["return x * 5 - 20", "println(f(4))"]);
+    }
+
+    #[test]
+    fn should_fold_logical_operators_only_when_the_result_is_known() {
+        let input = "\
+        func check() -> bool {
+            return true
+        }
+        let a = false && check()
+        let b = true || check()
+        let c = true && check()
+        let d = false || true
+        ";
+
+        assert_eq!(optimize(input), vec![
+            "return true",
+            "let a = false",
+            "let b = true",
+            "let c = true && check()",
+            "let d = true"
+        ]);
+    }
+
+    #[test]
+    fn should_remove_identity_operations_on_ints() {
+        let input = "\
+        func f(x: int, y: int) -> int {
+            let a = x * 1
+            let b = 1 * (x + y)
+            let c = 0 + x - 0
+            let d = (x - y) / 1
+            return a + 0 * 5 + b
+        }
+        ";
+
+        assert_eq!(optimize(input), vec![
+            "let a = x",
+            "let b = x + y",
+            "let c = x",
+            "let d = x - y",
+            "return a + b"
+        ]);
+    }
+
+    #[test]
+    fn should_keep_identity_operations_on_values_that_may_not_be_ints() {
+        let input = "\
+        func f(x) {
+            return x + 0
+        }
+        func g(x: float) -> float {
+            return x * 1.0
+        }
+        println(f(\"a\"))
+        ";
+
+        assert_eq!(optimize(input), vec!["return x + 0", "return x * 1.0", "println(f(\"a\"))"]);
+    }
+
+    #[test]
+    fn should_keep_identity_operations_on_arithmetic_over_unresolved_values() {
+        let input = "\
+        func twice(a) {
+            return (a + a) * 1
+        }
+        func shift(a, b) {
+            return 0 + a * b
+        }
+        println(twice(\"ab\"))
+        println(shift(1, 2))
+        ";
+
+        assert_eq!(optimize(input), vec![
+            "return (a + a) * 1",
+            "return 0 + a * b",
+            "println(twice(\"ab\"))",
+            "println(shift(1, 2))"
+        ]);
+
+        let compilation_unit = CompilationUnit::compile(input).expect("Failed to compile");
+        let mut output = Vec::new();
+        let mut eval = ASTEvaluator::new(&compilation_unit.global_scope, &mut output);
+        compilation_unit.execute(&mut eval);
+        let error = eval.runtime_error().cloned().expect("Expected a runtime error");
+        assert_eq!(error.message, "Operator '*' cannot be applied to types 'string' and 'int'");
+    }
+
+    #[test]
+    fn should_remove_redundant_parentheses() {
+        let input = "\
+        func f(a: int, b: int, c: int) -> int {
+            let x = ((a)) + (b * c)
+            let y = (a + b) * c
+            let z = a - (b - c)
+            let w = (a - b) - c
+            let v = -(a) + (-b)
+            let u = -(a + b)
+            let t = a ** (b ** c) + (a ** b) ** c
+            return (x)
+        }
+        ";
+
+        assert_eq!(optimize(input), vec![
+            "let x = a + b * c",
+            "let y = (a + b) * c",
+            "let z = a - (b - c)",
+            "let w = a - b - c",
+            "let v = -a + -b",
+            "let u = -(a + b)",
+            "let t = a ** b ** c + (a ** b) ** c",
+            "return x"
+        ]);
+    }
+
+    #[test]
+    fn should_regroup_after_removing_an_identity_operation() {
+        let input = "\
+        func f(a: int, b: int) -> int {
+            let x = (a + b) * 1
+            let y = a * ((a + b) * 1)
+            return x + y
+        }
+        ";
+
+        assert_eq!(optimize(input), vec!["let x = a + b", "let y = a * (a + b)", "return x + y"]);
+    }
+
+    #[test]
+    fn should_warn_about_constant_expressions_that_always_fail() {
+        let input = "\
+        func f(x: int) -> int {
+            return x / (2 - 2)
+        }
+        let a = 9223372036854775807 + 1
+        let b = -(-9223372036854775807 - 1)
+        let c = 2 ** (0 - 1)
+        ";
+
+        let expected = vec![
+            ("Division by zero", "x / (2 - 2)"),
+            ("Integer overflow in '+'", "9223372036854775807 + 1"),
+            ("Integer overflow in '-'", "-(-9223372036854775807 - 1)"),
+            ("Negative exponent -1 in integer power", "2 ** (0 - 1)")
+        ];
+        let expected: Vec<(String, String)> = expected
+            .into_iter()
+            .map(|(message, literal)| {
+                (format!("{}{}", FAILURE_PREFIX, message), literal.to_string())
+            })
+            .collect();
+        assert_eq!(failure_warnings(input), expected);
+        assert_eq!(optimize(input), vec![
+            "return x / 0",
+            "let a = 9223372036854775807 + 1",
+            "let b = --9223372036854775808",
+            "let c = 2 ** -1"
+        ]);
+    }
+
+    #[test]
+    fn should_still_fail_at_runtime_after_warning() {
+        let input = "\
+        println(\"before\")
+        let a = 1 / 0
+        println(\"after\")
+        ";
+
+        let compilation_unit = CompilationUnit::compile(input).expect("Failed to compile");
+        let mut output = Vec::new();
+        let mut eval = ASTEvaluator::new(&compilation_unit.global_scope, &mut output);
+        compilation_unit.execute(&mut eval);
+        let error = eval.runtime_error().cloned().expect("Expected a runtime error");
+        assert_eq!(error.message, "Division by zero");
+        assert_eq!(error.span.literal, "1 / 0");
+        drop(eval);
+        assert_eq!(String::from_utf8(output).unwrap(), "before\n");
+    }
+
+    #[test]
+    fn should_keep_the_parentheses_in_the_span_of_a_folded_literal() {
+        let input = "println(10 / (5 - 5))";
+
+        let compilation_unit = CompilationUnit::compile(input).expect("Failed to compile");
+        let mut output = Vec::new();
+        let mut eval = ASTEvaluator::new(&compilation_unit.global_scope, &mut output);
+        compilation_unit.execute(&mut eval);
+        let error = eval.runtime_error().cloned().expect("Expected a runtime error");
+        assert_eq!(error.message, "Division by zero");
+        assert_eq!((error.span.start, error.span.end), (8, 20));
+        assert_eq!(error.span.literal, "10 / (5 - 5)");
+        assert_eq!(failure_warnings(input)[0].1, error.span.literal);
+    }
+
+    #[test]
+    fn should_run_the_optimized_function_bodies() {
+        let input = "\
+        func f(x: int) -> int {
+            return (x + 0) * (1 + 1)
+        }
+        println(f(21))
+        ";
+
+        let compilation_unit = CompilationUnit::compile(input).expect("Failed to compile");
+        let Some(FunctionBody::Declared(body)) = compilation_unit.global_scope
+            .lookup_function("f")
+            .map(|function| &function.body) else {
+            panic!("Expected 'f' to be declared");
+        };
+        let mut lines = Vec::new();
+        render_statement(body, &mut lines);
+        assert_eq!(lines, vec!["return x * 2"]);
+
+        let mut output = Vec::new();
+        let mut eval = ASTEvaluator::new(&compilation_unit.global_scope, &mut output);
+        compilation_unit.execute(&mut eval);
+        drop(eval);
+        assert_eq!(String::from_utf8(output).unwrap(), "42\n");
+    }
+}
@@ -7,8 +7,10 @@ use crate::ast::{
     ASTUnaryOperatorKind,
     ASTElseStatement,
     FuncDeclParameter,
+    FuncReturnType,
+    TypeAnnotation,
 };
-use crate::ast::lexer::{ Token, TokenKind };
+use crate::ast::lexer::{ TextSpan, Token, TokenKind };
 use crate::diagnostics::DiagnosticsBagCell;
 use std::cell::Cell;
 
@@ -16,6 +18,12 @@ pub struct Counter {
     value: Cell<usize>,
 }
 
+impl Default for Counter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Counter {
     pub fn new() -> Self {
         Self {
@@ -35,24 +43,38 @@ impl Counter {
 
 pub struct Parser {
     tokens: Vec<Token>,
+    // Whether the trivia skipped before each token contains a line break
+    follows_line_break: Vec<bool>,
     current: Counter, // Pointer to cur token
     diagnostics_bag: DiagnosticsBagCell, // Cool stuff: https://www.geeksforgeeks.org/error-handling-compiler-design/
+    // Set by a syntax error and cleared once the parser resynchronizes; errors in between are
+    // knock-on effects of the first one and are not reported
+    panicking: Cell<bool>,
 }
 
+// Parsing functions return explicitly, even on their last line
+#[allow(clippy::needless_return, clippy::collapsible_if)]
 impl Parser {
     pub fn new(tokens: Vec<Token>, diagnostics_bag: DiagnosticsBagCell) -> Self {
+        let mut follows_line_break = Vec::new();
+        let mut line_break = false;
+        let tokens = tokens
+            .into_iter()
+            .filter(|token| {
+                if token.kind.is_trivia() {
+                    line_break |= token.span.literal.contains('\n');
+                    return false;
+                }
+                follows_line_break.push(std::mem::take(&mut line_break));
+                true
+            })
+            .collect();
         Self {
-            tokens: tokens
-                .into_iter()
-                .filter(|token| {
-                    match token.kind {
-                        TokenKind::Whitespace => false,
-                        _ => true,
-                    }
-                })
-                .collect(),
+            tokens,
+            follows_line_break,
             current: Counter::new(),
             diagnostics_bag,
+            panicking: Cell::new(false),
         }
     }
 
@@ -60,6 +82,13 @@ impl Parser {
         if self.is_at_end() {
             return None;
         }
+        // Blocks stop at `}`, so only an unmatched one can show up here
+        if self.current().kind == TokenKind::CloseBrace {
+            let close_brace = self.consume().clone();
+            self.report_expected_expression(&close_brace);
+            self.panicking.set(false);
+            return Some(ASTStatement::error(close_brace.span));
+        }
         Some(self.parse_statement())
     }
 
@@ -68,23 +97,111 @@ impl Parser {
     }
 
     fn parse_statement(&mut self) -> ASTStatement {
-        match self.current().kind {
+        let start = self.current.get_value();
+        // Each statement gets to report its own first error, even inside a broken one
+        let was_panicking = self.panicking.replace(false);
+        let statement = match self.current().kind {
             TokenKind::Let => { self.parse_let_statement() }
             TokenKind::If => { self.parse_if_statement() }
             TokenKind::OpenBrace => { self.parse_block_statement() }
             TokenKind::While => { self.parse_while_statement() }
+            TokenKind::For => { self.parse_for_statement() }
             TokenKind::Func => { self.parse_function_declaration() }
             TokenKind::Return => { self.parse_return_statement() }
+            TokenKind::Break => {
+                let break_keyword = self.consume_and_check(TokenKind::Break).clone();
+                ASTStatement::break_statement(break_keyword)
+            }
+            TokenKind::Continue => {
+                let continue_keyword = self.consume_and_check(TokenKind::Continue).clone();
+                ASTStatement::continue_statement(continue_keyword)
+            }
             _ => { self.parse_expression_statement() }
+        };
+        if self.panicking.get() {
+            let error = self.synchronize(start);
+            self.panicking.set(was_panicking);
+            return error;
         }
+        self.panicking.set(was_panicking);
+        statement
+    }
+
+    // Panic-mode recovery: the broken statement becomes an error statement, and everything up to
+    // the next statement keyword or closing brace is skipped along with it
+    fn synchronize(&mut self, start: usize) -> ASTStatement {
+        let mut depth = 0;
+        while !self.is_at_end() {
+            match self.current().kind {
+                TokenKind::OpenBrace => {
+                    depth += 1;
+                }
+                TokenKind::CloseBrace if depth > 0 => {
+                    depth -= 1;
+                }
+                ref kind if depth == 0 && Self::is_synchronization_point(kind) => {
+                    break;
+                }
+                _ => {}
+            }
+            self.consume();
+        }
+        self.panicking.set(false);
+
+        let end = self.current.get_value().min(self.tokens.len());
+        let skipped: Vec<TextSpan> = self.tokens[start..end]
+            .iter()
+            .map(|token| token.span.clone())
+            .collect();
+        if skipped.is_empty() {
+            return ASTStatement::error(Self::empty_span(self.current().span.start));
+        }
+        ASTStatement::error(TextSpan::combine(skipped))
+    }
+
+    fn empty_span(position: usize) -> TextSpan {
+        TextSpan::new(position, position, String::new())
+    }
+
+    fn is_synchronization_point(kind: &TokenKind) -> bool {
+        matches!(
+            kind,
+            TokenKind::Let |
+                TokenKind::If |
+                TokenKind::While |
+                TokenKind::For |
+                TokenKind::Func |
+                TokenKind::Return |
+                TokenKind::CloseBrace |
+                TokenKind::Eof
+        )
     }
 
     fn parse_function_declaration(&mut self) -> ASTStatement {
-        self.consume_and_check(TokenKind::Func);
+        let func_keyword = self.consume_and_check(TokenKind::Func).clone();
         let identifier = self.consume_and_check(TokenKind::Identifier).clone();
         let parameters = self.parse_optional_parameter_list();
+        let return_type = self.parse_optional_return_type();
         let body = self.parse_statement();
-        ASTStatement::func_decl_statement(identifier, parameters, body)
+        ASTStatement::func_decl_statement(func_keyword, identifier, parameters, return_type, body)
+    }
+
+    fn parse_optional_return_type(&mut self) -> Option<FuncReturnType> {
+        if self.current().kind != TokenKind::Arrow {
+            return None;
+        }
+        let arrow = self.consume_and_check(TokenKind::Arrow).clone();
+        let type_name = self.consume_and_check(TokenKind::Identifier).clone();
+        Some(FuncReturnType { arrow, type_name })
+    }
+
+    fn parse_optional_type_annotation(&mut self) -> Option<TypeAnnotation> {
+        if self.current().kind != TokenKind::Colon {
+            return None;
+        }
+        let colon = self.consume_and_check(TokenKind::Colon).clone();
+        let type_name = self.consume_and_check(TokenKind::Identifier).clone();
+        Some(TypeAnnotation { colon, type_name })
     }
 
     fn parse_optional_parameter_list(&mut self) -> Vec<FuncDeclParameter> {
@@ -93,13 +210,14 @@ impl Parser {
         }
         self.consume_and_check(TokenKind::LeftParen);
         let mut parameters = Vec::new();
-        while self.current().kind != TokenKind::RightParen && !self.is_at_end() {
-            parameters.push(FuncDeclParameter {
-                identifier: self.consume_and_check(TokenKind::Identifier).clone(),
-            });
-            if self.current().kind == TokenKind::Comma {
-                self.consume_and_check(TokenKind::Comma);
+        while self.current().kind == TokenKind::Identifier {
+            let identifier = self.consume_and_check(TokenKind::Identifier).clone();
+            let type_annotation = self.parse_optional_type_annotation();
+            parameters.push(FuncDeclParameter { identifier, type_annotation });
+            if self.current().kind != TokenKind::Comma {
+                break;
             }
+            self.consume_and_check(TokenKind::Comma);
         }
         self.consume_and_check(TokenKind::RightParen);
         parameters
@@ -107,9 +225,32 @@ impl Parser {
 
     fn parse_return_statement(&mut self) -> ASTStatement {
         let return_keyword = self.consume_and_check(TokenKind::Return).clone();
-        // todo: allow empty return statements
-        let expression = self.parse_expression();
-        ASTStatement::return_statement(return_keyword, Some(expression))
+        // The grammar ignores newlines, so a value has to start on the same line as the `return`
+        let expression = if
+            Self::can_start_expression(&self.current().kind) &&
+            !self.is_on_new_line()
+        {
+            Some(self.parse_expression())
+        } else {
+            None
+        };
+        ASTStatement::return_statement(return_keyword, expression)
+    }
+
+    fn can_start_expression(kind: &TokenKind) -> bool {
+        matches!(
+            kind,
+            TokenKind::Number(_) |
+                TokenKind::Float(_) |
+                TokenKind::String(_) |
+                TokenKind::Identifier |
+                TokenKind::LeftParen |
+                TokenKind::True |
+                TokenKind::False |
+                TokenKind::Minus |
+                TokenKind::Tilde |
+                TokenKind::Bang
+        )
     }
 
     fn parse_while_statement(&mut self) -> ASTStatement {
@@ -119,14 +260,37 @@ impl Parser {
         ASTStatement::while_statement(while_keyword, condition_expr, body)
     }
 
+    fn parse_for_statement(&mut self) -> ASTStatement {
+        let for_keyword = self.consume_and_check(TokenKind::For).clone();
+        let identifier = self.consume_and_check(TokenKind::Identifier).clone();
+        let in_keyword = self.consume_and_check(TokenKind::In).clone();
+        let start = self.parse_expression();
+        let range_operator = if self.current().kind == TokenKind::DotDotEquals {
+            self.consume_and_check(TokenKind::DotDotEquals).clone()
+        } else {
+            self.consume_and_check(TokenKind::DotDot).clone()
+        };
+        let end = self.parse_expression();
+        let body = self.parse_statement();
+        ASTStatement::for_statement(
+            for_keyword,
+            identifier,
+            in_keyword,
+            start,
+            range_operator,
+            end,
+            body
+        )
+    }
+
     fn parse_block_statement(&mut self) -> ASTStatement {
-        self.consume_and_check(TokenKind::OpenBrace);
+        let open_brace = self.consume_and_check(TokenKind::OpenBrace).clone();
         let mut statements = Vec::new();
         while self.current().kind != TokenKind::CloseBrace && !self.is_at_end() {
             statements.push(self.parse_statement());
         }
-        self.consume_and_check(TokenKind::CloseBrace);
-        ASTStatement::block_statement(statements)
+        let close_brace = self.consume_and_check(TokenKind::CloseBrace).clone();
+        ASTStatement::block_statement(open_brace, statements, close_brace)
     }
 
     fn parse_if_statement(&mut self) -> ASTStatement {
@@ -147,12 +311,13 @@ impl Parser {
     }
 
     fn parse_let_statement(&mut self) -> ASTStatement {
-        self.consume_and_check(TokenKind::Let); // let
+        let let_keyword = self.consume_and_check(TokenKind::Let).clone(); // let
         let identifier = self.consume_and_check(TokenKind::Identifier).clone(); // x
+        let type_annotation = self.parse_optional_type_annotation(); // : int
         self.consume_and_check(TokenKind::Equals); // =
         let expr = self.parse_expression(); // 21
 
-        return ASTStatement::let_statement(identifier, expr);
+        ASTStatement::let_statement(let_keyword, identifier, type_annotation, expr)
     }
 
     fn parse_expression_statement(&mut self) -> ASTStatement {
@@ -180,16 +345,21 @@ impl Parser {
         let mut left = self.parse_unary_expression();
 
         while let Some(operator) = self.parse_binary_operator() {
-            self.consume();
             let operator_precedence = operator.precedence();
 
-            // Base case
-            if operator_precedence < precedence {
+            // Base case: leave looser operators to the caller
+            if operator_precedence <= precedence {
                 break;
             }
+            self.consume();
 
             // Recursive case
-            let right = self.parse_binary_expression(operator_precedence);
+            let right_precedence = if operator.is_right_associative() {
+                operator_precedence - 1
+            } else {
+                operator_precedence
+            };
+            let right = self.parse_binary_expression(right_precedence);
             left = ASTExpression::binary(operator, left, right);
         }
 
@@ -201,7 +371,7 @@ impl Parser {
             let operator_token = self.consume();
 
             if self.is_at_end() {
-                self.diagnostics_bag.borrow_mut().report_expected_expression(operator_token);
+                self.report_expected_expression(operator_token);
                 return ASTExpression::error(operator_token.span.clone());
             }
 
@@ -217,6 +387,7 @@ impl Parser {
         let kind = match token.kind {
             TokenKind::Minus => { Some(ASTUnaryOperatorKind::Minus) }
             TokenKind::Tilde => { Some(ASTUnaryOperatorKind::BitwiseNot) }
+            TokenKind::Bang => { Some(ASTUnaryOperatorKind::LogicalNot) }
             _ => { None }
         };
 
@@ -241,6 +412,8 @@ impl Parser {
             TokenKind::LessThanEquals => { Some(ASTBinaryOperatorKind::LessThanOrEqual) }
             TokenKind::GreaterThan => { Some(ASTBinaryOperatorKind::GreaterThan) }
             TokenKind::GreaterThanEquals => { Some(ASTBinaryOperatorKind::GreaterThanOrEqual) }
+            TokenKind::AmpersandAmpersand => { Some(ASTBinaryOperatorKind::LogicalAnd) }
+            TokenKind::PipePipe => { Some(ASTBinaryOperatorKind::LogicalOr) }
             _ => { None }
         };
 
@@ -249,14 +422,24 @@ impl Parser {
 
     // For function calls, literals, strings, etc.
     fn parse_primary_expression(&mut self) -> ASTExpression {
+        // A keyword or brace where an expression should be most likely belongs to what comes next
+        let kind = &self.current().kind;
+        if *kind == TokenKind::OpenBrace || Self::is_synchronization_point(kind) {
+            let token = self.current();
+            self.report_expected_expression(token);
+            return ASTExpression::error(Self::empty_span(token.span.start));
+        }
         let token = self.consume();
 
         return match token.kind {
-            TokenKind::Number(number) => ASTExpression::number(number),
+            TokenKind::Number(number) => ASTExpression::number(token.clone(), number),
+            TokenKind::Float(number) => ASTExpression::float(token.clone(), number),
+            TokenKind::String(ref value) => ASTExpression::string(token.clone(), value.clone()),
             TokenKind::LeftParen => {
+                let left_paren = token.clone();
                 let expr = self.parse_expression();
-                let _token = self.consume_and_check(TokenKind::RightParen);
-                ASTExpression::parenthesized(expr)
+                let right_paren = self.consume_and_check(TokenKind::RightParen).clone();
+                ASTExpression::parenthesized(left_paren, expr, right_paren)
             }
             TokenKind::Identifier => {
                 if self.current().kind == TokenKind::LeftParen {
@@ -270,23 +453,24 @@ impl Parser {
                 ASTExpression::boolean(token.clone(), value)
             }
             _ => {
-                self.diagnostics_bag.borrow_mut().report_expected_expression(token);
+                self.report_expected_expression(token);
                 ASTExpression::error(token.span.clone())
             }
         };
     }
 
     fn parse_call_expression(&mut self, identifier: Token) -> ASTExpression {
-        self.consume_and_check(TokenKind::LeftParen);
+        let left_paren = self.consume_and_check(TokenKind::LeftParen).clone();
         let mut arguments = Vec::new();
         while self.current().kind != TokenKind::RightParen && !self.is_at_end() {
             arguments.push(self.parse_expression());
-            if self.current().kind != TokenKind::RightParen {
-                self.consume_and_check(TokenKind::Comma);
+            if self.current().kind != TokenKind::Comma {
+                break;
             }
+            self.consume_and_check(TokenKind::Comma);
         }
-        self.consume_and_check(TokenKind::RightParen);
-        return ASTExpression::call(identifier.clone(), arguments);
+        let right_paren = self.consume_and_check(TokenKind::RightParen).clone();
+        ASTExpression::call(identifier, left_paren, arguments, right_paren)
     }
 
     fn peek(&self, offset: isize) -> &Token {
@@ -302,16 +486,36 @@ impl Parser {
         self.peek(0)
     }
 
+    fn is_on_new_line(&self) -> bool {
+        self.follows_line_break
+            .get(self.current.get_value())
+            .copied()
+            .unwrap_or(false)
+    }
+
     fn consume(&self) -> &Token {
         self.current.increment();
         self.peek(-1)
     }
 
+    // A mismatched token is left in place for what comes next, as if the expected one was missing
     fn consume_and_check(&self, kind: TokenKind) -> &Token {
-        let token = self.consume();
-        if token.kind != kind {
-            self.diagnostics_bag.borrow_mut().report_unexpected_token(&kind, token);
+        if self.current().kind != kind {
+            self.report_unexpected_token(&kind, self.current());
+            return self.current();
+        }
+        self.consume()
+    }
+
+    fn report_unexpected_token(&self, expected: &TokenKind, token: &Token) {
+        if !self.panicking.replace(true) {
+            self.diagnostics_bag.borrow_mut().report_unexpected_token(expected, token);
+        }
+    }
+
+    fn report_expected_expression(&self, token: &Token) {
+        if !self.panicking.replace(true) {
+            self.diagnostics_bag.borrow_mut().report_expected_expression(token);
         }
-        token
     }
 }
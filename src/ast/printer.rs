@@ -6,7 +6,11 @@ use crate::ast::{
     ASTBinaryExpression,
     ASTBlockStatement,
     ASTBooleanExpression,
+    ASTBreakStatement,
     ASTCallExpression,
+    ASTContinueStatement,
+    ASTFloatExpression,
+    ASTForStatement,
     ASTFuncDeclStatement,
     ASTIfStatement,
     ASTLetStatement,
@@ -14,9 +18,11 @@ use crate::ast::{
     ASTParenthesizedExpression,
     ASTReturnStatement,
     ASTStatement,
+    ASTStringExpression,
     ASTUnaryExpression,
     ASTVariableExpression,
     ASTWhileStatement,
+    TypeAnnotation,
 };
 use crate::ast::lexer::TextSpan;
 use crate::ast::visitor::ASTVisitor;
@@ -26,17 +32,27 @@ pub struct ASTPrinter {
     pub result: String,
 }
 
+impl Default for ASTPrinter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ASTPrinter {
     const NUMBER_COLOR: color::Cyan = color::Cyan;
     const TEXT_COLOR: color::LightWhite = color::LightWhite;
     const KEYWORD_COLOR: color::Magenta = color::Magenta;
     const VARIABLE_COLOR: color::Green = color::Green;
     const BOOLEAN_COLOR: color::Yellow = color::Yellow;
+    const TYPE_COLOR: color::Blue = color::Blue;
+    const STRING_COLOR: color::LightGreen = color::LightGreen;
 
+    #[allow(clippy::single_char_add_str)]
     fn add_whitespace(&mut self) {
         self.result.push_str(" ");
     }
 
+    #[allow(clippy::single_char_add_str)]
     fn add_newline(&mut self) {
         self.result.push_str("\n");
     }
@@ -59,6 +75,16 @@ impl ASTPrinter {
         }
     }
 
+    fn add_type(&mut self, type_name: &str) {
+        self.result.push_str(&format!("{}{}", Self::TYPE_COLOR.fg_str(), type_name));
+    }
+
+    fn add_type_annotation(&mut self, type_annotation: &TypeAnnotation) {
+        self.add_text(":");
+        self.add_whitespace();
+        self.add_type(&type_annotation.type_name.span.literal);
+    }
+
     fn add_boolean(&mut self, boolean: bool) {
         self.result.push_str(&format!("{}{}", Self::BOOLEAN_COLOR.fg_str(), boolean));
     }
@@ -85,11 +111,20 @@ impl ASTVisitor<'_> for ASTPrinter {
                 self.add_whitespace();
             }
             self.add_text(&parameter.identifier.span.literal);
+            if let Some(type_annotation) = &parameter.type_annotation {
+                self.add_type_annotation(type_annotation);
+            }
         }
         if !are_parameters_empty {
             self.add_text(")");
             self.add_whitespace();
         }
+        if let Some(return_type) = &func_decl_statement.return_type {
+            self.add_text("->");
+            self.add_whitespace();
+            self.add_type(&return_type.type_name.span.literal);
+            self.add_whitespace();
+        }
         self.visit_statement(&func_decl_statement.body);
     }
     fn visit_return_statement(&mut self, return_statement: &ASTReturnStatement) {
@@ -99,6 +134,12 @@ impl ASTVisitor<'_> for ASTPrinter {
             self.visit_expression(expression);
         }
     }
+    fn visit_break_statement(&mut self, _break_statement: &ASTBreakStatement) {
+        self.add_keyword("break");
+    }
+    fn visit_continue_statement(&mut self, _continue_statement: &ASTContinueStatement) {
+        self.add_keyword("continue");
+    }
     fn visit_while_statement(&mut self, while_statement: &ASTWhileStatement) {
         self.add_keyword("while");
         self.add_whitespace();
@@ -106,6 +147,19 @@ impl ASTVisitor<'_> for ASTPrinter {
         self.add_whitespace();
         self.visit_statement(&while_statement.body);
     }
+    fn visit_for_statement(&mut self, for_statement: &ASTForStatement) {
+        self.add_keyword("for");
+        self.add_whitespace();
+        self.add_variable(&for_statement.identifier.span.literal);
+        self.add_whitespace();
+        self.add_keyword("in");
+        self.add_whitespace();
+        self.visit_expression(&for_statement.start);
+        self.add_text(&for_statement.range_operator.span.literal);
+        self.visit_expression(&for_statement.end);
+        self.add_whitespace();
+        self.visit_statement(&for_statement.body);
+    }
     fn visit_block_statement(&mut self, block_statement: &ASTBlockStatement) {
         self.add_text("{");
         self.add_newline();
@@ -136,6 +190,9 @@ impl ASTVisitor<'_> for ASTPrinter {
         self.add_keyword("let");
         self.add_whitespace();
         self.add_text(let_statement.identifier.span.literal.as_str());
+        if let Some(type_annotation) = &let_statement.type_annotation {
+            self.add_type_annotation(type_annotation);
+        }
         self.add_whitespace();
         self.add_text("=");
         self.add_whitespace();
@@ -183,6 +240,14 @@ impl ASTVisitor<'_> for ASTPrinter {
         self.result.push_str(&format!("{}{}", Self::NUMBER_COLOR.fg_str(), number.number));
     }
 
+    fn visit_float_expression(&mut self, float: &ASTFloatExpression) {
+        self.result.push_str(&format!("{}{:?}", Self::NUMBER_COLOR.fg_str(), float.number));
+    }
+
+    fn visit_string_expression(&mut self, string: &ASTStringExpression) {
+        self.result.push_str(&format!("{}{:?}", Self::STRING_COLOR.fg_str(), string.value));
+    }
+
     fn visit_boolean_expression(&mut self, boolean: &ASTBooleanExpression) {
         self.add_boolean(boolean.value);
     }
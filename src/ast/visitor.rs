@@ -3,9 +3,13 @@ use crate::ast::{
     ASTBinaryExpression,
     ASTBlockStatement,
     ASTBooleanExpression,
+    ASTBreakStatement,
     ASTCallExpression,
+    ASTContinueStatement,
     ASTExpression,
     ASTExpressionKind,
+    ASTFloatExpression,
+    ASTForStatement,
     ASTFuncDeclStatement,
     ASTIfStatement,
     ASTLetStatement,
@@ -14,6 +18,7 @@ use crate::ast::{
     ASTReturnStatement,
     ASTStatement,
     ASTStatementKind,
+    ASTStringExpression,
     ASTUnaryExpression,
     ASTVariableExpression,
     ASTWhileStatement,
@@ -24,7 +29,7 @@ pub trait ASTVisitor<'a> {
     fn do_visit_statement(&mut self, statement: &ASTStatement) {
         match &statement.kind {
             ASTStatementKind::Expression(expr) => {
-                self.visit_expression(expr);
+                self.visit_expression_statement(expr);
             }
             ASTStatementKind::Let(expr) => {
                 self.visit_let_statement(expr);
@@ -38,28 +43,55 @@ pub trait ASTVisitor<'a> {
             ASTStatementKind::While(stmt) => {
                 self.visit_while_statement(stmt);
             }
+            ASTStatementKind::For(stmt) => {
+                self.visit_for_statement(stmt);
+            }
             ASTStatementKind::FuncDecl(stmt) => {
                 self.visit_func_decl_statement(stmt);
             }
             ASTStatementKind::Return(stmt) => {
                 self.visit_return_statement(stmt);
             }
+            ASTStatementKind::Break(stmt) => {
+                self.visit_break_statement(stmt);
+            }
+            ASTStatementKind::Continue(stmt) => {
+                self.visit_continue_statement(stmt);
+            }
+            ASTStatementKind::Error(span) => {
+                self.visit_error(span);
+            }
         }
     }
 
     fn visit_func_decl_statement(&mut self, func_decl_statement: &ASTFuncDeclStatement);
 
+    // An expression whose value is discarded
+    fn visit_expression_statement(&mut self, expression: &ASTExpression) {
+        self.visit_expression(expression);
+    }
+
     fn visit_return_statement(&mut self, return_statement: &ASTReturnStatement) {
         if let Some(expr) = &return_statement.return_value {
             self.visit_expression(expr);
         }
     }
 
+    fn visit_break_statement(&mut self, _break_statement: &ASTBreakStatement) {}
+
+    fn visit_continue_statement(&mut self, _continue_statement: &ASTContinueStatement) {}
+
     fn visit_while_statement(&mut self, while_statement: &ASTWhileStatement) {
         self.visit_expression(&while_statement.condition);
         self.visit_statement(&while_statement.body);
     }
 
+    fn visit_for_statement(&mut self, for_statement: &ASTForStatement) {
+        self.visit_expression(&for_statement.start);
+        self.visit_expression(&for_statement.end);
+        self.visit_statement(&for_statement.body);
+    }
+
     fn visit_block_statement(&mut self, block_statement: &ASTBlockStatement) {
         for statement in &block_statement.statements {
             self.visit_statement(statement);
@@ -85,6 +117,12 @@ pub trait ASTVisitor<'a> {
             ASTExpressionKind::Number(number) => {
                 self.visit_number_expression(number);
             }
+            ASTExpressionKind::Float(float) => {
+                self.visit_float_expression(float);
+            }
+            ASTExpressionKind::String(string) => {
+                self.visit_string_expression(string);
+            }
             ASTExpressionKind::Binary(expr) => {
                 self.visit_binary_expression(expr);
             }
@@ -130,6 +168,10 @@ pub trait ASTVisitor<'a> {
 
     fn visit_number_expression(&mut self, number: &ASTNumberExpression);
 
+    fn visit_float_expression(&mut self, float: &ASTFloatExpression);
+
+    fn visit_string_expression(&mut self, string: &ASTStringExpression);
+
     fn visit_boolean_expression(&mut self, boolean: &ASTBooleanExpression);
 
     fn visit_error(&mut self, span: &TextSpan);
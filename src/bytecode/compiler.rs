@@ -0,0 +1,440 @@
+use std::collections::HashMap;
+
+use crate::ast::{
+    Ast,
+    ASTAssignmentExpression,
+    ASTBinaryExpression,
+    ASTBinaryOperatorKind,
+    ASTBlockStatement,
+    ASTBooleanExpression,
+    ASTBreakStatement,
+    ASTCallExpression,
+    ASTContinueStatement,
+    ASTExpression,
+    ASTFloatExpression,
+    ASTForStatement,
+    ASTFuncDeclStatement,
+    ASTIfStatement,
+    ASTLetStatement,
+    ASTNumberExpression,
+    ASTReturnStatement,
+    ASTStatement,
+    ASTStringExpression,
+    ASTUnaryExpression,
+    ASTVariableExpression,
+    ASTWhileStatement,
+};
+use crate::ast::evaluator::Value;
+use crate::ast::lexer::TextSpan;
+use crate::ast::visitor::ASTVisitor;
+use crate::bytecode::{ Function, Instruction, Program };
+use crate::compilation_unit::{ FunctionBody, GlobalScope, ParameterSymbol };
+
+const TOP_LEVEL_NAME: &str = "<top-level>";
+
+enum Variable {
+    Local(usize),
+    Global(usize),
+}
+
+// Jumps out of the innermost loop, patched once the loop's labels are known
+struct LoopJumps {
+    breaks: Vec<usize>,
+    continues: Vec<usize>,
+}
+
+// Lowers a resolved and type-checked Ast; top-level lets become globals, everything else a slot
+pub struct BytecodeCompiler<'a> {
+    global_scope: &'a GlobalScope,
+    functions: Vec<Function>,
+    function_indices: HashMap<String, usize>,
+    globals: Vec<String>,
+    global_indices: HashMap<String, usize>,
+    current: usize,
+    // Local scopes of the function being compiled; empty at the top level
+    scopes: Vec<HashMap<String, usize>>,
+    loops: Vec<LoopJumps>,
+    // Span of the innermost expression being compiled
+    span: TextSpan,
+}
+
+impl<'a> BytecodeCompiler<'a> {
+    pub fn compile(ast: &Ast, global_scope: &'a GlobalScope) -> Program {
+        let mut compiler = BytecodeCompiler {
+            global_scope,
+            functions: vec![Function::new(TOP_LEVEL_NAME.to_string(), 0)],
+            function_indices: HashMap::new(),
+            globals: Vec::new(),
+            global_indices: HashMap::new(),
+            current: 0,
+            scopes: Vec::new(),
+            loops: Vec::new(),
+            span: TextSpan::new(0, 0, String::new()),
+        };
+
+        // Sorted so that the same program always compiles to the same bytecode
+        let mut declared: Vec<(&String, &Vec<ParameterSymbol>, &ASTStatement)> = global_scope.functions
+            .iter()
+            .filter_map(|(name, function)| {
+                match &function.body {
+                    FunctionBody::Declared(body) => Some((name, &function.parameters, &**body)),
+                    FunctionBody::Intrinsic(_) => None,
+                }
+            })
+            .collect();
+        declared.sort_by_key(|(name, _, _)| *name);
+        for (name, parameters, _) in &declared {
+            compiler.function_indices.insert(name.to_string(), compiler.functions.len());
+            compiler.functions.push(Function::new(name.to_string(), parameters.len()));
+        }
+
+        for statement in &ast.statements {
+            compiler.visit_statement(statement);
+        }
+        compiler.emit_implicit_return();
+
+        for (name, parameters, body) in declared {
+            compiler.current = compiler.function_indices[name];
+            let parameter_slots = parameters
+                .iter()
+                .enumerate()
+                .map(|(slot, parameter)| (parameter.identifier.clone(), slot))
+                .collect();
+            compiler.scopes = vec![parameter_slots];
+            compiler.visit_statement(body);
+            compiler.emit_implicit_return();
+        }
+
+        let entry = compiler.function_indices.get("main").copied().unwrap_or(0);
+        Program { functions: compiler.functions, globals: compiler.globals, entry }
+    }
+
+    fn function(&mut self) -> &mut Function {
+        &mut self.functions[self.current]
+    }
+
+    fn emit(&mut self, instruction: Instruction) -> usize {
+        let span = self.span.clone();
+        self.emit_at(instruction, span)
+    }
+
+    fn emit_at(&mut self, instruction: Instruction, span: TextSpan) -> usize {
+        let function = self.function();
+        function.instructions.push(instruction);
+        function.spans.push(span);
+        function.instructions.len() - 1
+    }
+
+    fn emit_constant(&mut self, value: Value) {
+        let function = self.function();
+        function.constants.push(value);
+        let constant = function.constants.len() - 1;
+        self.emit(Instruction::Constant(constant));
+    }
+
+    fn emit_implicit_return(&mut self) {
+        self.emit_constant(Value::Unit);
+        self.emit(Instruction::Return);
+    }
+
+    fn next_offset(&mut self) -> usize {
+        self.function().instructions.len()
+    }
+
+    fn patch_jump(&mut self, jump: usize, target: usize) {
+        let instruction = &mut self.function().instructions[jump];
+        *instruction = match instruction {
+            Instruction::Jump(_) => Instruction::Jump(target),
+            Instruction::JumpIfFalse(_) => Instruction::JumpIfFalse(target),
+            Instruction::JumpIfTrue(_) => Instruction::JumpIfTrue(target),
+            _ => unreachable!("Only jumps can be patched"),
+        };
+    }
+
+    fn patch_jump_to_here(&mut self, jump: usize) {
+        let target = self.next_offset();
+        self.patch_jump(jump, target);
+    }
+
+    fn enter_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn exit_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    // Slots are never reused, so a function needs as many as it has declarations
+    fn allocate_local(&mut self) -> usize {
+        let function = self.function();
+        function.local_count += 1;
+        function.local_count - 1
+    }
+
+    fn declare_local(&mut self, identifier: &str) -> usize {
+        let slot = self.allocate_local();
+        self.scopes.last_mut().unwrap().insert(identifier.to_string(), slot);
+        slot
+    }
+
+    fn global(&mut self, identifier: &str) -> usize {
+        if let Some(index) = self.global_indices.get(identifier) {
+            return *index;
+        }
+        self.globals.push(identifier.to_string());
+        self.global_indices.insert(identifier.to_string(), self.globals.len() - 1);
+        self.globals.len() - 1
+    }
+
+    fn resolve(&mut self, identifier: &str) -> Variable {
+        let local = self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(identifier));
+        match local {
+            Some(slot) => Variable::Local(*slot),
+            None => Variable::Global(self.global(identifier)),
+        }
+    }
+
+    // Loop and branch bodies get their own scope even when they are not blocks
+    fn visit_scoped_statement(&mut self, statement: &ASTStatement) {
+        self.enter_scope();
+        self.visit_statement(statement);
+        self.exit_scope();
+    }
+
+    fn visit_loop_body(&mut self, body: &ASTStatement) -> LoopJumps {
+        self.loops.push(LoopJumps { breaks: Vec::new(), continues: Vec::new() });
+        self.visit_scoped_statement(body);
+        self.loops.pop().unwrap()
+    }
+
+    fn emit_loop_jump(&mut self, is_break: bool) {
+        let jump = self.emit(Instruction::Jump(0));
+        let loop_jumps = self.loops.last_mut().expect("The resolver rejects jumps outside of loops");
+        if is_break {
+            loop_jumps.breaks.push(jump);
+        } else {
+            loop_jumps.continues.push(jump);
+        }
+    }
+}
+
+impl ASTVisitor<'_> for BytecodeCompiler<'_> {
+    // Bodies are compiled from their function symbols once the top level is done
+    fn visit_func_decl_statement(&mut self, _func_decl_statement: &ASTFuncDeclStatement) {}
+
+    fn visit_expression_statement(&mut self, expression: &ASTExpression) {
+        self.visit_expression(expression);
+        self.emit(Instruction::Pop);
+    }
+
+    fn visit_return_statement(&mut self, return_statement: &ASTReturnStatement) {
+        match &return_statement.return_value {
+            Some(expression) => self.visit_expression(expression),
+            None => self.emit_constant(Value::Unit),
+        }
+        self.emit_at(Instruction::Return, return_statement.return_keyword.span.clone());
+    }
+
+    fn visit_break_statement(&mut self, _break_statement: &ASTBreakStatement) {
+        self.emit_loop_jump(true);
+    }
+
+    fn visit_continue_statement(&mut self, _continue_statement: &ASTContinueStatement) {
+        self.emit_loop_jump(false);
+    }
+
+    fn visit_while_statement(&mut self, while_statement: &ASTWhileStatement) {
+        let loop_start = self.next_offset();
+        self.visit_expression(&while_statement.condition);
+        let exit_jump = self.emit_at(Instruction::JumpIfFalse(0), while_statement.condition.span());
+        let loop_jumps = self.visit_loop_body(&while_statement.body);
+        self.emit(Instruction::Jump(loop_start));
+
+        self.patch_jump_to_here(exit_jump);
+        for jump in loop_jumps.breaks {
+            self.patch_jump_to_here(jump);
+        }
+        for jump in loop_jumps.continues {
+            self.patch_jump(jump, loop_start);
+        }
+    }
+
+    fn visit_for_statement(&mut self, for_statement: &ASTForStatement) {
+        self.enter_scope();
+        let counter = self.allocate_local();
+        let limit = self.allocate_local();
+        self.visit_expression(&for_statement.start);
+        self.emit_at(Instruction::ExpectInt, for_statement.start.span());
+        self.emit(Instruction::StoreLocal(counter));
+        self.visit_expression(&for_statement.end);
+        self.emit_at(Instruction::ExpectInt, for_statement.end.span());
+        self.emit(Instruction::StoreLocal(limit));
+        let variable = self.declare_local(&for_statement.identifier.span.literal);
+
+        let loop_start = self.next_offset();
+        let comparison = if for_statement.is_inclusive() {
+            ASTBinaryOperatorKind::LessThanOrEqual
+        } else {
+            ASTBinaryOperatorKind::LessThan
+        };
+        self.emit(Instruction::LoadLocal(counter));
+        self.emit(Instruction::LoadLocal(limit));
+        self.emit(Instruction::Binary(comparison));
+        let mut exit_jumps = vec![self.emit(Instruction::JumpIfFalse(0))];
+        self.emit(Instruction::LoadLocal(counter));
+        self.emit(Instruction::StoreLocal(variable));
+        let loop_jumps = self.visit_loop_body(&for_statement.body);
+
+        let increment = self.next_offset();
+        if for_statement.is_inclusive() {
+            self.emit(Instruction::LoadLocal(counter));
+            self.emit(Instruction::LoadLocal(limit));
+            self.emit(Instruction::Binary(ASTBinaryOperatorKind::Equals));
+            exit_jumps.push(self.emit(Instruction::JumpIfTrue(0)));
+        }
+        self.emit(Instruction::LoadLocal(counter));
+        self.emit_constant(Value::Int(1));
+        self.emit(Instruction::Binary(ASTBinaryOperatorKind::Plus));
+        self.emit(Instruction::StoreLocal(counter));
+        self.emit(Instruction::Jump(loop_start));
+
+        for jump in exit_jumps.into_iter().chain(loop_jumps.breaks) {
+            self.patch_jump_to_here(jump);
+        }
+        for jump in loop_jumps.continues {
+            self.patch_jump(jump, increment);
+        }
+        self.exit_scope();
+    }
+
+    fn visit_block_statement(&mut self, block_statement: &ASTBlockStatement) {
+        self.enter_scope();
+        for statement in &block_statement.statements {
+            self.visit_statement(statement);
+        }
+        self.exit_scope();
+    }
+
+    fn visit_if_statement(&mut self, if_statement: &ASTIfStatement) {
+        self.visit_expression(&if_statement.condition);
+        let else_jump = self.emit_at(Instruction::JumpIfFalse(0), if_statement.condition.span());
+        self.visit_scoped_statement(&if_statement.then_branch);
+        match &if_statement.else_branch {
+            Some(else_branch) => {
+                let end_jump = self.emit(Instruction::Jump(0));
+                self.patch_jump_to_here(else_jump);
+                self.visit_scoped_statement(&else_branch.else_statement);
+                self.patch_jump_to_here(end_jump);
+            }
+            None => self.patch_jump_to_here(else_jump),
+        }
+    }
+
+    fn visit_let_statement(&mut self, let_statement: &ASTLetStatement) {
+        self.visit_expression(&let_statement.initializer);
+        let identifier = &let_statement.identifier.span.literal;
+        if self.scopes.is_empty() {
+            let global = self.global(identifier);
+            self.emit(Instruction::DefineGlobal(global));
+        } else {
+            let slot = self.declare_local(identifier);
+            self.emit(Instruction::StoreLocal(slot));
+        }
+    }
+
+    fn visit_expression(&mut self, expression: &ASTExpression) {
+        let span = std::mem::replace(&mut self.span, expression.span());
+        self.do_visit_expression(expression);
+        self.span = span;
+    }
+
+    fn visit_call_expression(&mut self, call_expression: &ASTCallExpression) {
+        for argument in &call_expression.arguments {
+            self.visit_expression(argument);
+        }
+        let identifier = &call_expression.identifier.span.literal;
+        let function = self.global_scope
+            .lookup_function(identifier)
+            .expect("The resolver rejects calls to undeclared functions");
+        match &function.body {
+            FunctionBody::Declared(_) => {
+                let callee = self.function_indices[identifier];
+                self.emit(Instruction::Call(callee));
+            }
+            FunctionBody::Intrinsic(intrinsic) => {
+                self.emit(Instruction::CallIntrinsic(*intrinsic));
+            }
+        }
+    }
+
+    fn visit_assignment_expression(&mut self, assignment_expression: &ASTAssignmentExpression) {
+        self.visit_expression(&assignment_expression.expression);
+        self.emit(Instruction::Duplicate);
+        let identifier = &assignment_expression.identifier.span;
+        let instruction = match self.resolve(&identifier.literal) {
+            Variable::Local(slot) => Instruction::StoreLocal(slot),
+            Variable::Global(global) => Instruction::StoreGlobal(global),
+        };
+        self.emit_at(instruction, identifier.clone());
+    }
+
+    fn visit_variable_expression(&mut self, variable_expression: &ASTVariableExpression) {
+        let instruction = match self.resolve(variable_expression.identifier()) {
+            Variable::Local(slot) => Instruction::LoadLocal(slot),
+            Variable::Global(global) => Instruction::LoadGlobal(global),
+        };
+        self.emit(instruction);
+    }
+
+    fn visit_number_expression(&mut self, number: &ASTNumberExpression) {
+        self.emit_constant(Value::Int(number.number));
+    }
+
+    fn visit_float_expression(&mut self, float: &ASTFloatExpression) {
+        self.emit_constant(Value::Float(float.number));
+    }
+
+    fn visit_string_expression(&mut self, string: &ASTStringExpression) {
+        self.emit_constant(Value::String(string.value.clone()));
+    }
+
+    fn visit_boolean_expression(&mut self, boolean: &ASTBooleanExpression) {
+        self.emit_constant(Value::Bool(boolean.value));
+    }
+
+    fn visit_error(&mut self, _span: &TextSpan) {
+        unreachable!("Programs with errors are never compiled")
+    }
+
+    fn visit_unary_expression(&mut self, unary_expression: &ASTUnaryExpression) {
+        self.visit_expression(&unary_expression.operand);
+        self.emit(Instruction::Unary(unary_expression.operator.kind));
+    }
+
+    fn visit_binary_expression(&mut self, binary_expression: &ASTBinaryExpression) {
+        self.visit_expression(&binary_expression.left);
+        // `&&` and `||` keep the left operand when it decides the result
+        let short_circuit = match binary_expression.operator.kind {
+            ASTBinaryOperatorKind::LogicalAnd => Some(Instruction::JumpIfFalse(0)),
+            ASTBinaryOperatorKind::LogicalOr => Some(Instruction::JumpIfTrue(0)),
+            _ => None,
+        };
+        match short_circuit {
+            Some(jump) => {
+                self.emit(Instruction::Duplicate);
+                let end_jump = self.emit_at(jump, binary_expression.left.span());
+                self.emit(Instruction::Pop);
+                self.visit_expression(&binary_expression.right);
+                self.emit_at(Instruction::ExpectBool, binary_expression.right.span());
+                self.patch_jump_to_here(end_jump);
+            }
+            None => {
+                self.visit_expression(&binary_expression.right);
+                self.emit(Instruction::Binary(binary_expression.operator.kind));
+            }
+        }
+    }
+}
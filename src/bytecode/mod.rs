@@ -0,0 +1,178 @@
+use std::fmt::{ Display, Formatter };
+
+use crate::ast::{ ASTBinaryOperatorKind, ASTUnaryOperatorKind };
+use crate::ast::evaluator::Value;
+use crate::ast::lexer::TextSpan;
+use crate::intrinsics::Intrinsic;
+
+pub mod compiler;
+pub mod vm;
+
+// Stack machine instructions; jump targets are indices into the function's instructions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instruction {
+    // Pushes a value from the function's constant pool
+    Constant(usize),
+    Pop,
+    Duplicate,
+    LoadLocal(usize),
+    StoreLocal(usize),
+    LoadGlobal(usize),
+    StoreGlobal(usize),
+    // Like StoreGlobal, but also allowed before the global has a value
+    DefineGlobal(usize),
+    Unary(ASTUnaryOperatorKind),
+    Binary(ASTBinaryOperatorKind),
+    Jump(usize),
+    // Both conditional jumps pop the condition, which must be a bool
+    JumpIfFalse(usize),
+    JumpIfTrue(usize),
+    // Fail unless the value on top of the stack has the type, leaving it in place
+    ExpectInt,
+    ExpectBool,
+    Call(usize),
+    CallIntrinsic(Intrinsic),
+    Return,
+}
+
+pub struct Function {
+    pub name: String,
+    pub arity: usize,
+    // Parameters take the first slots
+    pub local_count: usize,
+    pub constants: Vec<Value>,
+    pub instructions: Vec<Instruction>,
+    // Source span of each instruction, used for runtime errors
+    pub spans: Vec<TextSpan>,
+}
+
+impl Function {
+    fn new(name: String, arity: usize) -> Self {
+        Function {
+            name,
+            arity,
+            local_count: arity,
+            constants: Vec::new(),
+            instructions: Vec::new(),
+            spans: Vec::new(),
+        }
+    }
+}
+
+pub struct Program {
+    pub functions: Vec<Function>,
+    // Names of the top-level variables, indexed by global slot
+    pub globals: Vec<String>,
+    // Either the top-level statements or `main` when the program declares one
+    pub entry: usize,
+}
+
+impl Display for Program {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (index, function) in self.functions.iter().enumerate() {
+            writeln!(
+                f,
+                "fn #{} {} (arity {}, locals {})",
+                index,
+                function.name,
+                function.arity,
+                function.local_count
+            )?;
+            for (offset, instruction) in function.instructions.iter().enumerate() {
+                match instruction {
+                    Instruction::Constant(constant) => {
+                        writeln!(f, "{:>4} Constant {:?}", offset, function.constants[*constant])?;
+                    }
+                    Instruction::LoadGlobal(global) |
+                    Instruction::StoreGlobal(global) |
+                    Instruction::DefineGlobal(global) => {
+                        writeln!(f, "{:>4} {:?} '{}'", offset, instruction, self.globals[*global])?;
+                    }
+                    Instruction::Call(callee) => {
+                        writeln!(f, "{:>4} Call '{}'", offset, self.functions[*callee].name)?;
+                    }
+                    _ => writeln!(f, "{:>4} {:?}", offset, instruction)?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ast::evaluator::ASTEvaluator;
+    use crate::bytecode::{ Function, Instruction, Program };
+    use crate::bytecode::vm::VirtualMachine;
+    use crate::compilation_unit::CompilationUnit;
+
+    fn compile(input: &str) -> Program {
+        CompilationUnit::compile(input).expect("Failed to compile").compile_bytecode()
+    }
+
+    fn function<'a>(program: &'a Program, name: &str) -> &'a Function {
+        program.functions
+            .iter()
+            .find(|function| function.name == name)
+            .expect("Function was not compiled")
+    }
+
+    #[test]
+    fn should_keep_function_variables_in_local_slots() {
+        let program = compile(
+            "\
+        let total = 0
+        func sum(n: int) -> int {
+            let acc = 0
+            for i in 0..n {
+                acc = acc + i
+            }
+            return acc
+        }
+        total = sum(4)
+        "
+        );
+
+        assert_eq!(program.globals, vec!["total".to_string()]);
+        let sum = function(&program, "sum");
+        assert_eq!(sum.arity, 1);
+        // n, acc, the hidden counter and limit, and i
+        assert_eq!(sum.local_count, 5);
+        assert!(
+            !sum.instructions
+                .iter()
+                .any(|instruction| matches!(instruction, Instruction::LoadGlobal(_)))
+        );
+    }
+
+    #[test]
+    fn should_start_at_main_when_declared() {
+        let program = compile(
+            "\
+        func main {
+            println(1)
+        }
+        "
+        );
+
+        assert_eq!(program.functions[program.entry].name, "main");
+    }
+
+    #[test]
+    fn should_run_showcase_like_the_evaluator() {
+        let input = include_str!("../../examples/showcase.tn");
+        let compilation_unit = CompilationUnit::compile(input).expect("Failed to compile");
+
+        let mut expected = Vec::new();
+        let mut eval = ASTEvaluator::new(&compilation_unit.global_scope, &mut expected);
+        compilation_unit.execute(&mut eval);
+        let expected_exit_code = eval.exit_code();
+
+        let program = compilation_unit.compile_bytecode();
+        let mut output = Vec::new();
+        let exit_code = VirtualMachine::new(&program, &mut output).run();
+
+        assert_eq!(exit_code, Ok(expected_exit_code));
+        assert_eq!(output, expected);
+    }
+}
@@ -0,0 +1,202 @@
+use std::io::Write;
+
+use crate::ast::evaluator::{
+    binary_operation,
+    unary_operation,
+    RuntimeError,
+    TraceFrame,
+    Value,
+    MAX_CALL_DEPTH,
+};
+use crate::bytecode::{ Instruction, Program };
+use crate::intrinsics::IntrinsicOutcome;
+
+struct CallFrame {
+    function: usize,
+    ip: usize,
+    // Index of the frame's first local in the shared slot stack
+    base: usize,
+}
+
+pub struct VirtualMachine<'a> {
+    program: &'a Program,
+    stack: Vec<Value>,
+    slots: Vec<Value>,
+    frames: Vec<CallFrame>,
+    // Top-level variables have no value until their `let` has run
+    globals: Vec<Option<Value>>,
+    output: &'a mut dyn Write,
+}
+
+impl<'a> VirtualMachine<'a> {
+    pub fn new(program: &'a Program, output: &'a mut dyn Write) -> Self {
+        VirtualMachine {
+            program,
+            stack: Vec::new(),
+            slots: Vec::new(),
+            frames: Vec::new(),
+            globals: vec![None; program.globals.len()],
+            output,
+        }
+    }
+
+    // Runs the program's entry point and returns its exit code
+    pub fn run(&mut self) -> Result<i64, RuntimeError> {
+        assert_eq!(
+            self.program.functions[self.program.entry].arity,
+            0,
+            "The resolver rejects a `main` with parameters"
+        );
+        self.push_frame(self.program.entry);
+        let result = self.execute();
+        self.output.flush().ok();
+        result
+    }
+
+    // The arguments are already on the stack; locals declared with `let` start as unit until
+    // their `let` runs
+    fn push_frame(&mut self, function: usize) {
+        let callee = &self.program.functions[function];
+        let base = self.stack.len() - callee.arity;
+        let slots_base = self.slots.len();
+        self.slots.extend(self.stack.drain(base..));
+        self.slots.resize(slots_base + callee.local_count, Value::Unit);
+        self.frames.push(CallFrame { function, ip: 0, base: slots_base });
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("The compiler keeps the operand stack balanced")
+    }
+
+    fn execute(&mut self) -> Result<i64, RuntimeError> {
+        let program = self.program;
+        loop {
+            let frame = self.frames.last_mut().unwrap();
+            let function = &program.functions[frame.function];
+            let instruction = function.instructions[frame.ip];
+            frame.ip += 1;
+            let base = frame.base;
+
+            match instruction {
+                Instruction::Constant(constant) => {
+                    self.stack.push(function.constants[constant].clone());
+                }
+                Instruction::Pop => {
+                    self.pop();
+                }
+                Instruction::Duplicate => {
+                    let value = self.stack.last().unwrap().clone();
+                    self.stack.push(value);
+                }
+                Instruction::LoadLocal(slot) => {
+                    self.stack.push(self.slots[base + slot].clone());
+                }
+                Instruction::StoreLocal(slot) => {
+                    self.slots[base + slot] = self.pop();
+                }
+                Instruction::LoadGlobal(global) => {
+                    match &self.globals[global] {
+                        Some(value) => self.stack.push(value.clone()),
+                        None => {
+                            return Err(self.undefined_global(global));
+                        }
+                    }
+                }
+                Instruction::StoreGlobal(global) => {
+                    if self.globals[global].is_none() {
+                        return Err(self.undefined_global(global));
+                    }
+                    self.globals[global] = Some(self.pop());
+                }
+                Instruction::DefineGlobal(global) => {
+                    self.globals[global] = Some(self.pop());
+                }
+                Instruction::Unary(operator) => {
+                    let operand = self.pop();
+                    let value = unary_operation(operator, operand).map_err(|message| {
+                        self.error(message)
+                    })?;
+                    self.stack.push(value);
+                }
+                Instruction::Binary(operator) => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    let value = binary_operation(operator, left, right).map_err(|message| {
+                        self.error(message)
+                    })?;
+                    self.stack.push(value);
+                }
+                Instruction::Jump(target) => {
+                    self.frames.last_mut().unwrap().ip = target;
+                }
+                Instruction::JumpIfFalse(target) => {
+                    if !self.pop().as_bool().map_err(|message| self.error(message))? {
+                        self.frames.last_mut().unwrap().ip = target;
+                    }
+                }
+                Instruction::JumpIfTrue(target) => {
+                    if self.pop().as_bool().map_err(|message| self.error(message))? {
+                        self.frames.last_mut().unwrap().ip = target;
+                    }
+                }
+                Instruction::ExpectInt => {
+                    self.stack.last().unwrap().as_int().map_err(|message| self.error(message))?;
+                }
+                Instruction::ExpectBool => {
+                    self.stack.last().unwrap().as_bool().map_err(|message| self.error(message))?;
+                }
+                Instruction::Call(callee) => {
+                    // The entry point is not a call
+                    if self.frames.len() > MAX_CALL_DEPTH {
+                        return Err(self.error("Stack overflow".to_string()));
+                    }
+                    self.push_frame(callee);
+                }
+                Instruction::CallIntrinsic(intrinsic) => {
+                    let arguments = self.stack.split_off(
+                        self.stack.len() - intrinsic.parameters().len()
+                    );
+                    match intrinsic.call(&arguments, self.output) {
+                        IntrinsicOutcome::Value(value) => self.stack.push(value),
+                        IntrinsicOutcome::Exit(code) => {
+                            return Ok(code);
+                        }
+                        IntrinsicOutcome::Failure(message) => {
+                            return Err(self.error(message));
+                        }
+                    }
+                }
+                Instruction::Return => {
+                    let value = self.pop();
+                    let frame = self.frames.pop().unwrap();
+                    self.slots.truncate(frame.base);
+                    // Returning from the entry point ends the program
+                    if self.frames.is_empty() {
+                        return Ok(0);
+                    }
+                    self.stack.push(value);
+                }
+            }
+        }
+    }
+
+    fn undefined_global(&self, global: usize) -> RuntimeError {
+        self.error(format!("Variable '{}' is not defined yet", self.program.globals[global]))
+    }
+
+    // Points at the instruction that was just executed, with one trace entry per active call
+    fn error(&self, message: String) -> RuntimeError {
+        let current_span = |frame: &CallFrame| {
+            self.program.functions[frame.function].spans[frame.ip - 1].clone()
+        };
+        let trace = self.frames
+            .windows(2)
+            .rev()
+            .map(|frames| TraceFrame {
+                function: self.program.functions[frames[1].function].name.clone(),
+                call_site: current_span(&frames[0]),
+            })
+            .collect();
+        RuntimeError { message, span: current_span(self.frames.last().unwrap()), trace }
+    }
+}
@@ -0,0 +1,1085 @@
+use std::collections::{ BTreeSet, HashMap };
+
+use crate::ast::{
+    Ast,
+    ASTAssignmentExpression,
+    ASTBinaryExpression,
+    ASTBinaryOperatorKind,
+    ASTBlockStatement,
+    ASTBooleanExpression,
+    ASTBreakStatement,
+    ASTCallExpression,
+    ASTContinueStatement,
+    ASTExpression,
+    ASTFloatExpression,
+    ASTForStatement,
+    ASTFuncDeclStatement,
+    ASTIfStatement,
+    ASTLetStatement,
+    ASTNumberExpression,
+    ASTReturnStatement,
+    ASTStatement,
+    ASTStringExpression,
+    ASTUnaryExpression,
+    ASTUnaryOperatorKind,
+    ASTVariableExpression,
+    ASTWhileStatement,
+};
+use crate::ast::evaluator::MAX_CALL_DEPTH;
+use crate::ast::lexer::TextSpan;
+use crate::ast::visitor::ASTVisitor;
+use crate::compilation_unit::{ FunctionBody, GlobalScope };
+use crate::intrinsics::Intrinsic;
+
+// Dynamically typed values and the operator semantics of `evaluator::binary_operation`, so
+// that the translation behaves exactly like the interpreter
+const RUNTIME: &str =
+    r#"#include <math.h>
+#include <stdarg.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+/* Zero-initialized globals are undefined until their `let` runs */
+typedef enum { TN_UNDEFINED, TN_INT, TN_FLOAT, TN_BOOL, TN_STRING, TN_UNIT } tn_tag;
+
+typedef struct {
+    tn_tag tag;
+    union {
+        int64_t i;
+        double f;
+        int b;
+        struct { const char *data; size_t length; } s;
+    } as;
+} tn_value;
+
+typedef enum {
+    TN_PLUS, TN_MINUS, TN_MULTIPLY, TN_DIVIDE, TN_POWER,
+    TN_BITWISEAND, TN_BITWISEOR, TN_BITWISEXOR,
+    TN_EQUALS, TN_NOTEQUALS,
+    TN_LESSTHAN, TN_LESSTHANOREQUAL, TN_GREATERTHAN, TN_GREATERTHANOREQUAL
+} tn_operator;
+
+static const char *tn_symbols[] = {
+    "+", "-", "*", "/", "**", "&", "|", "^", "==", "!=", "<", "<=", ">", ">="
+};
+
+static void tn_runtime_error(const char *format, ...) {
+    va_list arguments;
+    fflush(stdout);
+    fputs("Runtime error: ", stderr);
+    va_start(arguments, format);
+    vfprintf(stderr, format, arguments);
+    va_end(arguments);
+    fputc('\n', stderr);
+    exit(1);
+}
+
+/* Calls nest at most TN_MAX_CALL_DEPTH deep, like in the interpreter */
+static int64_t tn_call_depth;
+
+static void tn_enter(void) {
+    if (tn_call_depth == TN_MAX_CALL_DEPTH) {
+        tn_runtime_error("Stack overflow");
+    }
+    tn_call_depth++;
+}
+
+static tn_value tn_leave(tn_value value) {
+    tn_call_depth--;
+    return value;
+}
+
+static tn_value tn_int(int64_t value) {
+    tn_value result;
+    result.tag = TN_INT;
+    result.as.i = value;
+    return result;
+}
+
+static tn_value tn_float(double value) {
+    tn_value result;
+    result.tag = TN_FLOAT;
+    result.as.f = value;
+    return result;
+}
+
+static tn_value tn_bool(int value) {
+    tn_value result;
+    result.tag = TN_BOOL;
+    result.as.b = value != 0;
+    return result;
+}
+
+static tn_value tn_string(const char *data, size_t length) {
+    tn_value result;
+    result.tag = TN_STRING;
+    result.as.s.data = data;
+    result.as.s.length = length;
+    return result;
+}
+
+static tn_value tn_unit(void) {
+    tn_value result;
+    result.tag = TN_UNIT;
+    result.as.i = 0;
+    return result;
+}
+
+static void tn_format_float(double value, char *buffer, size_t capacity) {
+    char scientific[40];
+    char digits[24];
+    size_t digit_count = 0;
+    int exponent;
+    int precision;
+    const char *cursor;
+    double magnitude = fabs(value);
+    size_t length = 0;
+
+    if (value != value) {
+        snprintf(buffer, capacity, "NaN");
+        return;
+    }
+    if (magnitude == HUGE_VAL) {
+        snprintf(buffer, capacity, value < 0 ? "-inf" : "inf");
+        return;
+    }
+    if (value == 0) {
+        snprintf(buffer, capacity, 1 / value < 0 ? "-0.0" : "0.0");
+        return;
+    }
+    /* The fewest significant digits that read back as the same value, like Rust prints */
+    for (precision = 0; precision < 16; precision++) {
+        snprintf(scientific, sizeof scientific, "%.*e", precision, value);
+        if (strtod(scientific, NULL) == value) {
+            break;
+        }
+    }
+    snprintf(scientific, sizeof scientific, "%.*e", precision, value);
+    for (cursor = scientific; *cursor != 'e'; cursor++) {
+        if (*cursor >= '0' && *cursor <= '9') {
+            digits[digit_count++] = *cursor;
+        }
+    }
+    exponent = atoi(cursor + 1);
+    while (digit_count > 1 && digits[digit_count - 1] == '0') {
+        digit_count--;
+    }
+
+    if (value < 0) {
+        buffer[length++] = '-';
+    }
+    if (magnitude >= 1e-4 && magnitude < 1e16) {
+        if (exponent < 0) {
+            int zero;
+            buffer[length++] = '0';
+            buffer[length++] = '.';
+            for (zero = 0; zero < -exponent - 1; zero++) {
+                buffer[length++] = '0';
+            }
+            memcpy(buffer + length, digits, digit_count);
+            length += digit_count;
+        } else {
+            size_t index;
+            for (index = 0; index <= (size_t) exponent; index++) {
+                buffer[length++] = index < digit_count ? digits[index] : '0';
+            }
+            buffer[length++] = '.';
+            if (digit_count > (size_t) exponent + 1) {
+                memcpy(buffer + length, digits + exponent + 1, digit_count - exponent - 1);
+                length += digit_count - exponent - 1;
+            } else {
+                buffer[length++] = '0';
+            }
+        }
+        buffer[length] = '\0';
+    } else {
+        buffer[length++] = digits[0];
+        if (digit_count > 1) {
+            buffer[length++] = '.';
+            memcpy(buffer + length, digits + 1, digit_count - 1);
+            length += digit_count - 1;
+        }
+        snprintf(buffer + length, capacity - length, "e%d", exponent);
+    }
+}
+
+static tn_value tn_to_string(tn_value value) {
+    char buffer[64];
+    char *data;
+    size_t length;
+    switch (value.tag) {
+        case TN_STRING:
+            return value;
+        case TN_INT:
+            snprintf(buffer, sizeof buffer, "%lld", (long long) value.as.i);
+            break;
+        case TN_FLOAT:
+            tn_format_float(value.as.f, buffer, sizeof buffer);
+            break;
+        case TN_BOOL:
+            snprintf(buffer, sizeof buffer, value.as.b ? "true" : "false");
+            break;
+        default:
+            snprintf(buffer, sizeof buffer, "()");
+            break;
+    }
+    length = strlen(buffer);
+    data = malloc(length + 1);
+    memcpy(data, buffer, length + 1);
+    return tn_string(data, length);
+}
+
+static const char *tn_type_name(tn_value value) {
+    switch (value.tag) {
+        case TN_INT: return "int";
+        case TN_FLOAT: return "float";
+        case TN_BOOL: return "bool";
+        case TN_STRING: return "string";
+        default: return "unit";
+    }
+}
+
+static int64_t tn_as_int(tn_value value) {
+    if (value.tag != TN_INT) {
+        tn_runtime_error("Expected an int, found %s", tn_type_name(value));
+    }
+    return value.as.i;
+}
+
+static double tn_as_float(tn_value value) {
+    if (value.tag != TN_FLOAT) {
+        tn_runtime_error("Expected a float, found %s", tn_type_name(value));
+    }
+    return value.as.f;
+}
+
+static int tn_as_bool(tn_value value) {
+    if (value.tag != TN_BOOL) {
+        tn_runtime_error("Expected a bool, found %s", tn_type_name(value));
+    }
+    return value.as.b;
+}
+
+static tn_value tn_defined(tn_value value, const char *name) {
+    if (value.tag == TN_UNDEFINED) {
+        tn_runtime_error("Variable '%s' is not defined yet", name);
+    }
+    return value;
+}
+
+static void tn_assign(tn_value *variable, tn_value value, const char *name) {
+    tn_defined(*variable, name);
+    *variable = value;
+}
+
+static int tn_equals(tn_value left, tn_value right) {
+    if (left.tag != right.tag) {
+        return 0;
+    }
+    switch (left.tag) {
+        case TN_INT: return left.as.i == right.as.i;
+        case TN_FLOAT: return left.as.f == right.as.f;
+        case TN_BOOL: return left.as.b == right.as.b;
+        case TN_STRING:
+            return left.as.s.length == right.as.s.length &&
+                memcmp(left.as.s.data, right.as.s.data, left.as.s.length) == 0;
+        default: return 1;
+    }
+}
+
+static tn_value tn_concat(tn_value left, tn_value right) {
+    size_t length = left.as.s.length + right.as.s.length;
+    char *data = malloc(length + 1);
+    memcpy(data, left.as.s.data, left.as.s.length);
+    memcpy(data + left.as.s.length, right.as.s.data, right.as.s.length);
+    data[length] = '\0';
+    return tn_string(data, length);
+}
+
+static int tn_multiply_overflows(int64_t left, int64_t right) {
+    if (left == 0 || right == 0) {
+        return 0;
+    }
+    if (left > 0) {
+        return right > 0 ? left > INT64_MAX / right : right < INT64_MIN / left;
+    }
+    return right > 0 ? left < INT64_MIN / right : left < INT64_MAX / right;
+}
+
+static tn_value tn_int_binary(tn_operator operator, int64_t left, int64_t right) {
+    switch (operator) {
+        case TN_PLUS:
+            if ((right > 0 && left > INT64_MAX - right) || (right < 0 && left < INT64_MIN - right)) {
+                break;
+            }
+            return tn_int(left + right);
+        case TN_MINUS:
+            if ((right < 0 && left > INT64_MAX + right) || (right > 0 && left < INT64_MIN + right)) {
+                break;
+            }
+            return tn_int(left - right);
+        case TN_MULTIPLY:
+            if (tn_multiply_overflows(left, right)) {
+                break;
+            }
+            return tn_int(left * right);
+        case TN_DIVIDE:
+            if (right == 0) {
+                tn_runtime_error("Division by zero");
+            }
+            if (left == INT64_MIN && right == -1) {
+                break;
+            }
+            return tn_int(left / right);
+        case TN_POWER: {
+            /* Overflows exactly where i64::checked_pow does */
+            int64_t result = 1;
+            if (right < 0) {
+                tn_runtime_error("Negative exponent %lld in integer power", (long long) right);
+            }
+            if (right > 0xFFFFFFFFLL) {
+                break;
+            }
+            if (right == 0) {
+                return tn_int(1);
+            }
+            for (;;) {
+                if (right & 1) {
+                    if (tn_multiply_overflows(result, left)) {
+                        break;
+                    }
+                    result *= left;
+                    if (right == 1) {
+                        return tn_int(result);
+                    }
+                }
+                right /= 2;
+                if (tn_multiply_overflows(left, left)) {
+                    break;
+                }
+                left *= left;
+            }
+            break;
+        }
+        case TN_BITWISEAND: return tn_int(left & right);
+        case TN_BITWISEOR: return tn_int(left | right);
+        case TN_BITWISEXOR: return tn_int(left ^ right);
+        case TN_EQUALS: return tn_bool(left == right);
+        case TN_NOTEQUALS: return tn_bool(left != right);
+        case TN_LESSTHAN: return tn_bool(left < right);
+        case TN_LESSTHANOREQUAL: return tn_bool(left <= right);
+        case TN_GREATERTHAN: return tn_bool(left > right);
+        case TN_GREATERTHANOREQUAL: return tn_bool(left >= right);
+    }
+    tn_runtime_error("Integer overflow in '%s'", tn_symbols[operator]);
+    return tn_unit();
+}
+
+static tn_value tn_float_binary(tn_operator operator, double left, double right) {
+    switch (operator) {
+        case TN_PLUS: return tn_float(left + right);
+        case TN_MINUS: return tn_float(left - right);
+        case TN_MULTIPLY: return tn_float(left * right);
+        case TN_DIVIDE: return tn_float(left / right);
+        case TN_POWER: return tn_float(pow(left, right));
+        case TN_EQUALS: return tn_bool(left == right);
+        case TN_NOTEQUALS: return tn_bool(left != right);
+        case TN_LESSTHAN: return tn_bool(left < right);
+        case TN_LESSTHANOREQUAL: return tn_bool(left <= right);
+        case TN_GREATERTHAN: return tn_bool(left > right);
+        case TN_GREATERTHANOREQUAL: return tn_bool(left >= right);
+        default:
+            tn_runtime_error("Bitwise operators are not defined for floats");
+            return tn_unit();
+    }
+}
+
+static tn_value tn_binary(tn_operator operator, tn_value left, tn_value right) {
+    if (operator == TN_EQUALS) {
+        return tn_bool(tn_equals(left, right));
+    }
+    if (operator == TN_NOTEQUALS) {
+        return tn_bool(!tn_equals(left, right));
+    }
+    if (operator == TN_PLUS && left.tag == TN_STRING && right.tag == TN_STRING) {
+        return tn_concat(left, right);
+    }
+    if (left.tag == TN_FLOAT && right.tag == TN_FLOAT) {
+        return tn_float_binary(operator, left.as.f, right.as.f);
+    }
+    return tn_int_binary(operator, tn_as_int(left), tn_as_int(right));
+}
+
+static tn_value tn_negate(tn_value operand) {
+    if (operand.tag == TN_FLOAT) {
+        return tn_float(-operand.as.f);
+    }
+    if (tn_as_int(operand) == INT64_MIN) {
+        tn_runtime_error("Integer overflow in '-'");
+    }
+    return tn_int(-operand.as.i);
+}
+
+static tn_value tn_bitwise_not(tn_value operand) {
+    return tn_int(~tn_as_int(operand));
+}
+
+static tn_value tn_logical_not(tn_value operand) {
+    return tn_bool(!tn_as_bool(operand));
+}
+
+static tn_value tn_print(tn_value value) {
+    tn_value string = tn_to_string(value);
+    fwrite(string.as.s.data, 1, string.as.s.length, stdout);
+    return tn_unit();
+}
+
+static tn_value tn_println(tn_value value) {
+    tn_print(value);
+    fputc('\n', stdout);
+    return tn_unit();
+}
+
+static tn_value tn_assert(tn_value condition) {
+    if (!tn_as_bool(condition)) {
+        tn_runtime_error("Assertion failed");
+    }
+    return tn_unit();
+}
+
+static tn_value tn_exit(tn_value code) {
+    exit((int) tn_as_int(code));
+    return tn_unit();
+}
+
+static tn_value tn_to_float(tn_value value) {
+    return tn_float((double) tn_as_int(value));
+}
+
+/* Saturates like Rust's `as i64` */
+static tn_value tn_to_int(tn_value value) {
+    double number = tn_as_float(value);
+    if (number != number) {
+        return tn_int(0);
+    }
+    if (number >= 9223372036854775807.0) {
+        return tn_int(INT64_MAX);
+    }
+    if (number <= -9223372036854775808.0) {
+        return tn_int(INT64_MIN);
+    }
+    return tn_int((int64_t) number);
+}
+"#;
+
+// Translates a checked Ast into a single C99 file. Values stay dynamically typed like in the
+// interpreter, and expressions are split into temporaries because C leaves the evaluation order
+// of operands and arguments unspecified.
+pub struct CBackend<'a> {
+    global_scope: &'a GlobalScope,
+    // Body of the C function being generated
+    code: String,
+    indent: usize,
+    functions: Vec<String>,
+    prototypes: Vec<String>,
+    // C names of the locals in scope; every declaration gets a fresh name, so shadowing a
+    // variable never refers to the one being declared
+    scopes: Vec<HashMap<String, String>>,
+    globals: BTreeSet<String>,
+    is_in_function: bool,
+    // Labels that `continue` jumps to, innermost loop last, and whether any jump uses them
+    loops: Vec<(String, bool)>,
+    name_count: usize,
+    // C expression holding the value of the last generated expression
+    last_value: String,
+}
+
+impl<'a> CBackend<'a> {
+    pub fn generate(ast: &Ast, global_scope: &'a GlobalScope) -> String {
+        let mut backend = CBackend {
+            global_scope,
+            code: String::new(),
+            indent: 1,
+            functions: Vec::new(),
+            prototypes: Vec::new(),
+            scopes: Vec::new(),
+            globals: BTreeSet::new(),
+            is_in_function: false,
+            loops: Vec::new(),
+            name_count: 0,
+            last_value: String::new(),
+        };
+        for statement in &ast.statements {
+            backend.visit_statement(statement);
+        }
+
+        let main_body = if global_scope.main_function().is_some() {
+            format!("    tn_call_depth = -1;\n    {}();\n", Self::function_name("main"))
+        } else {
+            std::mem::take(&mut backend.code)
+        };
+
+        let mut source = format!("#define TN_MAX_CALL_DEPTH {}\n", MAX_CALL_DEPTH);
+        source.push_str(RUNTIME);
+        source.push('\n');
+        for global in &backend.globals {
+            source.push_str(&format!("static tn_value {};\n", Self::global_name(global)));
+        }
+        for prototype in &backend.prototypes {
+            source.push_str(&format!("{};\n", prototype));
+        }
+        for function in &backend.functions {
+            source.push('\n');
+            source.push_str(function);
+        }
+        source.push_str(&format!("\nint main(void) {{\n{}    return 0;\n}}\n", main_body));
+        source
+    }
+
+    fn function_name(identifier: &str) -> String {
+        format!("tn_fn_{}", identifier)
+    }
+
+    fn global_name(identifier: &str) -> String {
+        format!("tn_global_{}", identifier)
+    }
+
+    fn fresh_name(&mut self, prefix: &str) -> String {
+        self.name_count += 1;
+        format!("{}_{}", prefix, self.name_count)
+    }
+
+    fn line(&mut self, line: &str) {
+        for _ in 0..self.indent {
+            self.code.push_str("    ");
+        }
+        self.code.push_str(line);
+        self.code.push('\n');
+    }
+
+    // Stores the value in a new temporary, fixing the point at which it is evaluated
+    fn temporary(&mut self, value: &str) -> String {
+        let name = self.fresh_name("t");
+        self.line(&format!("tn_value {} = {};", name, value));
+        name
+    }
+
+    fn generate_expression(&mut self, expression: &ASTExpression) -> String {
+        self.visit_expression(expression);
+        self.last_value.clone()
+    }
+
+    fn enter_block(&mut self, header: &str) {
+        if header.is_empty() {
+            self.line("{");
+        } else {
+            self.line(&format!("{} {{", header));
+        }
+        self.indent += 1;
+        self.scopes.push(HashMap::new());
+    }
+
+    fn exit_block(&mut self) {
+        self.scopes.pop();
+        self.indent -= 1;
+        self.line("}");
+    }
+
+    fn generate_block(&mut self, header: &str, statement: &ASTStatement) {
+        self.enter_block(header);
+        self.visit_statement(statement);
+        self.exit_block();
+    }
+
+    // Follows the body with the label that `continue` jumps to, if it is used
+    fn generate_loop_body(&mut self, body: &ASTStatement) {
+        let continue_label = self.fresh_name("tn_continue");
+        self.loops.push((continue_label, false));
+        self.generate_block("", body);
+        let (continue_label, is_used) = self.loops.pop().unwrap();
+        if is_used {
+            self.line(&format!("{}:;", continue_label));
+        }
+    }
+
+    fn local_name(&self, identifier: &str) -> Option<&String> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(identifier))
+    }
+
+    fn string_literal(value: &str) -> String {
+        let mut literal = String::from("\"");
+        for byte in value.bytes() {
+            match byte {
+                b'"' => literal.push_str("\\\""),
+                b'\\' => literal.push_str("\\\\"),
+                // Escaping `?` avoids trigraphs
+                b'?' => literal.push_str("\\?"),
+                0x20..=0x7e => literal.push(byte as char),
+                _ => literal.push_str(&format!("\\{:03o}", byte)),
+            }
+        }
+        literal.push('"');
+        literal
+    }
+
+    fn operator_name(operator: ASTBinaryOperatorKind) -> String {
+        format!("TN_{:?}", operator).to_uppercase()
+    }
+
+    fn intrinsic_name(intrinsic: Intrinsic) -> String {
+        format!("tn_{}", intrinsic.name())
+    }
+}
+
+impl ASTVisitor<'_> for CBackend<'_> {
+    fn visit_func_decl_statement(&mut self, func_decl_statement: &ASTFuncDeclStatement) {
+        let caller_code = std::mem::take(&mut self.code);
+        let caller_scopes = std::mem::take(&mut self.scopes);
+        let caller_loops = std::mem::take(&mut self.loops);
+        let caller_indent = std::mem::replace(&mut self.indent, 1);
+        let caller_is_in_function = std::mem::replace(&mut self.is_in_function, true);
+
+        let mut parameters = HashMap::new();
+        let mut declarations = Vec::new();
+        for parameter in &func_decl_statement.parameters {
+            let identifier = &parameter.identifier.span.literal;
+            let name = self.fresh_name(identifier);
+            declarations.push(format!("tn_value {}", name));
+            parameters.insert(identifier.clone(), name);
+        }
+        self.scopes.push(parameters);
+        self.line("tn_enter();");
+        self.visit_statement(&func_decl_statement.body);
+        self.line("return tn_leave(tn_unit());");
+
+        let parameter_list = if declarations.is_empty() {
+            "void".to_string()
+        } else {
+            declarations.join(", ")
+        };
+        let signature = format!(
+            "static tn_value {}({})",
+            Self::function_name(&func_decl_statement.identifier.span.literal),
+            parameter_list
+        );
+        let body = std::mem::replace(&mut self.code, caller_code);
+        self.functions.push(format!("{} {{\n{}}}\n", signature, body));
+        self.prototypes.push(signature);
+        self.scopes = caller_scopes;
+        self.loops = caller_loops;
+        self.indent = caller_indent;
+        self.is_in_function = caller_is_in_function;
+    }
+
+    fn visit_expression_statement(&mut self, expression: &ASTExpression) {
+        let value = self.generate_expression(expression);
+        self.line(&format!("(void) {};", value));
+    }
+
+    fn visit_return_statement(&mut self, return_statement: &ASTReturnStatement) {
+        let value = match &return_statement.return_value {
+            Some(expression) => self.generate_expression(expression),
+            None => "tn_unit()".to_string(),
+        };
+        if self.is_in_function {
+            self.line(&format!("return tn_leave({});", value));
+        } else {
+            // Top-level statements are inside C's `main`
+            self.line(&format!("(void) {};", value));
+            self.line("return 0;");
+        }
+    }
+
+    fn visit_break_statement(&mut self, _break_statement: &ASTBreakStatement) {
+        self.line("break;");
+    }
+
+    fn visit_continue_statement(&mut self, _continue_statement: &ASTContinueStatement) {
+        let (continue_label, is_used) = self.loops.last_mut().unwrap();
+        *is_used = true;
+        let jump = format!("goto {};", continue_label);
+        self.line(&jump);
+    }
+
+    fn visit_while_statement(&mut self, while_statement: &ASTWhileStatement) {
+        self.enter_block("for (;;)");
+        let condition = self.generate_expression(&while_statement.condition);
+        self.line(&format!("if (!tn_as_bool({})) break;", condition));
+        self.generate_loop_body(&while_statement.body);
+        self.exit_block();
+    }
+
+    fn visit_for_statement(&mut self, for_statement: &ASTForStatement) {
+        self.enter_block("");
+        let start = self.generate_expression(&for_statement.start);
+        let end = self.generate_expression(&for_statement.end);
+        let counter = self.fresh_name("counter");
+        let limit = self.fresh_name("limit");
+        self.line(&format!("int64_t {} = tn_as_int({});", counter, start));
+        self.line(&format!("int64_t {} = tn_as_int({});", limit, end));
+        let comparison = if for_statement.is_inclusive() { "<=" } else { "<" };
+        self.enter_block(&format!("while ({} {} {})", counter, comparison, limit));
+        let identifier = &for_statement.identifier.span.literal;
+        let variable = self.fresh_name(identifier);
+        self.line(&format!("tn_value {} = tn_int({});", variable, counter));
+        self.scopes.last_mut().unwrap().insert(identifier.clone(), variable);
+        self.generate_loop_body(&for_statement.body);
+        if for_statement.is_inclusive() {
+            self.line(&format!("if ({} == {}) break;", counter, limit));
+        }
+        self.line(&format!("{}++;", counter));
+        self.exit_block();
+        self.exit_block();
+    }
+
+    fn visit_block_statement(&mut self, block_statement: &ASTBlockStatement) {
+        self.enter_block("");
+        for statement in &block_statement.statements {
+            self.visit_statement(statement);
+        }
+        self.exit_block();
+    }
+
+    fn visit_if_statement(&mut self, if_statement: &ASTIfStatement) {
+        let condition = self.generate_expression(&if_statement.condition);
+        self.generate_block(&format!("if (tn_as_bool({}))", condition), &if_statement.then_branch);
+        if let Some(else_branch) = &if_statement.else_branch {
+            self.generate_block("else", &else_branch.else_statement);
+        }
+    }
+
+    fn visit_let_statement(&mut self, let_statement: &ASTLetStatement) {
+        let value = self.generate_expression(&let_statement.initializer);
+        let identifier = &let_statement.identifier.span.literal;
+        match self.scopes.last_mut() {
+            Some(_) => {
+                let name = self.fresh_name(identifier);
+                self.line(&format!("tn_value {} = {};", name, value));
+                self.scopes.last_mut().unwrap().insert(identifier.clone(), name);
+            }
+            None => {
+                self.globals.insert(identifier.clone());
+                self.line(&format!("{} = {};", Self::global_name(identifier), value));
+            }
+        }
+    }
+
+    fn visit_call_expression(&mut self, call_expression: &ASTCallExpression) {
+        let arguments: Vec<String> = call_expression.arguments
+            .iter()
+            .map(|argument| self.generate_expression(argument))
+            .collect();
+        let identifier = &call_expression.identifier.span.literal;
+        let function = self.global_scope.lookup_function(identifier).unwrap();
+        let name = match &function.body {
+            FunctionBody::Intrinsic(intrinsic) => Self::intrinsic_name(*intrinsic),
+            FunctionBody::Declared(_) => Self::function_name(identifier),
+        };
+        self.last_value = self.temporary(&format!("{}({})", name, arguments.join(", ")));
+    }
+
+    fn visit_assignment_expression(&mut self, assignment_expression: &ASTAssignmentExpression) {
+        let value = self.generate_expression(&assignment_expression.expression);
+        let identifier = &assignment_expression.identifier.span.literal;
+        match self.local_name(identifier).cloned() {
+            Some(name) => self.line(&format!("{} = {};", name, value)),
+            None => {
+                self.globals.insert(identifier.clone());
+                let global = Self::global_name(identifier);
+                self.line(&format!("tn_assign(&{}, {}, \"{}\");", global, value, identifier));
+            }
+        }
+        self.last_value = value;
+    }
+
+    fn visit_variable_expression(&mut self, variable_expression: &ASTVariableExpression) {
+        let identifier = variable_expression.identifier();
+        let value = match self.local_name(identifier).cloned() {
+            Some(name) => name,
+            None => {
+                self.globals.insert(identifier.to_string());
+                format!("tn_defined({}, \"{}\")", Self::global_name(identifier), identifier)
+            }
+        };
+        self.last_value = self.temporary(&value);
+    }
+
+    fn visit_number_expression(&mut self, number: &ASTNumberExpression) {
+        // Folding can produce the one value whose magnitude has no literal
+        self.last_value = if number.number == i64::MIN {
+            "tn_int(INT64_MIN)".to_string()
+        } else {
+            format!("tn_int(INT64_C({}))", number.number)
+        };
+    }
+
+    fn visit_float_expression(&mut self, float: &ASTFloatExpression) {
+        // Debug formatting round-trips and is valid C for every finite value
+        self.last_value = if float.number.is_finite() {
+            format!("tn_float({:?})", float.number)
+        } else if float.number.is_nan() {
+            "tn_float(NAN)".to_string()
+        } else if float.number < 0.0 {
+            "tn_float(-HUGE_VAL)".to_string()
+        } else {
+            "tn_float(HUGE_VAL)".to_string()
+        };
+    }
+
+    fn visit_string_expression(&mut self, string: &ASTStringExpression) {
+        self.last_value = format!(
+            "tn_string({}, {})",
+            Self::string_literal(&string.value),
+            string.value.len()
+        );
+    }
+
+    fn visit_boolean_expression(&mut self, boolean: &ASTBooleanExpression) {
+        self.last_value = format!("tn_bool({})", boolean.value as u8);
+    }
+
+    fn visit_error(&mut self, _span: &TextSpan) {
+        unreachable!("Programs with syntax errors are never generated");
+    }
+
+    fn visit_unary_expression(&mut self, unary_expression: &ASTUnaryExpression) {
+        let operand = self.generate_expression(&unary_expression.operand);
+        let function = match unary_expression.operator.kind {
+            ASTUnaryOperatorKind::Minus => "tn_negate",
+            ASTUnaryOperatorKind::BitwiseNot => "tn_bitwise_not",
+            ASTUnaryOperatorKind::LogicalNot => "tn_logical_not",
+        };
+        self.last_value = self.temporary(&format!("{}({})", function, operand));
+    }
+
+    fn visit_binary_expression(&mut self, binary_expression: &ASTBinaryExpression) {
+        let operator = binary_expression.operator.kind;
+        let left = self.generate_expression(&binary_expression.left);
+        let condition = match operator {
+            ASTBinaryOperatorKind::LogicalAnd => format!("tn_as_bool({})", left),
+            ASTBinaryOperatorKind::LogicalOr => format!("!tn_as_bool({})", left),
+            _ => {
+                let right = self.generate_expression(&binary_expression.right);
+                let value = format!("tn_binary({}, {}, {})", Self::operator_name(operator), left, right);
+                self.last_value = self.temporary(&value);
+                return;
+            }
+        };
+
+        // The right operand only runs when the left one does not decide the result
+        let result = self.temporary(&left);
+        self.enter_block(&format!("if ({})", condition));
+        let right = self.generate_expression(&binary_expression.right);
+        self.line(&format!("{} = {};", result, right));
+        self.exit_block();
+        self.last_value = result;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+    use std::process::Command;
+    use std::sync::atomic::{ AtomicUsize, Ordering };
+    use std::thread;
+
+    use crate::ast::evaluator::ASTEvaluator;
+    use crate::compilation_unit::CompilationUnit;
+
+    fn temporary_path(extension: &str) -> PathBuf {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let count = COUNT.fetch_add(1, Ordering::Relaxed);
+        std::env::temp_dir().join(
+            format!("tungsten-c-test-{}-{}{}", std::process::id(), count, extension)
+        )
+    }
+
+    // Compiles the translation with the system C compiler and checks that it prints the same
+    // output and exits with the same code as the interpreter. Skipped without a C compiler.
+    fn assert_matches_interpreter(input: &str) -> Option<String> {
+        if Command::new("cc").arg("--version").output().is_err() {
+            return None;
+        }
+        let compilation_unit = CompilationUnit::compile(input).expect("Failed to compile");
+        let source = temporary_path(".c");
+        let executable = temporary_path("");
+        std::fs::write(&source, compilation_unit.compile_c()).unwrap();
+        let compiler = Command::new("cc")
+            .args(["-std=c99", "-o"])
+            .arg(&executable)
+            .arg(&source)
+            .arg("-lm")
+            .output()
+            .unwrap();
+        assert!(compiler.status.success(), "{}", String::from_utf8_lossy(&compiler.stderr));
+        let result = Command::new(&executable).output().unwrap();
+        let _ = std::fs::remove_file(&source);
+        let _ = std::fs::remove_file(&executable);
+
+        let mut expected = Vec::new();
+        let mut eval = ASTEvaluator::new(&compilation_unit.global_scope, &mut expected);
+        compilation_unit.execute(&mut eval);
+        let exit_code = eval.exit_code();
+        let output = String::from_utf8(result.stdout).unwrap();
+        assert_eq!(output, String::from_utf8(expected).unwrap());
+        assert_eq!(result.status.code(), Some(exit_code as u8 as i32));
+        Some(output)
+    }
+
+    #[test]
+    fn should_translate_showcase() {
+        assert_matches_interpreter(include_str!("../../examples/showcase.tn"));
+    }
+
+    #[test]
+    fn should_give_shadowing_variables_their_own_names() {
+        let input =
+            "\
+        let a = 1
+        func f(a) {
+            let a = a + 10
+            {
+                let a = a * 2
+                println(a)
+            }
+            return a
+        }
+        if a > 5 {
+            a = 25
+        } else {
+            a = 20
+            let a = a + 10
+            a = a + 1
+            println(a)
+        }
+        println(f(a))
+        let a = a + 100
+        println(a)
+        for a in 0..2 {
+            let a = a - 1
+            println(a)
+        }
+        println(a)
+        ";
+
+        let output = assert_matches_interpreter(input);
+        assert!(output.is_none_or(|output| output == "31\n60\n30\n120\n-1\n0\n120\n"));
+    }
+
+    #[test]
+    fn should_evaluate_operands_and_arguments_from_left_to_right() {
+        let input =
+            "\
+        func trace(value) {
+            print(value)
+            return value
+        }
+        func add(a, b) {
+            return a + b
+        }
+        println(add(trace(1), trace(2)) + trace(3) * trace(4))
+        let flag = trace(false) && trace(true) || trace(true)
+        println(flag)
+        ";
+
+        assert_matches_interpreter(input);
+    }
+
+    #[test]
+    fn should_format_values_like_the_interpreter() {
+        let input =
+            "\
+        println(1.0)
+        println(0.1 + 0.2)
+        println(1e16)
+        println(1.5e-7)
+        println(0.0001)
+        println(-2.5 * 4.0)
+        println(to_float(3) / 0.0)
+        println(to_int(1e300))
+        println(\"a\\tb\" + \"\\\"?\")
+        println(true != false)
+        println(-9223372036854775807 - 1)
+        ";
+
+        assert_matches_interpreter(input);
+    }
+
+    #[test]
+    fn should_translate_loops_and_control_flow() {
+        let input =
+            "\
+        func find(limit: int) -> int {
+            let i = 0
+            while true {
+                i = i + 1
+                if i / 2 * 2 == i {
+                    continue
+                }
+                if i > limit {
+                    return i
+                }
+            }
+            return -1
+        }
+        let total = 0
+        for i in 1..=10 {
+            if i == 3 {
+                continue
+            }
+            if i > 8 {
+                break
+            }
+            total = total + i ** 2
+        }
+        println(total)
+        println(find(6))
+        exit(total)
+        ";
+
+        assert_matches_interpreter(input);
+    }
+
+    #[test]
+    fn should_fail_on_runtime_errors_like_the_interpreter() {
+        assert_matches_interpreter("println(1)\nlet zero = 0\nprintln(1 / zero)");
+        assert_matches_interpreter("println(2 ** 63)");
+        assert_matches_interpreter("func f {\n    g()\n}\nf()\nlet a = 1\nfunc g {\n    a = 2\n}");
+        assert_matches_interpreter("assert(1 > 2)");
+    }
+
+    #[test]
+    fn should_overflow_the_stack_like_the_interpreter() {
+        // The interpreter recurses natively, and test threads have small stacks
+        let check = |input: String| {
+            thread::Builder::new()
+                .stack_size(64 * 1024 * 1024)
+                .spawn(move || assert_matches_interpreter(&input))
+                .unwrap()
+                .join()
+                .unwrap()
+        };
+        check("func f(n) {\n    return f(n + 1)\n}\nf(0)".to_string());
+        let recurse = "func f(n) {\n    if n > 0 {\n        f(n - 1)\n    }\n}\n";
+        check(format!("{}f(999)\nprintln(1)", recurse));
+        check(format!("{}func main {{\n    f(999)\n}}", recurse));
+        check(format!("{}func main {{\n    f(1000)\n}}", recurse));
+    }
+
+    #[test]
+    fn should_only_run_main_when_declared() {
+        let input = "\
+        println(1)
+        func main {
+            println(2)
+        }
+        ";
+
+        assert_matches_interpreter(input);
+    }
+}
@@ -0,0 +1,88 @@
+(module
+  (import "env" "print_bool" (func $tn.print_bool (param i32)))
+  (import "env" "print_int" (func $tn.print_int (param i64)))
+  (import "env" "print_newline" (func $tn.print_newline))
+  (func $odd_bits (export "odd_bits") (param $limit i64) (result i64)
+    (local $bits.1 i64)
+    (local $tn.counter.1 i64)
+    (local $tn.limit.1 i64)
+    (local $i.2 i64)
+    i64.const 0
+    local.set $bits.1
+    i64.const 0
+    local.set $tn.counter.1
+    local.get $limit
+    local.set $tn.limit.1
+    block $tn.break.1
+      loop $tn.loop.1
+        local.get $tn.counter.1
+        local.get $tn.limit.1
+        i64.ge_s
+        br_if $tn.break.1
+        local.get $tn.counter.1
+        local.set $i.2
+        block $tn.continue.1
+          local.get $i.2
+          i64.const 1
+          i64.and
+          i64.const 0
+          i64.eq
+          if
+            br $tn.continue.1
+          end
+          local.get $bits.1
+          local.get $i.2
+          i64.or
+          local.tee $bits.1
+          drop
+        end
+        local.get $tn.counter.1
+        i64.const 1
+        i64.add
+        local.set $tn.counter.1
+        br $tn.loop.1
+      end
+    end
+    local.get $bits.1
+    return
+    unreachable
+  )
+  (func $invert (export "invert") (param $flag i32) (result i32)
+    (local $flag.1 i32)
+    (local $flag.2 i64)
+    (local $flag.2.bool i32)
+    local.get $flag
+    i32.eqz
+    local.set $flag.1
+    i64.const 1
+    local.set $flag.2
+    local.get $flag.2
+    i64.const 1
+    i64.eq
+    local.set $flag.2.bool
+    local.get $flag.2.bool
+    return
+    unreachable
+  )
+  (func $report (export "report")
+    i64.const 10
+    call $odd_bits
+    call $tn.print_int
+    call $tn.print_newline
+    i32.const 1
+    call $invert
+    if (result i32)
+      i64.const 3
+      call $odd_bits
+      i64.const 1
+      i64.ge_s
+    else
+      i32.const 0
+    end
+    call $tn.print_bool
+    call $tn.print_newline
+  )
+  (func $tn.start (export "_start")
+    call $report
+  )
+)
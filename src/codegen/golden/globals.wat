@@ -0,0 +1,102 @@
+(module
+  (import "env" "exit" (func $tn.exit (param i64)))
+  (import "env" "runtime_error" (func $tn.runtime_error (param i32 i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "Variable 'calls' is not defined yet")
+  (data (i32.const 35) "Assertion failed")
+  (data (i32.const 51) "Integer overflow in '+'")
+  (global $calls (mut i64) (i64.const 0))
+  (global $calls.defined (mut i32) (i32.const 0))
+  (func $tn.add (param $left i64) (param $right i64) (result i64)
+    (local $result i64)
+    local.get $left
+    local.get $right
+    i64.add
+    local.set $result
+    ;; The sum overflowed if its sign differs from the sign of both operands
+    local.get $left
+    local.get $result
+    i64.xor
+    local.get $right
+    local.get $result
+    i64.xor
+    i64.and
+    i64.const 0
+    i64.lt_s
+    if
+      i32.const 51
+      i32.const 23
+      call $tn.runtime_error
+      unreachable
+    end
+    local.get $result
+  )
+  (func $count (export "count") (param $step i64) (result i64)
+    global.get $calls.defined
+    i32.eqz
+    if
+      i32.const 0
+      i32.const 35
+      call $tn.runtime_error
+      unreachable
+    end
+    global.get $calls
+    local.get $step
+    call $tn.add
+    global.get $calls.defined
+    i32.eqz
+    if
+      i32.const 0
+      i32.const 35
+      call $tn.runtime_error
+      unreachable
+    end
+    global.set $calls
+    global.get $calls
+    drop
+    global.get $calls.defined
+    i32.eqz
+    if
+      i32.const 0
+      i32.const 35
+      call $tn.runtime_error
+      unreachable
+    end
+    global.get $calls
+    return
+    unreachable
+  )
+  (func $tn.start (export "_start")
+    i64.const 0
+    global.set $calls
+    i32.const 1
+    global.set $calls.defined
+    block $tn.break.1
+      loop $tn.loop.1
+        i64.const 2
+        call $count
+        i64.const 5
+        i64.lt_s
+        i32.eqz
+        br_if $tn.break.1
+        block $tn.continue.1
+          global.get $calls
+          i64.const 3
+          i64.ne
+          i32.eqz
+          if
+            i32.const 35
+            i32.const 16
+            call $tn.runtime_error
+            unreachable
+          end
+        end
+        br $tn.loop.1
+      end
+    end
+    i64.const 0
+    call $count
+    call $tn.exit
+    unreachable
+  )
+)
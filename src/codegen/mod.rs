@@ -0,0 +1,4 @@
+// Backends that translate a checked Ast into source for another toolchain
+pub mod c;
+pub mod wat;
+pub mod x86_64;
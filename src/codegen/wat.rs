@@ -0,0 +1,1358 @@
+use std::collections::{ BTreeSet, HashMap };
+
+use crate::ast::{
+    Ast,
+    ASTAssignmentExpression,
+    ASTBinaryExpression,
+    ASTBinaryOperatorKind,
+    ASTBlockStatement,
+    ASTBooleanExpression,
+    ASTBreakStatement,
+    ASTCallExpression,
+    ASTContinueStatement,
+    ASTExpression,
+    ASTFloatExpression,
+    ASTForStatement,
+    ASTFuncDeclStatement,
+    ASTIfStatement,
+    ASTLetStatement,
+    ASTNumberExpression,
+    ASTReturnStatement,
+    ASTStatement,
+    ASTStringExpression,
+    ASTUnaryExpression,
+    ASTUnaryOperatorKind,
+    ASTVariableExpression,
+    ASTWhileStatement,
+};
+use crate::ast::lexer::{ TextSpan, Token };
+use crate::ast::visitor::ASTVisitor;
+use crate::compilation_unit::{ FunctionBody, FunctionSymbol, GlobalScope, Scopes };
+use crate::diagnostics::DiagnosticsBagCell;
+use crate::intrinsics::Intrinsic;
+use crate::types::Type;
+
+const BACKEND_NAME: &str = "WebAssembly";
+const ENTRY_EXPORT: &str = "_start";
+const MEMORY_EXPORT: &str = "memory";
+const PAGE_SIZE: usize = 64 * 1024;
+
+// Room for the digits and sign of any int, plus the message prefix copied in front of them
+const NEGATIVE_EXPONENT_SCRATCH: usize = 38;
+const NEGATIVE_EXPONENT_PREFIX: &str = "Negative exponent ";
+const NEGATIVE_EXPONENT_SUFFIX: &str = " in integer power";
+
+// Functions the host provides. Runtime errors pass a message in the exported memory and never
+// return, and neither does `exit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Import {
+    Exit,
+    PrintBool,
+    PrintInt,
+    PrintNewline,
+    PrintUnit,
+    RuntimeError,
+}
+
+impl Import {
+    fn name(self) -> &'static str {
+        match self {
+            Import::Exit => "exit",
+            Import::PrintBool => "print_bool",
+            Import::PrintInt => "print_int",
+            Import::PrintNewline => "print_newline",
+            Import::PrintUnit => "print_unit",
+            Import::RuntimeError => "runtime_error",
+        }
+    }
+
+    fn parameters(self) -> &'static str {
+        match self {
+            Import::Exit | Import::PrintInt => " (param i64)",
+            Import::PrintBool => " (param i32)",
+            Import::PrintNewline | Import::PrintUnit => "",
+            Import::RuntimeError => " (param i32 i32)",
+        }
+    }
+}
+
+// Routines with the checked semantics of `evaluator::binary_operation`, emitted only when used.
+// `ERROR <message>` lines become a call to the host's `runtime_error`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Helper {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Power,
+    Negate,
+    MultiplyOverflows,
+    NegativeExponent,
+}
+
+impl Helper {
+    fn for_operator(operator: ASTBinaryOperatorKind) -> Option<Helper> {
+        match operator {
+            ASTBinaryOperatorKind::Plus => Some(Helper::Add),
+            ASTBinaryOperatorKind::Minus => Some(Helper::Subtract),
+            ASTBinaryOperatorKind::Multiply => Some(Helper::Multiply),
+            ASTBinaryOperatorKind::Divide => Some(Helper::Divide),
+            ASTBinaryOperatorKind::Power => Some(Helper::Power),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Helper::Add => "$tn.add",
+            Helper::Subtract => "$tn.subtract",
+            Helper::Multiply => "$tn.multiply",
+            Helper::Divide => "$tn.divide",
+            Helper::Power => "$tn.power",
+            Helper::Negate => "$tn.negate",
+            Helper::MultiplyOverflows => "$tn.multiply_overflows",
+            Helper::NegativeExponent => "$tn.negative_exponent",
+        }
+    }
+
+    fn dependencies(self) -> &'static [Helper] {
+        match self {
+            Helper::Multiply => &[Helper::MultiplyOverflows],
+            Helper::Power => &[Helper::MultiplyOverflows, Helper::NegativeExponent],
+            _ => &[],
+        }
+    }
+
+    fn template(self) -> &'static str {
+        match self {
+            Helper::Add =>
+                r#"  (func $tn.add (param $left i64) (param $right i64) (result i64)
+    (local $result i64)
+    local.get $left
+    local.get $right
+    i64.add
+    local.set $result
+    ;; The sum overflowed if its sign differs from the sign of both operands
+    local.get $left
+    local.get $result
+    i64.xor
+    local.get $right
+    local.get $result
+    i64.xor
+    i64.and
+    i64.const 0
+    i64.lt_s
+    if
+      ERROR Integer overflow in '+'
+    end
+    local.get $result
+  )
+"#,
+            Helper::Subtract =>
+                r#"  (func $tn.subtract (param $left i64) (param $right i64) (result i64)
+    (local $result i64)
+    local.get $left
+    local.get $right
+    i64.sub
+    local.set $result
+    ;; The difference overflowed if the operand signs differ and the result took the sign of
+    ;; the right operand
+    local.get $left
+    local.get $right
+    i64.xor
+    local.get $left
+    local.get $result
+    i64.xor
+    i64.and
+    i64.const 0
+    i64.lt_s
+    if
+      ERROR Integer overflow in '-'
+    end
+    local.get $result
+  )
+"#,
+            Helper::Multiply =>
+                r#"  (func $tn.multiply (param $left i64) (param $right i64) (result i64)
+    local.get $left
+    local.get $right
+    call $tn.multiply_overflows
+    if
+      ERROR Integer overflow in '*'
+    end
+    local.get $left
+    local.get $right
+    i64.mul
+  )
+"#,
+            Helper::Divide =>
+                r#"  (func $tn.divide (param $left i64) (param $right i64) (result i64)
+    local.get $right
+    i64.eqz
+    if
+      ERROR Division by zero
+    end
+    local.get $left
+    i64.const -9223372036854775808
+    i64.eq
+    local.get $right
+    i64.const -1
+    i64.eq
+    i32.and
+    if
+      ERROR Integer overflow in '/'
+    end
+    local.get $left
+    local.get $right
+    i64.div_s
+  )
+"#,
+            Helper::Power =>
+                r#"  (func $tn.power (param $base i64) (param $exponent i64) (result i64)
+    (local $result i64)
+    local.get $exponent
+    i64.const 0
+    i64.lt_s
+    if
+      local.get $exponent
+      call $tn.negative_exponent
+    end
+    ;; Exponents have to fit in 32 bits, like for the interpreter
+    local.get $exponent
+    i64.const 4294967295
+    i64.gt_s
+    if
+      ERROR Integer overflow in '**'
+    end
+    local.get $exponent
+    i64.eqz
+    if
+      i64.const 1
+      return
+    end
+    i64.const 1
+    local.set $result
+    ;; Square-and-multiply in the order of Rust's checked_pow, so the same powers overflow
+    loop $tn.square
+      local.get $exponent
+      i32.wrap_i64
+      i32.const 1
+      i32.and
+      if
+        local.get $result
+        local.get $base
+        call $tn.multiply_overflows
+        if
+          ERROR Integer overflow in '**'
+        end
+        local.get $result
+        local.get $base
+        i64.mul
+        local.set $result
+        local.get $exponent
+        i64.const 1
+        i64.eq
+        if
+          local.get $result
+          return
+        end
+      end
+      local.get $exponent
+      i64.const 1
+      i64.shr_u
+      local.set $exponent
+      local.get $base
+      local.get $base
+      call $tn.multiply_overflows
+      if
+        ERROR Integer overflow in '**'
+      end
+      local.get $base
+      local.get $base
+      i64.mul
+      local.set $base
+      br $tn.square
+    end
+    unreachable
+  )
+"#,
+            Helper::Negate =>
+                r#"  (func $tn.negate (param $operand i64) (result i64)
+    local.get $operand
+    i64.const -9223372036854775808
+    i64.eq
+    if
+      ERROR Integer overflow in '-'
+    end
+    i64.const 0
+    local.get $operand
+    i64.sub
+  )
+"#,
+            Helper::MultiplyOverflows =>
+                r#"  (func $tn.multiply_overflows (param $left i64) (param $right i64) (result i32)
+    ;; Dividing the wrapped product by one operand gives back the other unless it overflowed.
+    ;; The only division that traps is itself an overflow.
+    local.get $left
+    i64.const -1
+    i64.eq
+    local.get $right
+    i64.const -9223372036854775808
+    i64.eq
+    i32.and
+    if
+      i32.const 1
+      return
+    end
+    local.get $left
+    i64.eqz
+    if
+      i32.const 0
+      return
+    end
+    local.get $left
+    local.get $right
+    i64.mul
+    local.get $left
+    i64.div_s
+    local.get $right
+    i64.ne
+  )
+"#,
+            Helper::NegativeExponent =>
+                r#"  (func $tn.negative_exponent (param $exponent i64)
+    (local $cursor i32)
+    ;; Write the digits backwards, right in front of the message suffix
+    i32.const SUFFIX
+    local.set $cursor
+    loop $tn.digits
+      local.get $cursor
+      i32.const 1
+      i32.sub
+      local.tee $cursor
+      i32.const 48
+      local.get $exponent
+      i64.const 10
+      i64.rem_s
+      i32.wrap_i64
+      i32.sub
+      i32.store8
+      local.get $exponent
+      i64.const 10
+      i64.div_s
+      local.tee $exponent
+      i64.const 0
+      i64.ne
+      br_if $tn.digits
+    end
+    local.get $cursor
+    i32.const 1
+    i32.sub
+    local.tee $cursor
+    i32.const 45
+    i32.store8
+    local.get $cursor
+    i32.const PREFIX_LENGTH
+    i32.sub
+    local.tee $cursor
+    i32.const PREFIX
+    i32.const PREFIX_LENGTH
+    memory.copy
+    local.get $cursor
+    i32.const MESSAGE_END
+    local.get $cursor
+    i32.sub
+    call $tn.runtime_error
+    unreachable
+  )
+"#,
+        }
+    }
+}
+
+// The function whose body is currently being generated
+struct FunctionContext {
+    name: String,
+    code: String,
+    indent: usize,
+    // Declared locals in order, each with its wasm type
+    locals: Vec<(String, &'static str)>,
+    local_types: HashMap<String, Type>,
+    // Scopes below this depth belong to the code around the declaration
+    base_depth: usize,
+    is_top_level: bool,
+    // Numbers of the labels of the enclosing loops, innermost last
+    loops: Vec<usize>,
+}
+
+impl FunctionContext {
+    fn new(name: &str, base_depth: usize, is_top_level: bool) -> Self {
+        FunctionContext {
+            name: name.to_string(),
+            code: String::new(),
+            indent: 2,
+            locals: Vec::new(),
+            local_types: HashMap::new(),
+            base_depth,
+            is_top_level,
+            loops: Vec::new(),
+        }
+    }
+}
+
+// Generates a WebAssembly text module. Ints are i64 and bools are i32, so every value needs a
+// type known at compile time: parameters have to be annotated, and a function without a return
+// type annotation returns nothing. Each declared function is exported under its own name and
+// `_start` runs the program. Printing and runtime errors go through functions imported from
+// "env", and the output only depends on the program, so it can be compared as text.
+pub struct WatCodegen<'a> {
+    global_scope: &'a GlobalScope,
+    diagnostics: DiagnosticsBagCell,
+    // Variable types by scope, mirroring the resolver; functions come from `global_scope`
+    scopes: Scopes,
+    context: FunctionContext,
+    functions: Vec<String>,
+    globals: Vec<(String, &'static str)>,
+    global_types: HashMap<String, Type>,
+    imports: BTreeSet<Import>,
+    helpers: BTreeSet<Helper>,
+    data: Vec<(usize, Vec<u8>)>,
+    data_size: usize,
+    messages: HashMap<String, usize>,
+    label_count: usize,
+    last_type: Type,
+}
+
+impl<'a> WatCodegen<'a> {
+    pub fn generate(
+        ast: &Ast,
+        global_scope: &'a GlobalScope,
+        diagnostics: DiagnosticsBagCell
+    ) -> String {
+        let mut codegen = WatCodegen {
+            global_scope,
+            diagnostics,
+            scopes: Scopes::for_variables(),
+            context: FunctionContext::new(ENTRY_EXPORT, 0, true),
+            functions: Vec::new(),
+            globals: Vec::new(),
+            global_types: HashMap::new(),
+            imports: BTreeSet::new(),
+            helpers: BTreeSet::new(),
+            data: Vec::new(),
+            data_size: 0,
+            messages: HashMap::new(),
+            label_count: 0,
+            last_type: Type::Unit,
+        };
+        for statement in &ast.statements {
+            codegen.visit_statement(statement);
+        }
+
+        if let Some(main) = global_scope.main_function() {
+            codegen.context = FunctionContext::new(ENTRY_EXPORT, 0, true);
+            codegen.emit("call $main");
+            if !matches!(main.return_type, Type::Unit | Type::Unresolved) {
+                codegen.emit("drop");
+            }
+        }
+        let header = format!("(func $tn.start (export \"{}\")", ENTRY_EXPORT);
+        let entry = codegen.function_code(&header);
+        codegen.module(&entry)
+    }
+
+    fn module(&mut self, entry: &str) -> String {
+        // Expanding the helpers can still add messages and imports
+        let helpers: Vec<String> = self.helpers
+            .clone()
+            .into_iter()
+            .map(|helper| self.expand_template(helper))
+            .collect();
+
+        let mut module = String::from("(module\n");
+        for import in &self.imports {
+            module.push_str(
+                &format!(
+                    "  (import \"env\" \"{}\" (func $tn.{}{}))\n",
+                    import.name(),
+                    import.name(),
+                    import.parameters()
+                )
+            );
+        }
+        if !self.data.is_empty() {
+            let pages = self.data_size.div_ceil(PAGE_SIZE).max(1);
+            module.push_str(&format!("  (memory (export \"{}\") {})\n", MEMORY_EXPORT, pages));
+            for (offset, bytes) in &self.data {
+                module.push_str(
+                    &format!("  (data (i32.const {}) {})\n", offset, Self::string_literal(bytes))
+                );
+            }
+        }
+        for (name, value_type) in &self.globals {
+            module.push_str(
+                &format!("  (global {} (mut {}) ({}.const 0))\n", name, value_type, value_type)
+            );
+            module.push_str(&format!("  (global {}.defined (mut i32) (i32.const 0))\n", name));
+        }
+        for function in helpers.iter().chain(self.functions.iter()) {
+            module.push_str(function);
+        }
+        module.push_str(entry);
+        module.push_str(")\n");
+        module
+    }
+
+    fn function_code(&self, header: &str) -> String {
+        let mut function = format!("  {}\n", header);
+        for (name, value_type) in &self.context.locals {
+            function.push_str(&format!("    (local {} {})\n", name, value_type));
+        }
+        function.push_str(&self.context.code);
+        function.push_str("  )\n");
+        function
+    }
+
+    fn expand_template(&mut self, helper: Helper) -> String {
+        let mut template = helper.template().to_string();
+        if helper == Helper::NegativeExponent {
+            let prefix = self.add_data(NEGATIVE_EXPONENT_PREFIX.as_bytes().to_vec());
+            self.data_size += NEGATIVE_EXPONENT_SCRATCH;
+            let suffix = self.add_data(NEGATIVE_EXPONENT_SUFFIX.as_bytes().to_vec());
+            let message_end = suffix + NEGATIVE_EXPONENT_SUFFIX.len();
+            self.imports.insert(Import::RuntimeError);
+            template = template
+                .replace("PREFIX_LENGTH", &NEGATIVE_EXPONENT_PREFIX.len().to_string())
+                .replace("PREFIX", &prefix.to_string())
+                .replace("SUFFIX", &suffix.to_string())
+                .replace("MESSAGE_END", &message_end.to_string());
+        }
+
+        let mut function = String::new();
+        for line in template.lines() {
+            let code = line.trim_start();
+            match code.strip_prefix("ERROR ") {
+                Some(message) => {
+                    let indent = &line[..line.len() - code.len()];
+                    for instruction in self.runtime_error(message) {
+                        function.push_str(&format!("{}{}\n", indent, instruction));
+                    }
+                }
+                None => {
+                    function.push_str(line);
+                    function.push('\n');
+                }
+            }
+        }
+        function
+    }
+
+    fn add_data(&mut self, bytes: Vec<u8>) -> usize {
+        let offset = self.data_size;
+        self.data_size += bytes.len();
+        self.data.push((offset, bytes));
+        offset
+    }
+
+    // Instructions that report the message to the host, storing it on first use
+    fn runtime_error(&mut self, message: &str) -> [String; 4] {
+        let offset = match self.messages.get(message) {
+            Some(offset) => *offset,
+            None => {
+                let offset = self.add_data(message.as_bytes().to_vec());
+                self.messages.insert(message.to_string(), offset);
+                offset
+            }
+        };
+        self.imports.insert(Import::RuntimeError);
+        [
+            format!("i32.const {}", offset),
+            format!("i32.const {}", message.len()),
+            "call $tn.runtime_error".to_string(),
+            "unreachable".to_string(),
+        ]
+    }
+
+    fn string_literal(bytes: &[u8]) -> String {
+        let mut literal = String::from("\"");
+        for byte in bytes {
+            match byte {
+                b'"' | b'\\' => literal.push_str(&format!("\\{}", *byte as char)),
+                0x20..=0x7e => literal.push(*byte as char),
+                _ => literal.push_str(&format!("\\{:02x}", byte)),
+            }
+        }
+        literal.push('"');
+        literal
+    }
+
+    fn emit(&mut self, instruction: &str) {
+        for _ in 0..self.context.indent {
+            self.context.code.push_str("  ");
+        }
+        self.context.code.push_str(instruction);
+        self.context.code.push('\n');
+    }
+
+    fn emit_runtime_error(&mut self, message: &str) {
+        for instruction in self.runtime_error(message) {
+            self.emit(&instruction);
+        }
+    }
+
+    fn enter_block(&mut self, instruction: &str) {
+        self.emit(instruction);
+        self.context.indent += 1;
+    }
+
+    fn exit_block(&mut self) {
+        self.context.indent -= 1;
+        self.emit("end");
+    }
+
+    fn call_import(&mut self, import: Import) {
+        self.imports.insert(import);
+        self.emit(&format!("call $tn.{}", import.name()));
+    }
+
+    fn call_helper(&mut self, helper: Helper) {
+        self.helpers.insert(helper);
+        self.helpers.extend(helper.dependencies());
+        self.emit(&format!("call {}", helper.name()));
+    }
+
+    fn new_label(&mut self) -> usize {
+        self.label_count += 1;
+        self.label_count
+    }
+
+    // Without an annotation a function returns nothing, unless its body returns a value, which
+    // the backend cannot give a type
+    fn return_type(function: &FunctionSymbol) -> Type {
+        match (&function.return_type, &function.body) {
+            (Type::Unresolved, FunctionBody::Declared(body)) => {
+                let mut finder = ReturnValueFinder { returns_value: false };
+                finder.visit_statement(body);
+                if finder.returns_value { Type::Error } else { Type::Unit }
+            }
+            (ty, _) => ty.clone(),
+        }
+    }
+
+    fn value_type(ty: &Type) -> Option<&'static str> {
+        match ty {
+            Type::Int => Some("i64"),
+            Type::Bool => Some("i32"),
+            _ => None,
+        }
+    }
+
+    // Declaring a variable again in the same scope with another type needs another variable,
+    // so names keep the first type they were declared with
+    fn variable_name(types: &mut HashMap<String, Type>, name: String, ty: &Type) -> String {
+        let first_type = types.entry(name.clone()).or_insert_with(|| ty.clone());
+        if first_type == ty { name } else { format!("{}.{}", name, ty) }
+    }
+
+    // Parameters keep their name, and other locals are told apart by the depth of their scope
+    fn local_name(&mut self, identifier: &str, depth: usize, ty: &Type) -> String {
+        let name = match depth - self.context.base_depth {
+            0 => format!("${}", identifier),
+            relative_depth => format!("${}.{}", identifier, relative_depth),
+        };
+        Self::variable_name(&mut self.context.local_types, name, ty)
+    }
+
+    fn global_name(&mut self, identifier: &str, ty: &Type) -> String {
+        Self::variable_name(&mut self.global_types, format!("${}", identifier), ty)
+    }
+
+    fn declare_local(&mut self, name: &str, value_type: &'static str) {
+        if !self.context.locals.iter().any(|(local, _)| local == name) {
+            self.context.locals.push((name.to_string(), value_type));
+        }
+    }
+
+    // Where a variable lives, or None if its scope belongs to an enclosing function
+    fn variable_location(&mut self, identifier: &Token, ty: &Type) -> Option<Variable> {
+        match self.scopes.lookup_local_depth(&identifier.span.literal) {
+            Some(depth) if depth < self.context.base_depth => {
+                let feature = format!(
+                    "using '{}' from outside of function '{}'",
+                    identifier.span.literal,
+                    self.context.name
+                );
+                self.report_unsupported(feature, identifier.span.clone());
+                None
+            }
+            Some(depth) => {
+                Some(Variable::Local(self.local_name(&identifier.span.literal, depth, ty)))
+            }
+            None => Some(Variable::Global(self.global_name(&identifier.span.literal, ty))),
+        }
+    }
+
+    // A function can run before the top-level `let` of a global it uses
+    fn emit_defined_check(&mut self, global: &str, identifier: &str) {
+        if self.context.is_top_level {
+            return;
+        }
+        self.emit(&format!("global.get {}.defined", global));
+        self.emit("i32.eqz");
+        self.enter_block("if");
+        self.emit_runtime_error(&format!("Variable '{}' is not defined yet", identifier));
+        self.exit_block();
+    }
+
+    fn generate_expression(&mut self, expression: &ASTExpression) -> Type {
+        self.visit_expression(expression);
+        self.last_type.clone()
+    }
+
+    fn generate_scoped_statement(&mut self, statement: &ASTStatement) {
+        self.scopes.enter_scope();
+        self.visit_statement(statement);
+        self.scopes.exit_scope();
+    }
+
+    // Runs the body in a block that `continue` leaves, inside a loop that `break` leaves
+    fn generate_loop_body(&mut self, label: usize, body: &ASTStatement) {
+        self.context.loops.push(label);
+        self.enter_block(&format!("block $tn.continue.{}", label));
+        self.visit_statement(body);
+        self.exit_block();
+        self.context.loops.pop();
+    }
+
+    fn report_unsupported(&mut self, feature: String, span: TextSpan) {
+        self.diagnostics.borrow_mut().report_unsupported_by_backend(BACKEND_NAME, &feature, span);
+    }
+
+    fn drop_value(&mut self, ty: &Type) {
+        if Self::value_type(ty).is_some() {
+            self.emit("drop");
+        }
+    }
+
+    fn generate_intrinsic_call(
+        &mut self,
+        intrinsic: Intrinsic,
+        call_expression: &ASTCallExpression
+    ) {
+        // Every intrinsic takes a single argument
+        let ty = self.generate_expression(&call_expression.arguments[0]);
+        self.last_type = Type::Unit;
+        match intrinsic {
+            Intrinsic::Print | Intrinsic::Println => {
+                match ty {
+                    Type::Int => self.call_import(Import::PrintInt),
+                    Type::Bool => self.call_import(Import::PrintBool),
+                    Type::Unit => self.call_import(Import::PrintUnit),
+                    Type::Error => {}
+                    _ => {
+                        self.report_unsupported(
+                            format!("printing values of type '{}'", ty),
+                            call_expression.span()
+                        );
+                    }
+                }
+                if intrinsic == Intrinsic::Println {
+                    self.call_import(Import::PrintNewline);
+                }
+            }
+            Intrinsic::Assert => {
+                self.emit("i32.eqz");
+                self.enter_block("if");
+                self.emit_runtime_error("Assertion failed");
+                self.exit_block();
+            }
+            Intrinsic::Exit => {
+                self.call_import(Import::Exit);
+                self.emit("unreachable");
+            }
+            Intrinsic::ToFloat | Intrinsic::ToInt => {
+                let span = call_expression.identifier.span.clone();
+                self.report_unsupported("floats".to_string(), span);
+                self.last_type = Type::Error;
+            }
+        }
+    }
+
+    fn generate_logical_expression(&mut self, binary_expression: &ASTBinaryExpression) {
+        self.generate_expression(&binary_expression.left);
+        self.enter_block("if (result i32)");
+        if binary_expression.operator.kind == ASTBinaryOperatorKind::LogicalAnd {
+            self.generate_expression(&binary_expression.right);
+            self.context.indent -= 1;
+            self.emit("else");
+            self.context.indent += 1;
+            self.emit("i32.const 0");
+        } else {
+            self.emit("i32.const 1");
+            self.context.indent -= 1;
+            self.emit("else");
+            self.context.indent += 1;
+            self.generate_expression(&binary_expression.right);
+        }
+        self.exit_block();
+        self.last_type = Type::Bool;
+    }
+
+    fn generate_int_operation(&mut self, operator: ASTBinaryOperatorKind) -> Type {
+        if let Some(helper) = Helper::for_operator(operator) {
+            self.call_helper(helper);
+            return Type::Int;
+        }
+        let (instruction, result) = match operator {
+            ASTBinaryOperatorKind::BitwiseAnd => ("i64.and", Type::Int),
+            ASTBinaryOperatorKind::BitwiseOr => ("i64.or", Type::Int),
+            ASTBinaryOperatorKind::BitwiseXor => ("i64.xor", Type::Int),
+            ASTBinaryOperatorKind::Equals => ("i64.eq", Type::Bool),
+            ASTBinaryOperatorKind::NotEquals => ("i64.ne", Type::Bool),
+            ASTBinaryOperatorKind::LessThan => ("i64.lt_s", Type::Bool),
+            ASTBinaryOperatorKind::LessThanOrEqual => ("i64.le_s", Type::Bool),
+            ASTBinaryOperatorKind::GreaterThan => ("i64.gt_s", Type::Bool),
+            ASTBinaryOperatorKind::GreaterThanOrEqual => ("i64.ge_s", Type::Bool),
+            _ => unreachable!("Arithmetic and logical operators are generated separately"),
+        };
+        self.emit(instruction);
+        result
+    }
+}
+
+enum Variable {
+    Local(String),
+    Global(String),
+}
+
+struct ReturnValueFinder {
+    returns_value: bool,
+}
+
+impl ASTVisitor<'_> for ReturnValueFinder {
+    // Returns in a nested function belong to that function
+    fn visit_func_decl_statement(&mut self, _func_decl_statement: &ASTFuncDeclStatement) {}
+
+    fn visit_return_statement(&mut self, return_statement: &ASTReturnStatement) {
+        self.returns_value |= return_statement.return_value.is_some();
+    }
+
+    fn visit_expression_statement(&mut self, _expression: &ASTExpression) {}
+
+    fn visit_let_statement(&mut self, _let_statement: &ASTLetStatement) {}
+
+    fn visit_variable_expression(&mut self, _variable_expression: &ASTVariableExpression) {}
+
+    fn visit_number_expression(&mut self, _number: &ASTNumberExpression) {}
+
+    fn visit_float_expression(&mut self, _float: &ASTFloatExpression) {}
+
+    fn visit_string_expression(&mut self, _string: &ASTStringExpression) {}
+
+    fn visit_boolean_expression(&mut self, _boolean: &ASTBooleanExpression) {}
+
+    fn visit_error(&mut self, _span: &TextSpan) {}
+
+    fn visit_unary_expression(&mut self, _unary_expression: &ASTUnaryExpression) {}
+}
+
+impl ASTVisitor<'_> for WatCodegen<'_> {
+    fn visit_func_decl_statement(&mut self, func_decl_statement: &ASTFuncDeclStatement) {
+        let identifier = &func_decl_statement.identifier.span;
+        let function = self.global_scope.lookup_function(&identifier.literal).unwrap();
+        if [ENTRY_EXPORT, MEMORY_EXPORT].contains(&identifier.literal.as_str()) {
+            let feature = format!("a function named '{}'", identifier.literal);
+            self.report_unsupported(feature, identifier.clone());
+        }
+
+        let name = &identifier.literal;
+        let mut header = format!("(func ${} (export \"{}\")", name, name);
+        let return_type = Self::return_type(function);
+        if return_type == Type::Error && function.return_type == Type::Unresolved {
+            self.report_unsupported(
+                "returning values without a return type annotation".to_string(),
+                identifier.clone()
+            );
+        }
+        let callee = FunctionContext::new(&identifier.literal, self.scopes.depth(), false);
+        let caller = std::mem::replace(&mut self.context, callee);
+        self.scopes.enter_scope();
+        for (parameter, symbol) in func_decl_statement.parameters.iter().zip(&function.parameters) {
+            let span = parameter.identifier.span.clone();
+            match (&symbol.ty, Self::value_type(&symbol.ty)) {
+                (_, Some(value_type)) => {
+                    let depth = self.scopes.depth() - 1;
+                    let name = self.local_name(&symbol.identifier, depth, &symbol.ty);
+                    header.push_str(&format!(" (param {} {})", name, value_type));
+                }
+                (Type::Unresolved, None) => {
+                    let feature = "parameters without a type annotation".to_string();
+                    self.report_unsupported(feature, span);
+                }
+                (ty, None) => {
+                    self.report_unsupported(format!("parameters of type '{}'", ty), span);
+                }
+            }
+            // Unsupported parameters are declared as errors, so their uses report nothing more
+            let ty = match Self::value_type(&symbol.ty) {
+                Some(_) => symbol.ty.clone(),
+                None => Type::Error,
+            };
+            self.scopes.declare_variable(&symbol.identifier, ty);
+        }
+        match (Self::value_type(&return_type), &func_decl_statement.return_type) {
+            (Some(value_type), _) => header.push_str(&format!(" (result {})", value_type)),
+            (None, Some(annotation)) if !matches!(return_type, Type::Unit | Type::Error) => {
+                self.report_unsupported(
+                    format!("returning values of type '{}'", return_type),
+                    annotation.type_name.span.clone()
+                );
+            }
+            (None, _) => {}
+        }
+
+        self.visit_statement(&func_decl_statement.body);
+        if Self::value_type(&return_type).is_some() {
+            // Falling off the end of a function that returns a value traps
+            self.emit("unreachable");
+        }
+        self.scopes.exit_scope();
+        let function = self.function_code(&header);
+        self.context = caller;
+        self.functions.push(function);
+    }
+
+    fn visit_expression_statement(&mut self, expression: &ASTExpression) {
+        let ty = self.generate_expression(expression);
+        self.drop_value(&ty);
+    }
+
+    fn visit_return_statement(&mut self, return_statement: &ASTReturnStatement) {
+        let ty = match &return_statement.return_value {
+            Some(expression) => self.generate_expression(expression),
+            None => Type::Unit,
+        };
+        if self.context.is_top_level {
+            // The function holding the top-level statements has no result
+            self.drop_value(&ty);
+        }
+        self.emit("return");
+    }
+
+    fn visit_break_statement(&mut self, _break_statement: &ASTBreakStatement) {
+        let label = *self.context.loops.last().unwrap();
+        self.emit(&format!("br $tn.break.{}", label));
+    }
+
+    fn visit_continue_statement(&mut self, _continue_statement: &ASTContinueStatement) {
+        let label = *self.context.loops.last().unwrap();
+        self.emit(&format!("br $tn.continue.{}", label));
+    }
+
+    fn visit_while_statement(&mut self, while_statement: &ASTWhileStatement) {
+        let label = self.new_label();
+        self.enter_block(&format!("block $tn.break.{}", label));
+        self.enter_block(&format!("loop $tn.loop.{}", label));
+        self.generate_expression(&while_statement.condition);
+        self.emit("i32.eqz");
+        self.emit(&format!("br_if $tn.break.{}", label));
+        self.generate_loop_body(label, &while_statement.body);
+        self.emit(&format!("br $tn.loop.{}", label));
+        self.exit_block();
+        self.exit_block();
+    }
+
+    fn visit_for_statement(&mut self, for_statement: &ASTForStatement) {
+        let label = self.new_label();
+        let counter = format!("$tn.counter.{}", label);
+        let limit = format!("$tn.limit.{}", label);
+        self.declare_local(&counter, "i64");
+        self.declare_local(&limit, "i64");
+        self.generate_expression(&for_statement.start);
+        self.emit(&format!("local.set {}", counter));
+        self.generate_expression(&for_statement.end);
+        self.emit(&format!("local.set {}", limit));
+
+        self.scopes.enter_scope();
+        let identifier = &for_statement.identifier.span.literal;
+        self.scopes.declare_variable(identifier, Type::Int);
+        let variable = self.local_name(identifier, self.scopes.depth() - 1, &Type::Int);
+        self.declare_local(&variable, "i64");
+        let exit_comparison = if for_statement.is_inclusive() { "i64.gt_s" } else { "i64.ge_s" };
+        self.enter_block(&format!("block $tn.break.{}", label));
+        self.enter_block(&format!("loop $tn.loop.{}", label));
+        self.emit(&format!("local.get {}", counter));
+        self.emit(&format!("local.get {}", limit));
+        self.emit(exit_comparison);
+        self.emit(&format!("br_if $tn.break.{}", label));
+        self.emit(&format!("local.get {}", counter));
+        self.emit(&format!("local.set {}", variable));
+        self.generate_loop_body(label, &for_statement.body);
+        if for_statement.is_inclusive() {
+            self.emit(&format!("local.get {}", counter));
+            self.emit(&format!("local.get {}", limit));
+            self.emit("i64.eq");
+            self.emit(&format!("br_if $tn.break.{}", label));
+        }
+        self.emit(&format!("local.get {}", counter));
+        self.emit("i64.const 1");
+        self.emit("i64.add");
+        self.emit(&format!("local.set {}", counter));
+        self.emit(&format!("br $tn.loop.{}", label));
+        self.exit_block();
+        self.exit_block();
+        self.scopes.exit_scope();
+    }
+
+    fn visit_block_statement(&mut self, block_statement: &ASTBlockStatement) {
+        self.scopes.enter_scope();
+        for statement in &block_statement.statements {
+            self.visit_statement(statement);
+        }
+        self.scopes.exit_scope();
+    }
+
+    fn visit_if_statement(&mut self, if_statement: &ASTIfStatement) {
+        self.scopes.enter_scope();
+        self.generate_expression(&if_statement.condition);
+        self.enter_block("if");
+        self.visit_statement(&if_statement.then_branch);
+        self.scopes.exit_scope();
+        if let Some(else_branch) = &if_statement.else_branch {
+            self.context.indent -= 1;
+            self.emit("else");
+            self.context.indent += 1;
+            self.generate_scoped_statement(&else_branch.else_statement);
+        }
+        self.exit_block();
+    }
+
+    fn visit_let_statement(&mut self, let_statement: &ASTLetStatement) {
+        let ty = self.generate_expression(&let_statement.initializer);
+        let identifier = &let_statement.identifier.span;
+        self.scopes.declare_variable(&identifier.literal, ty.clone());
+        let value_type = match (&ty, Self::value_type(&ty)) {
+            (_, Some(value_type)) => value_type,
+            (Type::Error, None) => {
+                return;
+            }
+            (_, None) => {
+                self.report_unsupported(format!("variables of type '{}'", ty), identifier.clone());
+                return;
+            }
+        };
+        match self.variable_location(&let_statement.identifier, &ty) {
+            Some(Variable::Local(name)) => {
+                self.declare_local(&name, value_type);
+                self.emit(&format!("local.set {}", name));
+            }
+            Some(Variable::Global(name)) => {
+                if !self.globals.iter().any(|(global, _)| *global == name) {
+                    self.globals.push((name.clone(), value_type));
+                }
+                self.emit(&format!("global.set {}", name));
+                self.emit("i32.const 1");
+                self.emit(&format!("global.set {}.defined", name));
+            }
+            None => {}
+        }
+    }
+
+    fn visit_call_expression(&mut self, call_expression: &ASTCallExpression) {
+        let identifier = &call_expression.identifier.span.literal;
+        let function = self.global_scope.lookup_function(identifier).unwrap();
+        if let FunctionBody::Intrinsic(intrinsic) = &function.body {
+            self.generate_intrinsic_call(*intrinsic, call_expression);
+            return;
+        }
+        for argument in &call_expression.arguments {
+            self.generate_expression(argument);
+        }
+        self.emit(&format!("call ${}", identifier));
+        self.last_type = Self::return_type(function);
+    }
+
+    fn visit_assignment_expression(&mut self, assignment_expression: &ASTAssignmentExpression) {
+        let ty = self.generate_expression(&assignment_expression.expression);
+        let identifier = &assignment_expression.identifier;
+        let expected = self.scopes
+            .lookup_variable(&identifier.span.literal)
+            .cloned()
+            .unwrap_or(Type::Error);
+        self.last_type = expected.clone();
+        if ty == Type::Error || expected == Type::Error {
+            return;
+        }
+        if ty != expected {
+            let feature = format!(
+                "assigning '{}' to '{}' of type '{}'",
+                ty,
+                identifier.span.literal,
+                expected
+            );
+            self.report_unsupported(feature, identifier.span.clone());
+            return;
+        }
+        match self.variable_location(identifier, &expected) {
+            Some(Variable::Local(name)) => self.emit(&format!("local.tee {}", name)),
+            Some(Variable::Global(name)) => {
+                self.emit_defined_check(&name, &identifier.span.literal);
+                self.emit(&format!("global.set {}", name));
+                self.emit(&format!("global.get {}", name));
+            }
+            None => {}
+        }
+    }
+
+    fn visit_variable_expression(&mut self, variable_expression: &ASTVariableExpression) {
+        let ty = self.scopes
+            .lookup_variable(variable_expression.identifier())
+            .cloned()
+            .unwrap_or(Type::Error);
+        self.last_type = ty.clone();
+        if ty == Type::Error {
+            return;
+        }
+        match self.variable_location(&variable_expression.identifier, &ty) {
+            Some(Variable::Local(name)) => self.emit(&format!("local.get {}", name)),
+            Some(Variable::Global(name)) => {
+                self.emit_defined_check(&name, variable_expression.identifier());
+                self.emit(&format!("global.get {}", name));
+            }
+            None => {}
+        }
+    }
+
+    fn visit_number_expression(&mut self, number: &ASTNumberExpression) {
+        self.emit(&format!("i64.const {}", number.number));
+        self.last_type = Type::Int;
+    }
+
+    fn visit_float_expression(&mut self, float: &ASTFloatExpression) {
+        self.report_unsupported("floats".to_string(), float.token.span.clone());
+        self.last_type = Type::Error;
+    }
+
+    fn visit_string_expression(&mut self, string: &ASTStringExpression) {
+        self.report_unsupported("strings".to_string(), string.token.span.clone());
+        self.last_type = Type::Error;
+    }
+
+    fn visit_boolean_expression(&mut self, boolean: &ASTBooleanExpression) {
+        self.emit(&format!("i32.const {}", boolean.value as u8));
+        self.last_type = Type::Bool;
+    }
+
+    fn visit_error(&mut self, _span: &TextSpan) {
+        unreachable!("Programs with syntax errors are never generated");
+    }
+
+    fn visit_unary_expression(&mut self, unary_expression: &ASTUnaryExpression) {
+        let operand = self.generate_expression(&unary_expression.operand);
+        match (unary_expression.operator.kind, &operand) {
+            (ASTUnaryOperatorKind::Minus, Type::Int) => self.call_helper(Helper::Negate),
+            (ASTUnaryOperatorKind::BitwiseNot, Type::Int) => {
+                self.emit("i64.const -1");
+                self.emit("i64.xor");
+            }
+            (ASTUnaryOperatorKind::LogicalNot, Type::Bool) => self.emit("i32.eqz"),
+            (_, Type::Error) => {}
+            (_, _) => {
+                let feature = format!(
+                    "'{}' on '{}'",
+                    unary_expression.operator.token.span.literal,
+                    operand
+                );
+                self.report_unsupported(feature, unary_expression.operator.token.span.clone());
+            }
+        }
+        self.last_type = operand;
+    }
+
+    fn visit_binary_expression(&mut self, binary_expression: &ASTBinaryExpression) {
+        let operator = binary_expression.operator.kind;
+        if
+            matches!(operator, ASTBinaryOperatorKind::LogicalAnd | ASTBinaryOperatorKind::LogicalOr)
+        {
+            self.generate_logical_expression(binary_expression);
+            return;
+        }
+
+        let left = self.generate_expression(&binary_expression.left);
+        let right = self.generate_expression(&binary_expression.right);
+        let is_equality = matches!(
+            operator,
+            ASTBinaryOperatorKind::Equals | ASTBinaryOperatorKind::NotEquals
+        );
+        self.last_type = match (&left, &right) {
+            (Type::Error, _) | (_, Type::Error) => Type::Error,
+            (Type::Int, Type::Int) => self.generate_int_operation(operator),
+            (Type::Bool, Type::Bool) if is_equality => {
+                let is_equals = operator == ASTBinaryOperatorKind::Equals;
+                self.emit(if is_equals { "i32.eq" } else { "i32.ne" });
+                Type::Bool
+            }
+            (left, right) if is_equality => {
+                self.drop_value(right);
+                self.drop_value(left);
+                let result = (left == right) == (operator == ASTBinaryOperatorKind::Equals);
+                self.emit(&format!("i32.const {}", result as u8));
+                Type::Bool
+            }
+            (left, right) => {
+                let feature = format!(
+                    "'{}' between '{}' and '{}'",
+                    operator.symbol(),
+                    left,
+                    right
+                );
+                self.report_unsupported(feature, binary_expression.operator.token.span.clone());
+                Type::Error
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::compilation_unit::CompilationUnit;
+    use crate::diagnostics::DiagnosticKind;
+
+    // Golden modules live next to their programs; regenerate one with
+    // `cargo run -- wat src/codegen/golden/<name>.tn` after reviewing the change
+    fn generate(input: &str) -> String {
+        let compilation_unit = CompilationUnit::compile(input).expect("Failed to compile");
+        compilation_unit.compile_wat().expect("Failed to generate")
+    }
+
+    fn unsupported_diagnostics(input: &str) -> Vec<String> {
+        let compilation_unit = CompilationUnit::compile(input).expect("Failed to compile");
+        assert!(compilation_unit.compile_wat().is_err());
+        let diagnostics = compilation_unit.diagnostics_bag.borrow();
+        diagnostics.diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.kind == DiagnosticKind::Error)
+            .map(|diagnostic| diagnostic.message.clone())
+            .collect()
+    }
+
+    #[test]
+    fn should_match_golden_functions_module() {
+        let module = generate(include_str!("golden/functions.tn"));
+
+        assert_eq!(module, include_str!("golden/functions.wat"));
+    }
+
+    #[test]
+    fn should_match_golden_globals_module() {
+        let module = generate(include_str!("golden/globals.tn"));
+
+        assert_eq!(module, include_str!("golden/globals.wat"));
+    }
+
+    #[test]
+    fn should_generate_the_same_module_every_time() {
+        let input =
+            "\
+        let a = 2 ** 3 - 1
+        func f(x: int) -> int {
+            return x * a / 2
+        }
+        func g(flag: bool) {
+            assert(flag)
+        }
+        g(f(-a) < 0)
+        ";
+
+        let first = generate(input);
+        for _ in 0..10 {
+            assert_eq!(generate(input), first);
+        }
+    }
+
+    #[test]
+    fn should_export_every_declared_function() {
+        let input =
+            "\
+        func first() {}
+        {
+            func nested(a: int) -> int {
+                return a
+            }
+        }
+        func last(b: bool) -> bool {
+            return b
+        }
+        ";
+
+        let module = generate(input);
+
+        assert!(module.contains("(func $first (export \"first\")\n"));
+        assert!(module.contains("(func $nested (export \"nested\") (param $a i64) (result i64)\n"));
+        assert!(module.contains("(func $last (export \"last\") (param $b i32) (result i32)\n"));
+        assert!(module.contains("(func $tn.start (export \"_start\")\n"));
+    }
+
+    #[test]
+    fn should_only_call_main_when_declared() {
+        let input = "\
+        let a = 1
+        func main {
+            exit(7)
+        }
+        ";
+
+        let module = generate(input);
+
+        assert!(module.ends_with("  (func $tn.start (export \"_start\")\n    call $main\n  )\n)\n"));
+    }
+
+    #[test]
+    fn should_report_values_without_a_static_wasm_type() {
+        let input =
+            "\
+        func add(a, b) {
+            return a + b
+        }
+        func name(s: string) {}
+        let ratio = 1.5
+        println(\"text\")
+        ";
+
+        let diagnostics = unsupported_diagnostics(input);
+
+        assert_eq!(
+            diagnostics,
+            vec![
+                "The WebAssembly backend does not support returning values without a return type annotation".to_string(),
+                "The WebAssembly backend does not support parameters without a type annotation".to_string(),
+                "The WebAssembly backend does not support parameters without a type annotation".to_string(),
+                "The WebAssembly backend does not support parameters of type 'string'".to_string(),
+                "The WebAssembly backend does not support floats".to_string(),
+                "The WebAssembly backend does not support strings".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn should_report_locals_of_enclosing_functions() {
+        let input =
+            "\
+        {
+            let a = 1
+            func inner() -> int {
+                return a
+            }
+        }
+        ";
+
+        let diagnostics = unsupported_diagnostics(input);
+
+        assert_eq!(
+            diagnostics,
+            vec![
+                "The WebAssembly backend does not support using 'a' from outside of function 'inner'".to_string()
+            ]
+        );
+    }
+}
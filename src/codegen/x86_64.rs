@@ -0,0 +1,1321 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{ Path, PathBuf };
+use std::process::Command;
+use std::sync::atomic::{ AtomicUsize, Ordering };
+
+use crate::ast::{
+    Ast,
+    ASTAssignmentExpression,
+    ASTBinaryExpression,
+    ASTBinaryOperatorKind,
+    ASTBlockStatement,
+    ASTBooleanExpression,
+    ASTBreakStatement,
+    ASTCallExpression,
+    ASTContinueStatement,
+    ASTExpression,
+    ASTFloatExpression,
+    ASTForStatement,
+    ASTFuncDeclStatement,
+    ASTIfStatement,
+    ASTLetStatement,
+    ASTNumberExpression,
+    ASTReturnStatement,
+    ASTStatement,
+    ASTStringExpression,
+    ASTUnaryExpression,
+    ASTUnaryOperatorKind,
+    ASTVariableExpression,
+    ASTWhileStatement,
+};
+use crate::ast::evaluator::MAX_CALL_DEPTH;
+use crate::ast::lexer::{ TextSpan, Token };
+use crate::ast::visitor::ASTVisitor;
+use crate::compilation_unit::{ FunctionBody, GlobalScope };
+use crate::diagnostics::DiagnosticsBagCell;
+use crate::intrinsics::Intrinsic;
+use crate::types::Type;
+
+const BACKEND_NAME: &str = "x86-64";
+const TOP_LEVEL_LABEL: &str = "tn_top_level";
+const OUTPUT_CAPACITY: usize = 4096;
+const HEAP_CAPACITY: usize = 16 * 1024 * 1024;
+
+// Integer operators that report an overflow, each with its own error routine
+const OVERFLOWING_OPERATORS: [ASTBinaryOperatorKind; 5] = [
+    ASTBinaryOperatorKind::Plus,
+    ASTBinaryOperatorKind::Minus,
+    ASTBinaryOperatorKind::Multiply,
+    ASTBinaryOperatorKind::Divide,
+    ASTBinaryOperatorKind::Power,
+];
+
+// Routines every program links against. Strings are a pointer to a 64-bit length followed by
+// the bytes, and output is buffered until the program exits.
+const RUNTIME: &str =
+    "\
+# Flushes buffered output and exits with the code in rdi
+tn_exit:
+    push rdi
+    call tn_flush
+    pop rdi
+    mov eax, 60
+    syscall
+
+tn_flush:
+    mov rdx, qword ptr [rip + tn_output_length]
+    mov edi, 1
+    lea rsi, [rip + tn_output_buffer]
+    mov qword ptr [rip + tn_output_length], 0
+    jmp tn_write_all
+
+# Writes rdx bytes at rsi to the file descriptor in rdi, retrying short writes
+tn_write_all:
+    test rdx, rdx
+    jz 1f
+    mov eax, 1
+    syscall
+    test rax, rax
+    jle 1f
+    add rsi, rax
+    sub rdx, rax
+    jmp tn_write_all
+1:
+    ret
+
+# Appends rdx bytes at rsi to the output buffer
+tn_write:
+    mov rax, qword ptr [rip + tn_output_length]
+    add rax, rdx
+    cmp rax, OUTPUT_CAPACITY
+    jbe 1f
+    push rsi
+    push rdx
+    call tn_flush
+    pop rdx
+    pop rsi
+    cmp rdx, OUTPUT_CAPACITY
+    jbe 1f
+    mov edi, 1
+    jmp tn_write_all
+1:
+    lea rdi, [rip + tn_output_buffer]
+    add rdi, qword ptr [rip + tn_output_length]
+    add qword ptr [rip + tn_output_length], rdx
+    mov rcx, rdx
+    rep movsb
+    ret
+
+# Formats rdi in decimal, returning the digits in rsi and their count in rdx
+tn_format_int:
+    lea rsi, [rip + tn_number_buffer + 32]
+    mov rax, rdi
+    test rax, rax
+    jns 1f
+    neg rax
+1:
+    mov ecx, 10
+2:
+    xor edx, edx
+    div rcx
+    add dl, 48
+    dec rsi
+    mov byte ptr [rsi], dl
+    test rax, rax
+    jnz 2b
+    test rdi, rdi
+    jns 3f
+    dec rsi
+    mov byte ptr [rsi], 45
+3:
+    lea rdx, [rip + tn_number_buffer + 32]
+    sub rdx, rsi
+    ret
+
+tn_print_int:
+    call tn_format_int
+    jmp tn_write
+
+tn_print_string:
+    mov rdx, qword ptr [rdi]
+    lea rsi, [rdi + 8]
+    jmp tn_write
+
+tn_print_bool:
+    test rdi, rdi
+    lea rdi, [rip + tn_string_false]
+    lea rax, [rip + tn_string_true]
+    cmovnz rdi, rax
+    jmp tn_print_string
+
+tn_print_unit:
+    lea rdi, [rip + tn_string_unit]
+    jmp tn_print_string
+
+tn_print_newline:
+    lea rdi, [rip + tn_string_newline]
+    jmp tn_print_string
+
+# Concatenates the strings in rdi and rsi into a new string on the heap
+tn_concat:
+    mov r8, qword ptr [rdi]
+    mov r9, qword ptr [rsi]
+    lea rcx, [r8 + r9 + 15]
+    and rcx, -8
+    mov rax, qword ptr [rip + tn_heap_used]
+    add rcx, rax
+    cmp rcx, HEAP_CAPACITY
+    ja tn_error_out_of_memory
+    mov qword ptr [rip + tn_heap_used], rcx
+    lea rdx, [rip + tn_heap]
+    add rax, rdx
+    lea rdx, [r8 + r9]
+    mov qword ptr [rax], rdx
+    mov r10, rsi
+    lea rsi, [rdi + 8]
+    lea rdi, [rax + 8]
+    mov rcx, r8
+    rep movsb
+    lea rsi, [r10 + 8]
+    mov rcx, r9
+    rep movsb
+    ret
+
+tn_string_equals:
+    xor eax, eax
+    mov rcx, qword ptr [rdi]
+    cmp rcx, qword ptr [rsi]
+    jne 1f
+    lea rdi, [rdi + 8]
+    lea rsi, [rsi + 8]
+    repe cmpsb
+    sete al
+1:
+    ret
+
+# Raises rdi to the power rsi, overflowing exactly where i64::checked_pow does
+tn_pow:
+    test rsi, rsi
+    js tn_error_negative_exponent
+    mov eax, 0xFFFFFFFF
+    cmp rsi, rax
+    ja tn_error_overflow_power
+    mov eax, 1
+    test rsi, rsi
+    jz 3f
+1:
+    test rsi, 1
+    jz 2f
+    imul rax, rdi
+    jo tn_error_overflow_power
+    cmp rsi, 1
+    je 3f
+2:
+    shr rsi, 1
+    imul rdi, rdi
+    jo tn_error_overflow_power
+    jmp 1b
+3:
+    ret
+
+tn_write_error:
+    mov rdx, qword ptr [rdi]
+    lea rsi, [rdi + 8]
+    mov edi, 2
+    jmp tn_write_all
+
+# Reports the message in rdi on stderr and exits with 1, like the interpreter
+tn_runtime_error:
+    push rdi
+    call tn_flush
+    lea rdi, [rip + tn_string_error_prefix]
+    call tn_write_error
+    pop rdi
+    call tn_write_error
+tn_error_exit:
+    lea rdi, [rip + tn_string_newline]
+    call tn_write_error
+    mov edi, 1
+    mov eax, 60
+    syscall
+
+tn_error_negative_exponent:
+    push rsi
+    call tn_flush
+    lea rdi, [rip + tn_string_error_prefix]
+    call tn_write_error
+    lea rdi, [rip + tn_string_negative_exponent]
+    call tn_write_error
+    pop rdi
+    call tn_format_int
+    mov edi, 2
+    call tn_write_all
+    lea rdi, [rip + tn_string_in_integer_power]
+    call tn_write_error
+    jmp tn_error_exit
+";
+
+struct Variable {
+    // Memory operand holding the variable, e.g. `qword ptr [rbp - 8]`
+    operand: String,
+    ty: Type,
+}
+
+struct LoopLabels {
+    continue_label: String,
+    break_label: String,
+}
+
+// The function whose body is currently being generated
+struct FunctionContext {
+    name: String,
+    // Label of the specialization being generated, or None at the top level
+    instance: Option<String>,
+    code: String,
+    scopes: Vec<HashMap<String, Variable>>,
+    slot_count: usize,
+    return_label: String,
+    loops: Vec<LoopLabels>,
+}
+
+// Generates GNU as assembly for x86-64 Linux. Values are 64-bit: ints, bools as 0 or 1, unit as
+// 0 and strings as pointers. Since parameters and return types may be left out, each function
+// is generated once per combination of argument types it is called with, which gives every
+// expression a static type.
+pub struct X86_64Codegen<'a> {
+    global_scope: &'a GlobalScope,
+    diagnostics: DiagnosticsBagCell,
+    context: FunctionContext,
+    functions: Vec<String>,
+    // Return type of each specialization; None while it is inferred from its first return
+    instances: HashMap<String, Option<Type>>,
+    globals: HashMap<String, Variable>,
+    global_count: usize,
+    strings: HashMap<String, String>,
+    label_count: usize,
+    last_type: Type,
+}
+
+impl<'a> X86_64Codegen<'a> {
+    pub fn generate(
+        ast: &Ast,
+        global_scope: &'a GlobalScope,
+        diagnostics: DiagnosticsBagCell
+    ) -> String {
+        let mut codegen = X86_64Codegen {
+            global_scope,
+            diagnostics,
+            context: FunctionContext {
+                name: TOP_LEVEL_LABEL.to_string(),
+                instance: None,
+                code: String::new(),
+                scopes: Vec::new(),
+                slot_count: 0,
+                return_label: ".Ltop_level_return".to_string(),
+                loops: Vec::new(),
+            },
+            functions: Vec::new(),
+            instances: HashMap::new(),
+            globals: HashMap::new(),
+            global_count: 0,
+            strings: HashMap::new(),
+            label_count: 0,
+            last_type: Type::Unit,
+        };
+
+        let entry = match global_scope.main_function() {
+            Some(main) => {
+                let parameter_types = main.parameters
+                    .iter()
+                    .map(|parameter| parameter.ty.clone())
+                    .collect();
+                codegen.instantiate("main", parameter_types)
+            }
+            None => {
+                for statement in &ast.statements {
+                    codegen.visit_statement(statement);
+                }
+                codegen.functions.push(Self::function_code(TOP_LEVEL_LABEL, &codegen.context));
+                TOP_LEVEL_LABEL.to_string()
+            }
+        };
+        codegen.assembly(&entry)
+    }
+
+    fn assembly(&self, entry: &str) -> String {
+        let mut assembly = String::from("    .intel_syntax noprefix\n\n    .text\n");
+        assembly.push_str("    .globl _start\n_start:\n");
+        if entry != TOP_LEVEL_LABEL {
+            assembly.push_str("    mov qword ptr [rip + tn_call_depth], -1\n");
+        }
+        assembly.push_str(&format!("    call {}\n    xor edi, edi\n    jmp tn_exit\n\n", entry));
+        assembly.push_str(
+            &RUNTIME.replace("OUTPUT_CAPACITY", &OUTPUT_CAPACITY.to_string()).replace(
+                "HEAP_CAPACITY",
+                &HEAP_CAPACITY.to_string()
+            )
+        );
+        for (label, _) in Self::runtime_errors() {
+            assembly.push_str(&format!("\n{}:\n", label));
+            assembly.push_str(&format!("    lea rdi, [rip + {}_message]\n", label));
+            assembly.push_str("    jmp tn_runtime_error\n");
+        }
+        for function in &self.functions {
+            assembly.push('\n');
+            assembly.push_str(function);
+        }
+
+        assembly.push_str("\n    .section .rodata\n");
+        let runtime_strings = [
+            ("tn_string_true", "true".to_string()),
+            ("tn_string_false", "false".to_string()),
+            ("tn_string_unit", "()".to_string()),
+            ("tn_string_newline", "\n".to_string()),
+            ("tn_string_error_prefix", "Runtime error: ".to_string()),
+            ("tn_string_negative_exponent", "Negative exponent ".to_string()),
+            ("tn_string_in_integer_power", " in integer power".to_string()),
+        ];
+        for (label, value) in runtime_strings {
+            assembly.push_str(&Self::string_data(label, &value));
+        }
+        for (label, message) in Self::runtime_errors() {
+            assembly.push_str(&Self::string_data(&format!("{}_message", label), &message));
+        }
+        // Sorted so that the same program always generates the same assembly
+        let mut strings: Vec<(&String, &String)> = self.strings.iter().collect();
+        strings.sort_by_key(|(_, label)| *label);
+        for (value, label) in strings {
+            assembly.push_str(&Self::string_data(label, value));
+        }
+
+        assembly.push_str("\n    .bss\n");
+        assembly.push_str(&format!("    .lcomm tn_output_buffer, {}\n", OUTPUT_CAPACITY));
+        assembly.push_str("    .lcomm tn_output_length, 8\n");
+        assembly.push_str("    .lcomm tn_number_buffer, 32\n");
+        assembly.push_str(&format!("    .lcomm tn_heap, {}\n", HEAP_CAPACITY));
+        assembly.push_str("    .lcomm tn_heap_used, 8\n");
+        assembly.push_str("    .lcomm tn_call_depth, 8\n");
+        for index in 1..=self.global_count {
+            assembly.push_str(&format!("    .lcomm tn_global_{}, 8\n", index));
+        }
+        assembly
+    }
+
+    // Labels of the routines that report a fixed message, and the message they report
+    fn runtime_errors() -> Vec<(String, String)> {
+        let mut errors = vec![
+            ("tn_error_division_by_zero".to_string(), "Division by zero".to_string()),
+            ("tn_error_assertion_failed".to_string(), "Assertion failed".to_string()),
+            ("tn_error_out_of_memory".to_string(), "Out of memory".to_string()),
+            ("tn_error_stack_overflow".to_string(), "Stack overflow".to_string())
+        ];
+        for operator in OVERFLOWING_OPERATORS {
+            errors.push((
+                Self::overflow_label(operator),
+                format!("Integer overflow in '{}'", operator.symbol()),
+            ));
+        }
+        errors
+    }
+
+    fn overflow_label(operator: ASTBinaryOperatorKind) -> String {
+        format!("tn_error_overflow_{:?}", operator).to_lowercase()
+    }
+
+    fn string_data(label: &str, value: &str) -> String {
+        let mut data = format!("{}:\n    .quad {}\n", label, value.len());
+        if !value.is_empty() {
+            let bytes: Vec<String> = value
+                .bytes()
+                .map(|byte| byte.to_string())
+                .collect();
+            data.push_str(&format!("    .byte {}\n", bytes.join(", ")));
+        }
+        data
+    }
+
+    fn function_code(label: &str, context: &FunctionContext) -> String {
+        let mut function = format!("{}:\n    push rbp\n    mov rbp, rsp\n", label);
+        let is_call = context.instance.is_some();
+        if is_call {
+            function.push_str(
+                &format!("    cmp qword ptr [rip + tn_call_depth], {}\n", MAX_CALL_DEPTH)
+            );
+            function.push_str("    je tn_error_stack_overflow\n");
+            function.push_str("    inc qword ptr [rip + tn_call_depth]\n");
+        }
+        if context.slot_count > 0 {
+            function.push_str(&format!("    sub rsp, {}\n", 8 * context.slot_count));
+        }
+        function.push_str(&context.code);
+        function.push_str("    xor eax, eax\n");
+        function.push_str(&format!("{}:\n", context.return_label));
+        if is_call {
+            function.push_str("    dec qword ptr [rip + tn_call_depth]\n");
+        }
+        function.push_str("    mov rsp, rbp\n    pop rbp\n    ret\n");
+        function
+    }
+
+    // Returns the label of `name` specialized for the argument types, generating it on first use
+    fn instantiate(&mut self, name: &str, argument_types: Vec<Type>) -> String {
+        let mut label = format!("tn_fn_{}", name);
+        for ty in &argument_types {
+            label.push_str(&format!("_{}", ty));
+        }
+        if self.instances.contains_key(&label) {
+            return label;
+        }
+
+        let function = self.global_scope.lookup_function(name).unwrap();
+        let body = match &function.body {
+            FunctionBody::Declared(body) => body,
+            FunctionBody::Intrinsic(_) => unreachable!("Intrinsics are generated inline"),
+        };
+        let return_type = match &function.return_type {
+            Type::Unresolved => None,
+            declared => Some(declared.clone()),
+        };
+        self.instances.insert(label.clone(), return_type);
+
+        // Arguments are pushed in order, so the last one is closest to the return address
+        let parameter_count = function.parameters.len();
+        let parameters = function.parameters
+            .iter()
+            .zip(argument_types)
+            .enumerate()
+            .map(|(index, (parameter, ty))| {
+                let offset = 16 + 8 * (parameter_count - 1 - index);
+                let operand = format!("qword ptr [rbp + {}]", offset);
+                (parameter.identifier.clone(), Variable { operand, ty })
+            })
+            .collect();
+        let return_label = self.new_label();
+        let callee = FunctionContext {
+            name: name.to_string(),
+            instance: Some(label.clone()),
+            code: String::new(),
+            scopes: vec![parameters],
+            slot_count: 0,
+            return_label,
+            loops: Vec::new(),
+        };
+        let caller = std::mem::replace(&mut self.context, callee);
+        self.visit_statement(body);
+        let callee = std::mem::replace(&mut self.context, caller);
+        self.functions.push(Self::function_code(&label, &callee));
+        self.instances.get_mut(&label).unwrap().get_or_insert(Type::Unit);
+        label
+    }
+
+    fn emit(&mut self, instruction: &str) {
+        self.context.code.push_str("    ");
+        self.context.code.push_str(instruction);
+        self.context.code.push('\n');
+    }
+
+    fn emit_label(&mut self, label: &str) {
+        self.context.code.push_str(label);
+        self.context.code.push_str(":\n");
+    }
+
+    fn new_label(&mut self) -> String {
+        self.label_count += 1;
+        format!(".L{}", self.label_count)
+    }
+
+    fn allocate_slot(&mut self) -> String {
+        self.context.slot_count += 1;
+        format!("qword ptr [rbp - {}]", 8 * self.context.slot_count)
+    }
+
+    fn string_label(&mut self, value: &str) -> String {
+        if let Some(label) = self.strings.get(value) {
+            return label.clone();
+        }
+        let label = format!("tn_string_literal_{}", self.strings.len() + 1);
+        self.strings.insert(value.to_string(), label.clone());
+        label
+    }
+
+    fn lookup_variable(&self, identifier: &str) -> Option<&Variable> {
+        self.context.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(identifier))
+            .or_else(|| self.globals.get(identifier))
+    }
+
+    fn declare_variable(&mut self, identifier: &str, ty: Type) -> String {
+        if self.context.scopes.is_empty() {
+            self.global_count += 1;
+            let operand = format!("qword ptr [rip + tn_global_{}]", self.global_count);
+            let variable = Variable { operand: operand.clone(), ty };
+            self.globals.insert(identifier.to_string(), variable);
+            return operand;
+        }
+        let operand = self.allocate_slot();
+        let variable = Variable { operand: operand.clone(), ty };
+        self.context.scopes.last_mut().unwrap().insert(identifier.to_string(), variable);
+        operand
+    }
+
+    fn generate_expression(&mut self, expression: &ASTExpression) -> Type {
+        self.visit_expression(expression);
+        self.last_type.clone()
+    }
+
+    fn generate_scoped_statement(&mut self, statement: &ASTStatement) {
+        self.context.scopes.push(HashMap::new());
+        self.visit_statement(statement);
+        self.context.scopes.pop();
+    }
+
+    fn generate_loop_body(&mut self, body: &ASTStatement, labels: LoopLabels) {
+        self.context.loops.push(labels);
+        self.generate_scoped_statement(body);
+        self.context.loops.pop();
+    }
+
+    fn report_unsupported(&mut self, feature: String, span: TextSpan) {
+        self.diagnostics.borrow_mut().report_unsupported_by_backend(BACKEND_NAME, &feature, span);
+    }
+
+    fn report_used_before_declaration(&mut self, identifier: &Token) {
+        let feature = format!("using '{}' before its declaration", identifier.span.literal);
+        self.report_unsupported(feature, identifier.span.clone());
+    }
+
+    // Types that already reported a diagnostic are compatible with anything
+    fn is_compatible(left: &Type, right: &Type) -> bool {
+        left == right || *left == Type::Error || *right == Type::Error
+    }
+
+    // Only a bool can decide a branch; anything else is the interpreter's runtime type error
+    fn expect_bool(&mut self, ty: &Type, usage: &str, span: TextSpan) {
+        if !Self::is_compatible(ty, &Type::Bool) {
+            self.report_unsupported(format!("{} of type '{}'", usage, ty), span);
+        }
+    }
+
+    fn generate_intrinsic_call(&mut self, intrinsic: Intrinsic, call_expression: &ASTCallExpression) {
+        // Every intrinsic takes a single argument
+        let ty = self.generate_expression(&call_expression.arguments[0]);
+        match intrinsic {
+            Intrinsic::Print | Intrinsic::Println => {
+                let routine = match ty {
+                    Type::Int => Some("tn_print_int"),
+                    Type::Bool => Some("tn_print_bool"),
+                    Type::String => Some("tn_print_string"),
+                    Type::Unit => Some("tn_print_unit"),
+                    Type::Error => None,
+                    _ => {
+                        self.report_unsupported(
+                            format!("printing values of type '{}'", ty),
+                            call_expression.span()
+                        );
+                        None
+                    }
+                };
+                if let Some(routine) = routine {
+                    self.emit("mov rdi, rax");
+                    self.emit(&format!("call {}", routine));
+                }
+                if intrinsic == Intrinsic::Println {
+                    self.emit("call tn_print_newline");
+                }
+            }
+            Intrinsic::Assert => {
+                self.expect_bool(&ty, "assertions", call_expression.arguments[0].span());
+                self.emit("test rax, rax");
+                self.emit("jz tn_error_assertion_failed");
+            }
+            Intrinsic::Exit => {
+                self.emit("mov rdi, rax");
+                self.emit("jmp tn_exit");
+            }
+            Intrinsic::ToFloat | Intrinsic::ToInt => {
+                self.report_unsupported("floats".to_string(), call_expression.identifier.span.clone());
+                self.last_type = Type::Error;
+                return;
+            }
+        }
+        self.emit("xor eax, eax");
+        self.last_type = Type::Unit;
+    }
+
+    fn generate_logical_expression(&mut self, binary_expression: &ASTBinaryExpression) {
+        let end = self.new_label();
+        let jump = match binary_expression.operator.kind {
+            ASTBinaryOperatorKind::LogicalAnd => "jz",
+            _ => "jnz",
+        };
+        // Bools are already 0 or 1, so whichever operand decides is the result
+        let usage = format!("'{}' on operands", binary_expression.operator.token.span.literal);
+        let left = self.generate_expression(&binary_expression.left);
+        self.expect_bool(&left, &usage, binary_expression.left.span());
+        self.emit("test rax, rax");
+        self.emit(&format!("{} {}", jump, end));
+        let right = self.generate_expression(&binary_expression.right);
+        self.expect_bool(&right, &usage, binary_expression.right.span());
+        self.emit_label(&end);
+        self.last_type = Type::Bool;
+    }
+
+    // Applies the operator to rax and rcx, leaving the result in rax
+    fn generate_int_operation(&mut self, operator: ASTBinaryOperatorKind) -> Type {
+        let overflow = Self::overflow_label(operator);
+        match operator {
+            ASTBinaryOperatorKind::Plus => {
+                self.emit("add rax, rcx");
+                self.emit(&format!("jo {}", overflow));
+            }
+            ASTBinaryOperatorKind::Minus => {
+                self.emit("sub rax, rcx");
+                self.emit(&format!("jo {}", overflow));
+            }
+            ASTBinaryOperatorKind::Multiply => {
+                self.emit("imul rax, rcx");
+                self.emit(&format!("jo {}", overflow));
+            }
+            ASTBinaryOperatorKind::Divide => {
+                // idiv traps on the one overflowing division, so it is negated instead
+                let divide = self.new_label();
+                let end = self.new_label();
+                self.emit("test rcx, rcx");
+                self.emit("jz tn_error_division_by_zero");
+                self.emit("cmp rcx, -1");
+                self.emit(&format!("jne {}", divide));
+                self.emit("neg rax");
+                self.emit(&format!("jo {}", overflow));
+                self.emit(&format!("jmp {}", end));
+                self.emit_label(&divide);
+                self.emit("cqo");
+                self.emit("idiv rcx");
+                self.emit_label(&end);
+            }
+            ASTBinaryOperatorKind::Power => {
+                self.emit("mov rdi, rax");
+                self.emit("mov rsi, rcx");
+                self.emit("call tn_pow");
+            }
+            ASTBinaryOperatorKind::BitwiseAnd => self.emit("and rax, rcx"),
+            ASTBinaryOperatorKind::BitwiseOr => self.emit("or rax, rcx"),
+            ASTBinaryOperatorKind::BitwiseXor => self.emit("xor rax, rcx"),
+            ASTBinaryOperatorKind::Equals |
+            ASTBinaryOperatorKind::NotEquals |
+            ASTBinaryOperatorKind::LessThan |
+            ASTBinaryOperatorKind::LessThanOrEqual |
+            ASTBinaryOperatorKind::GreaterThan |
+            ASTBinaryOperatorKind::GreaterThanOrEqual => {
+                let condition = match operator {
+                    ASTBinaryOperatorKind::Equals => "e",
+                    ASTBinaryOperatorKind::NotEquals => "ne",
+                    ASTBinaryOperatorKind::LessThan => "l",
+                    ASTBinaryOperatorKind::LessThanOrEqual => "le",
+                    ASTBinaryOperatorKind::GreaterThan => "g",
+                    _ => "ge",
+                };
+                self.emit("cmp rax, rcx");
+                self.emit(&format!("set{} al", condition));
+                self.emit("movzx eax, al");
+                return Type::Bool;
+            }
+            ASTBinaryOperatorKind::LogicalAnd | ASTBinaryOperatorKind::LogicalOr => {
+                unreachable!("Logical operators short-circuit before their operands are generated")
+            }
+        }
+        Type::Int
+    }
+}
+
+impl ASTVisitor<'_> for X86_64Codegen<'_> {
+    // Functions are generated when they are first called
+    fn visit_func_decl_statement(&mut self, _func_decl_statement: &ASTFuncDeclStatement) {}
+
+    fn visit_return_statement(&mut self, return_statement: &ASTReturnStatement) {
+        let ty = match &return_statement.return_value {
+            Some(expression) => self.generate_expression(expression),
+            None => {
+                self.emit("xor eax, eax");
+                Type::Unit
+            }
+        };
+        if let Some(instance) = &self.context.instance {
+            let return_type = self.instances.get_mut(instance).unwrap();
+            match return_type {
+                None => {
+                    *return_type = Some(ty);
+                }
+                Some(expected) if !Self::is_compatible(expected, &ty) => {
+                    let feature = format!(
+                        "returning both '{}' and '{}' from '{}'",
+                        expected,
+                        ty,
+                        self.context.name
+                    );
+                    self.report_unsupported(feature, return_statement.return_keyword.span.clone());
+                }
+                Some(_) => {}
+            }
+        }
+        let return_label = self.context.return_label.clone();
+        self.emit(&format!("jmp {}", return_label));
+    }
+
+    fn visit_break_statement(&mut self, _break_statement: &ASTBreakStatement) {
+        let label = self.context.loops.last().unwrap().break_label.clone();
+        self.emit(&format!("jmp {}", label));
+    }
+
+    fn visit_continue_statement(&mut self, _continue_statement: &ASTContinueStatement) {
+        let label = self.context.loops.last().unwrap().continue_label.clone();
+        self.emit(&format!("jmp {}", label));
+    }
+
+    fn visit_while_statement(&mut self, while_statement: &ASTWhileStatement) {
+        let start = self.new_label();
+        let end = self.new_label();
+        self.emit_label(&start);
+        let condition = self.generate_expression(&while_statement.condition);
+        self.expect_bool(&condition, "conditions", while_statement.condition.span());
+        self.emit("test rax, rax");
+        self.emit(&format!("jz {}", end));
+        let labels = LoopLabels { continue_label: start.clone(), break_label: end.clone() };
+        self.generate_loop_body(&while_statement.body, labels);
+        self.emit(&format!("jmp {}", start));
+        self.emit_label(&end);
+    }
+
+    fn visit_for_statement(&mut self, for_statement: &ASTForStatement) {
+        self.context.scopes.push(HashMap::new());
+        let counter = self.allocate_slot();
+        let limit = self.allocate_slot();
+        self.generate_expression(&for_statement.start);
+        self.emit(&format!("mov {}, rax", counter));
+        self.generate_expression(&for_statement.end);
+        self.emit(&format!("mov {}, rax", limit));
+        let variable = self.declare_variable(&for_statement.identifier.span.literal, Type::Int);
+
+        let start = self.new_label();
+        let increment = self.new_label();
+        let end = self.new_label();
+        let exit_jump = if for_statement.is_inclusive() { "jg" } else { "jge" };
+        self.emit_label(&start);
+        self.emit(&format!("mov rax, {}", counter));
+        self.emit(&format!("cmp rax, {}", limit));
+        self.emit(&format!("{} {}", exit_jump, end));
+        self.emit(&format!("mov {}, rax", variable));
+        let labels = LoopLabels { continue_label: increment.clone(), break_label: end.clone() };
+        self.generate_loop_body(&for_statement.body, labels);
+
+        self.emit_label(&increment);
+        if for_statement.is_inclusive() {
+            self.emit(&format!("mov rax, {}", counter));
+            self.emit(&format!("cmp rax, {}", limit));
+            self.emit(&format!("je {}", end));
+        }
+        self.emit(&format!("inc {}", counter));
+        self.emit(&format!("jmp {}", start));
+        self.emit_label(&end);
+        self.context.scopes.pop();
+    }
+
+    fn visit_block_statement(&mut self, block_statement: &ASTBlockStatement) {
+        self.context.scopes.push(HashMap::new());
+        for statement in &block_statement.statements {
+            self.visit_statement(statement);
+        }
+        self.context.scopes.pop();
+    }
+
+    fn visit_if_statement(&mut self, if_statement: &ASTIfStatement) {
+        let else_label = self.new_label();
+        let end = self.new_label();
+        let condition = self.generate_expression(&if_statement.condition);
+        self.expect_bool(&condition, "conditions", if_statement.condition.span());
+        self.emit("test rax, rax");
+        self.emit(&format!("jz {}", else_label));
+        self.generate_scoped_statement(&if_statement.then_branch);
+        self.emit(&format!("jmp {}", end));
+        self.emit_label(&else_label);
+        if let Some(else_branch) = &if_statement.else_branch {
+            self.generate_scoped_statement(&else_branch.else_statement);
+        }
+        self.emit_label(&end);
+    }
+
+    fn visit_let_statement(&mut self, let_statement: &ASTLetStatement) {
+        let ty = self.generate_expression(&let_statement.initializer);
+        let operand = self.declare_variable(&let_statement.identifier.span.literal, ty);
+        self.emit(&format!("mov {}, rax", operand));
+    }
+
+    fn visit_call_expression(&mut self, call_expression: &ASTCallExpression) {
+        let identifier = &call_expression.identifier.span.literal;
+        let function = self.global_scope.lookup_function(identifier).unwrap();
+        if let FunctionBody::Intrinsic(intrinsic) = &function.body {
+            self.generate_intrinsic_call(*intrinsic, call_expression);
+            return;
+        }
+
+        let argument_types = call_expression.arguments
+            .iter()
+            .map(|argument| {
+                let ty = self.generate_expression(argument);
+                self.emit("push rax");
+                ty
+            })
+            .collect();
+        let label = self.instantiate(identifier, argument_types);
+        self.emit(&format!("call {}", label));
+        if !call_expression.arguments.is_empty() {
+            self.emit(&format!("add rsp, {}", 8 * call_expression.arguments.len()));
+        }
+        self.last_type = match self.instances[&label].clone() {
+            Some(return_type) => return_type,
+            None => {
+                let feature = format!(
+                    "inferring the return type of recursive function '{}' before its first return",
+                    identifier
+                );
+                self.report_unsupported(feature, call_expression.identifier.span.clone());
+                Type::Error
+            }
+        };
+    }
+
+    fn visit_assignment_expression(&mut self, assignment_expression: &ASTAssignmentExpression) {
+        let ty = self.generate_expression(&assignment_expression.expression);
+        let identifier = &assignment_expression.identifier;
+        match self.lookup_variable(&identifier.span.literal) {
+            Some(variable) => {
+                let operand = variable.operand.clone();
+                let expected = variable.ty.clone();
+                if !Self::is_compatible(&expected, &ty) {
+                    let feature = format!(
+                        "assigning '{}' to '{}' of type '{}'",
+                        ty,
+                        identifier.span.literal,
+                        expected
+                    );
+                    self.report_unsupported(feature, identifier.span.clone());
+                }
+                self.emit(&format!("mov {}, rax", operand));
+            }
+            None => self.report_used_before_declaration(identifier),
+        }
+        self.last_type = ty;
+    }
+
+    fn visit_variable_expression(&mut self, variable_expression: &ASTVariableExpression) {
+        match self.lookup_variable(variable_expression.identifier()) {
+            Some(variable) => {
+                let instruction = format!("mov rax, {}", variable.operand);
+                self.last_type = variable.ty.clone();
+                self.emit(&instruction);
+            }
+            None => {
+                self.report_used_before_declaration(&variable_expression.identifier);
+                self.last_type = Type::Error;
+            }
+        }
+    }
+
+    fn visit_number_expression(&mut self, number: &ASTNumberExpression) {
+        self.emit(&format!("mov rax, {}", number.number));
+        self.last_type = Type::Int;
+    }
+
+    fn visit_float_expression(&mut self, float: &ASTFloatExpression) {
+        self.report_unsupported("floats".to_string(), float.token.span.clone());
+        self.last_type = Type::Error;
+    }
+
+    fn visit_string_expression(&mut self, string: &ASTStringExpression) {
+        let label = self.string_label(&string.value);
+        self.emit(&format!("lea rax, [rip + {}]", label));
+        self.last_type = Type::String;
+    }
+
+    fn visit_boolean_expression(&mut self, boolean: &ASTBooleanExpression) {
+        self.emit(&format!("mov eax, {}", boolean.value as u8));
+        self.last_type = Type::Bool;
+    }
+
+    fn visit_error(&mut self, _span: &TextSpan) {
+        unreachable!("Programs with syntax errors are never generated");
+    }
+
+    fn visit_unary_expression(&mut self, unary_expression: &ASTUnaryExpression) {
+        let operand = self.generate_expression(&unary_expression.operand);
+        match (unary_expression.operator.kind, &operand) {
+            (ASTUnaryOperatorKind::Minus, Type::Int) => {
+                self.emit("neg rax");
+                let overflow = Self::overflow_label(ASTBinaryOperatorKind::Minus);
+                self.emit(&format!("jo {}", overflow));
+            }
+            (ASTUnaryOperatorKind::BitwiseNot, Type::Int) => self.emit("not rax"),
+            (ASTUnaryOperatorKind::LogicalNot, Type::Bool) => self.emit("xor eax, 1"),
+            (_, Type::Error) => {}
+            (_, _) => {
+                let feature = format!(
+                    "'{}' on '{}'",
+                    unary_expression.operator.token.span.literal,
+                    operand
+                );
+                self.report_unsupported(feature, unary_expression.operator.token.span.clone());
+            }
+        }
+        self.last_type = operand;
+    }
+
+    fn visit_binary_expression(&mut self, binary_expression: &ASTBinaryExpression) {
+        let operator = binary_expression.operator.kind;
+        if
+            matches!(operator, ASTBinaryOperatorKind::LogicalAnd | ASTBinaryOperatorKind::LogicalOr)
+        {
+            self.generate_logical_expression(binary_expression);
+            return;
+        }
+
+        let left = self.generate_expression(&binary_expression.left);
+        self.emit("push rax");
+        let right = self.generate_expression(&binary_expression.right);
+        self.emit("mov rcx, rax");
+        self.emit("pop rax");
+
+        let is_equality = matches!(
+            operator,
+            ASTBinaryOperatorKind::Equals | ASTBinaryOperatorKind::NotEquals
+        );
+        self.last_type = match (&left, &right) {
+            (Type::Error, _) | (_, Type::Error) => Type::Error,
+            (Type::Int, Type::Int) => self.generate_int_operation(operator),
+            (Type::Bool, Type::Bool) if is_equality => self.generate_int_operation(operator),
+            (Type::String, Type::String) if operator == ASTBinaryOperatorKind::Plus => {
+                self.emit("mov rdi, rax");
+                self.emit("mov rsi, rcx");
+                self.emit("call tn_concat");
+                Type::String
+            }
+            (Type::String, Type::String) if is_equality => {
+                self.emit("mov rdi, rax");
+                self.emit("mov rsi, rcx");
+                self.emit("call tn_string_equals");
+                if operator == ASTBinaryOperatorKind::NotEquals {
+                    self.emit("xor eax, 1");
+                }
+                Type::Bool
+            }
+            (left, right) if is_equality && (left != right || *left == Type::Unit) => {
+                let is_equal = left == right;
+                let result = is_equal == (operator == ASTBinaryOperatorKind::Equals);
+                self.emit(&format!("mov eax, {}", result as u8));
+                Type::Bool
+            }
+            (left, right) => {
+                let feature = format!(
+                    "'{}' between '{}' and '{}'",
+                    operator.symbol(),
+                    left,
+                    right
+                );
+                self.report_unsupported(feature, binary_expression.operator.token.span.clone());
+                Type::Error
+            }
+        };
+    }
+}
+
+// The source without its extension unless one is given, or `<name>.out` for a source that has
+// none; building never replaces the source itself
+pub fn output_path(source: &Path, output: Option<&Path>) -> Result<PathBuf, String> {
+    let output = match output {
+        Some(output) => output.to_path_buf(),
+        None if source.extension().is_none() => source.with_extension("out"),
+        None => source.with_extension(""),
+    };
+    let canonical = |path: &Path| fs::canonicalize(path).ok();
+    if output == source || canonical(&output).is_some_and(|path| canonical(source) == Some(path)) {
+        return Err(format!("Refusing to overwrite the source file '{}'", source.display()));
+    }
+    Ok(output)
+}
+
+// Assembles and links the generated assembly into a static executable with the system `as`
+// and `ld`
+pub fn build_executable(assembly: &str, output: &Path) -> Result<(), String> {
+    // Unique per build, since tests build several programs at once
+    static BUILD_COUNT: AtomicUsize = AtomicUsize::new(0);
+    let stem = format!(
+        "tungsten-{}-{}",
+        std::process::id(),
+        BUILD_COUNT.fetch_add(1, Ordering::Relaxed)
+    );
+    let source = std::env::temp_dir().join(format!("{}.s", stem));
+    let object = std::env::temp_dir().join(format!("{}.o", stem));
+
+    fs::write(&source, assembly).map_err(|error| {
+        format!("Could not write '{}': {}", source.display(), error)
+    })?;
+    let result = run_tool("as", &[&source, &PathBuf::from("-o"), &object]).and_then(|_| {
+        run_tool("ld", &[&object, &PathBuf::from("-o"), output])
+    });
+    let _ = fs::remove_file(&source);
+    let _ = fs::remove_file(&object);
+    result
+}
+
+fn run_tool(program: &str, arguments: &[&Path]) -> Result<(), String> {
+    let result = Command::new(program)
+        .args(arguments)
+        .output()
+        .map_err(|error| format!("Could not run '{}': {}", program, error))?;
+    if !result.status.success() {
+        return Err(
+            format!("'{}' failed:\n{}", program, String::from_utf8_lossy(&result.stderr))
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+    use std::process::Command;
+    use std::sync::atomic::{ AtomicUsize, Ordering };
+    use std::thread;
+
+    use crate::ast::evaluator::ASTEvaluator;
+    use crate::codegen::x86_64::{ build_executable, output_path };
+    use crate::compilation_unit::CompilationUnit;
+    use crate::diagnostics::DiagnosticKind;
+
+    struct Execution {
+        output: String,
+        exit_code: i32,
+    }
+
+    fn is_toolchain_available() -> bool {
+        Command::new("as").arg("--version").output().is_ok() &&
+            Command::new("ld").arg("--version").output().is_ok()
+    }
+
+    fn interpret(compilation_unit: &CompilationUnit) -> Execution {
+        let mut output = Vec::new();
+        let mut eval = ASTEvaluator::new(&compilation_unit.global_scope, &mut output);
+        compilation_unit.execute(&mut eval);
+        let exit_code = eval.exit_code() as u8 as i32;
+        Execution { output: String::from_utf8(output).unwrap(), exit_code }
+    }
+
+    // Builds and runs the program natively, checking it behaves like the interpreter. Skipped
+    // when the system has no assembler or linker.
+    fn assert_matches_interpreter(input: &str) -> Option<Execution> {
+        if !is_toolchain_available() {
+            return None;
+        }
+        let compilation_unit = CompilationUnit::compile(input).expect("Failed to compile");
+        let assembly = compilation_unit.compile_x86_64().expect("Failed to generate assembly");
+        let executable = executable_path();
+        build_executable(&assembly, &executable).expect("Failed to build executable");
+        let result = Command::new(&executable).output().expect("Failed to run executable");
+        let _ = std::fs::remove_file(&executable);
+
+        let native = Execution {
+            output: String::from_utf8(result.stdout).unwrap(),
+            exit_code: result.status.code().unwrap(),
+        };
+        let interpreted = interpret(&compilation_unit);
+        assert_eq!(native.output, interpreted.output);
+        assert_eq!(native.exit_code, interpreted.exit_code);
+        Some(native)
+    }
+
+    fn executable_path() -> PathBuf {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let count = COUNT.fetch_add(1, Ordering::Relaxed);
+        std::env::temp_dir().join(format!("tungsten-test-{}-{}", std::process::id(), count))
+    }
+
+    fn unsupported_diagnostics(input: &str) -> Vec<String> {
+        let compilation_unit = CompilationUnit::compile(input).expect("Failed to compile");
+        assert!(compilation_unit.compile_x86_64().is_err());
+        let diagnostics = compilation_unit.diagnostics_bag.borrow();
+        diagnostics.diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.kind == DiagnosticKind::Error)
+            .map(|diagnostic| diagnostic.message.clone())
+            .collect()
+    }
+
+    #[test]
+    fn should_run_showcase_natively() {
+        assert_matches_interpreter(include_str!("../../examples/showcase.tn"));
+    }
+
+    #[test]
+    fn should_run_recursive_functions_natively() {
+        let input =
+            "\
+        func fib(n) {
+            if n < 2 {
+                return n
+            }
+            return fib(n - 1) + fib(n - 2)
+        }
+        println(fib(20))
+        ";
+
+        let execution = assert_matches_interpreter(input);
+        assert!(execution.is_none_or(|execution| execution.output == "6765\n"));
+    }
+
+    #[test]
+    fn should_specialize_functions_for_each_argument_type() {
+        let input =
+            "\
+        func same(a, b) {
+            return a == b
+        }
+        println(same(1, 1))
+        println(same(\"a\", \"b\"))
+        println(same(true, 1))
+        println(\"con\" + \"cat\" + \"enated\")
+        ";
+
+        assert_matches_interpreter(input);
+    }
+
+    #[test]
+    fn should_run_loops_and_arithmetic_natively() {
+        let input =
+            "\
+        let total = 0
+        for i in 1..=10 {
+            if i == 3 {
+                continue
+            }
+            if i > 8 {
+                break
+            }
+            total = total + i * 2 ** 3 - -i / 2
+        }
+        let j = 0
+        while true {
+            j = j + 1
+            if j >= 5 && !(total < 0) || false {
+                break
+            }
+        }
+        print(total)
+        print(\" \")
+        print(~j ^ 6 | 1 & 3)
+        println(-9223372036854775807 - 1)
+        ";
+
+        assert_matches_interpreter(input);
+    }
+
+    #[test]
+    fn should_exit_with_code_natively() {
+        let input = "\
+        println(1)
+        exit(42)
+        println(2)
+        ";
+
+        let execution = assert_matches_interpreter(input);
+        assert!(execution.is_none_or(|execution| execution.exit_code == 42));
+    }
+
+    #[test]
+    fn should_fail_natively_on_runtime_errors() {
+        assert_matches_interpreter("println(1)\nlet zero = 0\nprintln(1 / zero)");
+        assert_matches_interpreter("let big = 9223372036854775807\nprintln(big + 1)");
+        assert_matches_interpreter("println(2 ** -1)");
+        assert_matches_interpreter("assert(1 > 2)");
+    }
+
+    #[test]
+    fn should_overflow_the_stack_like_the_interpreter() {
+        let declaration =
+            "\
+        func d(n) {
+            if n == 0 {
+                return 0
+            }
+            return d(n - 1) + 1
+        }
+        func forever(n) {
+            if n < 0 {
+                return 0
+            }
+            return forever(n + 1)
+        }
+        ";
+        // The interpreter recurses natively, and test threads have small stacks
+        let check = |input: String| {
+            thread::Builder::new()
+                .stack_size(64 * 1024 * 1024)
+                .spawn(move || assert_matches_interpreter(&input))
+                .unwrap()
+                .join()
+                .unwrap()
+        };
+        for call in ["d(999)", "d(1000)", "d(1500)", "forever(0)"] {
+            check(format!("{}println(1)\nprintln({})", declaration, call));
+            check(format!("{}func main() {{\n    println({})\n}}", declaration, call));
+        }
+    }
+
+    #[test]
+    fn should_report_conditions_that_are_not_bools() {
+        let declaration = "func f(a) {\n    return a\n}\n";
+        let cases = [
+            ("if f(1) {\n    println(1)\n}", "conditions of type 'int'"),
+            ("while f(0) {\n    println(1)\n}", "conditions of type 'int'"),
+            ("assert(f(1))", "assertions of type 'int'"),
+            ("println(f(2) && true)", "'&&' on operands of type 'int'"),
+        ];
+        for (statement, usage) in cases {
+            let input = format!("{}{}", declaration, statement);
+            let compilation_unit = CompilationUnit::compile(&input).expect("Failed to compile");
+            assert!(interpret(&compilation_unit).exit_code != 0, "{}", statement);
+
+            let diagnostics = unsupported_diagnostics(&input);
+            assert_eq!(diagnostics, vec![format!("The x86-64 backend does not support {}", usage)]);
+        }
+        assert_matches_interpreter(&format!("{}println(f(true) || f(false))", declaration));
+    }
+
+    #[test]
+    fn should_report_floats_as_unsupported() {
+        let diagnostics = unsupported_diagnostics("let a = 1.5\nlet b = to_float(1)");
+
+        assert_eq!(
+            diagnostics,
+            vec![
+                "The x86-64 backend does not support floats".to_string(),
+                "The x86-64 backend does not support floats".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn should_never_build_over_the_source() {
+        let source = executable_path();
+        std::fs::write(&source, "println(1)").unwrap();
+        let with_extension = source.with_extension("tn");
+
+        assert_eq!(output_path(&with_extension, None), Ok(source.clone()));
+        assert_eq!(output_path(&source, None), Ok(source.with_extension("out")));
+        assert!(output_path(&source, Some(&source)).is_err());
+        let directory = source.parent().unwrap();
+        let detour = directory.join("..").join(directory.file_name().unwrap());
+        assert!(output_path(&source, Some(&detour.join(source.file_name().unwrap()))).is_err());
+        let _ = std::fs::remove_file(&source);
+    }
+}
@@ -1,36 +1,84 @@
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{ HashMap, HashSet };
+use std::io;
 use std::rc::Rc;
 
 use crate::{ diagnostics, text };
 use crate::ast::{
     Ast,
+    ASTAssignmentExpression,
+    ASTBinaryExpression,
+    ASTBinaryOperatorKind,
     ASTBlockStatement,
     ASTBooleanExpression,
+    ASTBreakStatement,
     ASTCallExpression,
+    ASTContinueStatement,
+    ASTExpression,
+    ASTFloatExpression,
+    ASTForStatement,
     ASTFuncDeclStatement,
     ASTIfStatement,
     ASTLetStatement,
     ASTNumberExpression,
+    ASTReturnStatement,
     ASTStatement,
+    ASTStringExpression,
     ASTUnaryExpression,
+    ASTUnaryOperatorKind,
     ASTVariableExpression,
+    ASTWhileStatement,
 };
-use crate::ast::evaluator::ASTEvaluator;
-use crate::ast::lexer::{ Lexer, TextSpan };
+use crate::ast::evaluator::{ ASTEvaluator, RuntimeError };
+use crate::ast::formatter::ASTFormatter;
+use crate::ast::lexer::{ Lexer, TextSpan, Token, TokenKind };
+use crate::ast::optimizer::{ ASTOptimizer, ExpressionTypes };
 use crate::ast::parser::Parser;
 use crate::ast::visitor::ASTVisitor;
-use crate::diagnostics::DiagnosticsBagCell;
-use crate::diagnostics::printer::DiagnosticsPrinter;
+use crate::bytecode::Program;
+use crate::bytecode::compiler::BytecodeCompiler;
+use crate::bytecode::vm::VirtualMachine;
+use crate::codegen::c::CBackend;
+use crate::codegen::wat::WatCodegen;
+use crate::codegen::x86_64::X86_64Codegen;
+use crate::diagnostics::{ Diagnostic, DiagnosticsBag, DiagnosticsBagCell };
+use crate::diagnostics::printer::{ DiagnosticsOutput, DiagnosticsPrinter };
+use crate::intrinsics::Intrinsic;
+use crate::types::Type;
 
+#[derive(Clone)]
 pub struct GlobalScope {
-    variables: HashMap<String, ()>,
+    // Top-level variables, which functions can reach as well
+    variables: HashMap<String, VariableSymbol>,
     pub functions: HashMap<String, FunctionSymbol>,
 }
 
+#[derive(Clone)]
+struct VariableSymbol {
+    ty: Type,
+    // Index of the declaration in the resolver's `Names`, for variables the resolver declared
+    declaration: Option<usize>,
+}
+
+#[derive(Clone)]
+pub struct ParameterSymbol {
+    pub identifier: String,
+    pub ty: Type,
+}
+
+#[derive(Clone)]
+pub enum FunctionBody {
+    Declared(Box<ASTStatement>),
+    Intrinsic(Intrinsic),
+}
+
+#[derive(Clone)]
 pub struct FunctionSymbol {
-    pub parameters: Vec<String>,
-    pub body: ASTStatement,
+    pub parameters: Vec<ParameterSymbol>,
+    pub body: FunctionBody,
+    pub return_type: Type,
+    // The name in the declaration; intrinsics have none
+    pub declaration: Option<TextSpan>,
 }
 
 impl GlobalScope {
@@ -41,39 +89,82 @@ impl GlobalScope {
         }
     }
 
-    fn declare_variable(&mut self, identifier: &str) {
-        self.variables.insert(identifier.to_string(), ());
+    // The scope every program starts from
+    pub(crate) fn with_intrinsics() -> Self {
+        let mut global_scope = GlobalScope::new();
+        global_scope.declare_intrinsics();
+        global_scope
+    }
+
+    // Spans from earlier inputs of a session point into text that is gone
+    fn forget_declarations(&mut self) {
+        for function in self.functions.values_mut() {
+            function.declaration = None;
+        }
+        for variable in self.variables.values_mut() {
+            variable.declaration = None;
+        }
+    }
+
+    fn declare_variable(&mut self, identifier: &str, variable: VariableSymbol) {
+        self.variables.insert(identifier.to_string(), variable);
     }
 
-    fn lookup_variable(&self, identifier: &str) -> bool {
-        self.variables.get(identifier).is_some()
+    fn lookup_variable(&self, identifier: &str) -> Option<&VariableSymbol> {
+        self.variables.get(identifier)
     }
 
     fn declare_function(
         &mut self,
         identifier: &str,
-        function: &ASTStatement,
-        parameters: Vec<String>
+        body: FunctionBody,
+        parameters: Vec<ParameterSymbol>,
+        return_type: Type,
+        declaration: Option<TextSpan>
     ) -> Result<(), ()> {
         if self.functions.contains_key(identifier) {
             return Err(());
         }
         let function = FunctionSymbol {
             parameters,
-            body: function.clone(),
+            body,
+            return_type,
+            declaration,
         };
 
         self.functions.insert(identifier.to_string(), function);
         Ok(())
     }
 
+    fn declare_intrinsics(&mut self) {
+        for intrinsic in Intrinsic::ALL {
+            let parameters = intrinsic
+                .parameters()
+                .into_iter()
+                .map(|(identifier, ty)| ParameterSymbol { identifier: identifier.to_string(), ty })
+                .collect();
+            self.declare_function(
+                intrinsic.name(),
+                FunctionBody::Intrinsic(intrinsic),
+                parameters,
+                intrinsic.return_type(),
+                None
+            ).expect("Intrinsics are declared before any user function");
+        }
+    }
+
     pub fn lookup_function(&self, identifier: &str) -> Option<&FunctionSymbol> {
         self.functions.get(identifier)
     }
+
+    // A program that declares `main` starts there and skips its top-level statements
+    pub fn main_function(&self) -> Option<&FunctionSymbol> {
+        self.lookup_function("main").filter(|main| matches!(main.body, FunctionBody::Declared(_)))
+    }
 }
 
 struct LocalScope {
-    variables: HashMap<String, ()>,
+    variables: HashMap<String, VariableSymbol>,
 }
 
 impl LocalScope {
@@ -83,28 +174,21 @@ impl LocalScope {
         }
     }
 
-    fn declare_variable(&mut self, identifier: &str) {
-        self.variables.insert(identifier.to_string(), ());
+    fn declare_variable(&mut self, identifier: &str, variable: VariableSymbol) {
+        self.variables.insert(identifier.to_string(), variable);
     }
 
-    fn lookup_variable(&self, identifier: &str) -> bool {
-        self.variables.get(identifier).is_some()
+    fn lookup_variable(&self, identifier: &str) -> Option<&VariableSymbol> {
+        self.variables.get(identifier)
     }
 }
 
-struct Scopes {
+pub(crate) struct Scopes {
     local_scopes: Vec<LocalScope>,
     global_scope: GlobalScope,
 }
 
 impl Scopes {
-    fn new() -> Self {
-        Scopes {
-            local_scopes: Vec::new(),
-            global_scope: GlobalScope::new(),
-        }
-    }
-
     fn from_global_scope(global_scope: GlobalScope) -> Self {
         Scopes {
             local_scopes: Vec::new(),
@@ -112,33 +196,57 @@ impl Scopes {
         }
     }
 
-    fn enter_scope(&mut self) {
+    // Scopes that only track variables, for passes that look functions up in a checked unit
+    pub(crate) fn for_variables() -> Self {
+        Self::from_global_scope(GlobalScope::new())
+    }
+
+    pub(crate) fn enter_scope(&mut self) {
         self.local_scopes.push(LocalScope::new());
     }
 
-    fn exit_scope(&mut self) {
+    pub(crate) fn exit_scope(&mut self) {
         self.local_scopes.pop();
     }
 
-    fn declare_variable(&mut self, identifier: &str) {
+    pub(crate) fn declare_variable(&mut self, identifier: &str, ty: Type) {
+        self.declare_variable_symbol(identifier, VariableSymbol { ty, declaration: None });
+    }
+
+    fn declare_variable_symbol(&mut self, identifier: &str, variable: VariableSymbol) {
         if self.is_inside_local_scope() {
-            self.local_scopes.last_mut().unwrap().declare_variable(identifier);
+            self.local_scopes.last_mut().unwrap().declare_variable(identifier, variable);
         } else {
-            self.global_scope.declare_variable(identifier);
+            self.global_scope.declare_variable(identifier, variable);
         }
     }
 
-    fn lookup_variable(&self, identifier: &str) -> bool {
+    pub(crate) fn lookup_variable(&self, identifier: &str) -> Option<&Type> {
+        self.lookup_variable_symbol(identifier).map(|variable| &variable.ty)
+    }
+
+    fn lookup_variable_symbol(&self, identifier: &str) -> Option<&VariableSymbol> {
         let inside_of_local_scope = self.local_scopes
             .iter()
             .rev()
-            .any(|scope| scope.lookup_variable(identifier));
-        if inside_of_local_scope {
-            return true;
+            .find_map(|scope| scope.lookup_variable(identifier));
+        if inside_of_local_scope.is_some() {
+            return inside_of_local_scope;
         }
         self.global_scope.lookup_variable(identifier)
     }
 
+    // Index of the innermost local scope that declares the variable, or None for a global
+    pub(crate) fn lookup_local_depth(&self, identifier: &str) -> Option<usize> {
+        self.local_scopes
+            .iter()
+            .rposition(|scope| scope.lookup_variable(identifier).is_some())
+    }
+
+    pub(crate) fn depth(&self) -> usize {
+        self.local_scopes.len()
+    }
+
     fn lookup_function(&self, identifier: &str) -> Option<&FunctionSymbol> {
         self.global_scope.lookup_function(identifier)
     }
@@ -148,9 +256,46 @@ impl Scopes {
     }
 }
 
+pub(crate) enum VariableKind {
+    // The annotation as written, and the initializer the type checker infers a type for
+    Let {
+        type_annotation: Option<String>,
+        initializer: TextSpan,
+    },
+    Parameter {
+        type_annotation: Option<String>,
+    },
+    LoopVariable,
+}
+
+pub(crate) struct VariableDeclaration {
+    pub(crate) identifier: TextSpan,
+    pub(crate) kind: VariableKind,
+}
+
+pub(crate) enum Reference {
+    // Index into `Names::variables`
+    Variable(usize),
+    // Looked up in the global scope, where the first declaration of a name wins
+    Function(String),
+}
+
+// What the resolver found every name in the tree to refer to, for editor features
+#[derive(Default)]
+pub(crate) struct Names {
+    pub(crate) variables: Vec<VariableDeclaration>,
+    // Every identifier that names a variable or function, including the ones declaring it
+    pub(crate) references: Vec<(TextSpan, Reference)>,
+    // The name of each function declaration, with the span from `func` to the end of its body
+    pub(crate) functions: Vec<(TextSpan, TextSpan)>,
+}
+
 struct Resolver {
     scopes: Scopes,
     diagnostics: DiagnosticsBagCell,
+    // Number of loops enclosing the current statement within the current function
+    loop_depth: usize,
+    names: Names,
 }
 
 impl Resolver {
@@ -158,7 +303,52 @@ impl Resolver {
         Resolver {
             scopes,
             diagnostics,
+            loop_depth: 0,
+            names: Names::default(),
+        }
+    }
+
+    fn declare_variable(&mut self, identifier: &Token, kind: VariableKind) {
+        let declaration = self.names.variables.len();
+        self.names.variables.push(VariableDeclaration { identifier: identifier.span.clone(), kind });
+        self.names.references.push((identifier.span.clone(), Reference::Variable(declaration)));
+        let variable = VariableSymbol { ty: Type::Unresolved, declaration: Some(declaration) };
+        self.scopes.declare_variable_symbol(&identifier.span.literal, variable);
+    }
+
+    // Whether the variable is declared at all; only the resolver's own declarations are named
+    fn refer_to_variable(&mut self, identifier: &Token) -> bool {
+        let Some(variable) = self.scopes.lookup_variable_symbol(&identifier.span.literal) else {
+            return false;
+        };
+        if let Some(declaration) = variable.declaration {
+            self.names.references.push((identifier.span.clone(), Reference::Variable(declaration)));
         }
+        true
+    }
+
+    fn refer_to_function(&mut self, identifier: &Token) {
+        let reference = Reference::Function(identifier.span.literal.clone());
+        self.names.references.push((identifier.span.clone(), reference));
+    }
+
+    fn visit_loop_body(&mut self, body: &ASTStatement) {
+        self.loop_depth += 1;
+        self.visit_statement(body);
+        self.loop_depth -= 1;
+    }
+}
+
+// Without an annotation the type checker accepts any type, and the runtime reports values that
+// turn out to be of the wrong type
+fn resolve_type_annotation(diagnostics: &DiagnosticsBagCell, type_name: Option<&Token>) -> Type {
+    match type_name {
+        None => Type::Unresolved,
+        Some(type_name) =>
+            Type::from_name(&type_name.span.literal).unwrap_or_else(|| {
+                diagnostics.borrow_mut().report_undeclared_type(type_name);
+                Type::Error
+            }),
     }
 }
 
@@ -168,10 +358,10 @@ struct GlobalSymbolResolver {
 }
 
 impl GlobalSymbolResolver {
-    fn new(diagnostics: DiagnosticsBagCell) -> Self {
+    fn new(diagnostics: DiagnosticsBagCell, global_scope: GlobalScope) -> Self {
         GlobalSymbolResolver {
             diagnostics,
-            global_scope: GlobalScope::new(),
+            global_scope,
         }
     }
 }
@@ -180,21 +370,43 @@ impl ASTVisitor<'_> for GlobalSymbolResolver {
     fn visit_func_decl_statement(&mut self, func_decl_statement: &ASTFuncDeclStatement) {
         let parameters = func_decl_statement.parameters
             .iter()
-            .map(|parameter| parameter.identifier.span.literal.clone())
+            .map(|parameter| ParameterSymbol {
+                identifier: parameter.identifier.span.literal.clone(),
+                ty: resolve_type_annotation(
+                    &self.diagnostics,
+                    parameter.type_annotation.as_ref().map(|annotation| &annotation.type_name)
+                ),
+            })
             .collect();
+        let return_type = resolve_type_annotation(
+            &self.diagnostics,
+            func_decl_statement.return_type.as_ref().map(|return_type| &return_type.type_name)
+        );
         let literal_span = &func_decl_statement.identifier.span;
+        if literal_span.literal == "main" && !func_decl_statement.parameters.is_empty() {
+            let identifier = &func_decl_statement.identifier;
+            self.diagnostics.borrow_mut().report_main_with_parameters(identifier);
+        }
         match
             self.global_scope.declare_function(
                 literal_span.literal.as_str(),
-                &func_decl_statement.body,
-                parameters
+                FunctionBody::Declared(func_decl_statement.body.clone()),
+                parameters,
+                return_type,
+                Some(literal_span.clone())
             )
         {
             Ok(_) => {}
             Err(_) => {
+                let previous = self.global_scope.lookup_function(&literal_span.literal).unwrap();
+                let is_intrinsic = matches!(previous.body, FunctionBody::Intrinsic(_));
                 self.diagnostics
                     .borrow_mut()
-                    .report_function_already_declared(&func_decl_statement.identifier);
+                    .report_function_already_declared(
+                        &func_decl_statement.identifier,
+                        previous.declaration.as_ref(),
+                        is_intrinsic
+                    );
             }
         }
     }
@@ -204,6 +416,10 @@ impl ASTVisitor<'_> for GlobalSymbolResolver {
 
     fn visit_number_expression(&mut self, _number: &ASTNumberExpression) {}
 
+    fn visit_float_expression(&mut self, _float: &ASTFloatExpression) {}
+
+    fn visit_string_expression(&mut self, _string: &ASTStringExpression) {}
+
     fn visit_boolean_expression(&mut self, _boolean: &ASTBooleanExpression) {}
 
     fn visit_error(&mut self, _span: &TextSpan) {}
@@ -213,14 +429,45 @@ impl ASTVisitor<'_> for GlobalSymbolResolver {
 
 impl ASTVisitor<'_> for Resolver {
     fn visit_func_decl_statement(&mut self, func_decl_statement: &ASTFuncDeclStatement) {
+        let identifier = &func_decl_statement.identifier;
+        self.refer_to_function(identifier);
+        let span = TextSpan::combine(
+            vec![func_decl_statement.func_keyword.span.clone(), func_decl_statement.body.span()]
+        );
+        self.names.functions.push((identifier.span.clone(), span));
         self.scopes.enter_scope();
         for parameter in &func_decl_statement.parameters {
-            self.scopes.declare_variable(&parameter.identifier.span.literal);
+            let type_annotation = parameter.type_annotation
+                .as_ref()
+                .map(|annotation| annotation.type_name.span.literal.clone());
+            self.declare_variable(&parameter.identifier, VariableKind::Parameter { type_annotation });
         }
+        // Loops around a declaration cannot be left from inside the function
+        let loop_depth = std::mem::take(&mut self.loop_depth);
         self.visit_statement(&func_decl_statement.body);
+        self.loop_depth = loop_depth;
         self.scopes.exit_scope();
     }
 
+    fn visit_while_statement(&mut self, while_statement: &ASTWhileStatement) {
+        self.visit_expression(&while_statement.condition);
+        self.visit_loop_body(&while_statement.body);
+    }
+
+    fn visit_break_statement(&mut self, break_statement: &ASTBreakStatement) {
+        if self.loop_depth == 0 {
+            self.diagnostics.borrow_mut().report_outside_of_loop(&break_statement.break_keyword);
+        }
+    }
+
+    fn visit_continue_statement(&mut self, continue_statement: &ASTContinueStatement) {
+        if self.loop_depth == 0 {
+            self.diagnostics
+                .borrow_mut()
+                .report_outside_of_loop(&continue_statement.continue_keyword);
+        }
+    }
+
     fn visit_block_statement(&mut self, block_statement: &ASTBlockStatement) {
         self.scopes.enter_scope();
         for statement in &block_statement.statements {
@@ -241,10 +488,26 @@ impl ASTVisitor<'_> for Resolver {
         }
     }
 
+    fn visit_for_statement(&mut self, for_statement: &ASTForStatement) {
+        // The bounds are outside the loop, so they cannot see the loop variable
+        self.visit_expression(&for_statement.start);
+        self.visit_expression(&for_statement.end);
+        self.scopes.enter_scope();
+        self.declare_variable(&for_statement.identifier, VariableKind::LoopVariable);
+        self.visit_loop_body(&for_statement.body);
+        self.scopes.exit_scope();
+    }
+
     fn visit_let_statement(&mut self, let_statement: &ASTLetStatement) {
-        let identifier = let_statement.identifier.span.literal.clone();
         self.visit_expression(&let_statement.initializer);
-        self.scopes.declare_variable(&identifier);
+        let type_annotation = let_statement.type_annotation
+            .as_ref()
+            .map(|annotation| annotation.type_name.span.literal.clone());
+        let initializer = let_statement.initializer.span();
+        self.declare_variable(
+            &let_statement.identifier,
+            VariableKind::Let { type_annotation, initializer }
+        );
     }
 
     fn visit_call_expression(&mut self, call_expression: &ASTCallExpression) {
@@ -259,10 +522,12 @@ impl ASTVisitor<'_> for Resolver {
                     let mut diagnostics_binding = self.diagnostics.borrow_mut();
                     diagnostics_binding.report_invalid_argument_count(
                         &call_expression.identifier,
+                        function.declaration.as_ref(),
                         function.parameters.len(),
                         call_expression.arguments.len()
                     );
                 }
+                self.refer_to_function(&call_expression.identifier);
             }
         }
         for argument in &call_expression.arguments {
@@ -270,8 +535,13 @@ impl ASTVisitor<'_> for Resolver {
         }
     }
 
+    fn visit_assignment_expression(&mut self, assignment_expression: &ASTAssignmentExpression) {
+        self.visit_expression(&assignment_expression.expression);
+        self.refer_to_variable(&assignment_expression.identifier);
+    }
+
     fn visit_variable_expression(&mut self, variable_expression: &ASTVariableExpression) {
-        if !self.scopes.lookup_variable(&variable_expression.identifier.span.literal) {
+        if !self.refer_to_variable(&variable_expression.identifier) {
             let mut diagnostics_binding = self.diagnostics.borrow_mut();
             diagnostics_binding.report_undeclared_variable(&variable_expression.identifier);
         }
@@ -279,6 +549,10 @@ impl ASTVisitor<'_> for Resolver {
 
     fn visit_number_expression(&mut self, _number: &ASTNumberExpression) {}
 
+    fn visit_float_expression(&mut self, _float: &ASTFloatExpression) {}
+
+    fn visit_string_expression(&mut self, _string: &ASTStringExpression) {}
+
     fn visit_boolean_expression(&mut self, _boolean: &ASTBooleanExpression) {}
 
     fn visit_error(&mut self, _span: &TextSpan) {}
@@ -288,73 +562,921 @@ impl ASTVisitor<'_> for Resolver {
     }
 }
 
+struct TypeChecker {
+    scopes: Scopes,
+    diagnostics: DiagnosticsBagCell,
+    last_type: Type,
+    return_types: Vec<Type>,
+    expression_types: ExpressionTypes,
+}
+
+impl TypeChecker {
+    fn new(diagnostics: DiagnosticsBagCell, scopes: Scopes) -> Self {
+        TypeChecker {
+            scopes,
+            diagnostics,
+            last_type: Type::Unit,
+            return_types: Vec::new(),
+            expression_types: ExpressionTypes::new(),
+        }
+    }
+
+    fn check_expression(&mut self, expression: &ASTExpression) -> Type {
+        self.visit_expression(expression);
+        self.last_type.clone()
+    }
+
+    // Whether the operands fit a numeric operator, and the type they are both taken to have.
+    // An unresolved operand could turn out to be a float, so only a known one decides
+    fn numeric_operands(left: &Type, right: &Type) -> (bool, Type) {
+        let operand = match (left, right) {
+            (Type::Float, _) | (_, Type::Float) => Type::Float,
+            (Type::Unresolved, _) | (_, Type::Unresolved) => Type::Unresolved,
+            _ => Type::Int,
+        };
+        let is_numeric = |ty: &Type| {
+            matches!(ty, Type::Int | Type::Float | Type::Unresolved | Type::Error)
+        };
+        let is_valid =
+            is_numeric(left) &&
+            is_numeric(right) &&
+            left.is_assignable_to(&operand) &&
+            right.is_assignable_to(&operand);
+        (is_valid, operand)
+    }
+
+    fn expect_type(&mut self, expression: &ASTExpression, expected: &Type) {
+        let actual = self.check_expression(expression);
+        if !actual.is_assignable_to(expected) {
+            self.diagnostics
+                .borrow_mut()
+                .report_type_mismatch(&expression.span(), expected, &actual);
+        }
+    }
+}
+
+impl ASTVisitor<'_> for TypeChecker {
+    fn visit_func_decl_statement(&mut self, func_decl_statement: &ASTFuncDeclStatement) {
+        let function = self.scopes
+            .lookup_function(&func_decl_statement.identifier.span.literal)
+            .unwrap();
+        let parameters: Vec<(String, Type)> = function.parameters
+            .iter()
+            .map(|parameter| (parameter.identifier.clone(), parameter.ty.clone()))
+            .collect();
+        let return_type = function.return_type.clone();
+        self.return_types.push(return_type.clone());
+        self.scopes.enter_scope();
+        for (identifier, ty) in parameters {
+            self.scopes.declare_variable(&identifier, ty);
+        }
+        self.visit_statement(&func_decl_statement.body);
+        self.scopes.exit_scope();
+        self.return_types.pop();
+        self.last_type = Type::Unit;
+
+        let returns_value = !matches!(return_type, Type::Unit | Type::Unresolved | Type::Error);
+        if returns_value && !func_decl_statement.body.always_returns() {
+            let type_name = &func_decl_statement.return_type.as_ref().unwrap().type_name;
+            self.diagnostics
+                .borrow_mut()
+                .report_missing_return(&func_decl_statement.identifier, type_name);
+        }
+    }
+
+    fn visit_return_statement(&mut self, return_statement: &ASTReturnStatement) {
+        let (actual, span) = match &return_statement.return_value {
+            Some(expression) => (self.check_expression(expression), expression.span()),
+            None => (Type::Unit, return_statement.return_keyword.span.clone()),
+        };
+        if let Some(expected) = self.return_types.last() {
+            if !actual.is_assignable_to(expected) {
+                self.diagnostics.borrow_mut().report_type_mismatch(&span, expected, &actual);
+            }
+        }
+    }
+
+    fn visit_while_statement(&mut self, while_statement: &ASTWhileStatement) {
+        self.expect_type(&while_statement.condition, &Type::Bool);
+        self.visit_statement(&while_statement.body);
+        self.last_type = Type::Unit;
+    }
+
+    fn visit_for_statement(&mut self, for_statement: &ASTForStatement) {
+        self.expect_type(&for_statement.start, &Type::Int);
+        self.expect_type(&for_statement.end, &Type::Int);
+        self.scopes.enter_scope();
+        self.scopes.declare_variable(&for_statement.identifier.span.literal, Type::Int);
+        self.visit_statement(&for_statement.body);
+        self.scopes.exit_scope();
+        self.last_type = Type::Unit;
+    }
+
+    fn visit_block_statement(&mut self, block_statement: &ASTBlockStatement) {
+        self.scopes.enter_scope();
+        for statement in &block_statement.statements {
+            self.visit_statement(statement);
+        }
+        self.scopes.exit_scope();
+        self.last_type = Type::Unit;
+    }
+
+    fn visit_if_statement(&mut self, if_statement: &ASTIfStatement) {
+        self.scopes.enter_scope();
+        self.expect_type(&if_statement.condition, &Type::Bool);
+        self.visit_statement(&if_statement.then_branch);
+        self.scopes.exit_scope();
+        if let Some(else_branch) = &if_statement.else_branch {
+            self.scopes.enter_scope();
+            self.visit_statement(&else_branch.else_statement);
+            self.scopes.exit_scope();
+        }
+        self.last_type = Type::Unit;
+    }
+
+    fn visit_let_statement(&mut self, let_statement: &ASTLetStatement) {
+        let ty = match &let_statement.type_annotation {
+            Some(type_annotation) => {
+                let ty = resolve_type_annotation(
+                    &self.diagnostics,
+                    Some(&type_annotation.type_name)
+                );
+                self.expect_type(&let_statement.initializer, &ty);
+                ty
+            }
+            None => self.check_expression(&let_statement.initializer),
+        };
+        let identifier = &let_statement.identifier;
+        if !self.scopes.is_inside_local_scope() {
+            let previous = self.scopes.lookup_variable(&identifier.span.literal);
+            if let Some(previous) = previous.filter(|previous| !ty.is_assignable_to(previous)) {
+                self.diagnostics
+                    .borrow_mut()
+                    .report_top_level_variable_retyped(identifier, previous, &ty);
+            }
+        }
+        self.scopes.declare_variable(&identifier.span.literal, ty);
+    }
+
+    fn visit_call_expression(&mut self, call_expression: &ASTCallExpression) {
+        let arguments: Vec<Type> = call_expression.arguments
+            .iter()
+            .map(|argument| self.check_expression(argument))
+            .collect();
+        let function = self.scopes.lookup_function(&call_expression.identifier.span.literal);
+        self.last_type = match function {
+            None => Type::Error,
+            Some(function) => {
+                for ((parameter, argument), expression) in function.parameters
+                    .iter()
+                    .zip(arguments.iter())
+                    .zip(call_expression.arguments.iter()) {
+                    if !argument.is_assignable_to(&parameter.ty) {
+                        self.diagnostics
+                            .borrow_mut()
+                            .report_invalid_argument_type(
+                                &call_expression.identifier,
+                                &parameter.identifier,
+                                &expression.span(),
+                                &parameter.ty,
+                                argument
+                            );
+                    }
+                }
+                function.return_type.clone()
+            }
+        };
+    }
+
+    fn visit_expression(&mut self, expression: &ASTExpression) {
+        self.do_visit_expression(expression);
+        let span = expression.span();
+        self.expression_types.insert((span.start, span.end), self.last_type.clone());
+    }
+
+    fn visit_assignment_expression(&mut self, assignment_expression: &ASTAssignmentExpression) {
+        let expected = self.scopes
+            .lookup_variable(&assignment_expression.identifier.span.literal)
+            .cloned()
+            .unwrap_or(Type::Error);
+        self.expect_type(&assignment_expression.expression, &expected);
+        self.last_type = expected;
+    }
+
+    fn visit_variable_expression(&mut self, variable_expression: &ASTVariableExpression) {
+        self.last_type = self.scopes
+            .lookup_variable(variable_expression.identifier())
+            .cloned()
+            .unwrap_or(Type::Error);
+    }
+
+    fn visit_number_expression(&mut self, _number: &ASTNumberExpression) {
+        self.last_type = Type::Int;
+    }
+
+    fn visit_float_expression(&mut self, _float: &ASTFloatExpression) {
+        self.last_type = Type::Float;
+    }
+
+    fn visit_string_expression(&mut self, _string: &ASTStringExpression) {
+        self.last_type = Type::String;
+    }
+
+    fn visit_boolean_expression(&mut self, _boolean: &ASTBooleanExpression) {
+        self.last_type = Type::Bool;
+    }
+
+    fn visit_error(&mut self, _span: &TextSpan) {
+        self.last_type = Type::Error;
+    }
+
+    fn visit_unary_expression(&mut self, unary_expression: &ASTUnaryExpression) {
+        let operand = self.check_expression(&unary_expression.operand);
+        let expected = match unary_expression.operator.kind {
+            ASTUnaryOperatorKind::Minus if operand == Type::Float => Type::Float,
+            ASTUnaryOperatorKind::Minus if operand == Type::Unresolved => Type::Unresolved,
+            ASTUnaryOperatorKind::Minus | ASTUnaryOperatorKind::BitwiseNot => Type::Int,
+            ASTUnaryOperatorKind::LogicalNot => Type::Bool,
+        };
+        if !operand.is_assignable_to(&expected) {
+            self.diagnostics
+                .borrow_mut()
+                .report_invalid_unary_operator(&unary_expression.operator.token, &operand);
+        }
+        self.last_type = expected;
+    }
+
+    fn visit_binary_expression(&mut self, binary_expression: &ASTBinaryExpression) {
+        let left = self.check_expression(&binary_expression.left);
+        let right = self.check_expression(&binary_expression.right);
+        let (is_valid, result) = match binary_expression.operator.kind {
+            ASTBinaryOperatorKind::Plus if left == Type::String || right == Type::String => {
+                let is_valid =
+                    left.is_assignable_to(&Type::String) && right.is_assignable_to(&Type::String);
+                (is_valid, Type::String)
+            }
+            ASTBinaryOperatorKind::Plus |
+            ASTBinaryOperatorKind::Minus |
+            ASTBinaryOperatorKind::Multiply |
+            ASTBinaryOperatorKind::Divide |
+            ASTBinaryOperatorKind::Power => {
+                // Mixed int and float operands need an explicit to_float or to_int
+                Self::numeric_operands(&left, &right)
+            }
+            ASTBinaryOperatorKind::BitwiseAnd |
+            ASTBinaryOperatorKind::BitwiseOr |
+            ASTBinaryOperatorKind::BitwiseXor => {
+                let is_valid =
+                    left.is_assignable_to(&Type::Int) && right.is_assignable_to(&Type::Int);
+                (is_valid, Type::Int)
+            }
+            ASTBinaryOperatorKind::LessThan |
+            ASTBinaryOperatorKind::LessThanOrEqual |
+            ASTBinaryOperatorKind::GreaterThan |
+            ASTBinaryOperatorKind::GreaterThanOrEqual => {
+                let (is_valid, _) = Self::numeric_operands(&left, &right);
+                (is_valid, Type::Bool)
+            }
+            ASTBinaryOperatorKind::Equals | ASTBinaryOperatorKind::NotEquals => {
+                (left.is_assignable_to(&right), Type::Bool)
+            }
+            ASTBinaryOperatorKind::LogicalAnd | ASTBinaryOperatorKind::LogicalOr => {
+                let is_valid =
+                    left.is_assignable_to(&Type::Bool) && right.is_assignable_to(&Type::Bool);
+                (is_valid, Type::Bool)
+            }
+        };
+        if !is_valid {
+            self.diagnostics
+                .borrow_mut()
+                .report_invalid_binary_operator(&binary_expression.operator.token, &left, &right);
+        }
+        self.last_type = result;
+    }
+}
+
+struct Binding {
+    identifier: Token,
+    is_parameter: bool,
+    is_read: bool,
+}
+
+// Warns about code that never runs and declarations that are never used
+struct FlowAnalyzer<'a> {
+    text: &'a text::SourceText,
+    // Collected here first so they can be reported in source order
+    diagnostics: DiagnosticsBag,
+    // Mirrors the resolver's scopes; the first one holds top-level variables
+    scopes: Vec<Vec<Binding>>,
+    functions: Vec<Token>,
+    called_functions: HashSet<String>,
+    // Calls a function makes to itself do not count as calls
+    function_names: Vec<String>,
+    // Whether control never continues past the statement just visited
+    exits: bool,
+    // Whether the statement just visited declares a function
+    declares_function: bool,
+}
+
+impl<'a> FlowAnalyzer<'a> {
+    // In a session, later inputs may still use top-level declarations, so they are not reported
+    fn analyze(
+        ast: &Ast,
+        text: &'a text::SourceText,
+        diagnostics: DiagnosticsBagCell,
+        is_session: bool
+    ) {
+        let mut analyzer = FlowAnalyzer {
+            text,
+            diagnostics: DiagnosticsBag::new(),
+            scopes: vec![Vec::new()],
+            functions: Vec::new(),
+            called_functions: HashSet::new(),
+            function_names: Vec::new(),
+            exits: false,
+            declares_function: false,
+        };
+        analyzer.visit_statements(&ast.statements);
+        if !is_session {
+            analyzer.exit_scope();
+            for function in &analyzer.functions {
+                let name = &function.span.literal;
+                let is_called = analyzer.called_functions.contains(name);
+                if !is_called && name != "main" && !name.starts_with('_') {
+                    analyzer.diagnostics.report_uncalled_function(function);
+                }
+            }
+        }
+        let mut warnings = analyzer.diagnostics.diagnostics;
+        warnings.sort_by_key(|warning| warning.span.start);
+        let mut diagnostics = diagnostics.borrow_mut();
+        for warning in warnings {
+            diagnostics.report(warning);
+        }
+    }
+
+    // Reports the first statement after one that always exits, and whether the sequence exits
+    fn visit_statements(&mut self, statements: &[ASTStatement]) -> bool {
+        let mut exits = false;
+        let mut reported = false;
+        for statement in statements {
+            self.exits = false;
+            self.declares_function = false;
+            self.visit_statement(statement);
+            // Declarations are hoisted, so they are never unreachable
+            if exits && !reported && !self.declares_function {
+                let span = statement.span();
+                self.diagnostics.report_unreachable_code(self.text.span(span.start, span.end));
+                reported = true;
+            }
+            exits |= self.exits;
+        }
+        self.exits = exits;
+        exits
+    }
+
+    fn enter_scope(&mut self) {
+        self.scopes.push(Vec::new());
+    }
+
+    // Names starting with `_` are unused on purpose
+    fn exit_scope(&mut self) {
+        let bindings = self.scopes.pop().unwrap();
+        for binding in bindings {
+            if binding.is_read || binding.identifier.span.literal.starts_with('_') {
+                continue;
+            }
+            if binding.is_parameter {
+                self.diagnostics.report_unused_parameter(&binding.identifier);
+            } else {
+                self.diagnostics.report_unused_variable(&binding.identifier);
+            }
+        }
+    }
+
+    fn declare(&mut self, identifier: &Token, is_parameter: bool) {
+        let binding = Binding { identifier: identifier.clone(), is_parameter, is_read: false };
+        self.scopes.last_mut().unwrap().push(binding);
+    }
+
+    fn mark_read(&mut self, identifier: &str) {
+        let is_named = |binding: &Binding| binding.identifier.span.literal == identifier;
+        for (depth, scope) in self.scopes.iter_mut().enumerate().rev() {
+            let Some(index) = scope.iter().rposition(is_named) else {
+                continue;
+            };
+            // A top-level variable is a single slot however often it is redeclared
+            if depth == 0 {
+                scope.iter_mut().filter(|binding| is_named(binding)).for_each(|binding| {
+                    binding.is_read = true;
+                });
+            } else {
+                scope[index].is_read = true;
+            }
+            return;
+        }
+    }
+
+    fn report_dead_statement(&mut self, statement: &ASTStatement, condition: bool, is_loop: bool) {
+        let span = statement.span();
+        let span = self.text.span(span.start, span.end);
+        if is_loop {
+            self.diagnostics.report_dead_loop(span);
+        } else {
+            self.diagnostics.report_dead_branch(span, condition);
+        }
+    }
+}
+
+impl ASTVisitor<'_> for FlowAnalyzer<'_> {
+    fn visit_func_decl_statement(&mut self, func_decl_statement: &ASTFuncDeclStatement) {
+        let name = func_decl_statement.identifier.span.literal.clone();
+        self.functions.push(func_decl_statement.identifier.clone());
+        self.function_names.push(name);
+        self.enter_scope();
+        for parameter in &func_decl_statement.parameters {
+            self.declare(&parameter.identifier, true);
+        }
+        self.visit_statement(&func_decl_statement.body);
+        self.exit_scope();
+        self.function_names.pop();
+        self.exits = false;
+        self.declares_function = true;
+    }
+
+    fn visit_return_statement(&mut self, return_statement: &ASTReturnStatement) {
+        if let Some(return_value) = &return_statement.return_value {
+            self.visit_expression(return_value);
+        }
+        self.exits = true;
+    }
+
+    fn visit_break_statement(&mut self, _break_statement: &ASTBreakStatement) {
+        self.exits = true;
+    }
+
+    fn visit_continue_statement(&mut self, _continue_statement: &ASTContinueStatement) {
+        self.exits = true;
+    }
+
+    fn visit_while_statement(&mut self, while_statement: &ASTWhileStatement) {
+        self.visit_expression(&while_statement.condition);
+        if while_statement.condition.as_boolean() == Some(false) {
+            self.report_dead_statement(&while_statement.body, false, true);
+        }
+        self.visit_statement(&while_statement.body);
+        // Leaving the body only leaves the loop
+        self.exits = false;
+    }
+
+    fn visit_block_statement(&mut self, block_statement: &ASTBlockStatement) {
+        self.enter_scope();
+        self.visit_statements(&block_statement.statements);
+        self.exit_scope();
+    }
+
+    fn visit_if_statement(&mut self, if_statement: &ASTIfStatement) {
+        let condition = if_statement.condition.as_boolean();
+        self.enter_scope();
+        self.visit_expression(&if_statement.condition);
+        if condition == Some(false) {
+            self.report_dead_statement(&if_statement.then_branch, false, false);
+        }
+        self.exits = false;
+        self.visit_statement(&if_statement.then_branch);
+        let then_exits = self.exits;
+        self.exit_scope();
+        let Some(else_branch) = &if_statement.else_branch else {
+            self.exits = false;
+            return;
+        };
+        self.enter_scope();
+        if condition == Some(true) {
+            self.report_dead_statement(&else_branch.else_statement, true, false);
+        }
+        self.exits = false;
+        self.visit_statement(&else_branch.else_statement);
+        self.exits &= then_exits;
+        self.exit_scope();
+    }
+
+    fn visit_for_statement(&mut self, for_statement: &ASTForStatement) {
+        self.visit_expression(&for_statement.start);
+        self.visit_expression(&for_statement.end);
+        self.enter_scope();
+        self.declare(&for_statement.identifier, false);
+        self.visit_statement(&for_statement.body);
+        self.exit_scope();
+        self.exits = false;
+    }
+
+    fn visit_let_statement(&mut self, let_statement: &ASTLetStatement) {
+        self.visit_expression(&let_statement.initializer);
+        self.declare(&let_statement.identifier, false);
+    }
+
+    fn visit_call_expression(&mut self, call_expression: &ASTCallExpression) {
+        let name = &call_expression.identifier.span.literal;
+        if self.function_names.last() != Some(name) {
+            self.called_functions.insert(name.clone());
+        }
+        for argument in &call_expression.arguments {
+            self.visit_expression(argument);
+        }
+    }
+
+    fn visit_variable_expression(&mut self, variable_expression: &ASTVariableExpression) {
+        self.mark_read(variable_expression.identifier());
+    }
+
+    fn visit_number_expression(&mut self, _number: &ASTNumberExpression) {}
+
+    fn visit_float_expression(&mut self, _float: &ASTFloatExpression) {}
+
+    fn visit_string_expression(&mut self, _string: &ASTStringExpression) {}
+
+    fn visit_boolean_expression(&mut self, _boolean: &ASTBooleanExpression) {}
+
+    fn visit_error(&mut self, _span: &TextSpan) {}
+
+    fn visit_unary_expression(&mut self, unary_expression: &ASTUnaryExpression) {
+        self.visit_expression(&unary_expression.operand);
+    }
+}
+
+// What a compilation is for, which decides what gets reported and how
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Program,
+    // One input of an interactive session, whose top-level declarations outlive it
+    Session,
+    // A file open in an editor, whose diagnostics are collected rather than printed
+    Editor,
+}
+
+// How `run` executes a compiled program; both backends must behave identically
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    Evaluator,
+    Bytecode,
+}
+
+// What an editor shows for a file
+pub(crate) struct EditorAnalysis {
+    pub(crate) diagnostics: Vec<Diagnostic>,
+    pub(crate) names: Names,
+    pub(crate) global_scope: GlobalScope,
+    pub(crate) expression_types: Option<ExpressionTypes>,
+}
+
 pub struct CompilationUnit {
     pub ast: Ast,
     pub diagnostics_bag: DiagnosticsBagCell,
     pub global_scope: GlobalScope,
+    pub text: text::SourceText,
+    pub output: DiagnosticsOutput,
 }
 
 impl CompilationUnit {
     pub fn compile(input: &str) -> Result<CompilationUnit, DiagnosticsBagCell> {
+        Self::compile_with_output(input, DiagnosticsOutput::default())
+    }
+
+    // Like `compile`, with diagnostics written in a chosen format and attributed to a file
+    pub fn compile_with_output(
+        input: &str,
+        output: DiagnosticsOutput
+    ) -> Result<CompilationUnit, DiagnosticsBagCell> {
+        let global_scope = GlobalScope::with_intrinsics();
+        Self::optimize(Self::check(input, global_scope, Mode::Program, output)?)
+    }
+
+    // Compiles one input of an interactive session on top of what earlier inputs declared
+    pub(crate) fn compile_in_session(
+        input: &str,
+        mut global_scope: GlobalScope,
+        output: DiagnosticsOutput
+    ) -> Result<CompilationUnit, DiagnosticsBagCell> {
+        global_scope.forget_declarations();
+        Self::optimize(Self::check(input, global_scope, Mode::Session, output)?)
+    }
+
+    // Like `compile`, but keeps the tree exactly as it was parsed
+    pub fn analyze(input: &str) -> Result<CompilationUnit, DiagnosticsBagCell> {
+        Self::analyze_with_output(input, DiagnosticsOutput::default())
+    }
+
+    // Like `analyze`, with diagnostics written in a chosen format and attributed to a file
+    pub fn analyze_with_output(
+        input: &str,
+        output: DiagnosticsOutput
+    ) -> Result<CompilationUnit, DiagnosticsBagCell> {
+        let checked = Self::check(input, GlobalScope::with_intrinsics(), Mode::Program, output);
+        checked.map(|(compilation_unit, _)| compilation_unit)
+    }
+
+    // Like `analyze`, but silent, and with what every name refers to and, once the file checks
+    // cleanly, the type of every expression
+    pub(crate) fn analyze_in_editor(input: &str) -> EditorAnalysis {
+        let output = DiagnosticsOutput::default();
+        let resolved = Self::resolve(input, GlobalScope::with_intrinsics(), Mode::Editor, output);
+        let (compilation_unit, names) = match resolved {
+            Ok(resolved) => resolved,
+            Err(diagnostics_bag) => {
+                return EditorAnalysis {
+                    diagnostics: diagnostics_bag.borrow().diagnostics.clone(),
+                    names: Names::default(),
+                    global_scope: GlobalScope::with_intrinsics(),
+                    expression_types: None,
+                };
+            }
+        };
+        let global_scope = compilation_unit.global_scope.clone();
+        let (diagnostics_bag, expression_types) = match
+            Self::check_types(compilation_unit, Mode::Editor)
+        {
+            Ok((compilation_unit, expression_types)) => {
+                (compilation_unit.diagnostics_bag, Some(expression_types))
+            }
+            Err(diagnostics_bag) => (diagnostics_bag, None),
+        };
+        let diagnostics = diagnostics_bag.borrow().diagnostics.clone();
+        EditorAnalysis { diagnostics, names, global_scope, expression_types }
+    }
+
+    fn optimize(
+        (mut compilation_unit, expression_types): (CompilationUnit, ExpressionTypes)
+    ) -> Result<CompilationUnit, DiagnosticsBagCell> {
+        ASTOptimizer::optimize(
+            &mut compilation_unit.ast,
+            &mut compilation_unit.global_scope,
+            &expression_types,
+            Rc::clone(&compilation_unit.diagnostics_bag)
+        );
+        Self::check_diagnostics(
+            &compilation_unit.text,
+            &compilation_unit.diagnostics_bag,
+            &compilation_unit.output
+        ).map_err(|_| Rc::clone(&compilation_unit.diagnostics_bag))?;
+        Ok(compilation_unit)
+    }
+
+    fn check(
+        input: &str,
+        global_scope: GlobalScope,
+        mode: Mode,
+        output: DiagnosticsOutput
+    ) -> Result<(CompilationUnit, ExpressionTypes), DiagnosticsBagCell> {
+        let (compilation_unit, _) = Self::resolve(input, global_scope, mode, output)?;
+        Self::check_types(compilation_unit, mode)
+    }
+
+    // Parses the input and resolves its names. Errors found while resolving are left for
+    // `check_types` to stop at, so what the names refer to is known in any file that parses
+    fn resolve(
+        input: &str,
+        global_scope: GlobalScope,
+        mode: Mode,
+        output: DiagnosticsOutput
+    ) -> Result<(CompilationUnit, Names), DiagnosticsBagCell> {
         let text = text::SourceText::new(input.to_string());
-        let mut lexer = Lexer::new(input);
-        let mut tokens = Vec::new();
-        while let Some(token) = lexer.next_token() {
-            tokens.push(token);
-        }
         let diagnostics_bag: DiagnosticsBagCell = Rc::new(
             RefCell::new(diagnostics::DiagnosticsBag::new())
         );
-        let mut ast = Ast::new();
-        let mut parser = Parser::new(tokens, Rc::clone(&diagnostics_bag));
-        while let Some(stmt) = parser.next_statement() {
-            ast.add_statement(stmt);
-        }
-        ast.visualize();
-        Self::check_diagnostics(&text, &diagnostics_bag).map_err(|_| Rc::clone(&diagnostics_bag))?;
-        let mut global_symbol_resolver = GlobalSymbolResolver::new(Rc::clone(&diagnostics_bag));
+        let (ast, _) = Self::parse(input, &diagnostics_bag);
+        Self::end_stage(&text, &diagnostics_bag, mode, &output)?;
+        let mut global_symbol_resolver = GlobalSymbolResolver::new(
+            Rc::clone(&diagnostics_bag),
+            global_scope
+        );
         ast.visit(&mut global_symbol_resolver);
         let global_scope = global_symbol_resolver.global_scope;
         let scopes = Scopes::from_global_scope(global_scope);
         let mut resolver = Resolver::new(Rc::clone(&diagnostics_bag), scopes);
         ast.visit(&mut resolver);
-        Self::check_diagnostics(&text, &diagnostics_bag).map_err(|_| Rc::clone(&diagnostics_bag))?;
-        Ok(CompilationUnit {
+        let compilation_unit = CompilationUnit {
             ast,
             diagnostics_bag,
             global_scope: resolver.scopes.global_scope,
-        })
+            text,
+            output,
+        };
+        Ok((compilation_unit, resolver.names))
+    }
+
+    fn check_types(
+        compilation_unit: CompilationUnit,
+        mode: Mode
+    ) -> Result<(CompilationUnit, ExpressionTypes), DiagnosticsBagCell> {
+        let CompilationUnit { ast, diagnostics_bag, global_scope, text, output } = compilation_unit;
+        Self::end_stage(&text, &diagnostics_bag, mode, &output)?;
+        let scopes = Scopes::from_global_scope(global_scope);
+        let mut type_checker = TypeChecker::new(Rc::clone(&diagnostics_bag), scopes);
+        ast.visit(&mut type_checker);
+        Self::end_stage(&text, &diagnostics_bag, mode, &output)?;
+        let is_session = mode == Mode::Session;
+        FlowAnalyzer::analyze(&ast, &text, Rc::clone(&diagnostics_bag), is_session);
+        Self::end_stage(&text, &diagnostics_bag, mode, &output)?;
+        let compilation_unit = CompilationUnit {
+            ast,
+            diagnostics_bag,
+            global_scope: type_checker.scopes.global_scope,
+            text,
+            output,
+        };
+        Ok((compilation_unit, type_checker.expression_types))
+    }
+
+    // The syntax tree along with the comments the parser skips over
+    fn parse(input: &str, diagnostics_bag: &DiagnosticsBagCell) -> (Ast, Vec<TextSpan>) {
+        let tokens = Lexer::new(input, Rc::clone(diagnostics_bag)).tokenize();
+        let comments = tokens
+            .iter()
+            .filter(|token| matches!(token.kind, TokenKind::LineComment | TokenKind::BlockComment))
+            .map(|token| token.span.clone())
+            .collect();
+        let mut ast = Ast::new();
+        let mut parser = Parser::new(tokens, Rc::clone(diagnostics_bag));
+        while let Some(stmt) = parser.next_statement() {
+            ast.add_statement(stmt);
+        }
+        (ast, comments)
+    }
+
+    // Reprints the source in the canonical style. Only syntax matters, and the result must
+    // parse back into the same program with the same comments before it is handed out
+    pub fn format(input: &str) -> Result<String, DiagnosticsBagCell> {
+        Self::format_with_output(input, DiagnosticsOutput::default())
+    }
+
+    // Like `format`, with diagnostics written in a chosen format and attributed to a file
+    pub fn format_with_output(
+        input: &str,
+        output: DiagnosticsOutput
+    ) -> Result<String, DiagnosticsBagCell> {
+        let text = text::SourceText::new(input.to_string());
+        let diagnostics_bag: DiagnosticsBagCell = Rc::new(
+            RefCell::new(diagnostics::DiagnosticsBag::new())
+        );
+        let (ast, comments) = Self::parse(input, &diagnostics_bag);
+        Self::end_stage(&text, &diagnostics_bag, Mode::Program, &output)?;
+        let formatted = ASTFormatter::format(&ast, comments.clone(), input);
+
+        let reparsed_bag = Rc::new(RefCell::new(diagnostics::DiagnosticsBag::new()));
+        let (reparsed, reparsed_comments) = Self::parse(&formatted, &reparsed_bag);
+        let literals = |comments: &[TextSpan]| {
+            comments
+                .iter()
+                .map(|comment| comment.literal.clone())
+                .collect::<Vec<_>>()
+        };
+        let is_equivalent =
+            !reparsed_bag.borrow().has_errors() &&
+            ast.is_equivalent_to(&reparsed) &&
+            literals(&comments) == literals(&reparsed_comments);
+        if !is_equivalent {
+            diagnostics_bag.borrow_mut().report_formatting_changed_program(text.span(0, 0));
+            Self::end_stage(&text, &diagnostics_bag, Mode::Program, &output)?;
+        }
+        Ok(formatted)
     }
 
     pub fn maybe_run(&self) {
-        if self.diagnostics_bag.borrow().diagnostics.len() > 0 {
+        if self.diagnostics_bag.borrow().has_errors() {
             return;
         }
-        self.run();
+        if let Err(error) = self.run(Backend::Evaluator) {
+            self.print_runtime_error(&error);
+        }
+    }
+
+    // Runs the program with output going to stdout and returns its exit code
+    pub fn run(&self, backend: Backend) -> Result<i64, RuntimeError> {
+        let mut stdout = io::stdout();
+        match backend {
+            Backend::Evaluator => {
+                let mut eval = ASTEvaluator::new(&self.global_scope, &mut stdout);
+                self.execute(&mut eval);
+                match eval.runtime_error() {
+                    Some(error) => Err(error.clone()),
+                    None => Ok(eval.exit_code()),
+                }
+            }
+            Backend::Bytecode => {
+                let program = self.compile_bytecode();
+                VirtualMachine::new(&program, &mut stdout).run()
+            }
+        }
     }
 
-    pub fn run(&self) {
-        let mut eval = ASTEvaluator::new(&self.global_scope);
-        let main_function = self.global_scope.lookup_function("main");
-        if let Some(function) = main_function {
-            eval.visit_statement(&function.body);
+    pub fn compile_bytecode(&self) -> Program {
+        BytecodeCompiler::compile(&self.ast, &self.global_scope)
+    }
+
+    pub fn compile_c(&self) -> String {
+        CBackend::generate(&self.ast, &self.global_scope)
+    }
+
+    // Generates x86-64 assembly, printing diagnostics for anything the backend cannot express
+    pub fn compile_x86_64(&self) -> Result<String, DiagnosticsBagCell> {
+        let assembly = X86_64Codegen::generate(
+            &self.ast,
+            &self.global_scope,
+            Rc::clone(&self.diagnostics_bag)
+        );
+        Self::check_diagnostics(&self.text, &self.diagnostics_bag, &self.output).map_err(|_| {
+            Rc::clone(&self.diagnostics_bag)
+        })?;
+        Ok(assembly)
+    }
+
+    // Generates a WebAssembly text module, printing diagnostics for anything it cannot express
+    pub fn compile_wat(&self) -> Result<String, DiagnosticsBagCell> {
+        let module = WatCodegen::generate(
+            &self.ast,
+            &self.global_scope,
+            Rc::clone(&self.diagnostics_bag)
+        );
+        Self::check_diagnostics(&self.text, &self.diagnostics_bag, &self.output).map_err(|_| {
+            Rc::clone(&self.diagnostics_bag)
+        })?;
+        Ok(module)
+    }
+
+    // The trace goes along as related spans, and a deferred format waits for the rest
+    pub fn print_runtime_error(&self, error: &RuntimeError) {
+        let format = self.output.format;
+        if format.is_deferred() {
+            self.diagnostics_bag.borrow_mut().report(error.to_diagnostic());
+            return;
+        }
+        let diagnostics = [error.to_diagnostic()];
+        DiagnosticsPrinter::new(&self.text, &diagnostics)
+            .with_path(self.output.path.as_deref())
+            .print_as(format);
+    }
+
+    pub fn execute(&self, eval: &mut ASTEvaluator) {
+        let main_function = self.global_scope.main_function();
+        if let Some(FunctionBody::Declared(body)) = main_function.map(|function| &function.body) {
+            eval.visit_statement(body);
         } else {
-            self.ast.visit(&mut eval);
+            for statement in &self.ast.statements {
+                eval.visit_statement(statement);
+                // A runtime error, `exit` or return stops the remaining statements
+                if eval.is_unwinding() {
+                    break;
+                }
+            }
+        }
+        eval.flush();
+    }
+
+    // Writes out what a deferred format held back, once nothing more will be reported
+    pub fn print_deferred_diagnostics(
+        input: &str,
+        diagnostics_bag: &DiagnosticsBagCell,
+        output: &DiagnosticsOutput
+    ) {
+        if output.format.is_deferred() {
+            let text = text::SourceText::new(input.to_string());
+            let mut diagnostics_binding = diagnostics_bag.borrow_mut();
+            DiagnosticsPrinter::new(&text, diagnostics_binding.take_unprinted())
+                .with_path(output.path.as_deref())
+                .print_as(output.format);
         }
-        println!("Result: {:?}", eval.last_value);
     }
 
+    fn end_stage(
+        text: &text::SourceText,
+        diagnostics_bag: &DiagnosticsBagCell,
+        mode: Mode,
+        output: &DiagnosticsOutput
+    ) -> Result<(), DiagnosticsBagCell> {
+        let result = if mode == Mode::Editor {
+            if diagnostics_bag.borrow().has_errors() { Err(()) } else { Ok(()) }
+        } else {
+            Self::check_diagnostics(text, diagnostics_bag, output)
+        };
+        result.map_err(|_| Rc::clone(diagnostics_bag))
+    }
+
+    // Prints whatever was reported since the last check; only errors stop compilation
     fn check_diagnostics(
         text: &text::SourceText,
-        diagnostics_bag: &DiagnosticsBagCell
+        diagnostics_bag: &DiagnosticsBagCell,
+        output: &DiagnosticsOutput
     ) -> Result<(), ()> {
-        let diagnostics_binding = diagnostics_bag.borrow();
-        if diagnostics_binding.diagnostics.len() > 0 {
-            let diagnostics_printer = DiagnosticsPrinter::new(
-                &text,
-                &diagnostics_binding.diagnostics
-            );
-            diagnostics_printer.print();
+        let mut diagnostics_binding = diagnostics_bag.borrow_mut();
+        if !output.format.is_deferred() {
+            let diagnostics = diagnostics_binding.take_unprinted();
+            if !diagnostics.is_empty() {
+                DiagnosticsPrinter::new(text, diagnostics)
+                    .with_path(output.path.as_deref())
+                    .print_as(output.format);
+            }
+        }
+        if diagnostics_binding.has_errors() {
             return Err(());
         }
         Ok(())
@@ -0,0 +1,439 @@
+use std::fmt::{ Display, Formatter };
+
+// A kind of diagnostic, identified by a code that never changes meaning once released
+#[derive(Debug, PartialEq, Eq)]
+pub struct DiagnosticCode {
+    pub id: &'static str,
+    pub title: &'static str,
+    pub explanation: &'static str,
+    // A program that triggers the diagnostic; some can't be shown with one
+    pub example: Option<&'static str>,
+}
+
+impl DiagnosticCode {
+    pub fn lookup(id: &str) -> Option<&'static DiagnosticCode> {
+        ALL.iter().copied().find(|code| code.id.eq_ignore_ascii_case(id))
+    }
+
+    // The long form printed by `tungsten explain`
+    pub fn describe(&self) -> String {
+        let mut description = format!("{}: {}\n\n{}\n", self.id, self.title, self.explanation);
+        if let Some(example) = self.example {
+            description.push_str("\nFor example:\n\n");
+            for line in example.lines() {
+                description.push_str(format!("    {}", line).trim_end());
+                description.push('\n');
+            }
+        }
+        description
+    }
+}
+
+impl Display for DiagnosticCode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.id)
+    }
+}
+
+pub static UNDECLARED_VARIABLE: DiagnosticCode = DiagnosticCode {
+    id: "T0001",
+    title: "Undeclared variable",
+    explanation: "\
+A variable was used before any `let` declared it, or outside the block that declared it.
+Variables live from their declaration to the end of the enclosing block, and a `for` loop
+variable only lives inside its loop. Check the spelling, or move the declaration up.",
+    example: Some("let total = count + 1"),
+};
+
+pub static INVALID_ARGUMENT_COUNT: DiagnosticCode = DiagnosticCode {
+    id: "T0002",
+    title: "Invalid argument count",
+    explanation: "\
+A function was called with more or fewer arguments than it declares parameters. Tungsten has
+no default or variadic parameters, so every call passes exactly one argument per parameter.",
+    example: Some("\
+func add(a: int, b: int) -> int {
+    return a + b
+}
+add(1)"),
+};
+
+pub static UNDECLARED_FUNCTION: DiagnosticCode = DiagnosticCode {
+    id: "T0003",
+    title: "Undeclared function",
+    explanation: "\
+A call names a function that is neither declared in the program nor one of the intrinsics.
+Functions can be called before their declaration, so this usually means a typo.",
+    example: Some("greet()"),
+};
+
+pub static FUNCTION_ALREADY_DECLARED: DiagnosticCode = DiagnosticCode {
+    id: "T0004",
+    title: "Function already declared",
+    explanation: "\
+Two functions have the same name, or a function reuses the name of an intrinsic such as
+`println`. Functions can't be overloaded, so give one of them another name.",
+    example: Some("\
+func f() {}
+func f() {}"),
+};
+
+pub static OUTSIDE_OF_LOOP: DiagnosticCode = DiagnosticCode {
+    id: "T0005",
+    title: "Break or continue outside of a loop",
+    explanation: "\
+`break` and `continue` only make sense inside the body of a `while` or `for` loop, and they
+can't reach a loop outside the function they are in. Use `return` to leave a function.",
+    example: Some("break"),
+};
+
+pub static UNDECLARED_TYPE: DiagnosticCode = DiagnosticCode {
+    id: "T0006",
+    title: "Undeclared type",
+    explanation: "\
+A type annotation names a type that doesn't exist. The types are `int`, `float`, `bool` and
+`string`.",
+    example: Some("let a: integer = 1"),
+};
+
+pub static INVALID_ARGUMENT_TYPE: DiagnosticCode = DiagnosticCode {
+    id: "T0007",
+    title: "Invalid argument type",
+    explanation: "\
+An argument doesn't have the type its parameter is annotated with. Values are never
+converted implicitly; use `to_int` or `to_float` to convert between numbers.",
+    example: Some("\
+func half(a: int) -> int {
+    return a / 2
+}
+half(true)"),
+};
+
+pub static TYPE_MISMATCH: DiagnosticCode = DiagnosticCode {
+    id: "T0008",
+    title: "Type mismatch",
+    explanation: "\
+An expression has a different type than its position requires: an initializer that doesn't
+match its annotation, an assignment that changes a variable's type, a return value that
+doesn't match the return type, or a condition that isn't a `bool`.",
+    example: Some("let a: int = true"),
+};
+
+pub static INVALID_BINARY_OPERATOR: DiagnosticCode = DiagnosticCode {
+    id: "T0009",
+    title: "Invalid operand types for a binary operator",
+    explanation: "\
+A binary operator was applied to types it doesn't support. Both sides of arithmetic and
+comparisons must have the same type, `&&` and `||` take `bool`s, and `+` joins two
+`string`s but not a `string` and a number.",
+    example: Some("let a = 1 + true"),
+};
+
+pub static INVALID_UNARY_OPERATOR: DiagnosticCode = DiagnosticCode {
+    id: "T0010",
+    title: "Invalid operand type for a unary operator",
+    explanation: "\
+A unary operator was applied to a type it doesn't support: `-` takes numbers, `!` takes a
+`bool` and `~` takes an `int`.",
+    example: Some("let a = -true"),
+};
+
+pub static UNEXPECTED_TOKEN: DiagnosticCode = DiagnosticCode {
+    id: "T0011",
+    title: "Unexpected token",
+    explanation: "\
+The parser expected a particular token, such as a name or a closing brace, and found
+something else. The parser skips ahead to the next statement, so a single mistake is
+reported once.",
+    example: Some("let = 1"),
+};
+
+pub static EXPECTED_EXPRESSION: DiagnosticCode = DiagnosticCode {
+    id: "T0012",
+    title: "Expected an expression",
+    explanation: "\
+A value was expected, for example after `=` or an operator, but the next token can't start
+an expression.",
+    example: Some("let a = * 2"),
+};
+
+pub static UNTERMINATED_STRING: DiagnosticCode = DiagnosticCode {
+    id: "T0013",
+    title: "Unterminated string literal",
+    explanation: "\
+A string literal has no closing `\"` before the end of its line. Strings can't span lines or
+contain an unescaped `\"`; write `\\n` and `\\\"` instead.",
+    example: Some("let a = \"tungsten"),
+};
+
+pub static UNTERMINATED_BLOCK_COMMENT: DiagnosticCode = DiagnosticCode {
+    id: "T0014",
+    title: "Unterminated block comment",
+    explanation: "\
+A `/*` comment has no matching `*/`. Block comments nest, so every `/*` inside one needs its
+own `*/` as well.",
+    example: Some("/* /* nested */"),
+};
+
+pub static INVALID_ESCAPE_SEQUENCE: DiagnosticCode = DiagnosticCode {
+    id: "T0015",
+    title: "Invalid escape sequence",
+    explanation: "\
+A string contains a backslash followed by a character that isn't an escape. The escapes are
+`\\n`, `\\t`, `\\\\` and `\\\"`.",
+    example: Some("let a = \"\\q\""),
+};
+
+pub static INTEGER_LITERAL_OUT_OF_RANGE: DiagnosticCode = DiagnosticCode {
+    id: "T0016",
+    title: "Integer literal out of range",
+    explanation: "\
+An integer literal doesn't fit into an `int`, which is a signed 64-bit integer. Use a
+`float` literal for larger magnitudes.",
+    example: Some("let a = 9223372036854775808"),
+};
+
+pub static UNSUPPORTED_BY_BACKEND: DiagnosticCode = DiagnosticCode {
+    id: "T0017",
+    title: "Unsupported by the backend",
+    explanation: "\
+The program is valid, but the chosen code generator can't express part of it. The
+WebAssembly backend, for instance, only handles `int` and `bool` values. The evaluator and
+the bytecode VM run every valid program.",
+    example: Some("\
+// tungsten wat example.tn
+println(\"hello\")"),
+};
+
+pub static FORMATTING_CHANGED_PROGRAM: DiagnosticCode = DiagnosticCode {
+    id: "T0018",
+    title: "Formatting would change the program",
+    explanation: "\
+`tungsten fmt` reparses what it prints and compares it with the original. If the two differ
+in any statement or comment, the file is left untouched. This is a bug in the formatter;
+please report it along with the file.",
+    example: None,
+};
+
+pub static CONSTANT_FAILURE: DiagnosticCode = DiagnosticCode {
+    id: "T0019",
+    title: "Expression always fails",
+    explanation: "\
+An expression made only of constants fails whenever it is evaluated, for example by
+dividing by zero or overflowing. The program still compiles, and fails at run time if the
+expression is reached.",
+    example: Some("let a = 1 / 0"),
+};
+
+pub static UNREACHABLE_CODE: DiagnosticCode = DiagnosticCode {
+    id: "T0020",
+    title: "Unreachable code",
+    explanation: "\
+Statements follow a `return`, `break` or `continue` in the same block, so they can never
+run. Function declarations are exempt, since they can be called from anywhere.",
+    example: Some("\
+func f() -> int {
+    return 1
+    println(\"never\")
+}
+f()"),
+};
+
+pub static DEAD_BRANCH: DiagnosticCode = DiagnosticCode {
+    id: "T0021",
+    title: "Branch never runs",
+    explanation: "\
+The condition of an `if` is `true` or `false`, so one of its branches can never run.",
+    example: Some("\
+if false {
+    println(\"never\")
+}"),
+};
+
+pub static DEAD_LOOP: DiagnosticCode = DiagnosticCode {
+    id: "T0022",
+    title: "Loop never runs",
+    explanation: "\
+The condition of a `while` loop is `false`, so its body never runs.",
+    example: Some("\
+while false {
+    println(\"never\")
+}"),
+};
+
+pub static UNUSED_VARIABLE: DiagnosticCode = DiagnosticCode {
+    id: "T0023",
+    title: "Unused variable",
+    explanation: "\
+A variable is declared but never read. Remove it, or start its name with `_` if it is unused
+on purpose.",
+    example: Some("let unused = 1"),
+};
+
+pub static UNUSED_PARAMETER: DiagnosticCode = DiagnosticCode {
+    id: "T0024",
+    title: "Unused parameter",
+    explanation: "\
+A function never reads one of its parameters, so callers pass a value for nothing. Start the
+name with `_` if the parameter is unused on purpose.",
+    example: Some("\
+func f(a: int) {}
+f(1)"),
+};
+
+pub static UNCALLED_FUNCTION: DiagnosticCode = DiagnosticCode {
+    id: "T0025",
+    title: "Function never called",
+    explanation: "\
+A function is declared but nothing calls it, directly or through other functions that are
+called. `main` and functions whose names start with `_` are exempt.",
+    example: Some("func helper() {}"),
+};
+
+pub static RUNTIME_ERROR: DiagnosticCode = DiagnosticCode {
+    id: "T0026",
+    title: "Runtime error",
+    explanation: "\
+The program failed while running, for example by dividing by zero, overflowing an `int` or
+failing an `assert`. The diagnostic lists the calls that led to the failure.",
+    example: Some("\
+func divide(a: int, b: int) -> int {
+    return a / b
+}
+divide(1, 0)"),
+};
+
+pub static MISSING_RETURN: DiagnosticCode = DiagnosticCode {
+    id: "T0027",
+    title: "Missing return",
+    explanation: "\
+A function declares a return type other than `unit`, but the end of its body can be reached
+without a `return`. Return a value on every path, or end the body with `while true` loops that
+only leave through `return`.",
+    example: Some("\
+func sign(n: int) -> int {
+    if n < 0 {
+        return -1
+    }
+}
+sign(1)"),
+};
+
+pub static MAIN_WITH_PARAMETERS: DiagnosticCode = DiagnosticCode {
+    id: "T0028",
+    title: "Parameters on main",
+    explanation: "\
+`main` is where the program starts, so nothing can pass it arguments. Remove its parameters,
+or read the values from top-level variables instead.",
+    example: Some("\
+func main(name: string) {
+    println(name)
+}"),
+};
+
+pub static TOP_LEVEL_VARIABLE_RETYPED: DiagnosticCode = DiagnosticCode {
+    id: "T0029",
+    title: "Top-level variable redeclared with a different type",
+    explanation: "\
+Functions are checked against the type a top-level variable has where they are declared, but
+read whichever declaration of it ran last. Redeclaring the variable with `let` and a value of
+another type would break them at runtime. Give the new variable a name of its own.",
+    example: Some("\
+let count = 0
+let count = \"zero\""),
+};
+
+pub static ALL: &[&DiagnosticCode] = &[
+    &UNDECLARED_VARIABLE,
+    &INVALID_ARGUMENT_COUNT,
+    &UNDECLARED_FUNCTION,
+    &FUNCTION_ALREADY_DECLARED,
+    &OUTSIDE_OF_LOOP,
+    &UNDECLARED_TYPE,
+    &INVALID_ARGUMENT_TYPE,
+    &TYPE_MISMATCH,
+    &INVALID_BINARY_OPERATOR,
+    &INVALID_UNARY_OPERATOR,
+    &UNEXPECTED_TOKEN,
+    &EXPECTED_EXPRESSION,
+    &UNTERMINATED_STRING,
+    &UNTERMINATED_BLOCK_COMMENT,
+    &INVALID_ESCAPE_SEQUENCE,
+    &INTEGER_LITERAL_OUT_OF_RANGE,
+    &UNSUPPORTED_BY_BACKEND,
+    &FORMATTING_CHANGED_PROGRAM,
+    &CONSTANT_FAILURE,
+    &UNREACHABLE_CODE,
+    &DEAD_BRANCH,
+    &DEAD_LOOP,
+    &UNUSED_VARIABLE,
+    &UNUSED_PARAMETER,
+    &UNCALLED_FUNCTION,
+    &RUNTIME_ERROR,
+    &MISSING_RETURN,
+    &MAIN_WITH_PARAMETERS,
+    &TOP_LEVEL_VARIABLE_RETYPED,
+];
+
+#[cfg(test)]
+mod test {
+    use crate::compilation_unit::{ Backend, CompilationUnit };
+    use crate::diagnostics::codes::{ self, DiagnosticCode };
+
+    // The codes an example is reported with, including those only found when generating or
+    // running code
+    fn reported_codes(example: &str) -> Vec<&'static DiagnosticCode> {
+        let compilation_unit = match CompilationUnit::compile(example) {
+            Ok(compilation_unit) => compilation_unit,
+            Err(diagnostics_bag) => {
+                return diagnostics_bag
+                    .borrow()
+                    .diagnostics.iter()
+                    .map(|diagnostic| diagnostic.code)
+                    .collect();
+            }
+        };
+        let _ = compilation_unit.compile_wat();
+        let mut codes = compilation_unit.diagnostics_bag
+            .borrow()
+            .diagnostics.iter()
+            .map(|diagnostic| diagnostic.code)
+            .collect::<Vec<_>>();
+        if let Err(error) = compilation_unit.run(Backend::Evaluator) {
+            codes.push(error.to_diagnostic().code);
+        }
+        codes
+    }
+
+    #[test]
+    pub fn should_number_codes_in_order() {
+        for (i, code) in codes::ALL.iter().enumerate() {
+            assert_eq!(code.id, format!("T{:04}", i + 1));
+        }
+    }
+
+    #[test]
+    pub fn should_look_up_codes_ignoring_case() {
+        assert_eq!(DiagnosticCode::lookup("T0002"), Some(&codes::INVALID_ARGUMENT_COUNT));
+        assert_eq!(DiagnosticCode::lookup("t0001"), Some(&codes::UNDECLARED_VARIABLE));
+        assert_eq!(DiagnosticCode::lookup("T9999"), None);
+    }
+
+    #[test]
+    pub fn should_report_every_example_with_its_code() {
+        for code in codes::ALL {
+            if let Some(example) = code.example {
+                assert!(reported_codes(example).contains(code), "{} was not reported", code);
+            }
+        }
+    }
+
+    #[test]
+    pub fn should_describe_codes_with_their_example() {
+        let description = codes::INVALID_ARGUMENT_COUNT.describe();
+        assert!(description.starts_with("T0002: Invalid argument count\n\nA function"));
+        let example =
+            "    func add(a: int, b: int) -> int {\n        return a + b\n    }\n    add(1)\n";
+        assert!(description.ends_with(&format!("For example:\n\n{}", example)));
+    }
+}
@@ -1,25 +1,68 @@
+pub mod codes;
 pub mod printer;
+mod snippet;
 
 use std::cell::RefCell;
+use std::mem;
 use std::rc::Rc;
 use crate::ast::lexer::{ TextSpan, Token, TokenKind };
+use crate::diagnostics::codes::DiagnosticCode;
+use crate::types::Type;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum DiagnosticKind {
     Error,
     Warning,
 }
 
+// Another place a diagnostic points to, such as a declaration it conflicts with
+#[derive(Clone, Debug)]
+pub struct RelatedSpan {
+    pub message: String,
+    pub span: TextSpan,
+}
+
+// Extra context shown below a diagnostic
+#[derive(Clone, Debug)]
+pub enum Footer {
+    Note(String),
+    // A suggestion for fixing the problem
+    Help(String),
+}
+
 #[derive(Clone, Debug)]
 pub struct Diagnostic {
+    pub code: &'static DiagnosticCode,
     pub message: String,
     pub span: TextSpan,
     pub kind: DiagnosticKind,
+    pub related: Vec<RelatedSpan>,
+    pub footers: Vec<Footer>,
 }
 
 impl Diagnostic {
-    pub fn new(message: String, span: TextSpan, kind: DiagnosticKind) -> Self {
-        Diagnostic { message, span, kind }
+    pub fn new(
+        code: &'static DiagnosticCode,
+        message: String,
+        span: TextSpan,
+        kind: DiagnosticKind
+    ) -> Self {
+        Diagnostic { code, message, span, kind, related: Vec::new(), footers: Vec::new() }
+    }
+
+    pub fn with_related(mut self, message: String, span: TextSpan) -> Self {
+        self.related.push(RelatedSpan { message, span });
+        self
+    }
+
+    pub fn with_note(mut self, note: String) -> Self {
+        self.footers.push(Footer::Note(note));
+        self
+    }
+
+    pub fn with_help(mut self, help: String) -> Self {
+        self.footers.push(Footer::Help(help));
+        self
     }
 }
 
@@ -28,31 +71,159 @@ pub type DiagnosticsBagCell = Rc<RefCell<DiagnosticsBag>>;
 #[derive(Debug)]
 pub struct DiagnosticsBag {
     pub diagnostics: Vec<Diagnostic>,
+    // Diagnostics before this index have already been shown to the user
+    printed: usize,
+}
+
+impl Default for DiagnosticsBag {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl DiagnosticsBag {
     pub fn new() -> Self {
-        DiagnosticsBag { diagnostics: vec![] }
+        DiagnosticsBag { diagnostics: vec![], printed: 0 }
+    }
+
+    pub fn report_error(&mut self, code: &'static DiagnosticCode, message: String, span: TextSpan) {
+        self.report(Diagnostic::new(code, message, span, DiagnosticKind::Error));
+    }
+
+    pub fn report_warning(
+        &mut self,
+        code: &'static DiagnosticCode,
+        message: String,
+        span: TextSpan
+    ) {
+        self.report(Diagnostic::new(code, message, span, DiagnosticKind::Warning));
+    }
+
+    // The same diagnostic for the same span is only reported once; repeats are usually fallout
+    // from the first. Different mistakes at one span are all reported
+    pub(crate) fn report(&mut self, diagnostic: Diagnostic) {
+        let is_duplicate = self.diagnostics.iter().any(|reported| {
+            reported.kind == diagnostic.kind &&
+                reported.code.id == diagnostic.code.id &&
+                reported.span.start == diagnostic.span.start &&
+                reported.span.end == diagnostic.span.end
+        });
+        if !is_duplicate {
+            self.diagnostics.push(diagnostic);
+        }
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(|diagnostic| matches!(diagnostic.kind, DiagnosticKind::Error))
+    }
+
+    // Diagnostics reported since the last call, which count as shown from then on
+    pub fn take_unprinted(&mut self) -> &[Diagnostic] {
+        let start = mem::replace(&mut self.printed, self.diagnostics.len());
+        &self.diagnostics[start..]
     }
 
-    pub fn report_error(&mut self, message: String, span: TextSpan) {
-        let error = Diagnostic::new(message, span, DiagnosticKind::Error);
-        self.diagnostics.push(error);
+    pub fn report_constant_failure(&mut self, message: &str, span: TextSpan) {
+        let message = format!("Evaluating this expression always fails: {}", message);
+        self.report_warning(&codes::CONSTANT_FAILURE, message, span);
     }
 
-    pub fn report_warning(&mut self, message: String, span: TextSpan) {
-        let warning = Diagnostic::new(message, span, DiagnosticKind::Warning);
-        self.diagnostics.push(warning);
+    pub fn report_unreachable_code(&mut self, span: TextSpan) {
+        self.report_warning(&codes::UNREACHABLE_CODE, "Unreachable code".to_string(), span);
+    }
+
+    pub fn report_dead_branch(&mut self, span: TextSpan, condition: bool) {
+        self.report_warning(
+            &codes::DEAD_BRANCH,
+            format!("Condition is always {}, so this branch never runs", condition),
+            span
+        );
+    }
+
+    pub fn report_dead_loop(&mut self, span: TextSpan) {
+        let message = "Condition is always false, so this loop never runs".to_string();
+        self.report_warning(&codes::DEAD_LOOP, message, span);
+    }
+
+    pub fn report_unused_variable(&mut self, token: &Token) {
+        let message = format!("Unused variable '{}'", token.span.literal);
+        self.report_unused(&codes::UNUSED_VARIABLE, message, token);
+    }
+
+    pub fn report_unused_parameter(&mut self, token: &Token) {
+        let message = format!("Unused parameter '{}'", token.span.literal);
+        self.report_unused(&codes::UNUSED_PARAMETER, message, token);
+    }
+
+    pub fn report_uncalled_function(&mut self, token: &Token) {
+        let message = format!("Function '{}' is never called", token.span.literal);
+        self.report_unused(&codes::UNCALLED_FUNCTION, message, token);
+    }
+
+    fn report_unused(&mut self, code: &'static DiagnosticCode, message: String, token: &Token) {
+        let help = format!(
+            "if this is intentional, prefix the name with an underscore: '_{}'",
+            token.span.literal
+        );
+        let span = token.span.clone();
+        self.report(Diagnostic::new(code, message, span, DiagnosticKind::Warning).with_help(help));
+    }
+
+    pub fn report_formatting_changed_program(&mut self, span: TextSpan) {
+        let message =
+            "Formatting would change the meaning of this file, so it was left alone".to_string();
+        let diagnostic = Diagnostic::new(
+            &codes::FORMATTING_CHANGED_PROGRAM,
+            message,
+            span,
+            DiagnosticKind::Error
+        );
+        self.report(diagnostic.with_note("this is a bug in the formatter".to_string()));
+    }
+
+    pub fn report_unterminated_string(&mut self, span: TextSpan) {
+        let message = "Unterminated string literal".to_string();
+        self.report_error(&codes::UNTERMINATED_STRING, message, span);
+    }
+
+    pub fn report_unterminated_block_comment(&mut self, span: TextSpan) {
+        let message = "Unterminated block comment".to_string();
+        self.report_error(&codes::UNTERMINATED_BLOCK_COMMENT, message, span);
+    }
+
+    pub fn report_invalid_escape_sequence(&mut self, span: TextSpan) {
+        let message = format!("Invalid escape sequence '{}'", span.literal);
+        let diagnostic = Diagnostic::new(
+            &codes::INVALID_ESCAPE_SEQUENCE,
+            message,
+            span,
+            DiagnosticKind::Error
+        );
+        self.report(diagnostic.with_help(r#"the escapes are \n, \t, \\ and \""#.to_string()));
+    }
+
+    pub fn report_integer_literal_out_of_range(&mut self, span: TextSpan) {
+        let message = format!("Integer literal '{}' is out of range", span.literal);
+        let diagnostic = Diagnostic::new(
+            &codes::INTEGER_LITERAL_OUT_OF_RANGE,
+            message,
+            span,
+            DiagnosticKind::Error
+        );
+        let note = format!("'int' values range from {} to {}", i64::MIN, i64::MAX);
+        self.report(diagnostic.with_note(note));
     }
 
     pub fn report_unexpected_token(&mut self, expected: &TokenKind, token: &Token) {
         self.report_error(
+            &codes::UNEXPECTED_TOKEN,
             format!("Expected <{}>, found <{}>", expected, token.kind),
             token.span.clone()
         );
     }
     pub fn report_expected_expression(&mut self, token: &Token) {
         self.report_error(
+            &codes::EXPECTED_EXPRESSION,
             format!("Expected expression, found <{}>", token.kind),
             token.span.clone()
         );
@@ -60,6 +231,7 @@ impl DiagnosticsBag {
 
     pub fn report_undeclared_variable(&mut self, token: &Token) {
         self.report_error(
+            &codes::UNDECLARED_VARIABLE,
             format!("Undeclared variable '{}'", token.span.literal),
             token.span.clone()
         );
@@ -67,27 +239,168 @@ impl DiagnosticsBag {
 
     pub fn report_undeclared_function(&mut self, token: &Token) {
         self.report_error(
+            &codes::UNDECLARED_FUNCTION,
             format!("Undeclared function '{}'", token.span.literal),
             token.span.clone()
         );
     }
 
-    pub fn report_invalid_argument_count(&mut self, token: &Token, expected: usize, actual: usize) {
+    // `declaration` is where the called function is declared, if that is in the same text
+    pub fn report_invalid_argument_count(
+        &mut self,
+        token: &Token,
+        declaration: Option<&TextSpan>,
+        expected: usize,
+        actual: usize
+    ) {
+        let message = format!(
+            "Function '{}' expects {} arguments, but was given {}",
+            token.span.literal,
+            expected,
+            actual
+        );
+        let diagnostic = Diagnostic::new(
+            &codes::INVALID_ARGUMENT_COUNT,
+            message,
+            token.span.clone(),
+            DiagnosticKind::Error
+        );
+        self.report(match declaration {
+            Some(span) => {
+                diagnostic.with_related("function declared here".to_string(), span.clone())
+            }
+            None => diagnostic,
+        });
+    }
+
+    // `previous` is where the function was first declared, if that is in the same text
+    pub fn report_function_already_declared(
+        &mut self,
+        token: &Token,
+        previous: Option<&TextSpan>,
+        is_intrinsic: bool
+    ) {
+        let diagnostic = Diagnostic::new(
+            &codes::FUNCTION_ALREADY_DECLARED,
+            format!("Function '{}' already declared", token.span.literal),
+            token.span.clone(),
+            DiagnosticKind::Error
+        );
+        let diagnostic = match previous {
+            Some(span) => diagnostic.with_related("first declared here".to_string(), span.clone()),
+            None => diagnostic,
+        };
+        if is_intrinsic {
+            let note = format!("'{}' is a built-in function", token.span.literal);
+            self.report(diagnostic.with_note(note));
+        } else {
+            self.report(diagnostic);
+        }
+    }
+
+    pub fn report_main_with_parameters(&mut self, token: &Token) {
+        self.report_error(
+            &codes::MAIN_WITH_PARAMETERS,
+            "'main' cannot take parameters".to_string(),
+            token.span.clone()
+        );
+    }
+
+    pub fn report_outside_of_loop(&mut self, token: &Token) {
         self.report_error(
+            &codes::OUTSIDE_OF_LOOP,
+            format!("'{}' can only be used inside a loop", token.span.literal),
+            token.span.clone()
+        );
+    }
+
+    pub fn report_unsupported_by_backend(&mut self, backend: &str, feature: &str, span: TextSpan) {
+        let message = format!("The {} backend does not support {}", backend, feature);
+        self.report_error(&codes::UNSUPPORTED_BY_BACKEND, message, span);
+    }
+
+    pub fn report_undeclared_type(&mut self, token: &Token) {
+        let message = format!("Undeclared type '{}'", token.span.literal);
+        self.report_error(&codes::UNDECLARED_TYPE, message, token.span.clone());
+    }
+
+    pub fn report_invalid_argument_type(
+        &mut self,
+        function: &Token,
+        parameter: &str,
+        span: &TextSpan,
+        expected: &Type,
+        actual: &Type
+    ) {
+        self.report_error(
+            &codes::INVALID_ARGUMENT_TYPE,
             format!(
-                "Function '{}' expects {} arguments, but was given {}",
-                token.span.literal,
+                "Parameter '{}' of function '{}' expects type '{}', but was given '{}'",
+                parameter,
+                function.span.literal,
                 expected,
                 actual
             ),
-            token.span.clone()
+            span.clone()
         );
     }
 
-    pub fn report_function_already_declared(&mut self, token: &Token) {
+    pub fn report_type_mismatch(&mut self, span: &TextSpan, expected: &Type, actual: &Type) {
         self.report_error(
-            format!("Function '{}' already declared", token.span.literal),
-            token.span.clone()
+            &codes::TYPE_MISMATCH,
+            format!("Expected type '{}', found '{}'", expected, actual),
+            span.clone()
+        );
+    }
+
+    pub fn report_top_level_variable_retyped(
+        &mut self,
+        identifier: &Token,
+        previous: &Type,
+        actual: &Type
+    ) {
+        self.report_error(
+            &codes::TOP_LEVEL_VARIABLE_RETYPED,
+            format!(
+                "Top-level variable '{}' was declared as '{}', found '{}'",
+                identifier.span.literal,
+                previous,
+                actual
+            ),
+            identifier.span.clone()
+        );
+    }
+
+    pub fn report_missing_return(&mut self, function: &Token, return_type: &Token) {
+        self.report_error(
+            &codes::MISSING_RETURN,
+            format!(
+                "Not all paths of '{}' return a value of type '{}'",
+                function.span.literal,
+                return_type.span.literal
+            ),
+            return_type.span.clone()
+        );
+    }
+
+    pub fn report_invalid_binary_operator(&mut self, operator: &Token, left: &Type, right: &Type) {
+        self.report_error(
+            &codes::INVALID_BINARY_OPERATOR,
+            format!(
+                "Operator '{}' cannot be applied to types '{}' and '{}'",
+                operator.span.literal,
+                left,
+                right
+            ),
+            operator.span.clone()
+        );
+    }
+
+    pub fn report_invalid_unary_operator(&mut self, operator: &Token, operand: &Type) {
+        self.report_error(
+            &codes::INVALID_UNARY_OPERATOR,
+            format!("Operator '{}' cannot be applied to type '{}'", operator.span.literal, operand),
+            operator.span.clone()
         );
     }
 }
@@ -95,7 +408,8 @@ impl DiagnosticsBag {
 #[cfg(test)]
 mod test {
     use crate::CompilationUnit;
-    use crate::diagnostics::{ Diagnostic, DiagnosticKind, TextSpan };
+    use crate::diagnostics::codes::{ self, DiagnosticCode };
+    use crate::diagnostics::{ Diagnostic, DiagnosticKind, DiagnosticsBag, TextSpan };
 
     struct DiagnosticsVerifier {
         actual: Vec<Diagnostic>,
@@ -103,28 +417,44 @@ mod test {
     }
 
     impl DiagnosticsVerifier {
-        pub fn new(input: &str, messages: Vec<&str>) -> Self {
-            let messages_len = messages.len();
-            let expected = Self::parse_input(input, messages);
-            assert_eq!(expected.len(), messages_len);
-            let actual = Self::compile(input);
+        pub fn new(input: &str, codes: Vec<&'static DiagnosticCode>) -> Self {
+            Self::of_kind(input, codes, DiagnosticKind::Error)
+        }
+
+        pub fn warnings(input: &str, codes: Vec<&'static DiagnosticCode>) -> Self {
+            Self::of_kind(input, codes, DiagnosticKind::Warning)
+        }
+
+        fn of_kind(input: &str, codes: Vec<&'static DiagnosticCode>, kind: DiagnosticKind) -> Self {
+            let codes_len = codes.len();
+            let expected = Self::parse_input(input, codes, kind);
+            assert_eq!(expected.len(), codes_len);
+            let actual = Self::compile(input)
+                .into_iter()
+                .filter(|diagnostic| diagnostic.kind == kind)
+                .collect();
             Self { expected, actual }
         }
 
         fn compile(input: &str) -> Vec<Diagnostic> {
             let raw = Self::get_raw_text(input);
-            let compilation_unit = CompilationUnit::compile(&raw);
-            match compilation_unit {
-                Ok(_) => vec![],
-                Err(e) => e.borrow().diagnostics.clone(),
-            }
+            let diagnostics_bag = match CompilationUnit::compile(&raw) {
+                Ok(compilation_unit) => compilation_unit.diagnostics_bag,
+                Err(diagnostics_bag) => diagnostics_bag,
+            };
+            let diagnostics = diagnostics_bag.borrow().diagnostics.clone();
+            diagnostics
         }
 
         fn get_raw_text(input: &str) -> String {
             input.replace("«", "").replace("»", "")
         }
 
-        fn parse_input(input: &str, messages: Vec<&str>) -> Vec<Diagnostic> {
+        fn parse_input(
+            input: &str,
+            codes: Vec<&'static DiagnosticCode>,
+            kind: DiagnosticKind
+        ) -> Vec<Diagnostic> {
             let raw_text = Self::get_raw_text(input);
             let mut start_index_stack = vec![];
 
@@ -142,8 +472,8 @@ mod test {
 
                         let literal = &raw_text[start_index..end_index];
                         let span = TextSpan::new(start_index, end_index, literal.to_string());
-                        let message = messages[diagnostics.len()].to_string();
-                        let diagnostic = Diagnostic::new(message, span, DiagnosticKind::Error);
+                        let code = codes[diagnostics.len()];
+                        let diagnostic = Diagnostic::new(code, String::new(), span, kind);
                         diagnostics.push(diagnostic);
                     }
                     _ => {
@@ -165,10 +495,12 @@ mod test {
 
             for (actual, expected) in self.actual.iter().zip(self.expected.iter()) {
                 assert_eq!(
-                    actual.message,
-                    expected.message,
-                    "Expected message '{}', found '{}'",
-                    expected.message,
+                    actual.code,
+                    expected.code,
+                    "Expected {} ({}), found {} ('{}')",
+                    expected.code,
+                    expected.code.title,
+                    actual.code,
                     actual.message
                 );
                 assert_eq!(
@@ -199,7 +531,7 @@ mod test {
     #[test]
     fn should_report_undeclared_variable() {
         let input = "let a = «b»";
-        let expected = vec!["Undeclared variable 'b'"];
+        let expected = vec![&codes::UNDECLARED_VARIABLE];
 
         let verifier = DiagnosticsVerifier::new(input, expected);
         verifier.verify();
@@ -208,7 +540,7 @@ mod test {
     #[test]
     fn should_report_expected_expression() {
         let input = "let a = «+»";
-        let expected = vec!["Expected expression, found <+>"];
+        let expected = vec![&codes::EXPECTED_EXPRESSION];
 
         let verifier = DiagnosticsVerifier::new(input, expected);
         verifier.verify();
@@ -217,12 +549,95 @@ mod test {
     #[test]
     fn should_report_bad_token() {
         let input = "let a = 8 «@» 2";
-        let expected = vec!["Expected expression, found <Bad>"];
+        let expected = vec![&codes::EXPECTED_EXPRESSION];
 
         let verifier = DiagnosticsVerifier::new(input, expected);
         verifier.verify();
     }
 
+    #[test]
+    fn should_report_every_syntax_error_once() {
+        let input =
+            "\
+        let a = 1
+        let «=» 5
+        let b = (a + 2
+        «println»(a)
+        if a > «{»
+            a = 2
+        }
+        let c = a + «*» 2
+    ";
+        let expected = vec![
+            &codes::UNEXPECTED_TOKEN,
+            &codes::UNEXPECTED_TOKEN,
+            &codes::EXPECTED_EXPRESSION,
+            &codes::EXPECTED_EXPRESSION
+        ];
+
+        let verifier = DiagnosticsVerifier::new(input, expected);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_recover_inside_blocks_without_losing_the_closing_brace() {
+        let input =
+            "\
+        func f(x: int) -> int {
+            let y = x «)»
+            return y
+        }
+        println(f(1 «2»))
+        func g(x: int «{»
+            let z = «}»
+    ";
+        let expected = vec![
+            &codes::EXPECTED_EXPRESSION,
+            &codes::UNEXPECTED_TOKEN,
+            &codes::UNEXPECTED_TOKEN,
+            &codes::EXPECTED_EXPRESSION
+        ];
+
+        let verifier = DiagnosticsVerifier::new(input, expected);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_report_unmatched_closing_brace() {
+        let input = "\
+        let a = 1
+        «}»
+        let b = a {
+    «»";
+        let expected = vec![&codes::EXPECTED_EXPRESSION, &codes::UNEXPECTED_TOKEN];
+
+        let verifier = DiagnosticsVerifier::new(input, expected);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_drop_duplicate_diagnostics_at_the_same_span() {
+        let mut diagnostics_bag = DiagnosticsBag::new();
+        let span = TextSpan::new(0, 1, "a".to_string());
+        diagnostics_bag.report_error(&codes::TYPE_MISMATCH, "First".to_string(), span.clone());
+        diagnostics_bag.report_error(&codes::TYPE_MISMATCH, "Second".to_string(), span.clone());
+        diagnostics_bag.report_warning(&codes::DEAD_BRANCH, "Third".to_string(), span);
+        let messages: Vec<&str> = diagnostics_bag.diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.message.as_str())
+            .collect();
+        assert_eq!(messages, vec!["First", "Third"]);
+    }
+
+    #[test]
+    fn should_keep_different_errors_at_the_same_span() {
+        let mut diagnostics_bag = DiagnosticsBag::new();
+        let span = TextSpan::new(0, 1, "a".to_string());
+        diagnostics_bag.report_error(&codes::TYPE_MISMATCH, "First".to_string(), span.clone());
+        diagnostics_bag.report_error(&codes::UNDECLARED_VARIABLE, "Second".to_string(), span);
+        assert_eq!(diagnostics_bag.diagnostics.len(), 2);
+    }
+
     #[test]
     fn should_report_undeclared_variable_when_variable_was_declared_in_another_scope() {
         let input =
@@ -240,7 +655,7 @@ mod test {
 b
 «c»
     ";
-        let expected = vec!["Undeclared variable 'c'"];
+        let expected = vec![&codes::UNDECLARED_VARIABLE];
 
         let verifier = DiagnosticsVerifier::new(input, expected);
         verifier.verify();
@@ -268,7 +683,7 @@ b
             let a = 10
         «a»
     ";
-        let expected = vec!["Undeclared variable 'a'"];
+        let expected = vec![&codes::UNDECLARED_VARIABLE];
 
         let verifier = DiagnosticsVerifier::new(input, expected);
         verifier.verify();
@@ -281,7 +696,7 @@ b
         func «a» {}
     ";
 
-        let expected = vec!["Function 'a' already declared"];
+        let expected = vec![&codes::FUNCTION_ALREADY_DECLARED];
 
         let verifier = DiagnosticsVerifier::new(input, expected);
         verifier.verify();
@@ -293,7 +708,7 @@ b
         «a»()
     ";
 
-        let expected = vec!["Undeclared function 'a'"];
+        let expected = vec![&codes::UNDECLARED_FUNCTION];
 
         let verifier = DiagnosticsVerifier::new(input, expected);
         verifier.verify();
@@ -306,9 +721,557 @@ b
         «a»(1)
     ";
 
-        let expected = vec!["Function 'a' expects 2 arguments, but was given 1"];
+        let expected = vec![&codes::INVALID_ARGUMENT_COUNT];
+
+        let verifier = DiagnosticsVerifier::new(input, expected);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_report_invalid_binary_operator_types() {
+        let input = "let a = true «+» 1";
+        let expected = vec![&codes::INVALID_BINARY_OPERATOR];
+
+        let verifier = DiagnosticsVerifier::new(input, expected);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_report_comparison_between_different_types() {
+        let input = "let a = 1 «==» false";
+        let expected = vec![&codes::INVALID_BINARY_OPERATOR];
+
+        let verifier = DiagnosticsVerifier::new(input, expected);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_report_invalid_unary_operator_type() {
+        let input = "let a = «-»true";
+        let expected = vec![&codes::INVALID_UNARY_OPERATOR];
+
+        let verifier = DiagnosticsVerifier::new(input, expected);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_report_non_boolean_if_condition() {
+        let input = "if «5» {}";
+        let expected = vec![&codes::TYPE_MISMATCH];
+
+        let verifier = DiagnosticsVerifier::new(input, expected);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_report_non_boolean_while_condition() {
+        let input = "while «1 + 2» {}";
+        let expected = vec![&codes::TYPE_MISMATCH];
+
+        let verifier = DiagnosticsVerifier::new(input, expected);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_report_assignment_of_mismatched_type() {
+        let input = "\
+        let a = 1
+        a = «true»
+    ";
+        let expected = vec![&codes::TYPE_MISMATCH];
+
+        let verifier = DiagnosticsVerifier::new(input, expected);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_report_let_initializer_not_matching_annotation() {
+        let input = "let a: int = «true»";
+        let expected = vec![&codes::TYPE_MISMATCH];
+
+        let verifier = DiagnosticsVerifier::new(input, expected);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_report_undeclared_type() {
+        let input = "\
+        func a(b: «foo») -> «bar» {}
+    ";
+        let expected = vec![&codes::UNDECLARED_TYPE, &codes::UNDECLARED_TYPE];
+
+        let verifier = DiagnosticsVerifier::new(input, expected);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_report_undeclared_type_in_let_annotation() {
+        let input = "let a: «foo» = 1";
+        let expected = vec![&codes::UNDECLARED_TYPE];
+
+        let verifier = DiagnosticsVerifier::new(input, expected);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_report_argument_not_matching_parameter_type() {
+        let input = "\
+        func add(a: int, b: int) -> int {
+            return a + b
+        }
+        add(1, «true»)
+    ";
+        let expected = vec![&codes::INVALID_ARGUMENT_TYPE];
+
+        let verifier = DiagnosticsVerifier::new(input, expected);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_report_return_value_not_matching_return_type() {
+        let input = "\
+        func a -> bool {
+            return «1»
+        }
+    ";
+        let expected = vec![&codes::TYPE_MISMATCH];
+
+        let verifier = DiagnosticsVerifier::new(input, expected);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_report_bare_return_in_function_with_return_type() {
+        let input = "\
+        func a -> int {
+            «return»
+        }
+    ";
+        let expected = vec![&codes::TYPE_MISMATCH];
+
+        let verifier = DiagnosticsVerifier::new(input, expected);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_report_function_that_can_end_without_returning_a_value() {
+        let input = "\
+        func f() -> «int» {}
+        func sign(n: int) -> «int» {
+            if n < 0 {
+                return -1
+            }
+        }
+        func first(n: int) -> «int» {
+            while true {
+                if n > 0 {
+                    break
+                }
+                return n
+            }
+        }
+        let x: int = f()
+        println(x + sign(x) + first(x))
+    ";
+        let expected = vec![&codes::MISSING_RETURN, &codes::MISSING_RETURN, &codes::MISSING_RETURN];
+
+        let verifier = DiagnosticsVerifier::new(input, expected);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_report_main_with_parameters() {
+        let input = "\
+        func «main»(a, b: int) {
+            println(a + b)
+        }
+    ";
+
+        let expected = vec![&codes::MAIN_WITH_PARAMETERS];
+
+        let verifier = DiagnosticsVerifier::new(input, expected);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_report_top_level_variable_redeclared_with_a_different_type() {
+        let input = "\
+        let g = 1
+        func f() -> int {
+            return g + 1
+        }
+        let g = 2
+        let «g» = \"two\"
+        {
+            let g = true
+        }
+        println(f())
+    ";
+
+        let expected = vec![&codes::TOP_LEVEL_VARIABLE_RETYPED];
 
         let verifier = DiagnosticsVerifier::new(input, expected);
         verifier.verify();
     }
+
+    #[test]
+    fn should_accept_functions_that_return_on_every_path() {
+        let input = "\
+        func sign(n: int) -> int {
+            if n < 0 {
+                return -1
+            } else if n == 0 {
+                return 0
+            } else {
+                return 1
+            }
+        }
+        func forever(n: int) -> int {
+            while true {
+                for i in 0..n {
+                    break
+                }
+                if n > 0 {
+                    return n
+                }
+            }
+        }
+        func nothing() {}
+        println(sign(forever(1)))
+        nothing()
+    ";
+
+        let verifier = DiagnosticsVerifier::new(input, vec![]);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_report_unterminated_string() {
+        let input = "\
+        let a = «\"abc»
+        let b = 1
+    ";
+        let expected = vec![&codes::UNTERMINATED_STRING];
+
+        let verifier = DiagnosticsVerifier::new(input, expected);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_report_unterminated_block_comment() {
+        let input = "\
+        let a = 1
+        «/*» outer /* inner */
+        let b = 2
+    ";
+        let expected = vec![&codes::UNTERMINATED_BLOCK_COMMENT];
+
+        let verifier = DiagnosticsVerifier::new(input, expected);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_report_invalid_escape_sequence() {
+        let input = "let a = «\"a«\\q»b\\\"»";
+        let expected = vec![&codes::INVALID_ESCAPE_SEQUENCE, &codes::UNTERMINATED_STRING];
+
+        let verifier = DiagnosticsVerifier::new(input, expected);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_report_string_and_int_concatenation() {
+        let input = "let a = \"a\" «+» 1";
+        let expected = vec![&codes::INVALID_BINARY_OPERATOR];
+
+        let verifier = DiagnosticsVerifier::new(input, expected);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_report_mixed_int_and_float_arithmetic() {
+        let input = "let a = 1 «+» 2.5";
+        let expected = vec![&codes::INVALID_BINARY_OPERATOR];
+
+        let verifier = DiagnosticsVerifier::new(input, expected);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_leave_arithmetic_on_unannotated_parameters_to_the_runtime() {
+        let input = "\
+        func join(a, b) {
+            let c = a + b
+            return c + \"!\"
+        }
+        func scale(a, b) {
+            let c = a * b
+            let d = -a
+            if c < 1.5 {
+                return c + 0.5
+            }
+            return d - 0.5
+        }
+        println(join(\"x\", \"y\"))
+        println(scale(1.5, 2.0))
+    ";
+
+        let verifier = DiagnosticsVerifier::new(input, vec![]);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_report_non_numeric_operands_next_to_unannotated_parameters() {
+        let input = "\
+        func f(a) {
+            let b = a «+» true
+            let c = a «<» \"x\"
+            return b
+        }
+        println(f(1))
+    ";
+        let expected = vec![&codes::INVALID_BINARY_OPERATOR, &codes::INVALID_BINARY_OPERATOR];
+
+        let verifier = DiagnosticsVerifier::new(input, expected);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_report_integer_literal_out_of_range() {
+        let input = "let a = «9223372036854775808»";
+        let expected = vec![&codes::INTEGER_LITERAL_OUT_OF_RANGE];
+
+        let verifier = DiagnosticsVerifier::new(input, expected);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_report_non_bool_logical_operands() {
+        let input = "\
+        let a = 1 «&&» true
+        let b = «!»2
+    ";
+        let expected = vec![
+            &codes::INVALID_BINARY_OPERATOR,
+            &codes::INVALID_UNARY_OPERATOR
+        ];
+
+        let verifier = DiagnosticsVerifier::new(input, expected);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_report_loop_variable_outside_of_for_loop() {
+        let input = "\
+        for i in 0..3 {
+            let a = i
+        }
+        let b = «i»
+    ";
+        let expected = vec![&codes::UNDECLARED_VARIABLE];
+
+        let verifier = DiagnosticsVerifier::new(input, expected);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_report_non_int_range_bounds() {
+        let input = "for i in «0.5»..«true» {}";
+        let expected = vec![&codes::TYPE_MISMATCH, &codes::TYPE_MISMATCH];
+
+        let verifier = DiagnosticsVerifier::new(input, expected);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_report_break_and_continue_outside_of_loop() {
+        let input =
+            "\
+        «break»
+        while true {
+            func skip() {
+                «continue»
+            }
+            break
+        }
+    ";
+        let expected = vec![
+            &codes::OUTSIDE_OF_LOOP,
+            &codes::OUTSIDE_OF_LOOP
+        ];
+
+        let verifier = DiagnosticsVerifier::new(input, expected);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_report_redeclared_intrinsic_function() {
+        let input = "func «println»(a) {}";
+        let expected = vec![&codes::FUNCTION_ALREADY_DECLARED];
+
+        let verifier = DiagnosticsVerifier::new(input, expected);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_report_invalid_argument_type_for_intrinsic_function() {
+        let input = "exit(«true»)";
+        let expected = vec![&codes::INVALID_ARGUMENT_TYPE];
+
+        let verifier = DiagnosticsVerifier::new(input, expected);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_warn_about_statements_after_return() {
+        let input = "\
+        func f(x: int) -> int {
+            if x > 0 {
+                return 1
+                «println(x)»
+                println(x)
+            }
+            return 0
+            «println(x + 1)»
+        }
+        println(f(1))
+        g()
+        return 0
+        «println(2)»
+        func g {}
+    ";
+        let expected = vec![
+            &codes::UNREACHABLE_CODE,
+            &codes::UNREACHABLE_CODE,
+            &codes::UNREACHABLE_CODE,
+        ];
+
+        let verifier = DiagnosticsVerifier::warnings(input, expected);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_warn_after_if_statements_that_always_exit() {
+        let input = "\
+        func f(x: int) -> int {
+            while x > 0 {
+                if x > 1 { break } else { continue }
+                «println(x)»
+            }
+            if x > 0 { return 1 }
+            return 0
+        }
+        println(f(1))
+    ";
+        let expected = vec![&codes::UNREACHABLE_CODE];
+
+        let verifier = DiagnosticsVerifier::warnings(input, expected);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_warn_about_branches_with_constant_conditions() {
+        let input = "\
+        if false «{
+            println(1)
+        }»
+        if (true) {
+            println(2)
+        } else «println(3)»
+        while false «{ println(4) }»
+        while true { break }
+    ";
+        let expected = vec![
+            &codes::DEAD_BRANCH,
+            &codes::DEAD_BRANCH,
+            &codes::DEAD_LOOP
+        ];
+
+        let verifier = DiagnosticsVerifier::warnings(input, expected);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_warn_about_unused_bindings() {
+        let input = "\
+        func f(«a», b, _c) {
+            let d = b
+            let _e = 1
+            for «i» in 0..3 {
+                println(d)
+            }
+            let «b» = 2
+            b = 3
+        }
+        let «x» = 1
+        let y = 2
+        {
+            let «y» = 3
+        }
+        println(y)
+        f(1, 2, 3)
+    ";
+        let expected = vec![
+            &codes::UNUSED_PARAMETER,
+            &codes::UNUSED_VARIABLE,
+            &codes::UNUSED_VARIABLE,
+            &codes::UNUSED_VARIABLE,
+            &codes::UNUSED_VARIABLE
+        ];
+
+        let verifier = DiagnosticsVerifier::warnings(input, expected);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_count_reads_of_top_level_variables_from_functions() {
+        let input = "\
+        let total = 0
+        func add(amount: int) {
+            total = total + amount
+        }
+        add(1)
+    ";
+        let expected = vec![];
+
+        let verifier = DiagnosticsVerifier::warnings(input, expected);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_warn_about_functions_that_are_never_called() {
+        let input = "\
+        func «countdown»(n: int) {
+            if n > 0 { countdown(n - 1) }
+        }
+        func «unused» {}
+        func _ignored {}
+        func helper -> int { return 1 }
+        func main {
+            println(helper())
+        }
+    ";
+        let expected = vec![
+            &codes::UNCALLED_FUNCTION,
+            &codes::UNCALLED_FUNCTION
+        ];
+
+        let verifier = DiagnosticsVerifier::warnings(input, expected);
+        verifier.verify();
+    }
+
+    #[test]
+    fn should_not_stop_compilation_because_of_warnings() {
+        let input = "\
+        let unused = 1
+        func never {}
+        if false { println(0) }
+        println(2)
+    ";
+
+        let compilation_unit = CompilationUnit::compile(input).expect("Failed to compile");
+        let diagnostics_bag = compilation_unit.diagnostics_bag.borrow();
+        assert!(!diagnostics_bag.has_errors());
+        assert_eq!(diagnostics_bag.diagnostics.len(), 3);
+    }
 }
@@ -1,108 +1,562 @@
 extern crate termion;
 
-use crate::diagnostics::Diagnostic;
+use crate::ast::lexer::TextSpan;
+use crate::diagnostics::{ Diagnostic, DiagnosticKind, Footer };
+use crate::diagnostics::codes::DiagnosticCode;
+use crate::diagnostics::snippet::{ Annotation, Palette, Snippet };
+use crate::lsp::json::Json;
 use crate::text::SourceText;
-use std::cmp;
-use termion::color::{ Fg, Red, Reset };
+use std::io;
+use termion::color::{ Blue, Fg, Red, Reset, Yellow };
+
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+// How diagnostics are written out
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ErrorFormat {
+    // The source lines with the spans underlined and labelled, for people
+    #[default]
+    Human,
+    // One `file:line:column: severity[code]: message` line per diagnostic
+    Short,
+    // One JSON object per line per diagnostic
+    Json,
+    // A single SARIF 2.1.0 log with every diagnostic of the run
+    Sarif,
+}
+
+impl ErrorFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "human" => Some(ErrorFormat::Human),
+            "short" => Some(ErrorFormat::Short),
+            "json" => Some(ErrorFormat::Json),
+            "sarif" => Some(ErrorFormat::Sarif),
+            _ => None,
+        }
+    }
+
+    // A SARIF log can only be written once nothing more will be reported
+    pub fn is_deferred(&self) -> bool {
+        *self == ErrorFormat::Sarif
+    }
+}
+
+// Where a compilation's diagnostics go and which file they are about
+#[derive(Clone, Debug, Default)]
+pub struct DiagnosticsOutput {
+    pub format: ErrorFormat,
+    // As given on the command line
+    pub path: Option<String>,
+}
 
 pub struct DiagnosticsPrinter<'a> {
     text: &'a SourceText,
     diagnostics: &'a [Diagnostic],
+    path: Option<&'a str>,
 }
 
-const PREFIX_LENGTH: usize = 8;
-
 impl<'a> DiagnosticsPrinter<'a> {
     pub fn new(text: &'a SourceText, diagnostics: &'a [Diagnostic]) -> Self {
         Self {
             text,
             diagnostics,
+            path: None,
         }
     }
 
+    pub fn with_path(mut self, path: Option<&'a str>) -> Self {
+        self.path = path;
+        self
+    }
+
     /*
-     * Stringify the diagnostic by using the format:
-     * let <red>x<reset> = 5;
-     *          ^
-     *          |
-     *          +-- This is the error message (<line>:<column>)
+     * Stringify the diagnostic in the style of rustc:
+     * error[T0002]: Function 'add' expects 2 arguments, but was given 1
+     *  --> main.tn:4:1
+     *   |
+     * 1 | func add(a: int, b: int) -> int {
+     *   |      --- function declared here
+     * ...
+     * 4 | add(1)
+     *   | ^^^
      */
     pub fn stringify_diagnostic(&self, diagnostic: &Diagnostic) -> String {
-        let line_index = self.text.line_index(diagnostic.span.start);
-        let line = self.text.get_line(line_index);
-        let line_start = self.text.line_start(line_index);
+        self.render(diagnostic, &Palette::default())
+    }
+
+    fn render(&self, diagnostic: &Diagnostic, palette: &Palette) -> String {
+        let mut annotations = vec![Annotation {
+            span: &diagnostic.span,
+            label: None,
+            is_primary: true,
+        }];
+        annotations.extend(
+            diagnostic.related.iter().map(|related| Annotation {
+                span: &related.span,
+                label: Some(related.message.as_str()),
+                is_primary: false,
+            })
+        );
+        let snippet = Snippet::new(self.text, annotations, palette);
+        let padding = " ".repeat(snippet.gutter_width());
+        let empty_gutter = palette.paint(&format!("{} |", padding), false);
+
+        let severity = format!("{}[{}]", Self::severity(diagnostic.kind), diagnostic.code);
+        let location = self.location(&diagnostic.span);
+        let mut rows = vec![
+            format!("{}: {}", palette.paint(&severity, true), diagnostic.message),
+            format!("{}{} {}", padding, palette.paint("-->", false), location),
+            empty_gutter.clone()
+        ];
+        rows.extend(snippet.render());
+        if !diagnostic.footers.is_empty() {
+            rows.push(empty_gutter);
+        }
+        for footer in &diagnostic.footers {
+            let (kind, message) = match footer {
+                Footer::Note(message) => ("note", message),
+                Footer::Help(message) => ("help", message),
+            };
+            rows.push(format!("{} {} {}: {}", padding, palette.paint("=", false), kind, message));
+        }
+        rows.join("\n")
+    }
+
+    // Errors are red and warnings yellow, with everything else in blue
+    fn terminal_palette(kind: DiagnosticKind) -> Palette {
+        let primary = match kind {
+            DiagnosticKind::Error => Fg(Red).to_string(),
+            DiagnosticKind::Warning => Fg(Yellow).to_string(),
+        };
+        Palette {
+            primary,
+            secondary: Fg(Blue).to_string(),
+            reset: Fg(Reset).to_string(),
+        }
+    }
 
-        let column = diagnostic.span.start - line_start;
-        let (prefix, span, suffix) = self.get_text_spans(diagnostic, &line, column);
+    pub fn print(&self) {
+        let is_terminal = termion::is_tty(&io::stderr());
+        for diagnostic in self.diagnostics {
+            let palette = if is_terminal {
+                Self::terminal_palette(diagnostic.kind)
+            } else {
+                Palette::default()
+            };
+            eprintln!("{}\n", self.render(diagnostic, &palette));
+        }
+    }
 
-        let indent = cmp::max(cmp::min(PREFIX_LENGTH, column), 0) as usize;
-        let (arrow_pointers, arrow_line) = Self::format_arrow(diagnostic, indent);
+    // A SARIF log is written even without diagnostics, so that tools can tell a clean run apart
+    pub fn print_as(&self, format: ErrorFormat) {
+        match format {
+            ErrorFormat::Human => self.print(),
+            _ if self.diagnostics.is_empty() && !format.is_deferred() => {}
+            _ => eprintln!("{}", self.stringify(format)),
+        }
+    }
 
-        let error_message = Self::format_error_message(diagnostic, indent, column, line_index);
+    pub fn stringify(&self, format: ErrorFormat) -> String {
+        let lines = |stringify: &dyn Fn(&Diagnostic) -> String| {
+            self.diagnostics.iter().map(stringify).collect::<Vec<_>>().join("\n")
+        };
+        match format {
+            ErrorFormat::Human => {
+                self.diagnostics
+                    .iter()
+                    .map(|diagnostic| self.stringify_diagnostic(diagnostic))
+                    .collect::<Vec<_>>()
+                    .join("\n\n")
+            }
+            ErrorFormat::Short => lines(&|diagnostic| self.stringify_short(diagnostic)),
+            ErrorFormat::Json => lines(&|diagnostic| self.to_json(diagnostic).to_string()),
+            ErrorFormat::Sarif => self.to_sarif().to_string(),
+        }
+    }
 
+    fn stringify_short(&self, diagnostic: &Diagnostic) -> String {
         format!(
-            "{}{}{}{}{}\n{}\n{}\n{}",
-            prefix,
-            Fg(Red),
-            span,
-            Fg(Reset),
-            suffix,
-            arrow_pointers,
-            arrow_line,
-            error_message
+            "{}: {}[{}]: {}",
+            self.location(&diagnostic.span),
+            Self::severity(diagnostic.kind),
+            diagnostic.code.id,
+            diagnostic.message
         )
     }
 
-    // Refactored logic for prefix, span, and suffix
-    fn get_text_spans(
-        &'a self,
-        diagnostic: &Diagnostic,
-        line: &'a str,
-        column: usize
-    ) -> (&'a str, &'a str, &'a str) {
-        let prefix_start = cmp::max(0, (column as isize) - (PREFIX_LENGTH as isize)) as usize;
-        let prefix_end = column;
-        let suffix_start = cmp::min(column + diagnostic.span.length(), line.len());
-        let suffix_end = cmp::min(suffix_start + PREFIX_LENGTH, line.len());
-
-        let prefix = &line[prefix_start..prefix_end];
-        let span = &line[prefix_end..suffix_start];
-        let suffix = &line[suffix_start..suffix_end];
-        (prefix, span, suffix)
-    }
-
-    // Refactored logic for arrow-formatting
-    fn format_arrow(diagnostic: &Diagnostic, indent: usize) -> (String, String) {
-        let arrow_pointers = format!(
-            "{:indent$}{}",
-            "",
-            std::iter::repeat('^').take(diagnostic.span.length()).collect::<String>(),
-            indent = indent
-        );
-        let arrow_line = format!("{:indent$}|", "", indent = indent);
-        (arrow_pointers, arrow_line)
+    // `file:line:column`, counting from 1
+    fn location(&self, span: &TextSpan) -> String {
+        let (line, column) = self.text.line_column(span.start);
+        match self.path {
+            Some(path) => format!("{}:{}:{}", path, line + 1, column + 1),
+            None => format!("{}:{}", line + 1, column + 1),
+        }
     }
 
-    // Refactored logic for error message-formatting
-    fn format_error_message(
-        diagnostic: &Diagnostic,
-        indent: usize,
-        column: usize,
-        line_index: usize
-    ) -> String {
-        format!(
-            "{:indent$}+-- {} ({}:{})",
-            "",
-            diagnostic.message,
-            column + 1,
-            line_index + 1,
-            indent = indent
+    fn severity(kind: DiagnosticKind) -> &'static str {
+        match kind {
+            DiagnosticKind::Error => "error",
+            DiagnosticKind::Warning => "warning",
+        }
+    }
+
+    // Lines and columns count from 1, and the end points just past the last character
+    fn to_json(&self, diagnostic: &Diagnostic) -> Json {
+        let related = diagnostic.related
+            .iter()
+            .map(|related| {
+                Json::object(
+                    vec![
+                        ("message", Json::string(&related.message)),
+                        ("span", self.span_to_json(&related.span))
+                    ]
+                )
+            })
+            .collect::<Vec<_>>();
+        Json::object(
+            vec![
+                ("code", Json::string(diagnostic.code.id)),
+                ("severity", Json::string(Self::severity(diagnostic.kind))),
+                ("message", Json::string(&diagnostic.message)),
+                ("file", self.path.map_or(Json::Null, Json::string)),
+                ("span", self.span_to_json(&diagnostic.span)),
+                ("related", Json::from(related))
+            ]
         )
     }
 
-    pub fn print(&self) {
+    fn span_to_json(&self, span: &TextSpan) -> Json {
+        let (line_start, column_start) = self.text.line_column(span.start);
+        let (line_end, column_end) = self.text.line_column(span.end);
+        Json::object(
+            vec![
+                ("byte_start", Json::from(span.start)),
+                ("byte_end", Json::from(span.end)),
+                ("line_start", Json::from(line_start + 1)),
+                ("column_start", Json::from(column_start + 1)),
+                ("line_end", Json::from(line_end + 1)),
+                ("column_end", Json::from(column_end + 1))
+            ]
+        )
+    }
+
+    fn to_sarif(&self) -> Json {
+        let mut codes: Vec<&DiagnosticCode> = Vec::new();
         for diagnostic in self.diagnostics {
-            println!("{}", self.stringify_diagnostic(diagnostic));
+            if !codes.contains(&diagnostic.code) {
+                codes.push(diagnostic.code);
+            }
         }
+        let rules = codes
+            .into_iter()
+            .map(|code| {
+                let text = |text: &str| Json::object(vec![("text", Json::string(text))]);
+                Json::object(
+                    vec![
+                        ("id", Json::string(code.id)),
+                        ("shortDescription", text(code.title)),
+                        ("fullDescription", text(code.explanation))
+                    ]
+                )
+            })
+            .collect::<Vec<_>>();
+        let results = self.diagnostics
+            .iter()
+            .map(|diagnostic| self.to_sarif_result(diagnostic))
+            .collect::<Vec<_>>();
+        let driver = Json::object(
+            vec![
+                ("name", Json::string("tungsten")),
+                ("version", Json::string(env!("CARGO_PKG_VERSION"))),
+                ("rules", Json::from(rules))
+            ]
+        );
+        let run = Json::object(
+            vec![
+                ("tool", Json::object(vec![("driver", driver)])),
+                ("columnKind", Json::string("unicodeCodePoints")),
+                ("results", Json::from(results))
+            ]
+        );
+        Json::object(
+            vec![
+                ("$schema", Json::string(SARIF_SCHEMA)),
+                ("version", Json::string("2.1.0")),
+                ("runs", Json::from(vec![run]))
+            ]
+        )
+    }
+
+    fn to_sarif_result(&self, diagnostic: &Diagnostic) -> Json {
+        let related = diagnostic.related
+            .iter()
+            .enumerate()
+            .map(|(id, related)| {
+                Json::object(
+                    vec![
+                        ("id", Json::from(id)),
+                        ("physicalLocation", self.to_sarif_location(&related.span)),
+                        ("message", Json::object(vec![("text", Json::string(&related.message))]))
+                    ]
+                )
+            })
+            .collect::<Vec<_>>();
+        let location = Json::object(
+            vec![("physicalLocation", self.to_sarif_location(&diagnostic.span))]
+        );
+        Json::object(
+            vec![
+                ("ruleId", Json::string(diagnostic.code.id)),
+                ("level", Json::string(Self::severity(diagnostic.kind))),
+                ("message", Json::object(vec![("text", Json::string(&diagnostic.message))])),
+                ("locations", Json::from(vec![location])),
+                ("relatedLocations", Json::from(related))
+            ]
+        )
+    }
+
+    fn to_sarif_location(&self, span: &TextSpan) -> Json {
+        let (start_line, start_column) = self.text.line_column(span.start);
+        let (end_line, end_column) = self.text.line_column(span.end);
+        let region = Json::object(
+            vec![
+                ("startLine", Json::from(start_line + 1)),
+                ("startColumn", Json::from(start_column + 1)),
+                ("endLine", Json::from(end_line + 1)),
+                ("endColumn", Json::from(end_column + 1)),
+                ("byteOffset", Json::from(span.start)),
+                ("byteLength", Json::from(span.end - span.start))
+            ]
+        );
+        let mut location = Vec::new();
+        if let Some(path) = self.path {
+            location.push(("artifactLocation", Json::object(vec![("uri", Json::string(path))])));
+        }
+        location.push(("region", region));
+        Json::object(location)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::ast::lexer::TextSpan;
+    use crate::compilation_unit::CompilationUnit;
+    use crate::diagnostics::{ codes, Diagnostic, DiagnosticKind };
+    use crate::diagnostics::printer::{ DiagnosticsPrinter, ErrorFormat };
+    use crate::lsp::json::Json;
+    use crate::text::SourceText;
+
+    const INPUT: &str = "let é = 1\nlet b = a\n";
+
+    fn diagnostics() -> Vec<Diagnostic> {
+        let span = |start: usize, end: usize| {
+            TextSpan::new(start, end, INPUT[start..end].to_string())
+        };
+        vec![
+            Diagnostic::new(
+                &codes::UNUSED_VARIABLE,
+                "Unused variable 'é'".to_string(),
+                span(4, 6),
+                DiagnosticKind::Warning
+            ),
+            Diagnostic::new(
+                &codes::UNDECLARED_VARIABLE,
+                "Undeclared variable 'a'".to_string(),
+                span(19, 20),
+                DiagnosticKind::Error
+            ).with_related("'é' was declared here".to_string(), span(4, 6))
+        ]
+    }
+
+    fn stringify(format: ErrorFormat) -> String {
+        let text = SourceText::new(INPUT.to_string());
+        let diagnostics = diagnostics();
+        DiagnosticsPrinter::new(&text, &diagnostics).with_path(Some("main.tn")).stringify(format)
+    }
+
+    #[test]
+    pub fn should_write_one_short_line_per_diagnostic() {
+        assert_eq!(
+            stringify(ErrorFormat::Short),
+            "main.tn:1:5: warning[T0023]: Unused variable 'é'\n\
+             main.tn:2:9: error[T0001]: Undeclared variable 'a'"
+        );
+    }
+
+    #[test]
+    pub fn should_write_json_with_character_columns_and_byte_offsets() {
+        let output = stringify(ErrorFormat::Json);
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        let warning = Json::parse(lines[0]).expect("Failed to parse");
+        assert_eq!(warning.get("severity").and_then(Json::as_str), Some("warning"));
+        let span = warning.get("span").unwrap();
+        assert_eq!(span.get("byte_end").and_then(Json::as_usize), Some(6));
+        assert_eq!(span.get("column_end").and_then(Json::as_usize), Some(6));
+
+        let error = Json::parse(lines[1]).expect("Failed to parse");
+        assert_eq!(error.get("code").and_then(Json::as_str), Some("T0001"));
+        assert_eq!(error.get("file").and_then(Json::as_str), Some("main.tn"));
+        let related = error.get("related").and_then(Json::as_array).unwrap();
+        let line = related[0].path(&["span", "line_start"]).and_then(Json::as_usize);
+        assert_eq!(line, Some(1));
+    }
+
+    #[test]
+    pub fn should_write_a_single_sarif_log() {
+        let log = Json::parse(&stringify(ErrorFormat::Sarif)).expect("Failed to parse");
+        assert_eq!(log.get("version").and_then(Json::as_str), Some("2.1.0"));
+        let run = &log.get("runs").and_then(Json::as_array).unwrap()[0];
+        let rules = run.path(&["tool", "driver", "rules"]).and_then(Json::as_array).unwrap();
+        assert_eq!(rules.len(), 2);
+        let results = run.get("results").and_then(Json::as_array).unwrap();
+        assert_eq!(results[1].get("level").and_then(Json::as_str), Some("error"));
+        let location = results[1].get("locations").and_then(Json::as_array).unwrap();
+        let region = location[0].path(&["physicalLocation", "region"]).unwrap();
+        assert_eq!(region.get("startLine").and_then(Json::as_usize), Some(2));
+        assert_eq!(region.get("startColumn").and_then(Json::as_usize), Some(9));
+        let uri = location[0].path(&["physicalLocation", "artifactLocation", "uri"]);
+        assert_eq!(uri.and_then(Json::as_str), Some("main.tn"));
+        let related = results[1].get("relatedLocations").and_then(Json::as_array).unwrap();
+        assert_eq!(related.len(), 1);
+    }
+
+    #[test]
+    pub fn should_count_columns_from_the_start_of_crlf_lines() {
+        let input = "let a = 1\r\nlet b = a\r\nprintln(a + c)\r\n";
+        let diagnostics_bag = CompilationUnit::compile(input).err().expect("Expected errors");
+        let text = SourceText::new(input.to_string());
+        let diagnostics = &diagnostics_bag.borrow().diagnostics;
+        let printer = DiagnosticsPrinter::new(&text, diagnostics);
+
+        let json = Json::parse(&printer.stringify(ErrorFormat::Json)).expect("Failed to parse");
+        let span = json.get("span").unwrap();
+        assert_eq!(span.get("byte_start").and_then(Json::as_usize), Some(34));
+        assert_eq!(span.get("line_start").and_then(Json::as_usize), Some(3));
+        assert_eq!(span.get("column_start").and_then(Json::as_usize), Some(13));
+        assert_eq!(span.get("column_end").and_then(Json::as_usize), Some(14));
+
+        let log = Json::parse(&printer.stringify(ErrorFormat::Sarif)).expect("Failed to parse");
+        let run = &log.get("runs").and_then(Json::as_array).unwrap()[0];
+        let results = run.get("results").and_then(Json::as_array).unwrap();
+        let location = results[0].get("locations").and_then(Json::as_array).unwrap();
+        let region = location[0].path(&["physicalLocation", "region"]).unwrap();
+        assert_eq!(region.get("startLine").and_then(Json::as_usize), Some(3));
+        assert_eq!(region.get("startColumn").and_then(Json::as_usize), Some(13));
+        assert_eq!(region.get("endColumn").and_then(Json::as_usize), Some(14));
+    }
+
+    #[test]
+    pub fn should_write_an_empty_sarif_log_for_a_clean_run() {
+        let text = SourceText::new(INPUT.to_string());
+        let log = DiagnosticsPrinter::new(&text, &[]).stringify(ErrorFormat::Sarif);
+        assert!(log.contains(r#""results":[]"#));
+    }
+
+    #[test]
+    pub fn should_write_a_header_a_gutter_and_whole_lines() {
+        assert_eq!(
+            stringify(ErrorFormat::Human),
+            "\
+warning[T0023]: Unused variable 'é'
+ --> main.tn:1:5
+  |
+1 | let é = 1
+  |     ^
+
+error[T0001]: Undeclared variable 'a'
+ --> main.tn:2:9
+  |
+1 | let é = 1
+  |     - 'é' was declared here
+2 | let b = a
+  |         ^"
+        );
+    }
+
+    #[test]
+    pub fn should_point_at_the_declaration_of_a_function_given_too_few_arguments() {
+        let input = "func add(a: int, b: int) -> int {\n    return a + b\n}\n\nadd(1)\n";
+        let diagnostics_bag = CompilationUnit::compile(input).err().expect("Expected errors");
+        let text = SourceText::new(input.to_string());
+        let diagnostics = &diagnostics_bag.borrow().diagnostics;
+        assert_eq!(
+            DiagnosticsPrinter::new(&text, diagnostics).stringify(ErrorFormat::Human),
+            "\
+error[T0002]: Function 'add' expects 2 arguments, but was given 1
+ --> 5:1
+  |
+1 | func add(a: int, b: int) -> int {
+  |      --- function declared here
+...
+5 | add(1)
+  | ^^^"
+        );
+    }
+
+    #[test]
+    pub fn should_shorten_spans_over_many_lines_and_write_footers() {
+        let input = (1..=12).map(|line| format!("line {}\n", line)).collect::<String>();
+        let text = SourceText::new(input.clone());
+        let (start, end) = (input.find("ne 2").unwrap(), input.find("line 11").unwrap());
+        let diagnostics = [
+            Diagnostic::new(
+                &codes::UNREACHABLE_CODE,
+                "Unreachable code".to_string(),
+                TextSpan::new(start, end, input[start..end].to_string()),
+                DiagnosticKind::Warning
+            )
+                .with_note("the block ends here".to_string())
+                .with_help("remove it".to_string()),
+        ];
+        assert_eq!(
+            DiagnosticsPrinter::new(&text, &diagnostics).stringify(ErrorFormat::Human),
+            "\
+warning[T0020]: Unreachable code
+  --> 2:3
+   |
+ 2 |   line 2
+   |  ___^
+ 3 | | line 3
+...
+ 9 | | line 9
+10 | | line 10
+   | |________^
+   |
+   = note: the block ends here
+   = help: remove it"
+        );
+    }
+
+    #[test]
+    pub fn should_hang_all_but_the_rightmost_label_below_the_marks() {
+        let input = "let a = add(first, second)\n";
+        let text = SourceText::new(input.to_string());
+        let span = |literal: &str| {
+            let start = input.find(literal).unwrap();
+            TextSpan::new(start, start + literal.len(), literal.to_string())
+        };
+        let diagnostics = [
+            Diagnostic::new(
+                &codes::TYPE_MISMATCH,
+                "Mismatched types".to_string(),
+                span("add"),
+                DiagnosticKind::Error
+            )
+                .with_related("this one".to_string(), span("first"))
+                .with_related("and this one".to_string(), span("second")),
+        ];
+        assert_eq!(
+            DiagnosticsPrinter::new(&text, &diagnostics).stringify(ErrorFormat::Human),
+            "\
+error[T0008]: Mismatched types
+ --> 1:9
+  |
+1 | let a = add(first, second)
+  |         ^^^ -----  ------ and this one
+  |             |
+  |             this one"
+        );
     }
 }
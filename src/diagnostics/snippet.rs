@@ -0,0 +1,288 @@
+use std::collections::BTreeSet;
+
+use crate::ast::lexer::TextSpan;
+use crate::text::SourceText;
+
+// Multi-line spans longer than this only show their first two and last two lines
+const MAX_SPAN_LINES: usize = 6;
+
+// A span to mark in the source, with an optional label next to the marks
+pub struct Annotation<'a> {
+    pub span: &'a TextSpan,
+    pub label: Option<&'a str>,
+    pub is_primary: bool,
+}
+
+// Escape sequences around marks and the gutter; all empty when the output is not a terminal
+#[derive(Default)]
+pub struct Palette {
+    pub primary: String,
+    pub secondary: String,
+    pub reset: String,
+}
+
+impl Palette {
+    pub fn paint(&self, text: &str, is_primary: bool) -> String {
+        if text.trim().is_empty() || self.reset.is_empty() {
+            return text.to_string();
+        }
+        let color = if is_primary { &self.primary } else { &self.secondary };
+        format!("{}{}{}", color, text, self.reset)
+    }
+}
+
+// Zero-based lines and character columns; the end column is exclusive
+struct Placement<'a> {
+    start_line: usize,
+    start_column: usize,
+    end_line: usize,
+    end_column: usize,
+    label: Option<&'a str>,
+    is_primary: bool,
+}
+
+impl Placement<'_> {
+    fn is_multiline(&self) -> bool {
+        self.start_line != self.end_line
+    }
+
+    fn mark(&self) -> char {
+        if self.is_primary { '^' } else { '-' }
+    }
+}
+
+// The annotated lines of a source text under a gutter of line numbers:
+//
+//   1 | func add(a: int, b: int) -> int {
+//     |      --- function declared here
+//   ...
+//   4 | add(1)
+//     | ^^^
+pub struct Snippet<'a> {
+    text: &'a SourceText,
+    placements: Vec<Placement<'a>>,
+    lines: BTreeSet<usize>,
+    palette: &'a Palette,
+}
+
+impl<'a> Snippet<'a> {
+    pub fn new(
+        text: &'a SourceText,
+        annotations: Vec<Annotation<'a>>,
+        palette: &'a Palette
+    ) -> Self {
+        let mut placements: Vec<Placement> = Vec::new();
+        let mut lines = BTreeSet::new();
+        for annotation in annotations {
+            // Recursive calls, for example, point at the same call site many times over
+            let placement = Self::place(text, &annotation);
+            let is_duplicate = placements.iter().any(|placed| {
+                (placed.start_line, placed.start_column, placed.end_line, placed.end_column) ==
+                    (
+                        placement.start_line,
+                        placement.start_column,
+                        placement.end_line,
+                        placement.end_column,
+                    ) && placed.label == placement.label
+            });
+            if is_duplicate {
+                continue;
+            }
+            let (start, end) = (placement.start_line, placement.end_line);
+            if end - start < MAX_SPAN_LINES {
+                lines.extend(start..=end);
+            } else {
+                lines.extend([start, start + 1, end - 1, end]);
+            }
+            placements.push(placement);
+        }
+        // A gap of a single line takes no more room than the `...` that would replace it
+        let gaps = lines
+            .iter()
+            .zip(lines.iter().skip(1))
+            .filter(|(line, next_line)| *next_line - *line == 2)
+            .map(|(line, _)| line + 1)
+            .collect::<Vec<_>>();
+        lines.extend(gaps);
+        Self { text, placements, lines, palette }
+    }
+
+    fn place(text: &SourceText, annotation: &Annotation<'a>) -> Placement<'a> {
+        let (start_line, start_column) = text.line_column(annotation.span.start);
+        let (mut end_line, mut end_column) = text.line_column(annotation.span.end);
+        // A span that takes in a line break ends just past the text of its last line
+        if end_line > start_line && end_column == 0 {
+            end_line -= 1;
+            end_column = text.get_line(end_line).chars().count() + 1;
+        }
+        // Empty spans still get a mark
+        if end_line == start_line {
+            end_column = end_column.max(start_column + 1);
+        }
+        Placement {
+            start_line,
+            start_column,
+            end_line,
+            end_column,
+            label: annotation.label,
+            is_primary: annotation.is_primary,
+        }
+    }
+
+    // Headers and footers line up with the gutter
+    pub fn gutter_width(&self) -> usize {
+        let last_line = self.lines.last().copied().unwrap_or(0);
+        (last_line + 1).to_string().len()
+    }
+
+    pub fn render(&self) -> Vec<String> {
+        let mut rows = Vec::new();
+        let mut previous_line: Option<usize> = None;
+        for &line in &self.lines {
+            if previous_line.is_some_and(|previous_line| line > previous_line + 1) {
+                rows.push("...".to_string());
+            }
+            previous_line = Some(line);
+
+            let source = self.text.get_line(line).replace('\t', " ");
+            let margin = self.margin(|placement| {
+                placement.start_line < line && line <= placement.end_line
+            });
+            rows.push(self.row(Some(line), &format!("{}{}", margin, source)));
+
+            for placement in self.multiline_placements(|placement| placement.start_line == line) {
+                let underscores = "_".repeat(placement.start_column + 1);
+                let marks = format!("{}{}", underscores, placement.mark());
+                let marks = self.paint_placement(&marks, placement);
+                rows.push(self.row(None, &format!(" {}", marks)));
+            }
+
+            let margin = self.margin(|placement| {
+                placement.start_line <= line && line < placement.end_line
+            });
+            self.render_single_line_placements(line, &margin, &mut rows);
+
+            for placement in self.multiline_placements(|placement| placement.end_line == line) {
+                let mut marks = format!("|{}{}", "_".repeat(placement.end_column), placement.mark());
+                if let Some(label) = placement.label {
+                    marks.push(' ');
+                    marks.push_str(label);
+                }
+                rows.push(self.row(None, &self.paint_placement(&marks, placement)));
+            }
+        }
+        rows
+    }
+
+    fn multiline_placements(
+        &self,
+        filter: impl Fn(&Placement) -> bool
+    ) -> impl Iterator<Item = &Placement<'a>> {
+        self.placements
+            .iter()
+            .filter(move |placement| placement.is_multiline() && filter(placement))
+    }
+
+    // Room on the left for the connecting lines of multi-line spans
+    fn margin(&self, is_inside: impl Fn(&Placement) -> bool) -> String {
+        if self.multiline_placements(|_| true).next().is_none() {
+            return String::new();
+        }
+        match self.multiline_placements(is_inside).next() {
+            Some(placement) => format!("{} ", self.paint_placement("|", placement)),
+            None => "  ".to_string(),
+        }
+    }
+
+    // Marks for every span on the line, with the rightmost label beside them and the others
+    // hanging below on connectors
+    fn render_single_line_placements(&self, line: usize, margin: &str, rows: &mut Vec<String>) {
+        let mut placements = self.placements
+            .iter()
+            .filter(|placement| !placement.is_multiline() && placement.start_line == line)
+            .collect::<Vec<_>>();
+        if placements.is_empty() {
+            return;
+        }
+        placements.sort_by_key(|placement| placement.start_column);
+        let width = placements
+            .iter()
+            .map(|placement| placement.end_column)
+            .max()
+            .unwrap();
+
+        let mut cells: Vec<(char, bool)> = vec![(' ', false); width];
+        // Primary marks win where spans overlap
+        let by_priority = placements
+            .iter()
+            .filter(|placement| !placement.is_primary)
+            .chain(placements.iter().filter(|placement| placement.is_primary));
+        for placement in by_priority {
+            for cell in &mut cells[placement.start_column..placement.end_column] {
+                *cell = (placement.mark(), placement.is_primary);
+            }
+        }
+
+        let mut labeled = placements
+            .iter()
+            .filter(|placement| placement.label.is_some())
+            .collect::<Vec<_>>();
+        let mut marks = self.paint_cells(&cells);
+        if labeled.last().is_some_and(|placement| placement.end_column == width) {
+            let placement = labeled.pop().unwrap();
+            let label = self.palette.paint(placement.label.unwrap(), placement.is_primary);
+            marks = format!("{} {}", marks, label);
+        }
+        rows.push(self.row(None, &format!("{}{}", margin, marks)));
+
+        while !labeled.is_empty() {
+            let connectors = self.connectors(&labeled);
+            rows.push(self.row(None, &format!("{}{}", margin, connectors)));
+            let placement = labeled.pop().unwrap();
+            let label = self.palette.paint(placement.label.unwrap(), placement.is_primary);
+            let connectors = self.connectors(&labeled);
+            let padding = placement.start_column - connectors_width(&labeled);
+            let row = format!("{}{}{}{}", margin, connectors, " ".repeat(padding), label);
+            rows.push(self.row(None, &row));
+        }
+    }
+
+    fn connectors(&self, placements: &[&&Placement]) -> String {
+        let mut cells = vec![(' ', false); connectors_width(placements)];
+        for placement in placements {
+            cells[placement.start_column] = ('|', placement.is_primary);
+        }
+        self.paint_cells(&cells)
+    }
+
+    fn paint_cells(&self, cells: &[(char, bool)]) -> String {
+        let mut result = String::new();
+        let mut run = String::new();
+        let mut run_is_primary = false;
+        for &(c, is_primary) in cells {
+            if is_primary != run_is_primary && !run.is_empty() {
+                result.push_str(&self.palette.paint(&run, run_is_primary));
+                run.clear();
+            }
+            run_is_primary = is_primary;
+            run.push(c);
+        }
+        result.push_str(&self.palette.paint(&run, run_is_primary));
+        result
+    }
+
+    fn paint_placement(&self, text: &str, placement: &Placement) -> String {
+        self.palette.paint(text, placement.is_primary)
+    }
+
+    fn row(&self, line: Option<usize>, content: &str) -> String {
+        let number = line.map_or(String::new(), |line| (line + 1).to_string());
+        let gutter = format!("{:>width$} |", number, width = self.gutter_width());
+        let row = format!("{} {}", self.palette.paint(&gutter, false), content);
+        row.trim_end().to_string()
+    }
+}
+
+fn connectors_width(placements: &[&&Placement]) -> usize {
+    placements.last().map_or(0, |placement| placement.start_column + 1)
+}
@@ -0,0 +1,119 @@
+use std::io::{ self, ErrorKind, Write };
+
+use crate::ast::evaluator::Value;
+use crate::types::Type;
+
+// What running an intrinsic did; each backend maps this onto its own control flow
+pub enum IntrinsicOutcome {
+    Value(Value),
+    Exit(i64),
+    Failure(String),
+}
+
+// Functions implemented natively by the runtime rather than in Tungsten source
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Intrinsic {
+    Print,
+    Println,
+    Assert,
+    Exit,
+    ToFloat,
+    ToInt,
+}
+
+impl Intrinsic {
+    pub const ALL: [Intrinsic; 6] = [
+        Intrinsic::Print,
+        Intrinsic::Println,
+        Intrinsic::Assert,
+        Intrinsic::Exit,
+        Intrinsic::ToFloat,
+        Intrinsic::ToInt,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Intrinsic::Print => "print",
+            Intrinsic::Println => "println",
+            Intrinsic::Assert => "assert",
+            Intrinsic::Exit => "exit",
+            Intrinsic::ToFloat => "to_float",
+            Intrinsic::ToInt => "to_int",
+        }
+    }
+
+    pub fn parameters(&self) -> Vec<(&'static str, Type)> {
+        match self {
+            Intrinsic::Print | Intrinsic::Println => vec![("value", Type::Unresolved)],
+            Intrinsic::Assert => vec![("condition", Type::Bool)],
+            Intrinsic::Exit => vec![("code", Type::Int)],
+            Intrinsic::ToFloat => vec![("value", Type::Int)],
+            Intrinsic::ToInt => vec![("value", Type::Float)],
+        }
+    }
+
+    pub fn return_type(&self) -> Type {
+        match self {
+            Intrinsic::ToFloat => Type::Float,
+            Intrinsic::ToInt => Type::Int,
+            _ => Type::Unit,
+        }
+    }
+
+    // Arguments of the wrong type, e.g. from unannotated parameters, are runtime errors
+    pub fn call(&self, arguments: &[Value], output: &mut dyn Write) -> IntrinsicOutcome {
+        self.try_call(arguments, output).unwrap_or_else(IntrinsicOutcome::Failure)
+    }
+
+    fn try_call(
+        &self,
+        arguments: &[Value],
+        output: &mut dyn Write
+    ) -> Result<IntrinsicOutcome, String> {
+        match self {
+            Intrinsic::Print => {
+                return Ok(Self::written(write!(output, "{}", arguments[0])));
+            }
+            Intrinsic::Println => {
+                return Ok(Self::written(writeln!(output, "{}", arguments[0])));
+            }
+            Intrinsic::Assert => {
+                if !arguments[0].as_bool()? {
+                    return Ok(IntrinsicOutcome::Failure("Assertion failed".to_string()));
+                }
+            }
+            Intrinsic::Exit => {
+                return Ok(IntrinsicOutcome::Exit(arguments[0].as_int()?));
+            }
+            Intrinsic::ToFloat => {
+                return Ok(IntrinsicOutcome::Value(Value::Float(arguments[0].as_int()? as f64)));
+            }
+            Intrinsic::ToInt => {
+                return Ok(IntrinsicOutcome::Value(Value::Int(arguments[0].as_float()? as i64)));
+            }
+        }
+        Ok(IntrinsicOutcome::Value(Value::Unit))
+    }
+
+    // Whoever reads a closed pipe, e.g. `head`, has seen all they wanted, so the program just ends
+    fn written(result: io::Result<()>) -> IntrinsicOutcome {
+        match result {
+            Ok(()) => IntrinsicOutcome::Value(Value::Unit),
+            Err(error) if error.kind() == ErrorKind::BrokenPipe => IntrinsicOutcome::Exit(0),
+            Err(error) => IntrinsicOutcome::Failure(format!("Failed to write output: {}", error)),
+        }
+    }
+}
+
+// Output written by a session around the program, e.g. REPL prompts, ends it by the same rules
+// as `print`. Returns the exit code once the session has to end
+pub fn written_or_exit(result: io::Result<()>) -> Option<i64> {
+    match Intrinsic::written(result) {
+        IntrinsicOutcome::Value(_) => None,
+        IntrinsicOutcome::Exit(code) => Some(code),
+        IntrinsicOutcome::Failure(message) => {
+            eprintln!("{}", message);
+            Some(1)
+        }
+    }
+}
@@ -0,0 +1,13 @@
+
+pub mod ast;
+pub mod diagnostics;
+pub mod text;
+pub mod types;
+pub mod intrinsics;
+pub mod bytecode;
+pub mod codegen;
+pub mod compilation_unit;
+pub mod repl;
+pub mod lsp;
+
+pub use crate::compilation_unit::CompilationUnit;
@@ -0,0 +1,365 @@
+use std::fmt::{ Display, Formatter };
+use std::iter::Peekable;
+use std::str::Chars;
+
+// Just enough JSON for the language server protocol; objects keep their keys in order so that
+// responses come out the same every time
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn object(members: Vec<(&str, Json)>) -> Self {
+        Json::Object(
+            members
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect()
+        )
+    }
+
+    pub fn string(value: &str) -> Self {
+        Json::String(value.to_string())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => {
+                members
+                    .iter()
+                    .find(|(name, _)| name == key)
+                    .map(|(_, value)| value)
+            }
+            _ => None,
+        }
+    }
+
+    // Follows a chain of object keys, e.g. `["textDocument", "uri"]`
+    pub fn path(&self, keys: &[&str]) -> Option<&Json> {
+        keys.iter().try_fold(self, |json, key| json.get(key))
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_usize(&self) -> Option<usize> {
+        match self {
+            Json::Number(value) if *value >= 0.0 && value.fract() == 0.0 => Some(*value as usize),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn parse(input: &str) -> Result<Json, String> {
+        let mut parser = JsonParser { chars: input.chars().peekable() };
+        let value = parser.parse_value()?;
+        parser.skip_whitespace();
+        match parser.chars.next() {
+            None => Ok(value),
+            Some(c) => Err(format!("Unexpected '{}' after the value", c)),
+        }
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Self {
+        Json::Bool(value)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(value: usize) -> Self {
+        Json::Number(value as f64)
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Self {
+        Json::String(value)
+    }
+}
+
+impl From<Vec<Json>> for Json {
+    fn from(values: Vec<Json>) -> Self {
+        Json::Array(values)
+    }
+}
+
+impl Display for Json {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            Json::Number(value) => {
+                if value.fract() == 0.0 && value.abs() < 1e15 {
+                    write!(f, "{}", *value as i64)
+                } else {
+                    write!(f, "{}", value)
+                }
+            }
+            Json::String(value) => write_string(f, value),
+            Json::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(members) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut Formatter<'_>, value: &str) -> std::fmt::Result {
+    write!(f, "\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+struct JsonParser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl JsonParser<'_> {
+    fn parse_value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some('n') => self.parse_keyword("null", Json::Null),
+            Some('t') => self.parse_keyword("true", Json::Bool(true)),
+            Some('f') => self.parse_keyword("false", Json::Bool(false)),
+            Some('"') => self.parse_string().map(Json::String),
+            Some('[') => self.parse_array(),
+            Some('{') => self.parse_object(),
+            Some(c) if *c == '-' || c.is_ascii_digit() => self.parse_number(),
+            Some(c) => Err(format!("Unexpected '{}'", c)),
+            None => Err("Unexpected end of input".to_string()),
+        }
+    }
+
+    fn parse_keyword(&mut self, keyword: &str, value: Json) -> Result<Json, String> {
+        for expected in keyword.chars() {
+            if self.chars.next() != Some(expected) {
+                return Err(format!("Expected '{}'", keyword));
+            }
+        }
+        Ok(value)
+    }
+
+    fn parse_number(&mut self) -> Result<Json, String> {
+        let mut literal = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if !(c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')) {
+                break;
+            }
+            literal.push(c);
+            self.chars.next();
+        }
+        literal
+            .parse()
+            .map(Json::Number)
+            .map_err(|_| format!("Invalid number '{}'", literal))
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut value = String::new();
+        loop {
+            match self.chars.next() {
+                Some('"') => {
+                    return Ok(value);
+                }
+                Some('\\') => {
+                    let escaped = match self.chars.next() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => self.parse_unicode_escape()?,
+                        _ => {
+                            return Err("Invalid escape sequence".to_string());
+                        }
+                    };
+                    value.push(escaped);
+                }
+                Some(c) => value.push(c),
+                None => {
+                    return Err("Unterminated string".to_string());
+                }
+            }
+        }
+    }
+
+    // Characters outside the basic plane arrive as a pair of UTF-16 surrogate escapes
+    fn parse_unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.parse_hex()?;
+        if !(0xd800..0xdc00).contains(&high) {
+            return char::from_u32(high).ok_or_else(|| "Invalid unicode escape".to_string());
+        }
+        if self.chars.next() != Some('\\') || self.chars.next() != Some('u') {
+            return Err("Unpaired surrogate".to_string());
+        }
+        let low = self.parse_hex()?;
+        let code = 0x10000 + ((high - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff);
+        char::from_u32(code).ok_or_else(|| "Invalid unicode escape".to_string())
+    }
+
+    fn parse_hex(&mut self) -> Result<u32, String> {
+        let digits: String = self.chars.by_ref().take(4).collect();
+        u32::from_str_radix(&digits, 16).map_err(|_| "Invalid unicode escape".to_string())
+    }
+
+    fn parse_array(&mut self) -> Result<Json, String> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&']') {
+            self.chars.next();
+            return Ok(Json::Array(values));
+        }
+        loop {
+            values.push(self.parse_value()?);
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some(',') => {}
+                Some(']') => {
+                    return Ok(Json::Array(values));
+                }
+                _ => {
+                    return Err("Expected ',' or ']'".to_string());
+                }
+            }
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<Json, String> {
+        self.expect('{')?;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&'}') {
+            self.chars.next();
+            return Ok(Json::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.parse_string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            members.push((key, self.parse_value()?));
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some(',') => {}
+                Some('}') => {
+                    return Ok(Json::Object(members));
+                }
+                _ => {
+                    return Err("Expected ',' or '}'".to_string());
+                }
+            }
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.chars.next() {
+            Some(c) if c == expected => Ok(()),
+            _ => Err(format!("Expected '{}'", expected)),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+            self.chars.next();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Json;
+
+    #[test]
+    pub fn should_parse_nested_values() {
+        let json = Json::parse(
+            r#" {"id": 3, "params": {"items": [true, null, -1.5e1, "a\"bé"]}} "#
+        ).expect("Failed to parse");
+        assert_eq!(json.get("id").and_then(Json::as_usize), Some(3));
+        let items = json.path(&["params", "items"]).and_then(Json::as_array).unwrap();
+        assert_eq!(
+            items,
+            [Json::Bool(true), Json::Null, Json::Number(-15.0), Json::string("a\"bé")]
+        );
+    }
+
+    #[test]
+    pub fn should_decode_surrogate_pairs() {
+        let json = Json::parse(r#""\ud83d\ude00""#).expect("Failed to parse");
+        assert_eq!(json, Json::string("😀"));
+    }
+
+    #[test]
+    pub fn should_reject_malformed_input() {
+        for input in ["", "{", "[1,]", "{\"a\" 1}", "tru", "\"abc", "1 2"] {
+            assert!(Json::parse(input).is_err(), "Parsed '{}'", input);
+        }
+    }
+
+    #[test]
+    pub fn should_serialize_in_insertion_order() {
+        let json = Json::object(
+            vec![
+                ("b", Json::from(1)),
+                ("a", Json::from(vec![Json::string("line\n\"quoted\""), Json::Null])),
+                ("c", Json::Number(0.5))
+            ]
+        );
+        assert_eq!(json.to_string(), r#"{"b":1,"a":["line\n\"quoted\"",null],"c":0.5}"#);
+    }
+
+    #[test]
+    pub fn should_round_trip() {
+        let input = r#"{"jsonrpc":"2.0","id":1,"result":{"items":[false,"\t"]}}"#;
+        assert_eq!(Json::parse(input).expect("Failed to parse").to_string(), input);
+    }
+}
@@ -0,0 +1,636 @@
+pub mod json;
+mod symbols;
+
+use std::collections::HashMap;
+use std::io::{ BufRead, Write };
+
+use crate::ast::lexer::TextSpan;
+use crate::compilation_unit::CompilationUnit;
+use crate::diagnostics::{ Diagnostic, DiagnosticKind };
+use crate::intrinsics::written_or_exit;
+use crate::lsp::json::Json;
+use crate::lsp::symbols::SymbolIndex;
+use crate::text::SourceText;
+
+// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+// LSP enumerations
+const FULL_SYNC: usize = 1;
+const SEVERITY_ERROR: usize = 1;
+const SEVERITY_WARNING: usize = 2;
+const SYMBOL_FUNCTION: usize = 12;
+
+struct Document {
+    text: SourceText,
+    diagnostics: Vec<Diagnostic>,
+    symbols: SymbolIndex,
+}
+
+impl Document {
+    fn new(input: &str) -> Self {
+        let analysis = CompilationUnit::analyze_in_editor(input);
+        Self {
+            text: SourceText::new(input.to_string()),
+            symbols: SymbolIndex::new(&analysis),
+            diagnostics: analysis.diagnostics,
+        }
+    }
+
+    // LSP positions count UTF-16 code units within a line
+    fn position(&self, offset: usize) -> Json {
+        let line_index = self.text.line_index(offset);
+        let line = self.text.get_line(line_index);
+        let column = (offset - self.text.line_start(line_index)).min(line.len());
+        let character = line[..column].encode_utf16().count();
+        Json::object(vec![("line", Json::from(line_index)), ("character", Json::from(character))])
+    }
+
+    fn range(&self, span: &TextSpan) -> Json {
+        Json::object(vec![("start", self.position(span.start)), ("end", self.position(span.end))])
+    }
+
+    // None for a position past the end of its line or of the text
+    fn offset(&self, (line_index, character): (usize, usize)) -> Option<usize> {
+        if line_index >= self.text.line_count() {
+            return None;
+        }
+        let line = self.text.get_line(line_index);
+        let mut units = 0;
+        let column = line
+            .char_indices()
+            .find(|(_, c)| {
+                units += c.len_utf16();
+                units > character
+            })
+            .map(|(column, _)| column);
+        let column = match column {
+            Some(column) => column,
+            None if units == character => line.len(),
+            None => {
+                return None;
+            }
+        };
+        Some(self.text.line_start(line_index) + column)
+    }
+}
+
+// A language server speaking JSON-RPC, with each document fully resent on every change
+pub struct LanguageServer {
+    documents: HashMap<String, Document>,
+    is_shutting_down: bool,
+}
+
+impl Default for LanguageServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LanguageServer {
+    pub fn new() -> Self {
+        Self {
+            documents: HashMap::new(),
+            is_shutting_down: false,
+        }
+    }
+
+    // Serves until the client sends `exit`, returning the exit code the protocol asks for
+    pub fn run(&mut self, input: &mut dyn BufRead, output: &mut dyn Write) -> i64 {
+        while let Some(content) = read_message(input) {
+            let message = match Json::parse(&content) {
+                Ok(message) => message,
+                Err(error) => {
+                    let reply = error_response(Json::Null, PARSE_ERROR, &error);
+                    if let Some(code) = write_message(output, &reply) {
+                        return code;
+                    }
+                    continue;
+                }
+            };
+            if message.get("method").and_then(Json::as_str) == Some("exit") {
+                return if self.is_shutting_down { 0 } else { 1 };
+            }
+            for reply in self.handle(&message) {
+                if let Some(code) = write_message(output, &reply) {
+                    return code;
+                }
+            }
+        }
+        1
+    }
+
+    // Responses to requests and any notifications a message triggers
+    fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let method = message.get("method").and_then(Json::as_str).unwrap_or_default();
+        let params = message.get("params").unwrap_or(&Json::Null);
+        let uri = params.path(&["textDocument", "uri"]).and_then(Json::as_str);
+        let position = params.get("position").and_then(|position| {
+            Some((position.get("line")?.as_usize()?, position.get("character")?.as_usize()?))
+        });
+        let invalid_params = (INVALID_PARAMS, format!("Invalid parameters for '{}'", method));
+        let result = match (method, uri) {
+            ("initialize", _) => Ok(Self::initialize()),
+            ("shutdown", _) => {
+                self.is_shutting_down = true;
+                Ok(Json::Null)
+            }
+            ("textDocument/didOpen", Some(uri)) => {
+                let text = params.path(&["textDocument", "text"]).and_then(Json::as_str);
+                return self.update(uri, text.unwrap_or_default());
+            }
+            ("textDocument/didChange", Some(uri)) => {
+                let changes = params.get("contentChanges").and_then(Json::as_array);
+                let text = changes.and_then(|changes| changes.last()?.get("text")?.as_str());
+                return self.update(uri, text.unwrap_or_default());
+            }
+            ("textDocument/didClose", Some(uri)) => {
+                self.documents.remove(uri);
+                return vec![publish_diagnostics(uri, Vec::new())];
+            }
+            ("textDocument/definition", Some(uri)) => {
+                position.map(|position| self.definition(uri, position)).ok_or(invalid_params)
+            }
+            ("textDocument/hover", Some(uri)) => {
+                position.map(|position| self.hover(uri, position)).ok_or(invalid_params)
+            }
+            ("textDocument/documentSymbol", Some(uri)) => Ok(self.document_symbols(uri)),
+            (
+                "textDocument/definition" | "textDocument/hover" | "textDocument/documentSymbol",
+                None,
+            ) => Err(invalid_params),
+            _ => Err((METHOD_NOT_FOUND, format!("Method '{}' is not supported", method))),
+        };
+        // Notifications the server does not care about go unanswered
+        match (message.get("id"), result) {
+            (Some(id), Ok(result)) => vec![response(id.clone(), result)],
+            (Some(id), Err((code, error))) => vec![error_response(id.clone(), code, &error)],
+            (None, _) => Vec::new(),
+        }
+    }
+
+    fn initialize() -> Json {
+        let capabilities = Json::object(
+            vec![
+                ("textDocumentSync", Json::from(FULL_SYNC)),
+                ("definitionProvider", Json::from(true)),
+                ("hoverProvider", Json::from(true)),
+                ("documentSymbolProvider", Json::from(true))
+            ]
+        );
+        let server_info = Json::object(vec![("name", Json::string("tungsten"))]);
+        Json::object(vec![("capabilities", capabilities), ("serverInfo", server_info)])
+    }
+
+    fn update(&mut self, uri: &str, text: &str) -> Vec<Json> {
+        let document = Document::new(text);
+        let diagnostics = document.diagnostics
+            .iter()
+            .map(|diagnostic| {
+                let severity = match diagnostic.kind {
+                    DiagnosticKind::Error => SEVERITY_ERROR,
+                    DiagnosticKind::Warning => SEVERITY_WARNING,
+                };
+                Json::object(
+                    vec![
+                        ("range", document.range(&diagnostic.span)),
+                        ("severity", Json::from(severity)),
+                        ("code", Json::string(diagnostic.code.id)),
+                        ("source", Json::string("tungsten")),
+                        ("message", Json::string(&diagnostic.message))
+                    ]
+                )
+            })
+            .collect();
+        self.documents.insert(uri.to_string(), document);
+        vec![publish_diagnostics(uri, diagnostics)]
+    }
+
+    fn definition(&self, uri: &str, position: (usize, usize)) -> Json {
+        let Some(document) = self.documents.get(uri) else {
+            return Json::Null;
+        };
+        let reference = document
+            .offset(position)
+            .and_then(|offset| document.symbols.reference_at(offset));
+        // Intrinsics have nowhere to go to
+        match reference.and_then(|(_, definition)| definition.span.as_ref()) {
+            Some(span) => {
+                Json::object(vec![("uri", Json::string(uri)), ("range", document.range(span))])
+            }
+            None => Json::Null,
+        }
+    }
+
+    fn hover(&self, uri: &str, position: (usize, usize)) -> Json {
+        let Some(document) = self.documents.get(uri) else {
+            return Json::Null;
+        };
+        let reference = document
+            .offset(position)
+            .and_then(|offset| document.symbols.reference_at(offset));
+        let Some((span, definition)) = reference else {
+            return Json::Null;
+        };
+        let contents = Json::object(
+            vec![
+                ("kind", Json::string("markdown")),
+                ("value", Json::from(format!("```tungsten\n{}\n```", definition.signature)))
+            ]
+        );
+        Json::object(vec![("contents", contents), ("range", document.range(span))])
+    }
+
+    fn document_symbols(&self, uri: &str) -> Json {
+        let Some(document) = self.documents.get(uri) else {
+            return Json::Null;
+        };
+        let symbols = document.symbols.document_symbols
+            .iter()
+            .map(|symbol| {
+                let definition = &document.symbols.definitions[symbol.definition];
+                Json::object(
+                    vec![
+                        ("name", Json::string(&symbol.identifier.literal)),
+                        ("detail", Json::string(&definition.signature)),
+                        ("kind", Json::from(SYMBOL_FUNCTION)),
+                        ("range", document.range(&symbol.span)),
+                        ("selectionRange", document.range(&symbol.identifier))
+                    ]
+                )
+            })
+            .collect();
+        Json::Array(symbols)
+    }
+}
+
+fn response(id: Json, result: Json) -> Json {
+    Json::object(vec![("jsonrpc", Json::string("2.0")), ("id", id), ("result", result)])
+}
+
+fn error_response(id: Json, code: i64, message: &str) -> Json {
+    let error = Json::object(
+        vec![("code", Json::Number(code as f64)), ("message", Json::string(message))]
+    );
+    Json::object(vec![("jsonrpc", Json::string("2.0")), ("id", id), ("error", error)])
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Json>) -> Json {
+    let params = Json::object(
+        vec![("uri", Json::string(uri)), ("diagnostics", Json::Array(diagnostics))]
+    );
+    Json::object(
+        vec![
+            ("jsonrpc", Json::string("2.0")),
+            ("method", Json::string("textDocument/publishDiagnostics")),
+            ("params", params)
+        ]
+    )
+}
+
+// Messages are framed by a `Content-Length` header; None once the stream ends
+fn read_message(input: &mut dyn BufRead) -> Option<String> {
+    let mut content_length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header).ok()? == 0 {
+            return None;
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                content_length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let mut content = vec![0; content_length?];
+    input.read_exact(&mut content).ok()?;
+    String::from_utf8(content).ok()
+}
+
+// Returns the exit code once the client can no longer be written to
+fn write_message(output: &mut dyn Write, message: &Json) -> Option<i64> {
+    let content = message.to_string();
+    let written = write!(output, "Content-Length: {}\r\n\r\n{}", content.len(), content).and_then(
+        |()| output.flush()
+    );
+    written_or_exit(written)
+}
+
+#[cfg(test)]
+mod test {
+    use super::LanguageServer;
+    use super::json::Json;
+
+    const URI: &str = "file:///main.tn";
+
+    fn frame(message: &str) -> String {
+        format!("Content-Length: {}\r\n\r\n{}", message.len(), message)
+    }
+
+    // Runs a session over canned messages, returning the exit code and every message sent back
+    fn serve(messages: &[String]) -> (i64, Vec<Json>) {
+        let input: String = messages
+            .iter()
+            .map(|message| frame(message))
+            .collect();
+        let mut output = Vec::new();
+        let code = LanguageServer::new().run(&mut input.as_bytes(), &mut output);
+        let output = String::from_utf8(output).expect("Output is not UTF-8");
+        let replies = output
+            .split("Content-Length: ")
+            .filter(|part| !part.is_empty())
+            .map(|part| {
+                let (_, content) = part.split_once("\r\n\r\n").expect("Missing header end");
+                Json::parse(content).expect("Reply is not JSON")
+            })
+            .collect();
+        (code, replies)
+    }
+
+    fn open(text: &str) -> String {
+        let params = Json::object(
+            vec![
+                (
+                    "textDocument",
+                    Json::object(
+                        vec![
+                            ("uri", Json::string(URI)),
+                            ("languageId", Json::string("tungsten")),
+                            ("version", Json::from(1)),
+                            ("text", Json::string(text))
+                        ]
+                    ),
+                )
+            ]
+        );
+        notification("textDocument/didOpen", params)
+    }
+
+    fn notification(method: &str, params: Json) -> String {
+        Json::object(
+            vec![
+                ("jsonrpc", Json::string("2.0")),
+                ("method", Json::string(method)),
+                ("params", params)
+            ]
+        ).to_string()
+    }
+
+    fn request(id: usize, method: &str, params: Json) -> String {
+        Json::object(
+            vec![
+                ("jsonrpc", Json::string("2.0")),
+                ("id", Json::from(id)),
+                ("method", Json::string(method)),
+                ("params", params)
+            ]
+        ).to_string()
+    }
+
+    fn at(line: usize, character: usize) -> Json {
+        Json::object(
+            vec![
+                ("textDocument", Json::object(vec![("uri", Json::string(URI))])),
+                (
+                    "position",
+                    Json::object(
+                        vec![("line", Json::from(line)), ("character", Json::from(character))]
+                    ),
+                )
+            ]
+        )
+    }
+
+    fn result_of(replies: &[Json], id: usize) -> &Json {
+        replies
+            .iter()
+            .find(|reply| reply.get("id") == Some(&Json::from(id)))
+            .and_then(|reply| reply.get("result"))
+            .expect("No result for request")
+    }
+
+    const PROGRAM: &str =
+        "\
+func add(a: int, b: int) -> int {
+    return a + b
+}
+let total = add(1, 2)
+println(total)
+";
+
+    #[test]
+    pub fn should_initialize_and_shut_down() {
+        let (code, replies) = serve(
+            &[
+                request(1, "initialize", Json::object(vec![])),
+                notification("initialized", Json::object(vec![])),
+                request(2, "shutdown", Json::Null),
+                notification("exit", Json::Null),
+            ]
+        );
+        assert_eq!(code, 0);
+        assert_eq!(replies.len(), 2);
+        let capabilities = result_of(&replies, 1).get("capabilities").unwrap();
+        assert_eq!(capabilities.get("hoverProvider"), Some(&Json::Bool(true)));
+        assert_eq!(result_of(&replies, 2), &Json::Null);
+    }
+
+    #[test]
+    pub fn should_exit_with_failure_without_shutdown() {
+        let (code, _) = serve(&[notification("exit", Json::Null)]);
+        assert_eq!(code, 1);
+    }
+
+    #[test]
+    pub fn should_publish_diagnostics_with_positions() {
+        let (_, replies) = serve(&[open("let a = 1\nlet b = a + c\n")]);
+        let params = replies[0].get("params").unwrap();
+        assert_eq!(params.get("uri"), Some(&Json::string(URI)));
+        let diagnostics = params.get("diagnostics").and_then(Json::as_array).unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "{\"range\":{\"start\":{\"line\":1,\"character\":12},\
+\"end\":{\"line\":1,\"character\":13}},\
+\"severity\":1,\"code\":\"T0001\",\"source\":\"tungsten\",\
+\"message\":\"Undeclared variable 'c'\"}"
+        );
+    }
+
+    #[test]
+    pub fn should_publish_warnings_and_clear_on_close() {
+        let close = notification(
+            "textDocument/didClose",
+            Json::object(vec![("textDocument", Json::object(vec![("uri", Json::string(URI))]))])
+        );
+        let (_, replies) = serve(&[open("let unused = 1\n"), close]);
+        let diagnostics = replies[0].path(&["params", "diagnostics"]).unwrap().as_array().unwrap();
+        assert_eq!(diagnostics[0].get("severity"), Some(&Json::from(2)));
+        let cleared = replies[1].path(&["params", "diagnostics"]).unwrap();
+        assert_eq!(cleared, &Json::Array(Vec::new()));
+    }
+
+    #[test]
+    pub fn should_go_to_definitions() {
+        let (_, replies) = serve(
+            &[
+                open(PROGRAM),
+                request(1, "textDocument/definition", at(3, 13)),
+                request(2, "textDocument/definition", at(4, 10)),
+                request(3, "textDocument/definition", at(1, 11)),
+                request(4, "textDocument/definition", at(4, 2)),
+            ]
+        );
+        let start_of = |id| result_of(&replies, id).path(&["range", "start"]).unwrap().to_string();
+        assert_eq!(start_of(1), "{\"line\":0,\"character\":5}");
+        assert_eq!(start_of(2), "{\"line\":3,\"character\":4}");
+        assert_eq!(start_of(3), "{\"line\":0,\"character\":9}");
+        // `println` is an intrinsic with no source to go to
+        assert_eq!(result_of(&replies, 4), &Json::Null);
+    }
+
+    #[test]
+    pub fn should_hover_with_signatures_and_inferred_types() {
+        let (_, replies) = serve(
+            &[
+                open(PROGRAM),
+                request(1, "textDocument/hover", at(3, 12)),
+                request(2, "textDocument/hover", at(4, 12)),
+                request(3, "textDocument/hover", at(4, 3)),
+                request(4, "textDocument/hover", at(2, 0)),
+            ]
+        );
+        let hover_of = |id| {
+            let value = result_of(&replies, id).path(&["contents", "value"]).unwrap();
+            value.as_str().unwrap().to_string()
+        };
+        assert_eq!(hover_of(1), "```tungsten\nfunc add(a: int, b: int) -> int\n```");
+        assert_eq!(hover_of(2), "```tungsten\nlet total: int\n```");
+        assert_eq!(hover_of(3), "```tungsten\nfunc println(value)\n```");
+        assert_eq!(result_of(&replies, 4), &Json::Null);
+    }
+
+    #[test]
+    pub fn should_resolve_names_in_files_with_errors() {
+        let program = "let a = 1\nfunc f(a: int) {\n    a = a + c\n}\nprintln(a)\n";
+        let (_, replies) = serve(
+            &[
+                open(program),
+                request(1, "textDocument/definition", at(2, 8)),
+                request(2, "textDocument/hover", at(4, 8)),
+                request(3, "textDocument/hover", at(2, 12)),
+            ]
+        );
+        let start = result_of(&replies, 1).path(&["range", "start"]).unwrap().to_string();
+        assert_eq!(start, "{\"line\":1,\"character\":7}");
+        let hover = result_of(&replies, 2).path(&["contents", "value"]).unwrap();
+        assert_eq!(hover.as_str(), Some("```tungsten\nlet a\n```"));
+        assert_eq!(result_of(&replies, 3), &Json::Null);
+    }
+
+    #[test]
+    pub fn should_list_functions_as_document_symbols() {
+        let program = "func first() {}\nlet x = 1\nfunc second(n) -> int {\n    return n\n}\n";
+        let params = Json::object(
+            vec![("textDocument", Json::object(vec![("uri", Json::string(URI))]))]
+        );
+        let (_, replies) = serve(
+            &[open(program), request(1, "textDocument/documentSymbol", params)]
+        );
+        let symbols = result_of(&replies, 1).as_array().unwrap();
+        assert_eq!(symbols.len(), 2);
+        assert_eq!(
+            symbols[1].to_string(),
+            "{\"name\":\"second\",\"detail\":\"func second(n) -> int\",\"kind\":12,\
+\"range\":{\"start\":{\"line\":2,\"character\":0},\"end\":{\"line\":4,\"character\":1}},\
+\"selectionRange\":{\"start\":{\"line\":2,\"character\":5},\"end\":{\"line\":2,\"character\":11}}}"
+        );
+    }
+
+    #[test]
+    pub fn should_find_nothing_past_the_end_of_a_line_or_the_text() {
+        // Both lines end in a name, which a clamped position would find
+        let (_, replies) = serve(
+            &[
+                open("let a = 1\nlet b = a\nb"),
+                request(1, "textDocument/hover", at(1, 9)),
+                request(2, "textDocument/hover", at(2, 1)),
+                request(3, "textDocument/hover", at(1, 10)),
+                request(4, "textDocument/hover", at(2, 2)),
+                request(5, "textDocument/hover", at(3, 0)),
+            ]
+        );
+        assert_ne!(result_of(&replies, 1), &Json::Null);
+        assert_ne!(result_of(&replies, 2), &Json::Null);
+        for id in 3..=5 {
+            assert_eq!(result_of(&replies, id), &Json::Null);
+        }
+    }
+
+    #[test]
+    pub fn should_reject_requests_with_invalid_params() {
+        let without_position = Json::object(
+            vec![("textDocument", Json::object(vec![("uri", Json::string(URI))]))]
+        );
+        let negative_position = Json::object(
+            vec![
+                ("textDocument", Json::object(vec![("uri", Json::string(URI))])),
+                (
+                    "position",
+                    Json::object(
+                        vec![("line", Json::Number(-1.0)), ("character", Json::from(0_usize))]
+                    ),
+                )
+            ]
+        );
+        let (_, replies) = serve(
+            &[
+                open(PROGRAM),
+                request(1, "textDocument/hover", Json::Null),
+                request(2, "textDocument/definition", without_position),
+                request(3, "textDocument/hover", negative_position),
+                request(4, "textDocument/documentSymbol", Json::object(vec![])),
+            ]
+        );
+        assert_eq!(replies.len(), 5);
+        for reply in &replies[1..] {
+            assert_eq!(reply.path(&["error", "code"]), Some(&Json::Number(-32602.0)));
+        }
+    }
+
+    #[test]
+    pub fn should_track_changes_and_reject_unknown_requests() {
+        let change = notification(
+            "textDocument/didChange",
+            Json::object(
+                vec![
+                    ("textDocument", Json::object(vec![("uri", Json::string(URI))])),
+                    (
+                        "contentChanges",
+                        Json::from(vec![Json::object(vec![("text", Json::string("let x = \n"))])]),
+                    )
+                ]
+            )
+        );
+        let (_, replies) = serve(
+            &[
+                open("let x = 1\n"),
+                change,
+                request(7, "textDocument/rename", Json::Null),
+                "{not json".to_string(),
+            ]
+        );
+        let diagnostics = replies[1].path(&["params", "diagnostics"]).unwrap().as_array().unwrap();
+        let message = diagnostics[0].get("message").and_then(Json::as_str);
+        assert_eq!(message, Some("Expected expression, found <Eof>"));
+        assert_eq!(replies[2].path(&["error", "code"]), Some(&Json::Number(-32601.0)));
+        assert_eq!(replies[3].get("id"), Some(&Json::Null));
+        assert_eq!(replies[3].path(&["error", "code"]), Some(&Json::Number(-32700.0)));
+    }
+}
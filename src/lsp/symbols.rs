@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+
+use crate::ast::lexer::TextSpan;
+use crate::compilation_unit::{
+    EditorAnalysis,
+    FunctionSymbol,
+    Reference,
+    VariableDeclaration,
+    VariableKind,
+};
+use crate::types::Type;
+
+// Something a name can refer to
+#[derive(Debug, Clone)]
+pub struct Definition {
+    // Where the name is declared; intrinsics have no source
+    pub span: Option<TextSpan>,
+    // Shown on hover, e.g. `let a: int` or `func add(a: int, b: int) -> int`
+    pub signature: String,
+}
+
+// A function declaration as listed in the document outline
+pub struct DocumentSymbol {
+    pub identifier: TextSpan,
+    // From `func` to the end of the body
+    pub span: TextSpan,
+    pub definition: usize,
+}
+
+// Which declaration every identifier in a file refers to, as the resolver found it
+pub struct SymbolIndex {
+    pub definitions: Vec<Definition>,
+    references: Vec<(TextSpan, usize)>,
+    pub document_symbols: Vec<DocumentSymbol>,
+}
+
+impl SymbolIndex {
+    // Types are only known when the file checked cleanly; otherwise hovers show annotations only
+    pub fn new(analysis: &EditorAnalysis) -> Self {
+        let mut definitions: Vec<Definition> = analysis.names.variables
+            .iter()
+            .map(|variable| Definition {
+                span: Some(variable.identifier.clone()),
+                signature: Self::describe_variable(variable, analysis),
+            })
+            .collect();
+        let mut functions = HashMap::new();
+        for (identifier, function) in &analysis.global_scope.functions {
+            functions.insert(identifier.as_str(), definitions.len());
+            definitions.push(Definition {
+                span: function.declaration.clone(),
+                signature: Self::describe_function(identifier, function),
+            });
+        }
+
+        let references = analysis.names.references
+            .iter()
+            .filter_map(|(span, reference)| {
+                let definition = match reference {
+                    Reference::Variable(index) => *index,
+                    Reference::Function(identifier) => *functions.get(identifier.as_str())?,
+                };
+                Some((span.clone(), definition))
+            })
+            .collect();
+        let document_symbols = analysis.names.functions
+            .iter()
+            .filter_map(|(identifier, span)| {
+                Some(DocumentSymbol {
+                    identifier: identifier.clone(),
+                    span: span.clone(),
+                    definition: *functions.get(identifier.literal.as_str())?,
+                })
+            })
+            .collect();
+        SymbolIndex { definitions, references, document_symbols }
+    }
+
+    // The identifier at a byte offset, counting the position just past its end
+    pub fn reference_at(&self, offset: usize) -> Option<(&TextSpan, &Definition)> {
+        self.references
+            .iter()
+            .find(|(span, _)| span.start <= offset && offset <= span.end)
+            .map(|(span, definition)| (span, &self.definitions[*definition]))
+    }
+
+    fn describe_variable(variable: &VariableDeclaration, analysis: &EditorAnalysis) -> String {
+        let name = &variable.identifier.literal;
+        let describe_annotated = |type_annotation: &Option<String>| {
+            match type_annotation {
+                Some(type_name) => format!("{}: {}", name, type_name),
+                None => name.clone(),
+            }
+        };
+        match &variable.kind {
+            VariableKind::Let { type_annotation: None, initializer } => {
+                let inferred_type = analysis.expression_types
+                    .as_ref()
+                    .and_then(|types| types.get(&(initializer.start, initializer.end)));
+                match inferred_type {
+                    Some(ty) => format!("let {}", Self::describe_typed(name, ty)),
+                    None => format!("let {}", name),
+                }
+            }
+            VariableKind::Let { type_annotation, .. } => {
+                format!("let {}", describe_annotated(type_annotation))
+            }
+            VariableKind::Parameter { type_annotation } => describe_annotated(type_annotation),
+            VariableKind::LoopVariable => Self::describe_typed(name, &Type::Int),
+        }
+    }
+
+    fn describe_function(identifier: &str, function: &FunctionSymbol) -> String {
+        let parameters = function.parameters
+            .iter()
+            .map(|parameter| Self::describe_typed(&parameter.identifier, &parameter.ty))
+            .collect::<Vec<_>>();
+        let return_type = match &function.return_type {
+            Type::Unit | Type::Unresolved | Type::Error => String::new(),
+            ty => format!(" -> {}", ty),
+        };
+        format!("func {}({}){}", identifier, parameters.join(", "), return_type)
+    }
+
+    fn describe_typed(name: &str, ty: &Type) -> String {
+        match ty {
+            Type::Unresolved | Type::Error => name.to_string(),
+            ty => format!("{}: {}", name, ty),
+        }
+    }
+}
@@ -1,42 +1,317 @@
-use crate::compilation_unit::CompilationUnit;
-
-mod ast;
-mod diagnostics;
-mod text;
-mod compilation_unit;
-
-// Precedence: paren, unary, mult/div, add/sub, bitwise (shift, AND, XOR, OR)
-fn main() -> Result<(), ()> {
-    let input =
-        "\
-        func doSomething {
-        }
-        func main {
-        }
-        let a = add(1, 2)
-        func add(a, b) {
-            return a + b
-        }
-        while a < 10 {
-            a = a + 1
-        }
-        if a >= 10 {
-            a = 25
-        }
-        else
-        {
-            a = 20
-            let a = 10
-            a = 15
-        }
-        let b = 10
-        if true {
-            b = 20
-        }
-        a
-        b
-    ";
-    let compilation_unit = CompilationUnit::compile(input).map_err(|_| ())?;
-    compilation_unit.run();
-    Ok(())
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::ExitCode;
+use std::rc::Rc;
+use std::thread;
+
+use tungsten::ast::lexer::{ Lexer, TokenKind };
+use tungsten::codegen::x86_64;
+use tungsten::diagnostics::DiagnosticsBag;
+use tungsten::diagnostics::codes::{ self, DiagnosticCode };
+use tungsten::diagnostics::printer::{ DiagnosticsOutput, DiagnosticsPrinter, ErrorFormat };
+use tungsten::text::SourceText;
+use tungsten::CompilationUnit;
+use tungsten::compilation_unit::Backend;
+use tungsten::lsp::LanguageServer;
+use tungsten::repl::Repl;
+
+const USAGE: &str =
+    "\
+Usage: tungsten <command> [options] <file.tn>
+       tungsten repl|lsp
+       tungsten explain [code]
+
+Commands:
+    run       Compile and run a source file
+    check     Report diagnostics without running
+    ast       Print the syntax tree of a source file
+    tokens    Print the token stream of a source file
+    bytecode  Print the compiled bytecode of a source file
+    c         Print the C translation of a source file
+    wat       Print the WebAssembly text module of a source file
+    build     Compile a source file to a native x86-64 Linux executable
+    fmt       Rewrite a source file in the canonical style
+    repl      Start an interactive session
+    lsp       Serve the Language Server Protocol over stdin and stdout
+    explain   Describe a diagnostic code such as T0001, or list every code
+
+Options:
+    --backend=ast|bytecode    Execution backend for `run` (default: ast)
+    -o <path>                 Output path for `build` (default: the file name without .tn)
+    --check                   Make `fmt` report unformatted files instead of rewriting them
+    --error-format=human|short|json|sarif
+                              How diagnostics are written to stderr (default: human)";
+
+enum Command {
+    Run,
+    Check,
+    Ast,
+    Tokens,
+    Bytecode,
+    C,
+    Wat,
+    Build,
+    Fmt,
+}
+
+impl Command {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "run" => Some(Command::Run),
+            "check" => Some(Command::Check),
+            "ast" => Some(Command::Ast),
+            "tokens" => Some(Command::Tokens),
+            "bytecode" => Some(Command::Bytecode),
+            "c" => Some(Command::C),
+            "wat" => Some(Command::Wat),
+            "build" => Some(Command::Build),
+            "fmt" => Some(Command::Fmt),
+            _ => None,
+        }
+    }
+}
+
+// The evaluator recurses natively for every call, up to `MAX_CALL_DEPTH` calls deep
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+fn main() -> ExitCode {
+    let command = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run_command)
+        .expect("Failed to start the main thread");
+    // A panic has already been reported by the thread itself
+    command.join().unwrap_or(ExitCode::from(101))
+}
+
+fn run_command() -> ExitCode {
+    let mut backend = Backend::Evaluator;
+    let mut output = None;
+    let mut check = false;
+    let mut error_format = ErrorFormat::Human;
+    let mut args = Vec::new();
+    let mut arguments = std::env::args().skip(1);
+    while let Some(argument) = arguments.next() {
+        match argument.as_str() {
+            "--backend=ast" => {
+                backend = Backend::Evaluator;
+            }
+            "--backend=bytecode" => {
+                backend = Backend::Bytecode;
+            }
+            "-o" =>
+                match arguments.next() {
+                    Some(path) => {
+                        output = Some(path);
+                    }
+                    None => {
+                        eprintln!("Option '-o' expects a path\n\n{}", USAGE);
+                        return ExitCode::from(2);
+                    }
+                }
+            "--check" => {
+                check = true;
+            }
+            option if option.starts_with("--error-format=") => {
+                let name = &option["--error-format=".len()..];
+                match ErrorFormat::from_name(name) {
+                    Some(format) => {
+                        error_format = format;
+                    }
+                    None => {
+                        eprintln!("Unknown error format '{}'\n\n{}", name, USAGE);
+                        return ExitCode::from(2);
+                    }
+                }
+            }
+            option if option.starts_with('-') => {
+                eprintln!("Unknown option '{}'\n\n{}", option, USAGE);
+                return ExitCode::from(2);
+            }
+            _ => args.push(argument),
+        }
+    }
+    let (command, path) = match args.as_slice() {
+        [name] if name == "repl" => {
+            return run_repl();
+        }
+        [name] if name == "explain" => {
+            for code in codes::ALL {
+                println!("{}  {}", code, code.title);
+            }
+            return ExitCode::SUCCESS;
+        }
+        [name, code] if name == "explain" => {
+            return explain(code);
+        }
+        [name] if name == "lsp" => {
+            let code = LanguageServer::new().run(&mut io::stdin().lock(), &mut io::stdout());
+            return ExitCode::from(code as u8);
+        }
+        [name, path] =>
+            match Command::from_name(name) {
+                Some(command) => (command, path),
+                None => {
+                    eprintln!("Unknown command '{}'\n\n{}", name, USAGE);
+                    return ExitCode::from(2);
+                }
+            }
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    let input = match fs::read_to_string(path) {
+        Ok(input) => input,
+        Err(error) => {
+            eprintln!("Could not read '{}': {}", path, error);
+            return ExitCode::from(2);
+        }
+    };
+
+    let diagnostics_output = DiagnosticsOutput { format: error_format, path: Some(path.clone()) };
+    match command {
+        Command::Tokens => print_tokens(&input, &diagnostics_output),
+        Command::Fmt => format_file(path, &input, check, diagnostics_output),
+        Command::Run |
+        Command::Check |
+        Command::Ast |
+        Command::Bytecode |
+        Command::C |
+        Command::Wat |
+        Command::Build => {
+            // The syntax tree is shown as it was parsed, before any optimization
+            let compiled = if matches!(command, Command::Ast) {
+                CompilationUnit::analyze_with_output(&input, diagnostics_output.clone())
+            } else {
+                CompilationUnit::compile_with_output(&input, diagnostics_output.clone())
+            };
+            let code = match &compiled {
+                Ok(compilation_unit) => {
+                    run_compiled(command, compilation_unit, path, backend, output)
+                }
+                Err(_) => ExitCode::FAILURE,
+            };
+            let diagnostics_bag = match &compiled {
+                Ok(compilation_unit) => &compilation_unit.diagnostics_bag,
+                Err(diagnostics_bag) => diagnostics_bag,
+            };
+            CompilationUnit::print_deferred_diagnostics(
+                &input,
+                diagnostics_bag,
+                &diagnostics_output
+            );
+            code
+        }
+    }
+}
+
+fn run_compiled(
+    command: Command,
+    compilation_unit: &CompilationUnit,
+    path: &str,
+    backend: Backend,
+    output: Option<String>
+) -> ExitCode {
+    match command {
+        Command::Run => {
+            return match compilation_unit.run(backend) {
+                // Exit codes wrap like they would for a native process
+                Ok(code) => ExitCode::from(code as u8),
+                Err(error) => {
+                    compilation_unit.print_runtime_error(&error);
+                    ExitCode::FAILURE
+                }
+            };
+        }
+        Command::Ast => compilation_unit.ast.visualize(),
+        Command::Bytecode => print!("{}", compilation_unit.compile_bytecode()),
+        Command::C => print!("{}", compilation_unit.compile_c()),
+        Command::Wat => {
+            let Ok(module) = compilation_unit.compile_wat() else {
+                return ExitCode::FAILURE;
+            };
+            print!("{}", module);
+        }
+        Command::Build => {
+            let Ok(assembly) = compilation_unit.compile_x86_64() else {
+                return ExitCode::FAILURE;
+            };
+            let output = x86_64::output_path(Path::new(path), output.as_deref().map(Path::new));
+            let result = output.and_then(|output| x86_64::build_executable(&assembly, &output));
+            if let Err(message) = result {
+                eprintln!("{}", message);
+                return ExitCode::FAILURE;
+            }
+        }
+        _ => {}
+    }
+    ExitCode::SUCCESS
+}
+
+fn run_repl() -> ExitCode {
+    let code = Repl::new().run(&mut io::stdin().lock(), &mut io::stdout());
+    ExitCode::from(code as u8)
+}
+
+fn explain(code: &str) -> ExitCode {
+    match DiagnosticCode::lookup(code) {
+        Some(code) => {
+            print!("{}", code.describe());
+            ExitCode::SUCCESS
+        }
+        None => {
+            eprintln!("Unknown diagnostic code '{}'; run `tungsten explain` to list them", code);
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn format_file(
+    path: &str,
+    input: &str,
+    check: bool,
+    diagnostics_output: DiagnosticsOutput
+) -> ExitCode {
+    let formatted = match CompilationUnit::format_with_output(input, diagnostics_output.clone()) {
+        Ok(formatted) => formatted,
+        Err(diagnostics_bag) => {
+            CompilationUnit::print_deferred_diagnostics(
+                input,
+                &diagnostics_bag,
+                &diagnostics_output
+            );
+            return ExitCode::FAILURE;
+        }
+    };
+    if formatted == input {
+        return ExitCode::SUCCESS;
+    }
+    if check {
+        eprintln!("'{}' is not formatted", path);
+        return ExitCode::FAILURE;
+    }
+    if let Err(error) = fs::write(path, formatted) {
+        eprintln!("Could not write '{}': {}", path, error);
+        return ExitCode::from(2);
+    }
+    ExitCode::SUCCESS
+}
+
+fn print_tokens(input: &str, diagnostics_output: &DiagnosticsOutput) -> ExitCode {
+    let diagnostics_bag = Rc::new(RefCell::new(DiagnosticsBag::new()));
+    for token in Lexer::new(input, Rc::clone(&diagnostics_bag)).tokenize() {
+        if token.kind == TokenKind::Whitespace {
+            continue;
+        }
+        println!("{}..{} {} {:?}", token.span.start, token.span.end, token.kind, token.span.literal);
+    }
+    let diagnostics = &diagnostics_bag.borrow().diagnostics;
+    let text = SourceText::new(input.to_string());
+    DiagnosticsPrinter::new(&text, diagnostics)
+        .with_path(diagnostics_output.path.as_deref())
+        .print_as(diagnostics_output.format);
+    if diagnostics.is_empty() { ExitCode::SUCCESS } else { ExitCode::FAILURE }
 }
@@ -0,0 +1,374 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{ BufRead, ErrorKind, Write };
+use std::mem;
+use std::rc::Rc;
+
+use crate::ast::evaluator::{ ASTEvaluator, ControlFlow, Frames, RuntimeError, TraceFrame, Value };
+use crate::ast::lexer::{ Lexer, TokenKind };
+use crate::ast::visitor::ASTVisitor;
+use crate::compilation_unit::{ CompilationUnit, GlobalScope };
+use crate::diagnostics::DiagnosticsBag;
+use crate::diagnostics::printer::{ DiagnosticsOutput, DiagnosticsPrinter };
+use crate::intrinsics::written_or_exit;
+use crate::text::SourceText;
+
+const PROMPT: &str = "> ";
+const CONTINUATION_PROMPT: &str = "... ";
+// Stands in for a file name in the locations of diagnostics
+const PATH: &str = "<repl>";
+
+// An interactive session where each input builds on the declarations and values of earlier ones
+pub struct Repl {
+    global_scope: GlobalScope,
+    frames: Frames,
+    // The input each function was declared in, which the spans in its body point into
+    sources: HashMap<String, Rc<SourceText>>,
+}
+
+impl Default for Repl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        Self {
+            global_scope: GlobalScope::with_intrinsics(),
+            frames: Frames::new(),
+            sources: HashMap::new(),
+        }
+    }
+
+    // Reads inputs until the end of the stream or a call to `exit`, returning the exit code
+    pub fn run(&mut self, input: &mut dyn BufRead, output: &mut dyn Write) -> i64 {
+        let mut buffer = String::new();
+        loop {
+            let prompt = if buffer.is_empty() { PROMPT } else { CONTINUATION_PROMPT };
+            let written = write!(output, "{}", prompt).and_then(|()| output.flush());
+            if let Some(code) = written_or_exit(written) {
+                return code;
+            }
+            let mut line = String::new();
+            let read = match input.read_line(&mut line) {
+                Ok(read) => read,
+                // The line is consumed all the same, so the session carries on with the next one
+                Err(error) if error.kind() == ErrorKind::InvalidData => {
+                    eprintln!("Failed to read input: {}", error);
+                    continue;
+                }
+                Err(error) => {
+                    eprintln!("Failed to read input: {}", error);
+                    return 1;
+                }
+            };
+            if read == 0 {
+                if let Some(code) = written_or_exit(writeln!(output)) {
+                    return code;
+                }
+                // An unfinished input still gets its diagnostics
+                if !buffer.trim().is_empty() {
+                    return self.evaluate(&buffer, output).unwrap_or(0);
+                }
+                return 0;
+            }
+            buffer.push_str(&line);
+            if Self::has_open_braces(&buffer) {
+                continue;
+            }
+            let source = mem::take(&mut buffer);
+            if source.trim().is_empty() {
+                continue;
+            }
+            if let Some(code) = self.evaluate(&source, output) {
+                return code;
+            }
+        }
+    }
+
+    // Compiles and runs one complete input, printing the value of a trailing expression.
+    // Returns the exit code once the input calls `exit`
+    pub fn evaluate(&mut self, input: &str, output: &mut dyn Write) -> Option<i64> {
+        match self.execute(input, output) {
+            Ok(exit_code) => exit_code,
+            Err((text, error)) => {
+                let diagnostics = [error.to_diagnostic()];
+                DiagnosticsPrinter::new(&text, &diagnostics).with_path(Some(PATH)).print();
+                None
+            }
+        }
+    }
+
+    // Like `evaluate`, but hands back a runtime error along with the input it points into
+    fn execute(
+        &mut self,
+        input: &str,
+        output: &mut dyn Write
+    ) -> Result<Option<i64>, (Rc<SourceText>, RuntimeError)> {
+        // Compilation prints its own diagnostics, and a failed input leaves the session untouched
+        let Ok(compilation_unit) = CompilationUnit::compile_in_session(
+            input,
+            self.global_scope.clone(),
+            Self::diagnostics_output()
+        ) else {
+            return Ok(None);
+        };
+        // Only the functions this input declared still know where their declaration is
+        let text = Rc::new(SourceText::new(input.to_string()));
+        for (identifier, function) in &compilation_unit.global_scope.functions {
+            if function.declaration.is_some() {
+                self.sources.insert(identifier.clone(), Rc::clone(&text));
+            }
+        }
+        let mut eval = ASTEvaluator::new(&compilation_unit.global_scope, output);
+        eval.frames = mem::take(&mut self.frames);
+        for statement in &compilation_unit.ast.statements {
+            eval.visit_statement(statement);
+            if eval.is_unwinding() {
+                break;
+            }
+        }
+        eval.flush();
+        self.frames = mem::take(&mut eval.frames);
+        let control_flow = eval.control_flow.take();
+        let last_value = eval.last_value.take();
+        drop(eval);
+
+        let ends_with_expression = compilation_unit.ast.statements
+            .last()
+            .is_some_and(|statement| statement.is_expression());
+        self.global_scope = compilation_unit.global_scope;
+        match control_flow {
+            Some(ControlFlow::Error(error)) => Err(self.locate_runtime_error(error, text)),
+            Some(ControlFlow::Exit(code)) => Ok(Some(code)),
+            Some(_) => Ok(None),
+            None => {
+                match last_value {
+                    Some(Value::Unit) | None => Ok(None),
+                    Some(value) if ends_with_expression => {
+                        Ok(written_or_exit(writeln!(output, "{}", value)))
+                    }
+                    Some(_) => Ok(None),
+                }
+            }
+        }
+    }
+
+    // Each span points into the input that declared the function it is in, or the current one
+    // at the top level. Calls made from other inputs than the error itself are left out
+    fn locate_runtime_error(
+        &self,
+        error: RuntimeError,
+        input: Rc<SourceText>
+    ) -> (Rc<SourceText>, RuntimeError) {
+        let source = |frame: Option<&TraceFrame>| {
+            frame.map_or(&input, |frame| &self.sources[&frame.function])
+        };
+        let text = source(error.trace.first());
+        let trace = error.trace
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| Rc::ptr_eq(source(error.trace.get(i + 1)), text))
+            .map(|(_, frame)| frame.clone())
+            .collect();
+        (Rc::clone(text), RuntimeError { trace, ..error })
+    }
+
+    fn diagnostics_output() -> DiagnosticsOutput {
+        DiagnosticsOutput { path: Some(PATH.to_string()), ..DiagnosticsOutput::default() }
+    }
+
+    // An input continues onto the next line while it has more `{` than `}`
+    fn has_open_braces(input: &str) -> bool {
+        let diagnostics_bag = Rc::new(RefCell::new(DiagnosticsBag::new()));
+        let mut depth = 0;
+        for token in Lexer::new(input, diagnostics_bag).tokenize() {
+            match token.kind {
+                TokenKind::OpenBrace => {
+                    depth += 1;
+                }
+                TokenKind::CloseBrace => {
+                    depth -= 1;
+                }
+                _ => {}
+            }
+        }
+        depth > 0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{ self, ErrorKind, Write };
+
+    use super::{ Repl, PATH };
+    use crate::compilation_unit::{ CompilationUnit, GlobalScope };
+    use crate::diagnostics::printer::{ DiagnosticsPrinter, ErrorFormat };
+    use crate::text::SourceText;
+
+    fn evaluate(inputs: &[&str]) -> String {
+        let mut repl = Repl::new();
+        let mut output = Vec::new();
+        for input in inputs {
+            assert_eq!(repl.evaluate(input, &mut output), None);
+        }
+        String::from_utf8(output).expect("Output is not UTF-8")
+    }
+
+    fn run(input: &str) -> (i64, String) {
+        let mut repl = Repl::new();
+        let mut output = Vec::new();
+        let code = repl.run(&mut input.as_bytes(), &mut output);
+        (code, String::from_utf8(output).expect("Output is not UTF-8"))
+    }
+
+    #[test]
+    pub fn should_keep_variables_between_inputs() {
+        assert_eq!(evaluate(&["let a = 1", "a + 1"]), "2\n");
+    }
+
+    #[test]
+    pub fn should_keep_functions_between_inputs() {
+        let output = evaluate(&[
+            "\
+        func double(x: int) -> int {
+            return x * 2
+        }",
+            "let a = double(4)",
+            "a = a + 1",
+            "double(a)",
+        ]);
+        assert_eq!(output, "9\n18\n");
+    }
+
+    #[test]
+    pub fn should_print_values_of_trailing_expressions() {
+        assert_eq!(evaluate(&["let a = 5", "println(a)", "a = 6", "a"]), "5\n6\n6\n");
+    }
+
+    #[test]
+    pub fn should_survive_compile_errors() {
+        assert_eq!(evaluate(&["let a = 1", "let b = a + c", "b", "a * 10"]), "10\n");
+    }
+
+    #[test]
+    pub fn should_survive_runtime_errors() {
+        assert_eq!(evaluate(&["let a = 3", "let b = a / 0", "a + 1"]), "4\n");
+    }
+
+    #[test]
+    pub fn should_continue_lines_while_braces_are_open() {
+        let input =
+            "\
+        let total = 0
+        let i = 0
+        while i < 4 {
+            total = total + i
+            i = i + 1
+        }
+        total
+";
+        let (code, output) = run(input);
+        assert_eq!(code, 0);
+        assert_eq!(output, "> > > ... ... ... > 6\n> \n");
+    }
+
+    #[test]
+    pub fn should_stop_at_exit() {
+        let (code, output) = run("let a = 7\nexit(a)\na\n");
+        assert_eq!(code, 7);
+        assert_eq!(output, "> > ");
+    }
+
+    #[test]
+    pub fn should_skip_lines_that_are_not_utf8() {
+        let mut repl = Repl::new();
+        let mut output = Vec::new();
+        let code = repl.run(&mut &b"let a = 1\n\xff\na + 1\n"[..], &mut output);
+        assert_eq!(code, 0);
+        assert_eq!(String::from_utf8(output).unwrap(), "> > > 2\n> \n");
+    }
+
+    struct FailingOutput(ErrorKind);
+
+    impl Write for FailingOutput {
+        fn write(&mut self, _buffer: &[u8]) -> io::Result<usize> {
+            Err(io::Error::from(self.0))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Err(io::Error::from(self.0))
+        }
+    }
+
+    #[test]
+    pub fn should_end_quietly_when_output_is_closed() {
+        let mut output = FailingOutput(ErrorKind::BrokenPipe);
+        let code = Repl::new().run(&mut "1\n".as_bytes(), &mut output);
+        assert_eq!(code, 0);
+    }
+
+    #[test]
+    pub fn should_fail_when_output_cannot_be_written() {
+        let mut output = FailingOutput(ErrorKind::StorageFull);
+        let code = Repl::new().run(&mut "1\n".as_bytes(), &mut output);
+        assert_eq!(code, 1);
+    }
+
+    #[test]
+    pub fn should_locate_diagnostics_in_the_repl() {
+        let input = "let b = a";
+        let output = Repl::diagnostics_output();
+        let diagnostics_bag = CompilationUnit::compile_in_session(
+            input,
+            GlobalScope::with_intrinsics(),
+            output.clone()
+        ).err().expect("Expected a compile error");
+        let text = SourceText::new(input.to_string());
+        let diagnostics = &diagnostics_bag.borrow().diagnostics;
+        let rendered = DiagnosticsPrinter::new(&text, diagnostics)
+            .with_path(output.path.as_deref())
+            .stringify(ErrorFormat::Human);
+        assert!(rendered.contains(" --> <repl>:1:9"), "{}", rendered);
+    }
+
+    #[test]
+    pub fn should_point_into_the_input_that_declared_the_failing_function() {
+        let mut repl = Repl::new();
+        let mut output = Vec::new();
+        let inputs = [
+            "func f(x) {\n    let zero = 0\n    return x / zero\n}",
+            "func g(x) {\n    return f(x)\n}",
+            "let a = 3\ng(a)",
+        ];
+        assert!(matches!(repl.execute(inputs[0], &mut output), Ok(None)));
+        assert!(matches!(repl.execute(inputs[1], &mut output), Ok(None)));
+        let Err((text, error)) = repl.execute(inputs[2], &mut output) else {
+            panic!("Expected a runtime error");
+        };
+
+        let diagnostics = [error.to_diagnostic()];
+        let rendered = DiagnosticsPrinter::new(&text, &diagnostics)
+            .with_path(Some(PATH))
+            .stringify(ErrorFormat::Short);
+        assert_eq!(rendered, "<repl>:3:12: error[T0026]: Division by zero");
+        assert_eq!(error.span.literal, "x / zero");
+        // The calls were made from later inputs
+        assert!(error.trace.is_empty());
+    }
+
+    #[test]
+    pub fn should_keep_calls_made_from_the_input_of_the_error() {
+        let mut repl = Repl::new();
+        let mut output = Vec::new();
+        let input = "func f(x) {\n    return x / 0\n}\nf(3)";
+        let Err((_, error)) = repl.execute(input, &mut output) else {
+            panic!("Expected a runtime error");
+        };
+
+        assert_eq!(error.span.literal, "x / 0");
+        assert_eq!(error.trace.len(), 1);
+        assert_eq!(error.trace[0].call_site.literal, "f(3)");
+    }
+}
@@ -1,3 +1,5 @@
+use crate::ast::lexer::TextSpan;
+
 pub struct SourceText {
     text: String,
 }
@@ -9,19 +11,43 @@ impl SourceText {
         }
     }
 
+    // Positions up to and including the end of the text are valid, for diagnostics at the end
     pub fn line_index(&self, position: usize) -> usize {
-        self.text[..=position].lines().count() - 1
+        self.text[..position.min(self.text.len())].matches('\n').count()
+    }
+
+    // Text after the last `\n` is a line of its own, even when it is empty
+    pub fn line_count(&self) -> usize {
+        self.text.matches('\n').count() + 1
     }
 
     pub fn get_line(&self, index: usize) -> &str {
-        self.text.lines().nth(index).unwrap()
+        self.text.lines().nth(index).unwrap_or("")
     }
 
+    // Counted from the `\n` itself, since a line may also end in `\r\n`
     pub fn line_start(&self, index: usize) -> usize {
-        self.text
-            .lines()
-            .take(index)
-            .map(|line| line.len() + 1)
-            .sum()
+        match index.checked_sub(1) {
+            None => 0,
+            Some(previous) => {
+                self.text
+                    .match_indices('\n')
+                    .nth(previous)
+                    .map_or(self.text.len(), |(newline, _)| newline + 1)
+            }
+        }
+    }
+
+    // Zero-based line and column of a position, with the column counted in characters
+    pub fn line_column(&self, position: usize) -> (usize, usize) {
+        let position = position.min(self.text.len());
+        let line_index = self.line_index(position);
+        let line_start = self.line_start(line_index).min(position);
+        (line_index, self.text[line_start..position].chars().count())
+    }
+
+    // The span of `start..end` with its literal taken from the source
+    pub fn span(&self, start: usize, end: usize) -> TextSpan {
+        TextSpan::new(start, end, self.text[start..end].to_string())
     }
 }
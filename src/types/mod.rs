@@ -0,0 +1,48 @@
+use std::fmt::{ Display, Formatter };
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Int,
+    Float,
+    Bool,
+    String,
+    Unit,
+    // Not known until runtime, e.g. an unannotated parameter
+    Unresolved,
+    // Produced by an expression that already reported a diagnostic
+    Error,
+}
+
+impl Type {
+    pub fn from_name(name: &str) -> Option<Type> {
+        match name {
+            "int" => Some(Type::Int),
+            "float" => Some(Type::Float),
+            "bool" => Some(Type::Bool),
+            "string" => Some(Type::String),
+            "unit" => Some(Type::Unit),
+            _ => None,
+        }
+    }
+
+    pub fn is_assignable_to(&self, other: &Type) -> bool {
+        match (self, other) {
+            (Type::Unresolved | Type::Error, _) | (_, Type::Unresolved | Type::Error) => true,
+            _ => self == other,
+        }
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Float => write!(f, "float"),
+            Type::Bool => write!(f, "bool"),
+            Type::String => write!(f, "string"),
+            Type::Unit => write!(f, "unit"),
+            Type::Unresolved => write!(f, "unresolved"),
+            Type::Error => write!(f, "error"),
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::{ Command, Output };
+use std::sync::atomic::{ AtomicUsize, Ordering };
+
+// Writes the source to a file of its own and runs the driver on it
+fn tungsten(arguments: &[&str], source: &str) -> (Output, PathBuf) {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let count = COUNT.fetch_add(1, Ordering::Relaxed);
+    let path = std::env::temp_dir().join(
+        format!("tungsten-cli-{}-{}.tn", std::process::id(), count)
+    );
+    fs::write(&path, source).unwrap();
+    let path_argument = path.display().to_string();
+    let output = run(&[arguments, &[path_argument.as_str()]].concat());
+    (output, path)
+}
+
+fn run(arguments: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_tungsten"))
+        .args(arguments)
+        .output()
+        .expect("Failed to run tungsten")
+}
+
+fn exit_code_of(arguments: &[&str], source: &str) -> Option<i32> {
+    let (output, path) = tungsten(arguments, source);
+    let _ = fs::remove_file(&path);
+    output.status.code()
+}
+
+#[test]
+fn should_exit_with_2_on_usage_errors() {
+    assert_eq!(run(&[]).status.code(), Some(2));
+    assert_eq!(run(&["run"]).status.code(), Some(2));
+    assert_eq!(run(&["run", "--no-such-option", "file.tn"]).status.code(), Some(2));
+    assert_eq!(run(&["frobnicate", "file.tn"]).status.code(), Some(2));
+    assert_eq!(run(&["run", "/no/such/file.tn"]).status.code(), Some(2));
+}
+
+#[test]
+fn should_exit_with_1_on_diagnostics_and_runtime_errors() {
+    assert_eq!(exit_code_of(&["check"], "let a = b\n"), Some(1));
+    assert_eq!(exit_code_of(&["run"], "let a = b\n"), Some(1));
+    assert_eq!(exit_code_of(&["run"], "let zero = 0\nprintln(1 / zero)\n"), Some(1));
+    assert_eq!(exit_code_of(&["run", "--backend=bytecode"], "assert(1 > 2)\n"), Some(1));
+}
+
+#[test]
+fn should_exit_with_the_code_the_program_exits_with() {
+    assert_eq!(exit_code_of(&["run"], "println(1)\n"), Some(0));
+    assert_eq!(exit_code_of(&["run"], "exit(42)\n"), Some(42));
+    assert_eq!(exit_code_of(&["run", "--backend=bytecode"], "exit(3)\n"), Some(3));
+    // Like a native process, the code wraps to a byte
+    assert_eq!(exit_code_of(&["run"], "exit(257)\n"), Some(1));
+}
+
+#[test]
+fn should_write_format_errors_in_the_chosen_format() {
+    let (output, path) = tungsten(&["fmt", "--error-format=json"], "let = 1\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr.lines().count() > 0);
+    for line in stderr.lines() {
+        assert!(line.starts_with('{'), "{} is not JSON", line);
+        assert!(line.contains(&path.display().to_string()), "{} has no path", line);
+    }
+    assert_eq!(fs::read_to_string(&path).unwrap(), "let = 1\n");
+    let _ = fs::remove_file(&path);
+
+    let (output, path) = tungsten(&["fmt", "--error-format=sarif"], "let = 1\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr.contains("\"ruleId\":\"T0011\""), "{}", stderr);
+    let _ = fs::remove_file(&path);
+}